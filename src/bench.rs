@@ -0,0 +1,252 @@
+use crate::error::EdrError;
+use crate::events::network::{NetworkDirection, Protocol};
+use crate::events::{BaseEvent, DnsEvent, EventType, NetworkEvent, ProcessEvent};
+use crate::monitoring::clock::SystemClock;
+use crate::monitoring::correlation_engine::{EVENTS_PROCESSED, TRACKED_CONTEXTS};
+use crate::monitoring::start_correlation_engine;
+use crate::utils::common::cache_process_start;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+use windows::Win32::System::Threading::GetCurrentProcess;
+
+// Small enough that an engine which can't keep up pushes back on the sender
+// instead of hiding behind an ever-growing queue
+const BENCH_QUEUE_CAPACITY: usize = 1024;
+// How long the engine gets to work through what is queued after the last send
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+// Synthetic PIDs sit far above anything Windows hands out, so lookups the engine
+// makes against the live system find nothing rather than a real process
+const FIRST_SYNTHETIC_PID: u32 = 0x4000_0000;
+const PARENT_POOL: u32 = 32;
+
+/// Process, command line and remote port for one synthetic process. Most are
+/// benign; every eighth one trips a rule, so the scoring and alert paths are
+/// exercised alongside the cheap ones.
+const PROCESS_TEMPLATES: &[(&str, &str, u16)] = &[
+    ("notepad.exe", r"C:\Windows\System32\notepad.exe C:\Users\bench\notes.txt", 443),
+    ("chrome.exe", r#""C:\Program Files\Google\Chrome\Application\chrome.exe" --type=renderer"#, 443),
+    ("cmd.exe", r"C:\Windows\System32\cmd.exe /c dir C:\Users\bench", 80),
+    ("git.exe", r#""C:\Program Files\Git\cmd\git.exe" fetch origin"#, 443),
+    ("code.exe", r#""C:\Users\bench\AppData\Local\Programs\Microsoft VS Code\Code.exe""#, 443),
+    ("msedge.exe", r#""C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe""#, 443),
+    ("python.exe", r"C:\Python312\python.exe -m http.client", 8080),
+    (
+        "powershell.exe",
+        "powershell.exe -NoProfile -WindowStyle Hidden -EncodedCommand VwByAGkAdABlAC0ATwB1AHQAcAB1AHQAIAAnAGIAZQBuAGMAaAAnAA==",
+        4444,
+    ),
+];
+
+/// Turns a counter into a steady stream of process lifecycles: start, three
+/// connections, a DNS lookup and exit, six events per process.
+struct Workload {
+    sequence: u64,
+    machine_name: String,
+    user_name: String,
+}
+
+impl Workload {
+    fn new() -> Self {
+        let workload = Self {
+            sequence: 0,
+            machine_name: whoami::fallible::hostname().unwrap_or_else(|_| "unknown".to_string()),
+            user_name: whoami::fallible::username().unwrap_or_else(|_| "unknown".to_string()),
+        };
+        // Stand in for the process monitor, as replay does, so parent lookups hit the cache
+        for parent in 0..PARENT_POOL {
+            cache_process_start(FIRST_SYNTHETIC_PID + parent * 4, 0, "explorer.exe", Some("explorer.exe".to_string()));
+        }
+        workload
+    }
+
+    fn next_event(&mut self) -> BaseEvent {
+        const EVENTS_PER_PROCESS: u64 = 6;
+        let process = self.sequence / EVENTS_PER_PROCESS;
+        let step = self.sequence % EVENTS_PER_PROCESS;
+        self.sequence += 1;
+
+        let (name, command_line, port) = PROCESS_TEMPLATES[process as usize % PROCESS_TEMPLATES.len()];
+        let pid = FIRST_SYNTHETIC_PID + (PARENT_POOL + (process % 0x0100_0000) as u32) * 4;
+        let parent_pid = FIRST_SYNTHETIC_PID + (process % PARENT_POOL as u64) as u32 * 4;
+        let remote_address = format!("203.0.113.{}", process % 250 + 1);
+
+        let event_type = match step {
+            0 => {
+                cache_process_start(pid, parent_pid, name, Some(command_line.to_string()));
+                let mut event = ProcessEvent::new_start(pid, parent_pid, name.to_string());
+                event.command_line = command_line.to_string();
+                EventType::ProcessStart(event)
+            }
+            1..=3 => EventType::NetworkConnection(NetworkEvent::new(
+                pid,
+                name.to_string(),
+                NetworkDirection::Outbound,
+                Protocol::TCP,
+                "10.0.0.5".to_string(),
+                49152 + (self.sequence % 16000) as u16,
+                remote_address,
+                port,
+            )),
+            4 => EventType::DnsQuery(DnsEvent::new(
+                pid,
+                name.to_string(),
+                format!("host{}.example.com", process % 500),
+                0,
+                vec![remote_address],
+            )),
+            _ => EventType::ProcessEnd(ProcessEvent::new_end(pid, name.to_string(), Some(0))),
+        };
+        BaseEvent {
+            timestamp: chrono::Utc::now(),
+            event_id: uuid::Uuid::new_v4().to_string(),
+            machine_name: self.machine_name.clone(),
+            user_name: self.user_name.clone(),
+            event_type,
+        }
+    }
+}
+
+/// `--bench`: feeds synthetic events through the correlation engine for `duration`
+/// at `rate` events per second (0 = as fast as the engine takes them), with no
+/// ETW sessions or alert outputs, and prints throughput, per-event latency and
+/// memory. Latency runs from the send to the engine counting the event handled.
+pub fn run_benchmark(config_path: Option<&Path>, rate: u64, duration: Duration) -> Result<(), EdrError> {
+    let mut config = crate::config::rules::load_rules(config_path)?;
+    // Synthetic alerts must not end up in the learned baseline
+    config.baseline = None;
+    let config = Arc::new(config);
+
+    // Everything goes down one queue so events are handled in the order they were sent
+    let (process_tx, process_rx) = crossbeam_channel::bounded(BENCH_QUEUE_CAPACITY);
+    let (network_tx, network_rx) = crossbeam_channel::unbounded::<BaseEvent>();
+    let (alert_tx, alert_rx) = crossbeam_channel::unbounded();
+    let engine_running = Arc::new(AtomicBool::new(true));
+    let memory_before = memory_usage();
+
+    let engine = start_correlation_engine(
+        process_rx,
+        network_rx,
+        alert_tx,
+        config,
+        Arc::new(SystemClock),
+        Arc::clone(&engine_running),
+    );
+
+    match rate {
+        0 => println!("Benchmarking the correlation engine for {}s, unthrottled...", duration.as_secs()),
+        _ => println!("Benchmarking the correlation engine for {}s at {} events/s...", duration.as_secs(), rate),
+    }
+
+    let mut workload = Workload::new();
+    let mut pending: VecDeque<Instant> = VecDeque::new();
+    let mut latencies: Vec<Duration> = Vec::new();
+    let mut handled_before = EVENTS_PROCESSED.load(Ordering::Relaxed);
+    let mut alerts = 0u64;
+    let mut sent = 0u64;
+
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        let due = match rate {
+            0 => sent + 1,
+            _ => (started.elapsed().as_secs_f64() * rate as f64) as u64,
+        };
+        while sent < due {
+            // Blocks once the queue is full; that wait is part of the event's latency
+            pending.push_back(Instant::now());
+            if process_tx.send(workload.next_event()).is_err() {
+                return Err(EdrError::Bench("correlation engine stopped".to_string()));
+            }
+            sent += 1;
+        }
+        record_handled(&mut handled_before, &mut pending, &mut latencies);
+        alerts += alert_rx.try_iter().count() as u64;
+        std::thread::yield_now();
+    }
+    let sending_time = started.elapsed();
+
+    let drain_started = Instant::now();
+    while !pending.is_empty() && drain_started.elapsed() < DRAIN_TIMEOUT {
+        record_handled(&mut handled_before, &mut pending, &mut latencies);
+        std::thread::yield_now();
+    }
+    let elapsed = started.elapsed();
+    alerts += alert_rx.try_iter().count() as u64;
+    let memory_after = memory_usage();
+    let tracked_contexts = TRACKED_CONTEXTS.load(Ordering::Relaxed);
+
+    engine_running.store(false, Ordering::Relaxed);
+    drop(process_tx);
+    drop(network_tx);
+    let _ = engine.join();
+
+    let handled = latencies.len() as u64;
+    latencies.sort_unstable();
+    let percentile = |p: f64| {
+        latencies.get(((latencies.len() as f64 * p).ceil() as usize).saturating_sub(1))
+            .map_or(0.0, |latency| latency.as_secs_f64() * 1000.0)
+    };
+
+    println!();
+    println!("Events sent        {}", sent);
+    println!(
+        "Events handled     {} ({:.2}s to drain after the last send)",
+        handled, (elapsed - sending_time).as_secs_f64()
+    );
+    println!("Throughput         {:.0} events/s", handled as f64 / elapsed.as_secs_f64());
+    println!(
+        "Latency            p50 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+        percentile(0.50), percentile(0.99), percentile(1.0)
+    );
+    println!("Alerts raised      {}", alerts);
+    println!("Tracked contexts   {}", tracked_contexts);
+    if let (Some((before, _)), Some((after, peak))) = (memory_before, memory_after) {
+        println!(
+            "Working set        {:.1} MB before, {:.1} MB after, {:.1} MB peak",
+            megabytes(before), megabytes(after), megabytes(peak)
+        );
+    }
+
+    if handled < sent {
+        return Err(EdrError::Bench(format!(
+            "engine handled {} of {} events within {}s of the last send",
+            handled, sent, DRAIN_TIMEOUT.as_secs()
+        )));
+    }
+    Ok(())
+}
+
+/// Moves every event the engine has handled since the last call from `pending`
+/// to `latencies`, oldest first.
+fn record_handled(handled_before: &mut u64, pending: &mut VecDeque<Instant>, latencies: &mut Vec<Duration>) {
+    let handled = EVENTS_PROCESSED.load(Ordering::Relaxed);
+    let now = Instant::now();
+    for _ in *handled_before..handled {
+        match pending.pop_front() {
+            Some(sent_at) => latencies.push(now - sent_at),
+            None => break,
+        }
+    }
+    *handled_before = handled;
+}
+
+/// Current and peak working set of this process, in bytes.
+fn memory_usage() -> Option<(usize, usize)> {
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    unsafe {
+        GetProcessMemoryInfo(
+            GetCurrentProcess(),
+            &mut counters,
+            std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        )
+    }
+    .ok()?;
+    Some((counters.WorkingSetSize, counters.PeakWorkingSetSize))
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
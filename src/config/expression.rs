@@ -0,0 +1,574 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A field an `alert_rules` expression can test. Process fields are available on
+/// every event; network fields only on connections, so a rule that names one is
+/// only evaluated there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Image,
+    Pid,
+    CommandLine,
+    ParentImage,
+    ParentPid,
+    ParentCommandLine,
+    User,
+    Elevated,
+    Integrity,
+    RemoteAddress,
+    RemotePort,
+    LocalPort,
+    Protocol,
+    Direction,
+    Domain,
+    IsExternal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Text,
+    Number,
+    Bool,
+}
+
+const FIELDS: &[(&str, Field, FieldKind)] = &[
+    ("image", Field::Image, FieldKind::Text),
+    ("pid", Field::Pid, FieldKind::Number),
+    ("command_line", Field::CommandLine, FieldKind::Text),
+    ("parent_image", Field::ParentImage, FieldKind::Text),
+    ("parent_pid", Field::ParentPid, FieldKind::Number),
+    ("parent_command_line", Field::ParentCommandLine, FieldKind::Text),
+    ("user", Field::User, FieldKind::Text),
+    ("elevated", Field::Elevated, FieldKind::Bool),
+    ("integrity", Field::Integrity, FieldKind::Text),
+    ("remote_address", Field::RemoteAddress, FieldKind::Text),
+    ("remote_port", Field::RemotePort, FieldKind::Number),
+    ("local_port", Field::LocalPort, FieldKind::Number),
+    ("protocol", Field::Protocol, FieldKind::Text),
+    ("direction", Field::Direction, FieldKind::Text),
+    ("domain", Field::Domain, FieldKind::Text),
+    ("is_external", Field::IsExternal, FieldKind::Bool),
+];
+
+impl Field {
+    pub fn name(self) -> &'static str {
+        FIELDS.iter().find(|(_, field, _)| *field == self).map_or("?", |(name, _, _)| name)
+    }
+
+    pub fn is_network(self) -> bool {
+        matches!(
+            self,
+            Field::RemoteAddress | Field::RemotePort | Field::LocalPort | Field::Protocol
+                | Field::Direction | Field::Domain | Field::IsExternal
+        )
+    }
+}
+
+/// A field's value on one event, as handed to `Expression::evaluate`.
+#[derive(Debug, Clone)]
+pub enum FieldValue {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::Text(text) => write!(f, "{:?}", text),
+            FieldValue::Number(number) => write!(f, "{}", number),
+            FieldValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Comparison {
+    Equals(String),
+    NotEquals(String),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    Matches(regex::Regex),
+    Number(NumberOp, u64),
+    Bool(bool, bool), // (expected, negated)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NumberOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// Deepest run of NOT and '(' accepted; bounds the parser's recursion on hostile input
+const MAX_NESTING_DEPTH: usize = 32;
+
+// AND and OR hold every operand of a chain side by side, so a long chain adds
+// width rather than depth and recursion stays bounded by MAX_NESTING_DEPTH
+#[derive(Debug, Clone)]
+enum Node {
+    Test(Field, Comparison),
+    Not(Box<Node>),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+}
+
+/// A compiled `alert_rules` expression such as
+/// `parent_image ends_with "winword.exe" AND (image contains "powershell" OR image contains "cmd")`.
+///
+/// Tests are `field operator value`. Text fields take `==`, `!=`, `contains`,
+/// `starts_with`, `ends_with` and `matches` (a regex) with a quoted value, compared
+/// case-insensitively. Number fields take `==`, `!=`, `<`, `<=`, `>`, `>=` and bool
+/// fields `==` and `!=` with `true` or `false`. Tests combine with `NOT`, `AND` and
+/// `OR`, binding in that order, and parentheses group them. Keywords and field
+/// names are case-insensitive.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    root: Node,
+    source: String,
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    /// Errors name the 1-based column they were found at.
+    fn from_str(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens: &tokens, position: 0, end: source.chars().count() + 1, depth: 0 };
+        let root = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("column {}: expected AND, OR or the end, found {}", token.column, token.kind));
+        }
+        Ok(Self { root, source: source.trim().to_string() })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expression {
+    /// Whether the event matches. `lookup` gives each field's value on the event,
+    /// or `None` when the event has no such field; a test on a missing field is false.
+    pub fn evaluate(&self, lookup: &dyn Fn(Field) -> Option<FieldValue>) -> bool {
+        evaluate_node(&self.root, lookup)
+    }
+
+    /// Every field the expression tests, once each, in order of appearance.
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields = Vec::new();
+        collect_fields(&self.root, &mut fields);
+        fields
+    }
+
+    /// Whether the expression tests a network field, and so only applies to connections.
+    pub fn needs_network(&self) -> bool {
+        self.fields().iter().any(|field| field.is_network())
+    }
+}
+
+fn evaluate_node(node: &Node, lookup: &dyn Fn(Field) -> Option<FieldValue>) -> bool {
+    match node {
+        Node::Not(inner) => !evaluate_node(inner, lookup),
+        Node::And(operands) => operands.iter().all(|operand| evaluate_node(operand, lookup)),
+        Node::Or(operands) => operands.iter().any(|operand| evaluate_node(operand, lookup)),
+        Node::Test(field, comparison) => {
+            let Some(value) = lookup(*field) else {
+                return false;
+            };
+            match (comparison, value) {
+                (Comparison::Matches(regex), FieldValue::Text(text)) => regex.is_match(&text),
+                (Comparison::Number(op, expected), FieldValue::Number(actual)) => match op {
+                    NumberOp::Eq => actual == *expected,
+                    NumberOp::Ne => actual != *expected,
+                    NumberOp::Lt => actual < *expected,
+                    NumberOp::Le => actual <= *expected,
+                    NumberOp::Gt => actual > *expected,
+                    NumberOp::Ge => actual >= *expected,
+                },
+                (Comparison::Bool(expected, negated), FieldValue::Bool(actual)) => (actual == *expected) != *negated,
+                // Text operands are lowercased when the expression is compiled
+                (comparison, FieldValue::Text(text)) => {
+                    let text = text.to_lowercase();
+                    match comparison {
+                        Comparison::Equals(expected) => text == *expected,
+                        Comparison::NotEquals(expected) => text != *expected,
+                        Comparison::Contains(expected) => text.contains(expected.as_str()),
+                        Comparison::StartsWith(expected) => text.starts_with(expected.as_str()),
+                        Comparison::EndsWith(expected) => text.ends_with(expected.as_str()),
+                        _ => false,
+                    }
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+fn collect_fields(node: &Node, fields: &mut Vec<Field>) {
+    match node {
+        Node::Test(field, _) => {
+            if !fields.contains(field) {
+                fields.push(*field);
+            }
+        }
+        Node::Not(inner) => collect_fields(inner, fields),
+        Node::And(operands) | Node::Or(operands) => {
+            for operand in operands {
+                collect_fields(operand, fields);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Text(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "'{}'", word),
+            TokenKind::Text(text) => write!(f, "{:?}", text),
+            TokenKind::Operator(operator) => write!(f, "'{}'", operator),
+            TokenKind::Open => f.write_str("'('"),
+            TokenKind::Close => f.write_str("')'"),
+        }
+    }
+}
+
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: &[&str] = &["==", "!=", "<=", ">=", "<", ">"];
+
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let column = index + 1;
+        if c.is_whitespace() {
+            index += 1;
+        } else if c == '(' || c == ')' {
+            tokens.push(Token { kind: if c == '(' { TokenKind::Open } else { TokenKind::Close }, column });
+            index += 1;
+        } else if c == '"' {
+            // Backslash escapes only a quote or a backslash, so Windows paths read naturally
+            let mut text = String::new();
+            index += 1;
+            loop {
+                match chars.get(index) {
+                    None => return Err(format!("column {}: unterminated string", column)),
+                    Some('"') => break,
+                    Some('\\') if matches!(chars.get(index + 1), Some('"') | Some('\\')) => {
+                        text.push(chars[index + 1]);
+                        index += 2;
+                    }
+                    Some(other) => {
+                        text.push(*other);
+                        index += 1;
+                    }
+                }
+            }
+            index += 1;
+            tokens.push(Token { kind: TokenKind::Text(text), column });
+        } else if let Some(operator) = OPERATORS.iter().find(|operator| {
+            operator.chars().enumerate().all(|(offset, expected)| chars.get(index + offset) == Some(&expected))
+        }) {
+            tokens.push(Token { kind: TokenKind::Operator(operator), column });
+            index += operator.len();
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_alphanumeric() || chars[index] == '_' || chars[index] == '.') {
+                index += 1;
+            }
+            tokens.push(Token { kind: TokenKind::Word(chars[start..index].iter().collect()), column });
+        } else {
+            return Err(format!("column {}: unexpected character '{}'", column, c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    // Column reported for errors at the end of the input
+    end: usize,
+    // NOTs and open parentheses enclosing the current position
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<&'a Token, String> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token)
+            }
+            None => Err(format!("column {}: expected {}, found the end", self.end, expected)),
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Word(word), .. }) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn enter(&mut self, column: usize) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(format!("column {}: nested deeper than {} NOTs and parentheses", column, MAX_NESTING_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Node, String> {
+        let mut operands = vec![self.parse_and()?];
+        while self.at_keyword("or") {
+            self.position += 1;
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { Node::Or(operands) })
+    }
+
+    fn parse_and(&mut self) -> Result<Node, String> {
+        let mut operands = vec![self.parse_not()?];
+        while self.at_keyword("and") {
+            self.position += 1;
+            operands.push(self.parse_not()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { Node::And(operands) })
+    }
+
+    fn parse_not(&mut self) -> Result<Node, String> {
+        if self.at_keyword("not") {
+            let column = self.tokens[self.position].column;
+            self.position += 1;
+            self.enter(column)?;
+            let inner = self.parse_not()?;
+            self.depth -= 1;
+            return Ok(Node::Not(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, String> {
+        let token = self.next("a field or '('")?;
+        let column = token.column;
+        match &token.kind {
+            TokenKind::Open => {
+                self.enter(column)?;
+                let node = self.parse_or()?;
+                self.depth -= 1;
+                match self.next("')'")? {
+                    Token { kind: TokenKind::Close, .. } => Ok(node),
+                    other => Err(format!("column {}: expected ')' to close the '(' at column {}, found {}", other.column, column, other.kind)),
+                }
+            }
+            TokenKind::Word(name) => {
+                let Some((_, field, kind)) = FIELDS.iter().find(|(known, _, _)| known.eq_ignore_ascii_case(name)) else {
+                    let known: Vec<&str> = FIELDS.iter().map(|(known, _, _)| *known).collect();
+                    return Err(format!("column {}: unknown field '{}' (expected one of {})", column, name, known.join(", ")));
+                };
+                let (field, kind) = (*field, *kind);
+                self.parse_comparison(field, kind).map(|comparison| Node::Test(field, comparison))
+            }
+            other => Err(format!("column {}: expected a field or '(', found {}", column, other)),
+        }
+    }
+
+    fn parse_comparison(&mut self, field: Field, kind: FieldKind) -> Result<Comparison, String> {
+        let operator_token = self.next("an operator")?;
+        let operator_column = operator_token.column;
+        let operator = match &operator_token.kind {
+            TokenKind::Operator(operator) => operator.to_string(),
+            TokenKind::Word(word) => word.to_lowercase(),
+            other => return Err(format!("column {}: expected an operator, found {}", operator_column, other)),
+        };
+        let value = self.next("a value")?;
+        let value_column = value.column;
+        let wrong_operator = |allowed: &str| {
+            format!(
+                "column {}: '{}' does not apply to {} (a {} field; use {})",
+                operator_column, operator, field.name(), kind_name(kind), allowed
+            )
+        };
+
+        match kind {
+            FieldKind::Text => {
+                let TokenKind::Text(text) = &value.kind else {
+                    return Err(format!("column {}: {} needs a quoted value, found {}", value_column, field.name(), value.kind));
+                };
+                // Lowercased for the plain comparisons only; lowercasing a regex would turn \D into \d
+                if operator == "matches" {
+                    return regex::RegexBuilder::new(text)
+                        .case_insensitive(true)
+                        .build()
+                        .map(Comparison::Matches)
+                        .map_err(|e| {
+                            let e = e.to_string();
+                            format!("column {}: invalid regex: {}", value_column, e.lines().last().unwrap_or(&e).trim())
+                        });
+                }
+                let text = text.to_lowercase();
+                Ok(match operator.as_str() {
+                    "==" => Comparison::Equals(text),
+                    "!=" => Comparison::NotEquals(text),
+                    "contains" => Comparison::Contains(text),
+                    "starts_with" => Comparison::StartsWith(text),
+                    "ends_with" => Comparison::EndsWith(text),
+                    _ => return Err(wrong_operator("==, !=, contains, starts_with, ends_with or matches")),
+                })
+            }
+            FieldKind::Number => {
+                let op = match operator.as_str() {
+                    "==" => NumberOp::Eq,
+                    "!=" => NumberOp::Ne,
+                    "<" => NumberOp::Lt,
+                    "<=" => NumberOp::Le,
+                    ">" => NumberOp::Gt,
+                    ">=" => NumberOp::Ge,
+                    _ => return Err(wrong_operator("==, !=, <, <=, > or >=")),
+                };
+                match &value.kind {
+                    TokenKind::Word(number) => number.parse().map(|number| Comparison::Number(op, number))
+                        .map_err(|_| format!("column {}: {} needs a number, found '{}'", value_column, field.name(), number)),
+                    other => Err(format!("column {}: {} needs a number, found {}", value_column, field.name(), other)),
+                }
+            }
+            FieldKind::Bool => {
+                let negated = match operator.as_str() {
+                    "==" => false,
+                    "!=" => true,
+                    _ => return Err(wrong_operator("== or !=")),
+                };
+                match &value.kind {
+                    TokenKind::Word(word) if word.eq_ignore_ascii_case("true") => Ok(Comparison::Bool(true, negated)),
+                    TokenKind::Word(word) if word.eq_ignore_ascii_case("false") => Ok(Comparison::Bool(false, negated)),
+                    other => Err(format!("column {}: {} needs true or false, found {}", value_column, field.name(), other)),
+                }
+            }
+        }
+    }
+}
+
+fn kind_name(kind: FieldKind) -> &'static str {
+    match kind {
+        FieldKind::Text => "text",
+        FieldKind::Number => "number",
+        FieldKind::Bool => "true/false",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(image: &str, parent_image: &str, pid: u64) -> impl Fn(Field) -> Option<FieldValue> {
+        let (image, parent_image) = (image.to_string(), parent_image.to_string());
+        move |field| match field {
+            Field::Image => Some(FieldValue::Text(image.clone())),
+            Field::ParentImage => Some(FieldValue::Text(parent_image.clone())),
+            Field::Pid => Some(FieldValue::Number(pid)),
+            _ => None,
+        }
+    }
+
+    fn matches(expression: &str, lookup: &dyn Fn(Field) -> Option<FieldValue>) -> bool {
+        expression.parse::<Expression>().unwrap().evaluate(lookup)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let cmd = process("cmd.exe", "explorer.exe", 10);
+        // a OR (b AND c): true through the left arm alone
+        assert!(matches(r#"image == "cmd.exe" OR image == "x" AND pid == 99"#, &cmd));
+        // (a OR b) AND c would be false here
+        assert!(!matches(r#"(image == "cmd.exe" OR image == "x") AND pid == 99"#, &cmd));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let cmd = process("cmd.exe", "explorer.exe", 10);
+        // (NOT a) AND b, not NOT (a AND b)
+        assert!(!matches(r#"NOT image == "cmd.exe" AND pid == 99"#, &cmd));
+        assert!(matches(r#"NOT (image == "cmd.exe" AND pid == 99)"#, &cmd));
+        assert!(matches(r#"not not image == "CMD.EXE""#, &cmd));
+    }
+
+    #[test]
+    fn parentheses_group_across_levels() {
+        let word = process("powershell.exe", "winword.exe", 10);
+        let rule = r#"parent_image ends_with "winword.exe" AND (image contains "powershell" OR image contains "cmd")"#;
+        assert!(matches(rule, &word));
+        assert!(!matches(rule, &process("notepad.exe", "winword.exe", 10)));
+        assert!(matches(r#"((((pid > 5))))"#, &word));
+    }
+
+    #[test]
+    fn unknown_field_names_its_column() {
+        let error = r#"image == "a" AND imgae == "b""#.parse::<Expression>().unwrap_err();
+        assert!(error.starts_with("column 18: unknown field 'imgae'"), "{}", error);
+    }
+
+    #[test]
+    fn missing_field_makes_the_test_false() {
+        let cmd = process("cmd.exe", "explorer.exe", 10);
+        assert!(!matches(r#"user == "system""#, &cmd));
+        assert!(matches(r#"NOT user == "system""#, &cmd));
+    }
+
+    #[test]
+    fn nesting_is_capped_with_a_column() {
+        let at_limit = format!("{}pid == 1{}", "(".repeat(MAX_NESTING_DEPTH), ")".repeat(MAX_NESTING_DEPTH));
+        assert!(at_limit.parse::<Expression>().is_ok());
+
+        let too_deep = format!("{}pid == 1{}", "(".repeat(MAX_NESTING_DEPTH + 1), ")".repeat(MAX_NESTING_DEPTH + 1));
+        let error = too_deep.parse::<Expression>().unwrap_err();
+        assert!(error.starts_with(&format!("column {}: nested deeper", MAX_NESTING_DEPTH + 1)), "{}", error);
+
+        let nots = format!("{}pid == 1", "NOT ".repeat(100_000));
+        let error = nots.parse::<Expression>().unwrap_err();
+        assert!(error.starts_with(&format!("column {}: nested deeper", 4 * MAX_NESTING_DEPTH + 1)), "{}", error);
+    }
+
+    #[test]
+    fn siblings_do_not_count_towards_depth() {
+        let wide = vec!["(pid == 1)"; MAX_NESTING_DEPTH * 4].join(" OR ");
+        assert!(wide.parse::<Expression>().is_ok());
+    }
+
+    #[test]
+    fn long_chains_parse_evaluate_and_drop() {
+        let cmd = process("cmd.exe", "explorer.exe", 10);
+        let mut terms = vec!["pid == 1"; 100_000];
+        let ors = terms.join(" OR ");
+        assert!(!matches(&ors, &cmd));
+        terms.push("pid == 10");
+        assert!(matches(&terms.join(" or "), &cmd));
+
+        let ands = vec![r#"image == "cmd.exe""#; 100_000].join(" AND ");
+        assert!(matches(&ands, &cmd));
+        assert!(!matches(&format!("{} AND pid == 11", ands), &cmd));
+        assert_eq!(ands.parse::<Expression>().unwrap().fields(), vec![Field::Image]);
+    }
+}
@@ -1 +1,2 @@
+pub mod expression;
 pub mod rules;
\ No newline at end of file
@@ -1,3 +1,5 @@
+use crate::error::EdrError;
+use crate::events::alert::AlertSeverity;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -11,7 +13,101 @@ pub struct Config {
     pub network_baselines: NetworkBaselines,
     pub alert_cooldowns: AlertCooldowns,
     pub known_malicious_iocs: Option<MaliciousIOCs>,
+    pub ip_reputation: Option<IpReputation>,
     pub keylogger_detection: Option<KeyloggerDetection>,
+    /// Known-good remote destinations that network rules should never score.
+    /// Entries may be exact hosts/IPs, `.suffix` domain matches, or CIDR ranges.
+    #[serde(default)]
+    pub destination_allowlist: Vec<String>,
+    pub pipe_output: Option<PipeOutput>,
+    pub webhook_output: Option<WebhookOutput>,
+    pub syslog_output: Option<SyslogOutput>,
+    pub control_pipe: Option<ControlPipe>,
+    pub config_reload: Option<ConfigReload>,
+    pub rule_metrics: Option<RuleMetrics>,
+    pub sqlite_output: Option<SqliteOutput>,
+    pub decision_log: Option<DecisionLog>,
+    pub baseline: Option<Baseline>,
+    pub registry_monitor: Option<RegistryMonitor>,
+    pub named_pipe_monitor: Option<NamedPipeMonitor>,
+    pub scheduled_task_monitor: Option<ScheduledTaskMonitor>,
+    pub removable_media_monitor: Option<RemovableMediaMonitor>,
+    pub spawn_storm: Option<SpawnStorm>,
+    pub dga_detection: Option<DgaDetection>,
+    pub ransomware_detection: Option<RansomwareDetection>,
+    pub exfil_detection: Option<ExfilDetection>,
+    /// Alerts below this severity are neither logged nor delivered to outputs.
+    #[serde(default)]
+    pub min_severity: AlertSeverity,
+    /// Alerts below this confidence (0–100) are dropped; overridden by `--min-confidence`.
+    /// Alerts without a detector-set confidence count as 50.
+    #[serde(default)]
+    pub min_confidence: u8,
+    /// How alerts are written to the terminal and log file; overridden by `--compact`.
+    #[serde(default)]
+    pub alert_log_format: AlertLogFormat,
+    #[serde(default)]
+    pub etw_flags: EtwFlags,
+    /// User-mode ETW providers enabled on the network session. The TCP/IP and
+    /// DNS-Client entries are also gated by `etw_flags`; any other provider's events
+    /// are passed through as raw `EtwProviderEvent`s (visible in logs and recordings).
+    #[serde(default = "default_etw_providers")]
+    pub etw_providers: Vec<EtwProvider>,
+    /// Case-insensitive regexes on process names whose ETW events are dropped in the callback.
+    #[serde(default)]
+    pub etw_drop_patterns: Vec<String>,
+    /// Service names (case-insensitive) whose stopping or disabling is treated as
+    /// defense evasion. Add third-party AV/EDR services here.
+    #[serde(default = "default_protected_services")]
+    pub protected_services: Vec<String>,
+    /// Case-insensitive path fragments of browser and Windows credential stores.
+    /// Opening one from an unsigned, non-browser process is credential theft (T1555.003).
+    #[serde(default = "default_credential_store_paths")]
+    pub credential_store_paths: Vec<String>,
+    /// Web, application and database server images (case-insensitive) whose shell or
+    /// script-host children are treated as a webshell or exploited service.
+    #[serde(default = "default_server_processes")]
+    pub server_processes: Vec<String>,
+    /// Seconds an exited process's context is kept so late network events still correlate.
+    #[serde(default = "default_process_exit_grace_secs")]
+    pub process_exit_grace_secs: u64,
+    /// Recent events the engine keeps for cross-correlation. 0 keeps none.
+    #[serde(default = "default_max_process_history")]
+    pub max_process_history: usize,
+    /// Connections kept per process for the rate and beaconing rules.
+    #[serde(default = "default_max_network_history")]
+    pub max_network_history: usize,
+    /// Process contexts tracked at once; past this the oldest are evicted, exited ones first.
+    #[serde(default = "default_max_contexts")]
+    pub max_contexts: usize,
+    /// Ancestors walked from a process when matching parent-chain rules (an Office
+    /// application anywhere above a shell) and when describing its spawn chain.
+    #[serde(default = "default_max_spawn_chain_depth")]
+    pub max_spawn_chain_depth: usize,
+    /// Times each crashed monitor thread is restarted before it is left down.
+    /// 0 only reports the crash.
+    #[serde(default = "default_max_monitor_restarts")]
+    pub max_monitor_restarts: u32,
+    /// Seconds an ETW session may deliver nothing while the system is visibly active
+    /// before it is reported degraded (network falls back to polling). 0 disables.
+    #[serde(default = "default_etw_watchdog_secs")]
+    pub etw_watchdog_secs: u64,
+    /// First port of the OS dynamic port range (`netsh int ipv4 show dynamicport tcp`).
+    /// When a connection's connect or accept wasn't traced and neither end is a
+    /// known listener, the side on a port from this range is taken as the one that
+    /// opened it.
+    #[serde(default = "default_ephemeral_port_start")]
+    pub ephemeral_port_start: u16,
+    /// Seconds between heartbeat log lines (uptime, event counts, ETW health). 0 disables.
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// Which outputs receive which alerts. Empty sends every alert to every enabled output.
+    #[serde(default)]
+    pub alert_routes: Vec<AlertRoute>,
+    /// `RUST_LOG`-style filter, e.g. `info,monitoring::network=warn`. Unset means `info`;
+    /// `--log-level` / `CUSTOMEDR_LOG` take precedence.
+    #[serde(default)]
+    pub log_level: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,8 +116,15 @@ pub struct AlertRule {
     pub description: String,
     pub severity: String,
     pub enabled: bool,
+    #[serde(default)]
     pub conditions: Vec<Condition>,
     pub cooldown_seconds: u64,
+    /// Detection written as an expression over process and connection fields, e.g.
+    /// `parent_image ends_with "winword.exe" AND image == "powershell.exe"`. The
+    /// engine evaluates it on process starts, or on connections when it tests a
+    /// network field. Checked when the rules file is loaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +172,568 @@ pub struct KeyloggerDetection {
     pub suspicious_powershell_flags: Vec<String>,
 }
 
+/// Local abuse-feed export scored against external connections. One
+/// `ip_or_cidr,score,category` entry per line; score is 0–100.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpReputation {
+    pub enabled: bool,
+    pub path: String,
+}
+
+/// Publishes every alert as a JSON line on a local named pipe for a UI or agent.
+/// Only SYSTEM and Administrators may connect, plus the group or account whose
+/// SID is given in `reader_sid`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipeOutput {
+    pub enabled: bool,
+    pub pipe_name: String,
+    pub client_queue_size: usize,
+    #[serde(default)]
+    pub reader_sid: Option<String>,
+}
+
+/// POSTs every alert as JSON to `url` (http or https, through the system proxy).
+/// Delivery runs on its own thread behind a queue of `queue_size` alerts; each
+/// alert is tried `max_attempts` times with exponential backoff, each attempt
+/// bounded by `timeout_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookOutput {
+    pub enabled: bool,
+    pub url: String,
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_webhook_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_output_queue_size")]
+    pub queue_size: usize,
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    5
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    3
+}
+
+fn default_output_queue_size() -> usize {
+    256
+}
+
+/// Sends every alert to a syslog collector as an RFC 5424 message (facility
+/// local0) over UDP or TCP. `server` is `host:port`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyslogOutput {
+    pub enabled: bool,
+    pub server: String,
+    #[serde(default)]
+    pub transport: SyslogTransport,
+    #[serde(default = "default_syslog_app_name")]
+    pub app_name: String,
+    #[serde(default = "default_output_queue_size")]
+    pub queue_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+fn default_syslog_app_name() -> String {
+    "CustomEDR".to_string()
+}
+
+/// Local named pipe accepting admin commands (`reload-config`, `dump-contexts`,
+/// `set-level`, `stats`) with JSON replies. Only Administrators and SYSTEM can connect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlPipe {
+    pub enabled: bool,
+    pub pipe_name: String,
+}
+
+/// Polls the rules file and reloads it when it changes, as `reload-config` does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigReload {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+}
+
+/// `pretty` is the boxed multi-line block; `compact` is one grep-friendly line
+/// per alert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertLogFormat {
+    #[default]
+    Pretty,
+    Compact,
+}
+
+/// Which ETW sources to trace. Turning one off lowers overhead but disables
+/// every detection that depends on it (see README).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EtwFlags {
+    pub process: bool,
+    pub network: bool,
+    pub dns: bool,
+    pub file: bool,
+}
+
+impl Default for EtwFlags {
+    fn default() -> Self {
+        Self {
+            process: true,
+            network: true,
+            dns: true,
+            file: true,
+        }
+    }
+}
+
+/// One ETW provider to enable: `guid` in registry form (braces optional),
+/// `keywords` as a hex mask such as `"0xFFFFFFFF"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EtwProvider {
+    pub name: String,
+    pub guid: String,
+    pub level: u8,
+    pub keywords: String,
+    #[serde(default = "default_provider_enabled")]
+    pub enabled: bool,
+}
+
+impl EtwProvider {
+    pub fn guid_value(&self) -> Result<u128, String> {
+        let groups: Vec<&str> = self.guid.trim().trim_start_matches('{').trim_end_matches('}').split('-').collect();
+        if groups.iter().map(|group| group.len()).ne([8, 4, 4, 4, 12]) {
+            return Err(format!("'{}' is not a GUID (expected xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx)", self.guid));
+        }
+        u128::from_str_radix(&groups.concat(), 16).map_err(|_| format!("'{}' is not a GUID", self.guid))
+    }
+
+    pub fn keyword_mask(&self) -> Result<u64, String> {
+        let trimmed = self.keywords.trim();
+        let hex = trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")).unwrap_or(trimmed);
+        u64::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex keyword mask", self.keywords))
+    }
+}
+
+fn default_provider_enabled() -> bool {
+    true
+}
+
+pub const TCPIP_PROVIDER_GUID: &str = "7dd42a49-5329-4832-8dfd-43d979153a88";
+pub const DNS_CLIENT_PROVIDER_GUID: &str = "1c95126e-7eea-49a9-a3fe-a378b03ddb4d";
+pub const KERNEL_FILE_PROVIDER_GUID: &str = "edd08927-9cc4-4e65-b970-c2560fb5c289";
+
+fn default_etw_providers() -> Vec<EtwProvider> {
+    vec![
+        EtwProvider {
+            name: "Microsoft-Windows-Kernel-Network".to_string(),
+            guid: TCPIP_PROVIDER_GUID.to_string(),
+            level: 5,
+            keywords: "0xFFFFFFFF".to_string(),
+            enabled: true,
+        },
+        EtwProvider {
+            name: "Microsoft-Windows-DNS-Client".to_string(),
+            guid: DNS_CLIENT_PROVIDER_GUID.to_string(),
+            level: 4,
+            keywords: "0xFFFFFFFF".to_string(),
+            enabled: true,
+        },
+        // KERNEL_FILE_KEYWORD_CREATE, _RENAME_SETLINK_PATH and _CREATE_NEW_FILE:
+        // opens, renames and new files, not reads and writes
+        EtwProvider {
+            name: "Microsoft-Windows-Kernel-File".to_string(),
+            guid: KERNEL_FILE_PROVIDER_GUID.to_string(),
+            level: 4,
+            keywords: "0x1880".to_string(),
+            enabled: true,
+        },
+    ]
+}
+
+// Chromium saved passwords (any profile) and their key, Firefox's logins and key
+// database, and the Windows Credential Manager / Vault stores
+fn default_credential_store_paths() -> Vec<String> {
+    [
+        r"\Login Data",
+        r"\User Data\Local State",
+        r"\logins.json",
+        r"\key4.db",
+        r"\Microsoft\Credentials\",
+        r"\Microsoft\Vault\",
+    ]
+    .iter()
+    .map(|path| path.to_string())
+    .collect()
+}
+
+fn default_server_processes() -> Vec<String> {
+    [
+        "w3wp.exe", "UMWorkerProcess.exe", "httpd.exe", "nginx.exe", "php-cgi.exe",
+        "tomcat.exe", "tomcat8.exe", "tomcat9.exe", "tomcat10.exe", "java.exe",
+        "sqlservr.exe", "mysqld.exe", "postgres.exe", "oracle.exe",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect()
+}
+
+fn default_protected_services() -> Vec<String> {
+    [
+        "WinDefend", "WdNisSvc", "WdFilter", "WdBoot", "Sense", "SecurityHealthService",
+        "wscsvc", "MpsSvc", "BFE", "EventLog", "CustomEDR",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect()
+}
+
+fn default_process_exit_grace_secs() -> u64 {
+    5
+}
+
+fn default_max_process_history() -> usize {
+    1000
+}
+
+fn default_max_network_history() -> usize {
+    256
+}
+
+fn default_max_monitor_restarts() -> u32 {
+    3
+}
+
+fn default_max_contexts() -> usize {
+    10_000
+}
+
+fn default_max_spawn_chain_depth() -> usize {
+    8
+}
+
+fn default_document_dirs() -> Vec<String> {
+    [
+        "\\documents\\",
+        "\\desktop\\",
+        "\\pictures\\",
+        "\\videos\\",
+        "\\music\\",
+        "\\downloads\\",
+        "\\onedrive",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_ransom_extensions() -> Vec<String> {
+    [
+        ".locked", ".encrypted", ".enc", ".crypt", ".crypted", ".cry", ".locky", ".cerber",
+        ".wncry", ".wnry", ".ryk", ".conti", ".lockbit", ".akira", ".royal", ".play",
+        ".djvu", ".phobos", ".makop", ".basta", ".blackcat", ".hive",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+// Indexers, sync clients, AV and backup agents rewrite many documents by design
+fn default_ransomware_exempt_processes() -> Vec<String> {
+    [
+        "SearchIndexer.exe",
+        "SearchProtocolHost.exe",
+        "SearchFilterHost.exe",
+        "OneDrive.exe",
+        "Dropbox.exe",
+        "GoogleDriveFS.exe",
+        "MsMpEng.exe",
+        "wbengine.exe",
+        "sdclt.exe",
+        "VeeamAgent.exe",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+// Backup and sync clients that upload in bulk as a matter of course
+fn default_exfil_allowlist() -> Vec<String> {
+    [
+        "OneDrive.exe",
+        "Dropbox.exe",
+        "GoogleDriveFS.exe",
+        "MsMpEng.exe",
+        "VeeamAgent.exe",
+        "wuauclt.exe",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+// Reverse lookups and CDNs whose host names are random by design
+fn default_dga_allowlist() -> Vec<String> {
+    [
+        "in-addr.arpa",
+        "ip6.arpa",
+        "akamaiedge.net",
+        "akamai.net",
+        "cloudfront.net",
+        "amazonaws.com",
+        "azureedge.net",
+        "trafficmanager.net",
+        "msedge.net",
+        "1e100.net",
+        "googlevideo.com",
+        "fastly.net",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+// Build systems and installers that routinely start a child per file or target
+fn default_spawn_storm_allowlist() -> Vec<String> {
+    [
+        "msbuild.exe",
+        "devenv.exe",
+        "cl.exe",
+        "link.exe",
+        "cargo.exe",
+        "rustc.exe",
+        "ninja.exe",
+        "cmake.exe",
+        "make.exe",
+        "mingw32-make.exe",
+        "bash.exe",
+        "sh.exe",
+        "msiexec.exe",
+        "TiWorker.exe",
+        "TrustedInstaller.exe",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect()
+}
+
+// Cobalt Strike defaults (and close copies in other frameworks)
+fn default_suspicious_pipe_patterns() -> Vec<String> {
+    [
+        r"^msagent_[0-9a-f]{2,4}$",
+        r"^postex_[0-9a-f]{4}$",
+        r"^postex_ssh_[0-9a-f]{4}$",
+        r"^status_[0-9a-f]{2}$",
+        r"^msse-\d+-server$",
+        r"^(wkssvc|ntsvcs|scerpc)_[0-9a-f]{2}$",
+    ]
+    .iter()
+    .map(|pattern| pattern.to_string())
+    .collect()
+}
+
+fn default_etw_watchdog_secs() -> u64 {
+    120
+}
+
+fn default_ephemeral_port_start() -> u16 {
+    49152
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    300
+}
+
+/// Stores alerts in an indexed SQLite table. Requires a build with `--features sqlite`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqliteOutput {
+    pub enabled: bool,
+    pub database_path: String,
+}
+
+/// JSON Lines audit of the engine's near-misses: rules that matched but were
+/// suppressed (signed image, trusted process, allowlist, score, cooldown,
+/// min_severity / min_confidence), and the alerts that did fire. Verbose.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecisionLog {
+    pub enabled: bool,
+    pub path: String,
+    /// Size at which the log is renamed to `path.1` (older ones shift up) and a
+    /// fresh file started. 0 never rotates.
+    #[serde(default = "default_decision_log_max_mb")]
+    pub max_mb: u64,
+    /// Rotated files kept beside the log; the oldest is deleted past this.
+    #[serde(default = "default_decision_log_max_files")]
+    pub max_files: u32,
+}
+
+fn default_decision_log_max_mb() -> u64 {
+    50
+}
+
+fn default_decision_log_max_files() -> u32 {
+    5
+}
+
+/// First-deployment learning: until `learning_until`, alerts below Critical are
+/// recorded to `path` instead of raised. Afterwards an alert is suppressed when
+/// the same rule fired for the same process and parent while learning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    pub enabled: bool,
+    #[serde(default = "default_baseline_path")]
+    pub path: String,
+    /// RFC 3339 end of the learning period; overridden by `--learn`. When unset,
+    /// the deadline saved in the baseline file applies.
+    #[serde(default)]
+    pub learning_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub fn default_baseline_path() -> String {
+    "baseline.json".to_string()
+}
+
+/// Output names an `AlertRoute` may reference.
+pub const ALERT_SINK_NAMES: &[&str] = &["pipe", "sqlite", "webhook", "syslog"];
+
+/// Sends alerts at or above `min_severity` (and, if set, only those from `rule`)
+/// to the listed outputs. Routes are tried in order and the first match wins;
+/// alerts matching no route go to every enabled output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRoute {
+    #[serde(default)]
+    pub min_severity: AlertSeverity,
+    /// Rule name, compared case-insensitively.
+    #[serde(default)]
+    pub rule: Option<String>,
+    pub sinks: Vec<String>,
+}
+
+impl AlertRoute {
+    pub fn matches(&self, severity: &AlertSeverity, rule_name: &str) -> bool {
+        *severity >= self.min_severity
+            && self.rule.as_ref().is_none_or(|rule| rule.eq_ignore_ascii_case(rule_name))
+    }
+}
+
+/// Polls per-user COM registrations (`HKCU\Software\Classes\CLSID`) for hijacks and
+/// the `Run`/`RunOnce` keys for suspicious autostart values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryMonitor {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+}
+
+/// Polls `\\.\pipe\` for new pipes named like C2 framework defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedPipeMonitor {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+    /// Case-insensitive regexes matched against the pipe name (without `\\.\pipe\`).
+    #[serde(default = "default_suspicious_pipe_patterns")]
+    pub suspicious_patterns: Vec<String>,
+}
+
+/// Polls the scheduled-task folders for task files written directly rather than
+/// through `schtasks`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledTaskMonitor {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+}
+
+/// Polls drive letters for newly mounted removable drives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemovableMediaMonitor {
+    pub enabled: bool,
+    pub poll_interval_secs: u64,
+    /// How long after a drive is mounted a launch from it counts as removable-media
+    /// execution (T1091).
+    pub launch_window_secs: u64,
+}
+
+/// Flags a parent that starts more than `max_children` processes within
+/// `window_secs` (droppers, fork bombs, per-file encryption workers).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnStorm {
+    pub enabled: bool,
+    pub max_children: usize,
+    pub window_secs: u64,
+    /// Parent image names that legitimately fan out, such as build tools.
+    #[serde(default = "default_spawn_storm_allowlist")]
+    pub allowlist: Vec<String>,
+}
+
+/// Scores queried domains for algorithmic generation (DGA) and counts unique
+/// subdomains per parent domain (DNS tunneling). Needs `etw_flags.dns`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DgaDetection {
+    pub enabled: bool,
+    /// DGA score (0–100) at which a domain counts as generated.
+    pub min_score: u8,
+    /// Generated domains one process must query within the window before it is flagged.
+    pub min_domains: usize,
+    /// Unique subdomains of one parent domain, from one process, within the window
+    /// that count as tunneling.
+    pub max_unique_subdomains: usize,
+    pub window_secs: u64,
+    /// Domain suffixes never scored or counted, e.g. CDNs with random host labels.
+    #[serde(default = "default_dga_allowlist")]
+    pub allowlist: Vec<String>,
+}
+
+/// Flags a process that rewrites, renames or creates many files in user document
+/// folders within a short window and leaves ransom extensions or encrypted
+/// content behind (T1486). Needs `etw_flags.file` and the Kernel-File rename and
+/// new-file keywords (0x1880).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RansomwareDetection {
+    pub enabled: bool,
+    /// Distinct document files one process must touch within the window.
+    pub min_files: usize,
+    pub window_secs: u64,
+    /// Case-insensitive path fragments of the folders watched, under `\Users\`.
+    #[serde(default = "default_document_dirs")]
+    pub document_dirs: Vec<String>,
+    /// Extensions (with the dot) that ransomware families append.
+    #[serde(default = "default_ransom_extensions")]
+    pub ransom_extensions: Vec<String>,
+    /// Backup, sync and indexing images (case-insensitive) never counted.
+    #[serde(default = "default_ransomware_exempt_processes")]
+    pub exempt_processes: Vec<String>,
+    /// Suspend every thread of the flagged process when the alert fires.
+    #[serde(default)]
+    pub suspend_process: bool,
+}
+
+/// Flags a process that sends more than `max_sent_mb` to external hosts within
+/// `window_secs`, counted from the TCP send sizes ETW reports (T1041). Needs
+/// `etw_flags.network`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExfilDetection {
+    pub enabled: bool,
+    pub max_sent_mb: u64,
+    pub window_secs: u64,
+    /// Image names (case-insensitive) whose uploads are never counted.
+    #[serde(default = "default_exfil_allowlist")]
+    pub allowlist: Vec<String>,
+}
+
+/// Lifetime per-rule hit counts, periodically saved so tuning data survives restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleMetrics {
+    pub enabled: bool,
+    pub persist_path: String,
+    pub persist_interval_secs: u64,
+    pub reset_on_start: bool,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -79,6 +744,7 @@ impl Default for Config {
                     severity: "Critical".to_string(),
                     enabled: true,
                     cooldown_seconds: 300,
+                    expression: None,
                     conditions: vec![
                         Condition {
                             field: "process_age".to_string(),
@@ -103,6 +769,7 @@ impl Default for Config {
                     severity: "Medium".to_string(),
                     enabled: true,
                     cooldown_seconds: 600,
+                    expression: None,
                     conditions: vec![
                         Condition {
                             field: "process_type".to_string(),
@@ -162,6 +829,10 @@ impl Default for Config {
                 per_process_minutes: 30,
                 global_minutes: 5,
             },
+            ip_reputation: Some(IpReputation {
+                enabled: false,
+                path: "config/ip_reputation.csv".to_string(),
+            }),
             known_malicious_iocs: Some(MaliciousIOCs {
                 ips: vec![
                     "41.47.145.151".to_string(),    // These IPs don't belong to normal residential users or major legitimate services.
@@ -213,28 +884,285 @@ impl Default for Config {
                     "-NonInteractive".to_string(),
                 ],
             }),
+            destination_allowlist: vec![
+                ".windowsupdate.com".to_string(),
+                ".update.microsoft.com".to_string(),
+                ".delivery.mp.microsoft.com".to_string(),
+            ],
+            pipe_output: Some(PipeOutput {
+                enabled: false,
+                pipe_name: r"\\.\pipe\CustomEDR".to_string(),
+                client_queue_size: 256,
+                reader_sid: None,
+            }),
+            webhook_output: Some(WebhookOutput {
+                enabled: false,
+                url: String::new(),
+                timeout_secs: default_webhook_timeout_secs(),
+                max_attempts: default_webhook_max_attempts(),
+                queue_size: default_output_queue_size(),
+            }),
+            syslog_output: Some(SyslogOutput {
+                enabled: false,
+                server: "127.0.0.1:514".to_string(),
+                transport: SyslogTransport::Udp,
+                app_name: default_syslog_app_name(),
+                queue_size: default_output_queue_size(),
+            }),
+            control_pipe: Some(ControlPipe {
+                enabled: false,
+                pipe_name: r"\\.\pipe\CustomEDR-control".to_string(),
+            }),
+            config_reload: Some(ConfigReload {
+                enabled: true,
+                poll_interval_secs: 5,
+            }),
+            rule_metrics: Some(RuleMetrics {
+                enabled: true,
+                persist_path: "metrics.json".to_string(),
+                persist_interval_secs: 300,
+                reset_on_start: false,
+            }),
+            sqlite_output: Some(SqliteOutput {
+                enabled: false,
+                database_path: "alerts.db".to_string(),
+            }),
+            decision_log: Some(DecisionLog {
+                enabled: false,
+                path: "decisions.jsonl".to_string(),
+                max_mb: default_decision_log_max_mb(),
+                max_files: default_decision_log_max_files(),
+            }),
+            baseline: Some(Baseline {
+                enabled: false,
+                path: default_baseline_path(),
+                learning_until: None,
+            }),
+            registry_monitor: Some(RegistryMonitor {
+                enabled: true,
+                poll_interval_secs: 30,
+            }),
+            named_pipe_monitor: Some(NamedPipeMonitor {
+                enabled: true,
+                poll_interval_secs: 5,
+                suspicious_patterns: default_suspicious_pipe_patterns(),
+            }),
+            scheduled_task_monitor: Some(ScheduledTaskMonitor {
+                enabled: true,
+                poll_interval_secs: 30,
+            }),
+            removable_media_monitor: Some(RemovableMediaMonitor {
+                enabled: true,
+                poll_interval_secs: 2,
+                launch_window_secs: 600,
+            }),
+            spawn_storm: Some(SpawnStorm {
+                enabled: true,
+                max_children: 20,
+                window_secs: 10,
+                allowlist: default_spawn_storm_allowlist(),
+            }),
+            dga_detection: Some(DgaDetection {
+                enabled: true,
+                min_score: 60,
+                min_domains: 3,
+                max_unique_subdomains: 50,
+                window_secs: 60,
+                allowlist: default_dga_allowlist(),
+            }),
+            ransomware_detection: Some(RansomwareDetection {
+                enabled: true,
+                min_files: 25,
+                window_secs: 30,
+                document_dirs: default_document_dirs(),
+                ransom_extensions: default_ransom_extensions(),
+                exempt_processes: default_ransomware_exempt_processes(),
+                suspend_process: false,
+            }),
+            exfil_detection: Some(ExfilDetection {
+                enabled: true,
+                max_sent_mb: 50,
+                window_secs: 60,
+                allowlist: default_exfil_allowlist(),
+            }),
+            min_severity: AlertSeverity::Low,
+            min_confidence: 0,
+            alert_log_format: AlertLogFormat::Pretty,
+            etw_flags: EtwFlags::default(),
+            etw_providers: default_etw_providers(),
+            etw_drop_patterns: Vec::new(),
+            protected_services: default_protected_services(),
+            credential_store_paths: default_credential_store_paths(),
+            server_processes: default_server_processes(),
+            process_exit_grace_secs: default_process_exit_grace_secs(),
+            max_process_history: default_max_process_history(),
+            max_network_history: default_max_network_history(),
+            max_contexts: default_max_contexts(),
+            max_spawn_chain_depth: default_max_spawn_chain_depth(),
+            max_monitor_restarts: default_max_monitor_restarts(),
+            etw_watchdog_secs: default_etw_watchdog_secs(),
+            ephemeral_port_start: default_ephemeral_port_start(),
+            heartbeat_interval_secs: default_heartbeat_interval_secs(),
+            log_level: None,
+            alert_routes: Vec::new(),
         }
     }
 }
 
-pub fn load_rules() -> Config {
-    let config_path = "config/edr_rules.json";
-    
-    if Path::new(config_path).exists() {
-        match fs::read_to_string(config_path) {
-            Ok(content) => match serde_json::from_str(&content) {
-                Ok(config) => {
-                    log::info!("Loaded configuration from {}", config_path);
-                    return config;
-                }
-                Err(e) => {
-                    log::warn!("Failed to parse config file: {}. Using defaults.", e);
-                }
-            },
-            Err(e) => {
-                log::warn!("Failed to read config file: {}. Using defaults.", e);
+pub const DEFAULT_CONFIG_PATH: &str = "config/edr_rules.json";
+
+/// Loads the rule file. An explicit path (`--config` / `CUSTOMEDR_CONFIG`) must
+/// exist; the default relative path falls back to built-in defaults when absent.
+/// A file that exists but cannot be read or parsed is always a startup error.
+pub fn load_rules(explicit_path: Option<&Path>) -> Result<Config, EdrError> {
+    let config_path = match explicit_path {
+        Some(path) => {
+            let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+            if !absolute.exists() {
+                return Err(EdrError::ConfigNotFound(absolute));
             }
+            absolute
         }
+        None => {
+            let default_path = Path::new(DEFAULT_CONFIG_PATH);
+            if !default_path.exists() {
+                log::warn!("Config file {} not found. Using defaults.", DEFAULT_CONFIG_PATH);
+                return Ok(Config::default());
+            }
+            default_path.to_path_buf()
+        }
+    };
+
+    let content = fs::read_to_string(&config_path).map_err(|e| EdrError::InvalidConfig {
+        path: config_path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    let config: Config = serde_json::from_str(&content).map_err(|e| EdrError::InvalidConfig {
+        path: config_path.display().to_string(),
+        reason: e.to_string(),
+    })?;
+    validate_alert_routes(&config.alert_routes).map_err(|reason| EdrError::InvalidConfig {
+        path: config_path.display().to_string(),
+        reason,
+    })?;
+    validate_alert_rules(&config.alert_rules).map_err(|reason| EdrError::InvalidConfig {
+        path: config_path.display().to_string(),
+        reason,
+    })?;
+    if let Some(webhook) = config.webhook_output.as_ref().filter(|webhook| webhook.enabled)
+        && let Err(reason) = crate::monitoring::notifier::parse_webhook_url(&webhook.url)
+    {
+        return Err(EdrError::InvalidConfig {
+            path: config_path.display().to_string(),
+            reason: format!("webhook_output.url {}", reason),
+        });
     }
-    Config::default()
-}
\ No newline at end of file
+    if let Some(syslog) = config.syslog_output.as_ref().filter(|syslog| syslog.enabled)
+        && syslog.server.rsplit_once(':').is_none_or(|(host, port)| host.is_empty() || port.parse::<u16>().is_err())
+    {
+        return Err(EdrError::InvalidConfig {
+            path: config_path.display().to_string(),
+            reason: format!("syslog_output.server '{}' must be host:port", syslog.server),
+        });
+    }
+    if let Some(sid) = config.pipe_output.as_ref().and_then(|pipe| pipe.reader_sid.as_deref())
+        && !is_sid_string(sid)
+    {
+        return Err(EdrError::InvalidConfig {
+            path: config_path.display().to_string(),
+            reason: format!("pipe_output.reader_sid '{}' is not a SID (S-1-…)", sid),
+        });
+    }
+    if config.max_contexts == 0 {
+        return Err(EdrError::InvalidConfig {
+            path: config_path.display().to_string(),
+            reason: "max_contexts must be at least 1".to_string(),
+        });
+    }
+    for provider in &config.etw_providers {
+        if let Err(reason) = provider.guid_value().and_then(|_| provider.keyword_mask()) {
+            return Err(EdrError::InvalidConfig {
+                path: config_path.display().to_string(),
+                reason: format!("etw_providers '{}': {}", provider.name, reason),
+            });
+        }
+    }
+    if let Some(filter) = &config.log_level
+        && let Err(reason) = filter.parse::<crate::utils::log_filter::LogFilter>()
+    {
+        return Err(EdrError::InvalidConfig {
+            path: config_path.display().to_string(),
+            reason: format!("log_level: {}", reason),
+        });
+    }
+
+    log::info!("Loaded configuration from {}", config_path.display());
+    Ok(config)
+}
+
+// Only a literal SID is accepted, so the value can't inject extra ACEs into the pipe's SDDL
+fn is_sid_string(sid: &str) -> bool {
+    sid.strip_prefix("S-1-").is_some_and(|rest| {
+        !rest.is_empty() && rest.split('-').all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+fn validate_alert_rules(rules: &[AlertRule]) -> Result<(), String> {
+    for rule in rules {
+        let Some(expression) = &rule.expression else {
+            continue;
+        };
+        expression.parse::<crate::config::expression::Expression>()
+            .map_err(|reason| format!("alert_rules '{}': expression {}", rule.name, reason))?;
+        <AlertSeverity as clap::ValueEnum>::from_str(&rule.severity, true)
+            .map_err(|_| format!("alert_rules '{}': unknown severity '{}'", rule.name, rule.severity))?;
+    }
+    Ok(())
+}
+
+/// Compiles every regex in the rules file. Startup warns about and skips a bad
+/// pattern; a reload refuses the whole file instead, so a typo never silently
+/// drops a filter that is already working.
+pub fn validate_patterns(config: &Config) -> Result<(), String> {
+    for pattern in &config.etw_drop_patterns {
+        crate::utils::common::compile_config_pattern(pattern)
+            .map_err(|e| format!("etw_drop_patterns '{}': {}", pattern, e))?;
+    }
+    if let Some(pipes) = &config.named_pipe_monitor {
+        for pattern in &pipes.suspicious_patterns {
+            crate::utils::common::compile_config_pattern(pattern)
+                .map_err(|e| format!("named_pipe_monitor '{}': {}", pattern, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_alert_routes(routes: &[AlertRoute]) -> Result<(), String> {
+    for (index, route) in routes.iter().enumerate() {
+        if route.sinks.is_empty() {
+            return Err(format!("alert_routes[{}] lists no sinks", index));
+        }
+        if let Some(unknown) = route.sinks.iter().find(|sink| !ALERT_SINK_NAMES.contains(&sink.as_str())) {
+            return Err(format!(
+                "alert_routes[{}] references unknown sink '{}' (expected one of: {})",
+                index, unknown, ALERT_SINK_NAMES.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_sid_accepts_only_literal_sids() {
+        assert!(is_sid_string("S-1-5-32-545"));
+        assert!(is_sid_string("S-1-5-21-1004336348-1177238915-682003330-1105"));
+        assert!(!is_sid_string("BU"));
+        assert!(!is_sid_string("S-1-"));
+        assert!(!is_sid_string("S-1-5--32"));
+        assert!(!is_sid_string("S-1-5-32-545)(A;;GA;;;WD"));
+    }
+}
@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Fatal startup failures. Each maps to a distinct process exit code so service
+/// managers and scheduled tasks can tell why the EDR did not run.
+#[derive(Debug, thiserror::Error)]
+pub enum EdrError {
+    #[error("failed to initialize logging: {0}")]
+    Logging(#[from] log::SetLoggerError),
+
+    #[error("required privileges are missing (SeDebug/SeSystemProfile/SeSecurity) — run as Administrator")]
+    InsufficientPrivileges,
+
+    #[error("configuration file '{}' does not exist", .0.display())]
+    ConfigNotFound(PathBuf),
+
+    #[error("configuration file '{path}' is invalid: {reason}")]
+    InvalidConfig { path: String, reason: String },
+
+    #[error("{0} could not start its ETW session")]
+    EtwStartFailed(&'static str),
+
+    #[error("service control failed: {0}")]
+    Service(String),
+
+    #[error("cannot replay recording '{path}': {reason}")]
+    Replay { path: String, reason: String },
+
+    #[error("cannot build report: {0}")]
+    Report(String),
+
+    #[error("control command failed: {0}")]
+    Control(String),
+
+    #[error("self-test failed: {0}")]
+    SelfTest(String),
+
+    #[error("invalid pattern: {0}")]
+    Pattern(String),
+
+    #[error("benchmark failed: {0}")]
+    Bench(String),
+
+    #[error("cannot use baseline: {0}")]
+    Baseline(String),
+
+    #[error("scenario check failed: {0}")]
+    Scenario(String),
+}
+
+impl EdrError {
+    pub fn code(&self) -> u8 {
+        match self {
+            EdrError::Logging(_) => 1,
+            EdrError::InsufficientPrivileges => 2,
+            EdrError::ConfigNotFound(_) | EdrError::InvalidConfig { .. } => 3,
+            EdrError::EtwStartFailed(_) => 4,
+            EdrError::Service(_) => 5,
+            EdrError::Replay { .. } => 6,
+            EdrError::Report(_) => 7,
+            EdrError::Control(_) => 8,
+            EdrError::SelfTest(_) => 9,
+            // 10 is --fail-on-match, which is not an error
+            EdrError::Pattern(_) => 11,
+            EdrError::Bench(_) => 12,
+            EdrError::Baseline(_) => 13,
+            EdrError::Scenario(_) => 14,
+        }
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+}
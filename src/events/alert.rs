@@ -1,7 +1,36 @@
+use crate::events::ProcessSecurity;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Debug, Clone)]
+/// Confidence assumed for alerts whose detector did not set one (medium).
+pub const DEFAULT_CONFIDENCE: u8 = 50;
+
+// Values for `Alert::source`: what produced the alert
+/// Scored from ETW process/network/DNS events.
+pub const SOURCE_ETW: &str = "etw";
+/// Raised from a polled registry snapshot.
+pub const SOURCE_REGISTRY_POLL: &str = "registry-poll";
+/// Raised from a polled listing of the named-pipe namespace.
+pub const SOURCE_PIPE_POLL: &str = "pipe-poll";
+/// Raised from a polled scan of the scheduled-task folders.
+pub const SOURCE_TASK_POLL: &str = "task-poll";
+/// Cross-process correlation over a time window rather than a single event.
+pub const SOURCE_CORRELATION: &str = "correlation";
+/// The EDR reporting on itself, e.g. a monitor thread that died.
+pub const SOURCE_SUPERVISOR: &str = "supervisor";
+/// Alerts written before the field existed.
+pub const SOURCE_UNKNOWN: &str = "unknown";
+
+fn default_source() -> String {
+    SOURCE_UNKNOWN.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Alert {
+    /// Stable identifier (UUID v4) for acknowledging the alert later. Empty for
+    /// alerts serialized before the field existed.
+    #[serde(default)]
+    pub id: String,
     pub severity: AlertSeverity,
     pub rule_name: String,
     pub description: String,
@@ -9,24 +38,70 @@ pub struct Alert {
     pub pid: u32,
     pub evidence: Vec<String>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// 0–100: how strongly the matched signals corroborate each other.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+    /// Which detector produced the alert (`SOURCE_*`), so merged outputs can be told apart.
+    #[serde(default = "default_source")]
+    pub source: String,
+    /// Shared by alerts that belong to one incident, e.g. a process's first alert
+    /// and its later escalation. Unset for alerts that stand alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Account, elevation and integrity of the alerting process, when its start was traced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security: Option<ProcessSecurity>,
+    /// Image name of the alerting process's parent, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_name: Option<String>,
+    /// Command line of the alerting process, when its start was traced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// Ordered from least to most severe, so thresholds can compare with `<`.
+/// Uppercase and lowercase spellings (`"HIGH"`, `"high"`) deserialize too.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum)]
 pub enum AlertSeverity {
+    #[default]
+    #[serde(alias = "LOW", alias = "low")]
     Low,
+    #[serde(alias = "MEDIUM", alias = "medium")]
     Medium,
+    #[serde(alias = "HIGH", alias = "high")]
     High,
+    #[serde(alias = "CRITICAL", alias = "critical")]
     Critical,
 }
 
+impl AlertSeverity {
+    /// The uppercase label alert lines print.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertSeverity::Low => "LOW",
+            AlertSeverity::Medium => "MEDIUM",
+            AlertSeverity::High => "HIGH",
+            AlertSeverity::Critical => "CRITICAL",
+        }
+    }
+}
+
+impl fmt::Display for AlertSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl fmt::Display for Alert {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "[{}] {} - PID: {} - {} - Evidence: {:?}",
-            self.severity_str(),
+            "[{}] {} ({}) - PID: {} - Confidence: {}% - {} - Evidence: {:?}",
+            self.severity,
             self.rule_name,
+            self.source,
             self.pid,
+            self.confidence(),
             self.description,
             self.evidence
         )
@@ -43,6 +118,7 @@ impl Alert {
         details: &[String],
     ) -> Self {
         Self {
+            id: uuid::Uuid::new_v4().to_string(),
             severity: severity.clone(),
             rule_name: rule_name.to_string(),
             description: description.to_string(),
@@ -50,15 +126,47 @@ impl Alert {
             pid,
             evidence: details.to_vec(),
             timestamp: chrono::Utc::now(),
+            confidence: None,
+            source: default_source(),
+            correlation_id: None,
+            security: None,
+            parent_name: None,
+            command_line: None,
         }
     }
 
-    fn severity_str(&self) -> &str {
-        match self.severity {
-            AlertSeverity::Low => "LOW",
-            AlertSeverity::Medium => "MEDIUM",
-            AlertSeverity::High => "HIGH",
-            AlertSeverity::Critical => "CRITICAL",
-        }
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: &str) -> Self {
+        self.correlation_id = Some(correlation_id.to_string());
+        self
+    }
+
+    pub fn with_security(mut self, security: ProcessSecurity) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    pub fn with_parent_name(mut self, parent_name: &str) -> Self {
+        self.parent_name = Some(parent_name.to_string());
+        self
+    }
+
+    pub fn with_command_line(mut self, command_line: &str) -> Self {
+        self.command_line = Some(command_line.to_string());
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: u8) -> Self {
+        self.confidence = Some(confidence.min(100));
+        self
+    }
+
+    /// The detector's confidence, or `DEFAULT_CONFIDENCE` when it didn't set one.
+    pub fn confidence(&self) -> u8 {
+        self.confidence.unwrap_or(DEFAULT_CONFIDENCE)
     }
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// A removable drive (USB stick, SD card) that was mounted since the previous
+/// poll. Drives already present when the monitor starts are not reported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemovableMediaEvent {
+    /// Drive root, e.g. `E:\`.
+    pub drive: String,
+    /// Volume GUID path (`\\?\Volume{...}\`), stable for the device across mounts.
+    pub volume_id: String,
+    pub label: String,
+    pub serial_number: u32,
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsEvent {
+    pub pid: u32,
+    pub process_name: String,
+    pub query_name: String,
+    pub query_status: u32,
+    pub resolved_addresses: Vec<String>,
+}
+
+impl DnsEvent {
+    pub fn new(
+        pid: u32,
+        process_name: String,
+        query_name: String,
+        query_status: u32,
+        resolved_addresses: Vec<String>,
+    ) -> Self {
+        Self {
+            pid,
+            process_name,
+            query_name,
+            query_status,
+            resolved_addresses,
+        }
+    }
+}
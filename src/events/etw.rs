@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// A record from an ETW provider the detectors don't parse (one added through
+/// `etw_providers`), kept as header fields plus a hex dump of the payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EtwProviderEvent {
+    pub provider: String,
+    pub provider_guid: String,
+    pub event_id: u16,
+    pub opcode: u8,
+    pub level: u8,
+    pub keyword: u64,
+    pub pid: u32,
+    pub process_name: String,
+    /// Hex of the user data, truncated to the first 256 bytes.
+    pub payload: String,
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// Which watched set of files an open came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileWatch {
+    /// One of the configured `credential_store_paths`.
+    #[default]
+    CredentialStore,
+    /// `System32\drivers\etc\hosts`, opened with a disposition that can write it.
+    HostsFile,
+    /// A file in a `ransomware_detection.document_dirs` folder overwritten, created
+    /// or renamed.
+    Document,
+}
+
+/// A process opening a watched file, from a Kernel-File create record. Other file
+/// opens are dropped in the callback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileAccessEvent {
+    pub pid: u32,
+    pub process_name: String,
+    /// NT path as traced, e.g. `\Device\HarddiskVolume3\Users\...\Login Data`.
+    pub path: String,
+    /// The `credential_store_paths` entry it matched, `hosts`, or for documents
+    /// what happened to it: `overwrite`, `create` or `rename`.
+    pub matched: String,
+    #[serde(default)]
+    pub watch: FileWatch,
+}
+
+/// One host name whose mapping in the hosts file appeared, changed or went away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostsEntryChange {
+    pub host: String,
+    /// `None` when the host was added.
+    pub old_address: Option<String>,
+    /// `None` when the host was removed.
+    pub new_address: Option<String>,
+}
+
+/// The hosts file's entries differ from the previous poll. Comment and whitespace
+/// edits are not reported. Polling can't attribute the write; the engine matches
+/// it against recent `FileWatch::HostsFile` opens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostsFileEvent {
+    pub path: String,
+    pub changes: Vec<HostsEntryChange>,
+}
@@ -1,22 +1,45 @@
 pub mod alert;
+pub mod device;
+pub mod dns;
+pub mod etw;
+pub mod file;
 pub mod network;
+pub mod pipe;
 pub mod process;
+pub mod registry;
+pub mod task;
 
 pub use alert::Alert;
+pub use device::RemovableMediaEvent;
+pub use dns::DnsEvent;
+pub use etw::EtwProviderEvent;
+pub use file::{FileAccessEvent, HostsFileEvent};
 pub use network::NetworkEvent;
-pub use process::ProcessEvent;
+pub use pipe::NamedPipeEvent;
+pub use process::{ProcessEvent, ProcessSecurity};
+pub use registry::RegistryEvent;
+pub use task::ScheduledTaskEvent;
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventType {
     ProcessStart(ProcessEvent),
     ProcessEnd(ProcessEvent),
     NetworkConnection(NetworkEvent),
+    DnsQuery(DnsEvent),
+    RegistryChange(RegistryEvent),
+    NamedPipeCreated(NamedPipeEvent),
+    ScheduledTaskChanged(ScheduledTaskEvent),
+    RemovableMediaArrived(RemovableMediaEvent),
+    EtwProvider(EtwProviderEvent),
+    FileAccess(FileAccessEvent),
+    HostsFileChanged(HostsFileEvent),
     Alert(Alert),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaseEvent {
     pub timestamp: DateTime<Utc>,
     pub event_id: String,
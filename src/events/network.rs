@@ -1,4 +1,7 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkEvent {
     pub pid: u32,
     pub process_name: String,
@@ -13,24 +16,45 @@ pub struct NetworkEvent {
     pub connection_state: ConnectionState,
     pub domain: Option<String>,
     pub data_size: Option<u64>,
+    /// Seconds the connection was seen open, on a `Closed` event from the TCP
+    /// table poll; `None` on every other event and in older recordings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NetworkDirection {
+    /// The peer connected to a local listening socket.
     Inbound,
+    /// This host opened the connection.
     Outbound,
+    /// A listening socket; recordings made before direction inference also used it
+    /// for accepted connections.
     Listening,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Protocol {
     TCP,
     UDP,
+    /// UDP/443 to an external host (HTTP/3).
     QUIC,
+    /// Anything else by name, e.g. `ICMP` or `RAW`.
     Other(String),
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::TCP => write!(f, "TCP"),
+            Protocol::UDP => write!(f, "UDP"),
+            Protocol::QUIC => write!(f, "QUIC"),
+            Protocol::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConnectionState {
     Established,
     Listening,
@@ -64,6 +88,7 @@ impl NetworkEvent {
             connection_state: ConnectionState::Established,
             domain: None,
             data_size: None,
+            duration_secs: None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// A named pipe that appeared since the previous scan of `\\.\pipe\`. Enumeration
+/// can't tell who created it, so there is no PID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedPipeEvent {
+    /// Name without the `\\.\pipe\` prefix.
+    pub pipe_name: String,
+}
@@ -1,6 +1,19 @@
+use serde::{Deserialize, Serialize};
 use windows::Win32::Foundation::FILETIME;
 
-#[derive(Debug, Clone)]
+/// Who a process runs as, read from its token when it starts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessSecurity {
+    /// `DOMAIN\name`, or the SID when the account can't be resolved.
+    pub user: String,
+    pub user_sid: String,
+    /// Full admin token (UAC elevated, or an account with no split token such as SYSTEM).
+    pub elevated: bool,
+    /// Untrusted, Low, Medium, MediumPlus, High, System or Protected.
+    pub integrity_level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessEvent {
     pub pid: u32,
     pub parent_pid: u32,
@@ -9,7 +22,19 @@ pub struct ProcessEvent {
     pub command_line: String,
     pub session_id: u32,
     pub integrity_level: String,
+    /// Token identity at start; `None` when the token couldn't be read, and in
+    /// recordings made before it was captured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security: Option<ProcessSecurity>,
+    /// Lowercase hex SHA-256 of the image at start, when it was already cached.
+    /// Other images are hashed in the background and the hash reaches alerts
+    /// instead; `None` also in recordings made before it was captured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    // FILETIME has no serde support; recordings carry BaseEvent::timestamp instead
+    #[serde(skip)]
     pub create_time: Option<FILETIME>,
+    #[serde(skip)]
     pub exit_time: Option<FILETIME>,
     pub exit_code: Option<u32>,
 }
@@ -24,6 +49,8 @@ impl ProcessEvent {
             command_line: String::new(),
             session_id: 0,
             integrity_level: String::from("Unknown"),
+            security: None,
+            sha256: None,
             create_time: None,
             exit_time: None,
             exit_code: None,
@@ -39,6 +66,8 @@ impl ProcessEvent {
             command_line: String::new(),
             session_id: 0,
             integrity_level: String::from("Unknown"),
+            security: None,
+            sha256: None,
             create_time: None,
             exit_time: None,
             exit_code,
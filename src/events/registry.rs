@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistryChange {
+    Created,
+    Modified,
+}
+
+/// Which watched set of keys a change came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistryWatch {
+    /// Per-user `CLSID\{…}\InprocServer32` / `TreatAs`.
+    #[default]
+    ComRegistration,
+    /// `CurrentVersion\Run` / `RunOnce`, machine-wide or per user.
+    RunKey,
+    /// Static `NameServer` of `Tcpip`/`Tcpip6` or one of their interfaces.
+    DnsServer,
+}
+
+/// A watched registry value that appeared or changed since the previous scan.
+/// Registry polling can't attribute the write, so there is no PID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEvent {
+    /// Full key path, e.g. `HKU\S-1-5-21-…_Classes\CLSID\{…}\InprocServer32`.
+    pub key_path: String,
+    /// Empty for the key's default value.
+    pub value_name: String,
+    pub value_data: String,
+    pub change: RegistryChange,
+    #[serde(default)]
+    pub watch: RegistryWatch,
+    /// For COM keys: the DLL the CLSID now loads (for `TreatAs`, the target's server).
+    pub resolved_server: Option<String>,
+    /// The same CLSID is registered machine-wide, so the per-user key shadows it.
+    pub shadows_machine_key: bool,
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskFileChange {
+    Created,
+    Modified,
+}
+
+/// One `Exec` action of a task: what runs when it triggers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskAction {
+    /// Environment variables are expanded.
+    pub command: String,
+    pub arguments: String,
+}
+
+/// A task definition under `%SystemRoot%\System32\Tasks`, or a legacy `at` job
+/// under `%SystemRoot%\Tasks`, that appeared or changed since the previous scan.
+/// The folder is polled, so the writer is unknown and there is no PID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledTaskEvent {
+    /// Path relative to the tasks folder, e.g. `\Microsoft\Windows\Defrag\ScheduledDefrag`.
+    pub task_name: String,
+    pub file_path: String,
+    pub change: TaskFileChange,
+    /// A `.job` file from `at` / the Task Scheduler 1.0 API rather than task XML.
+    pub legacy_job: bool,
+    pub actions: Vec<TaskAction>,
+}
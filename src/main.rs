@@ -1,85 +1,853 @@
+mod bench;
 mod config;
+mod error;
 mod events;
 mod monitoring;
+mod report;
+mod scenario;
+mod self_test;
+mod service;
+mod timeline;
 mod utils;
 
-use crate::monitoring::{start_process_monitor, start_network_monitor, start_correlation_engine};
+use crate::monitoring::{
+    start_process_monitor, start_network_monitor, start_registry_monitor, start_named_pipe_monitor,
+    start_scheduled_task_monitor, start_removable_media_monitor,
+    start_correlation_engine, start_alert_handler,
+};
+use crate::monitoring::clock::{ManualClock, SystemClock};
+use crate::monitoring::config_watcher::start_config_watcher;
+use crate::monitoring::event_recorder::{
+    start_event_recorder, recording_segments, replay_events, RecordingOptions, ReplayTiming,
+};
+use crate::monitoring::etw_watchdog::{PROCESS_HEARTBEAT, NETWORK_HEARTBEAT};
+use crate::monitoring::baseline::{run_baseline_command, BaselineAction};
+use crate::monitoring::heartbeat::start_heartbeat;
+use crate::monitoring::supervisor::{
+    dead_monitors, monitor_status, start_supervisor, SupervisedMonitor, MONITOR_RESTARTS,
+};
+use crate::monitoring::control_pipe::{start_control_pipe, send_control_command};
+use crate::monitoring::correlation_engine::{
+    alerts_by_user, ALERTS_RAISED, CONTEXTS_EVICTED, EVENTS_PROCESSED, TRACKED_CONNECTIONS, TRACKED_CONTEXTS, TRACKED_EVENTS,
+    request_context_dump, reload_engine_config,
+};
+use crate::error::EdrError;
 use crate::utils::privilege;
+use crate::utils::log_filter::{CONSOLE_TARGET, FilteredLogger, LogFilter, set_log_filter};
+use clap::Parser;
 use simplelog::*;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use std::io::{self, Read};
-use std::sync::Once;
+use std::sync::{Once, OnceLock};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 // Global shutdown flag with atomic ordering
 static RUNNING: AtomicBool = AtomicBool::new(true);
 static SHUTDOWN_ONCE: Once = Once::new();
+// Set when the log filter came from the command line or environment
+static LOG_LEVEL_FROM_CLI: AtomicBool = AtomicBool::new(false);
+// End of the --learn period, applied to the baseline settings by load_config
+static LEARN_UNTIL: OnceLock<chrono::DateTime<chrono::Utc>> = OnceLock::new();
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Setup logging
-    CombinedLogger::init(vec![
+// How long the monitors get to open their ETW sessions before startup is judged failed
+const MONITOR_STARTUP_GRACE: Duration = Duration::from_secs(1);
+// Exit code of `--alerts --fail-on-match` when at least one alert is listed; kept
+// clear of the EdrError codes so a wrapper can tell a match from a failure
+const ALERTS_MATCHED_EXIT_CODE: u8 = 10;
+// How often the supervisor looks for monitor threads that died
+const MONITOR_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// How long a control-pipe dump-contexts waits for the correlation engine
+const CONTEXT_DUMP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Windows endpoint threat detection using ETW")]
+#[command(group = clap::ArgGroup::new("store_query").args(["report", "alerts", "timeline", "watch"]))]
+#[command(group = clap::ArgGroup::new("alert_filter").args(["alerts", "watch"]).multiple(false))]
+struct Cli {
+    /// Path to the rules file (defaults to config/edr_rules.json in the working directory)
+    #[arg(long, value_name = "PATH", env = "CUSTOMEDR_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Register the EDR as an auto-start Windows service (runs with --service) and exit
+    #[arg(long, conflicts_with_all = ["uninstall_service", "service"])]
+    install_service: bool,
+
+    /// Stop and remove the Windows service and exit
+    #[arg(long, conflicts_with = "service")]
+    uninstall_service: bool,
+
+    /// Run under the Service Control Manager instead of the interactive console
+    #[arg(long)]
+    service: bool,
+
+    /// Only report alerts at or above this confidence (0-100); overrides min_confidence in the rules file
+    #[arg(long, value_name = "0-100", value_parser = clap::value_parser!(u8).range(0..=100))]
+    min_confidence: Option<u8>,
+
+    /// Log each alert as one grep-friendly line instead of the boxed block; overrides alert_log_format in the rules file
+    #[arg(long)]
+    compact: bool,
+
+    /// Record every monitor event as JSON Lines to this file for later --replay
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["replay", "service", "install_service", "uninstall_service"])]
+    record: Option<PathBuf>,
+
+    /// Rotate the recording after this many megabytes, keeping 3 older files (all but the newest gzipped)
+    #[arg(long, value_name = "MB", default_value_t = 64, requires = "record")]
+    record_max_mb: u64,
+
+    /// Feed a recording (plain or .gz) to the correlation engine instead of tracing live, then exit
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["service", "install_service", "uninstall_service"])]
+    replay: Option<PathBuf>,
+
+    /// Replay events back to back instead of at the recorded pace
+    #[arg(long, requires = "replay")]
+    replay_fast: bool,
+
+    /// Replay the recording's rotated segments (.3.gz, .2.gz, .1) before the file itself, oldest first
+    #[arg(long, requires = "replay")]
+    replay_all: bool,
+
+    /// Print a summary of stored alerts (SQLite alert sink) for the last day or week and exit
+    #[arg(long, value_enum, value_name = "PERIOD", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "alerts"])]
+    report: Option<report::ReportPeriod>,
+
+    /// List stored alerts with their IDs for the last day or week and exit
+    #[arg(long, value_enum, value_name = "PERIOD", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay"])]
+    alerts: Option<report::ReportPeriod>,
+
+    /// Print everything known about a process (by PID or image name) in time order: its alerts and, with --timeline-recording, its starts, exits, children, connections and lookups
+    #[arg(long, value_name = "PID|NAME", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack", "control", "self_test", "test_pattern", "bench"])]
+    timeline: Option<String>,
+
+    /// A --record recording (its rotated segments are read too) to build --timeline from
+    #[arg(long, value_name = "PATH", requires = "timeline")]
+    timeline_recording: Option<PathBuf>,
+
+    /// How far back --timeline reads stored alerts
+    #[arg(long, value_enum, value_name = "PERIOD", default_value = "week", requires = "timeline")]
+    timeline_period: report::ReportPeriod,
+
+    /// Mark a stored alert as acknowledged (full ID or a unique prefix) and exit
+    #[arg(long, value_name = "ID", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts"])]
+    ack: Option<String>,
+
+    /// Only list (--alerts) or print (--watch) alerts at or above this severity
+    #[arg(long, visible_alias = "min-severity", value_enum, value_name = "SEVERITY", ignore_case = true, requires = "alert_filter")]
+    severity: Option<crate::events::alert::AlertSeverity>,
+
+    /// Exit with code 10 when --alerts lists at least one alert, for monitoring scripts
+    #[arg(long, requires = "alerts")]
+    fail_on_match: bool,
+
+    /// Print each alert as the running EDR stores it, until Ctrl+C
+    #[arg(long, conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "timeline", "ack", "control", "self_test", "test_pattern", "bench", "scenarios", "baseline"])]
+    watch: bool,
+
+    /// Write the --alerts list to this file instead of printing it
+    #[arg(long, value_name = "PATH", requires = "alerts", conflicts_with = "json")]
+    export: Option<PathBuf>,
+
+    /// File format for --export
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "csv", requires = "export")]
+    export_format: report::ExportFormat,
+
+    /// Leave acknowledged alerts out of --report, --alerts or --timeline
+    #[arg(long, requires = "store_query")]
+    unacked: bool,
+
+    /// Print the report, alert list, timeline or watched alerts as JSON instead of text
+    #[arg(long, requires = "store_query")]
+    json: bool,
+
+    /// Only include alerts from this source in the report, alert list, timeline or watch (etw, registry-poll, pipe-poll, task-poll, correlation, supervisor, unknown)
+    #[arg(long, value_name = "SOURCE", requires = "store_query")]
+    source: Option<String>,
+
+    /// Trip a few rules with harmless actions, check the running EDR detected them (needs control_pipe), print a pass/fail matrix and exit
+    #[arg(long, conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack", "control"])]
+    self_test: bool,
+
+    /// Compile a regex the way the rules file applies it (case-insensitive), report syntax errors, test it against each --sample and exit
+    #[arg(long, value_name = "REGEX", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack", "control", "self_test"])]
+    test_pattern: Option<String>,
+
+    /// Text to test --test-pattern against; repeat for several samples
+    #[arg(long, value_name = "TEXT", requires = "test_pattern")]
+    sample: Vec<String>,
+
+    /// Also list the stored alerts (SQLite alert sink) of the last day or week whose process or evidence the pattern matches
+    #[arg(long, value_enum, value_name = "PERIOD", requires = "test_pattern")]
+    against_alerts: Option<report::ReportPeriod>,
+
+    /// Feed synthetic events through the correlation engine (no ETW, no alert outputs), print throughput, latency and memory and exit
+    #[arg(long, conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack", "control", "self_test", "test_pattern"])]
+    bench: bool,
+
+    /// Events per second for --bench; 0 sends as fast as the engine takes them
+    #[arg(long, value_name = "N", default_value_t = 500, requires = "bench")]
+    bench_rate: u64,
+
+    /// How long --bench sends events for
+    #[arg(long, value_name = "SECONDS", default_value_t = 30, requires = "bench")]
+    bench_secs: u64,
+
+    /// Play scripted attack and benign scenarios through the correlation engine (no ETW, no alert outputs), check each raised exactly its expected alerts in order and exit
+    #[arg(long, conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack", "control", "self_test", "test_pattern", "bench"])]
+    scenarios: bool,
+
+    /// Only run the --scenarios whose name contains this text
+    #[arg(long, value_name = "NAME", requires = "scenarios")]
+    scenario: Option<String>,
+
+    /// Send a command (reload-config, dump-contexts, set-level <FILTER>, stats, rule-hits) to the running EDR's control pipe, print the JSON reply and exit
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack"])]
+    control: Option<String>,
+
+    /// Fail on the first unparseable recording line or stored alert instead of skipping it and warning at the end (--replay, --timeline, --report, --alerts, --against-alerts)
+    #[arg(long)]
+    strict: bool,
+
+    /// Log level and per-module overrides, e.g. "info,monitoring::network=warn"; overrides log_level in the rules file
+    #[arg(long, value_name = "FILTER", env = "CUSTOMEDR_LOG")]
+    log_level: Option<LogFilter>,
+
+    /// Learn a baseline for this long (e.g. 30m, 12h, 7d): alerts below Critical are recorded instead of raised, and afterwards only deviations alert. Enables the baseline and overrides baseline.learning_until
+    #[arg(long, value_name = "DURATION", value_parser = parse_learning_period, conflicts_with_all = ["service", "install_service", "uninstall_service", "baseline"])]
+    learn: Option<chrono::Duration>,
+
+    /// Print the learned baseline or delete it, then exit. Restart a running EDR after clearing, or it keeps its in-memory baseline
+    #[arg(long, value_enum, value_name = "ACTION", conflicts_with_all = ["service", "install_service", "uninstall_service", "record", "replay", "report", "alerts", "ack", "control", "self_test", "test_pattern", "bench", "timeline"])]
+    baseline: Option<BaselineAction>,
+}
+
+/// Parses a `--learn` period: a whole number followed by m, h or d.
+fn parse_learning_period(text: &str) -> Result<chrono::Duration, String> {
+    let text = text.trim();
+    let split = text.len() - text.chars().last().map_or(0, char::len_utf8);
+    let (number, unit) = text.split_at(split);
+    let amount: i64 = number.parse()
+        .ok()
+        .filter(|amount| *amount > 0)
+        .ok_or_else(|| format!("'{}' is not a period such as 30m, 12h or 7d", text))?;
+    match unit {
+        "m" => Ok(chrono::Duration::minutes(amount)),
+        "h" => Ok(chrono::Duration::hours(amount)),
+        "d" => Ok(chrono::Duration::days(amount)),
+        _ => Err(format!("'{}' needs a unit of m, h or d, e.g. 7d", text)),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Reports go to stdout, so keep log lines off it
+    let prints_to_stdout = cli.report.is_some() || cli.alerts.is_some() || cli.timeline.is_some() || cli.ack.is_some() || cli.control.is_some()
+        || cli.self_test || cli.test_pattern.is_some() || cli.bench || cli.scenarios || cli.baseline.is_some() || cli.watch;
+    let terminal_mode = if prints_to_stdout { TerminalMode::Stderr } else { TerminalMode::Mixed };
+    let mut alerts_matched = false;
+    utils::common::STRICT_PARSING.store(cli.strict, Ordering::Relaxed);
+    if let Some(period) = cli.learn {
+        let _ = LEARN_UNTIL.set(chrono::Utc::now() + period);
+    }
+    let result = init_logging(cli.service, terminal_mode, cli.log_level.clone()).and_then(|()| {
+        if cli.install_service {
+            service::install_service(cli.config.as_deref())
+        } else if cli.uninstall_service {
+            service::uninstall_service()
+        } else if cli.service {
+            service::run_as_service(cli.config)
+        } else if let Some(period) = cli.report {
+            report::print_report(cli.config.as_deref(), period, cli.source.as_deref(), cli.unacked, cli.json)
+        } else if let Some(period) = cli.alerts
+            && let Some(output) = &cli.export
+        {
+            report::export_alert_list(
+                cli.config.as_deref(),
+                period,
+                cli.source.as_deref(),
+                cli.unacked,
+                cli.severity.clone(),
+                cli.export_format,
+                output,
+            )
+            .map(|written| alerts_matched = written > 0)
+        } else if let Some(period) = cli.alerts {
+            report::print_alert_list(cli.config.as_deref(), period, cli.source.as_deref(), cli.unacked, cli.severity.clone(), cli.json)
+                .map(|listed| alerts_matched = listed > 0)
+        } else if cli.watch {
+            report::watch_alerts(cli.config.as_deref(), cli.source.as_deref(), cli.severity.clone(), cli.json)
+        } else if let Some(target) = &cli.timeline {
+            timeline::print_process_timeline(
+                cli.config.as_deref(),
+                target,
+                cli.timeline_period,
+                cli.timeline_recording.as_deref(),
+                cli.source.as_deref(),
+                cli.unacked,
+                cli.json,
+            )
+        } else if let Some(id) = &cli.ack {
+            report::acknowledge(cli.config.as_deref(), id)
+        } else if let Some(command) = &cli.control {
+            send_control(cli.config.as_deref(), command)
+        } else if cli.self_test {
+            self_test::run_self_test(cli.config.as_deref())
+        } else if let Some(action) = cli.baseline {
+            run_baseline_command(cli.config.as_deref(), action)
+        } else if let Some(pattern) = &cli.test_pattern {
+            report::print_pattern_test(cli.config.as_deref(), pattern, &cli.sample, cli.against_alerts)
+        } else if cli.bench {
+            // Alert blocks would bury the results; --log-level brings them back
+            if cli.log_level.is_none()
+                && let Ok(filter) = "error".parse::<LogFilter>()
+            {
+                set_log_filter(filter);
+            }
+            bench::run_benchmark(cli.config.as_deref(), cli.bench_rate, Duration::from_secs(cli.bench_secs.max(1)))
+        } else if cli.scenarios {
+            // As with --bench, alert blocks would bury the results; --log-level brings them back
+            if cli.log_level.is_none()
+                && let Ok(filter) = "error".parse::<LogFilter>()
+            {
+                set_log_filter(filter);
+            }
+            scenario::run_scenarios(cli.config.as_deref(), cli.scenario.as_deref())
+        } else if let Some(recording) = &cli.replay {
+            let timing = if cli.replay_fast { ReplayTiming::Fast } else { ReplayTiming::Original };
+            let segments = if cli.replay_all { recording_segments(recording) } else { vec![recording.clone()] };
+            replay(cli.config.as_deref(), cli.min_confidence, cli.compact, &segments, timing)
+        } else {
+            let recording = cli.record.clone().map(|path| RecordingOptions {
+                path,
+                max_bytes: cli.record_max_mb.max(1) * 1024 * 1024,
+            });
+            run(cli.config.as_deref(), cli.min_confidence, cli.compact, recording, true)
+        }
+    });
+
+    utils::common::warn_unparsed_records();
+
+    match result {
+        Ok(()) if cli.fail_on_match && alerts_matched => ExitCode::from(ALERTS_MATCHED_EXIT_CODE),
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if matches!(e, EdrError::Logging(_)) {
+                eprintln!("Fatal: {}", e);
+            } else {
+                log::error!("❌ Fatal: {}", e);
+            }
+            e.exit_code()
+        }
+    }
+}
+
+fn init_logging(service_mode: bool, terminal_mode: TerminalMode, filter: Option<LogFilter>) -> Result<(), EdrError> {
+    // The outputs take everything; FilteredLogger applies the level and module overrides
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![
         TermLogger::new(
             LevelFilter::Trace,
             Config::default(),
-            TerminalMode::Mixed,
+            terminal_mode,
             ColorChoice::Auto,
         ),
-    ])?;
+    ];
+
+    // A service has no console; log to a file next to the executable instead
+    if service_mode {
+        let log_path = service::executable_dir()
+            .unwrap_or_default()
+            .join("customedr.log");
+        match std::fs::OpenOptions::new().create(true).append(true).open(&log_path) {
+            Ok(file) => loggers.push(WriteLogger::new(LevelFilter::Trace, Config::default(), file)),
+            Err(e) => eprintln!("Cannot open log file {}: {}", log_path.display(), e),
+        }
+    }
+
+    // --log-level / CUSTOMEDR_LOG wins; otherwise the rules file may change it once loaded
+    LOG_LEVEL_FROM_CLI.store(filter.is_some(), Ordering::Relaxed);
+    FilteredLogger::init(CombinedLogger::new(loggers), filter.unwrap_or_default())?;
+    Ok(())
+}
+
+/// Records a shutdown request once, whichever source (Ctrl+C, console, SCM) asks first.
+fn request_shutdown(source: &str) {
+    SHUTDOWN_ONCE.call_once(|| {
+        log::info!("");
+        log::info!("🛑 Shutdown requested via {}", source);
+        RUNNING.store(false, Ordering::Relaxed);
+    });
+}
+
+/// Loads the rules file and applies command-line overrides.
+fn load_config(
+    config_path: Option<&Path>,
+    min_confidence: Option<u8>,
+    compact: bool,
+) -> Result<Arc<config::rules::Config>, EdrError> {
+    let mut config = config::rules::load_rules(config_path)?;
+    if !LOG_LEVEL_FROM_CLI.load(Ordering::Relaxed)
+        && let Some(filter) = &config.log_level
+    {
+        // Validated by load_rules
+        if let Ok(filter) = filter.parse::<LogFilter>() {
+            set_log_filter(filter);
+        }
+    }
+    if let Some(min_confidence) = min_confidence {
+        config.min_confidence = min_confidence;
+    }
+    if config.min_confidence > 0 {
+        log::info!("🎚️  Reporting alerts with confidence >= {}%", config.min_confidence);
+    }
+    if compact {
+        config.alert_log_format = config::rules::AlertLogFormat::Compact;
+    }
+    if let Some(until) = LEARN_UNTIL.get() {
+        let baseline = config.baseline.get_or_insert_with(|| config::rules::Baseline {
+            enabled: true,
+            path: config::rules::default_baseline_path(),
+            learning_until: None,
+        });
+        baseline.enabled = true;
+        baseline.learning_until = Some(*until);
+    }
+    Ok(Arc::new(config))
+}
 
+/// Runs the EDR until shutdown. `interactive` enables Ctrl+C and the stdin
+/// command loop; service mode waits for the SCM stop request instead.
+fn run(
+    config_path: Option<&Path>,
+    min_confidence: Option<u8>,
+    compact: bool,
+    recording: Option<RecordingOptions>,
+    interactive: bool,
+) -> Result<(), EdrError> {
     // Check and enable required privileges
     if !privilege::enable_required_privileges() {
         log::error!("Failed to enable required privileges. Run as Administrator!");
         log::error!("Right-click Command Prompt/PowerShell and select 'Run as Administrator'");
-        return Ok(());
+        return Err(EdrError::InsufficientPrivileges);
     }
     log::info!("✅ Required privileges are present");
 
+    // Load configuration
+    let config = load_config(config_path, min_confidence, compact)?;
+
     // Create event channels
     let (process_tx, process_rx) = crossbeam_channel::unbounded();
     let (network_tx, network_rx) = crossbeam_channel::unbounded();
-    let (alert_tx, _) = crossbeam_channel::unbounded();
-
-    // Load configuration
-    let config = Arc::new(config::rules::load_rules());
+    let (alert_tx, alert_rx) = crossbeam_channel::unbounded();
 
     // Create shutdown flags for each component
     let correlation_shutdown = Arc::new(AtomicBool::new(true));
     let process_shutdown = Arc::new(AtomicBool::new(true));
     let network_shutdown = Arc::new(AtomicBool::new(true));
+    let alert_shutdown = Arc::new(AtomicBool::new(true));
+    let recorder_shutdown = Arc::new(AtomicBool::new(true));
+
+    // Start alert delivery (named pipe and other outputs). It owns the outputs'
+    // connections, so it is watched but never restarted.
+    let alert_monitor = SupervisedMonitor::watch("Alert Handler", start_alert_handler(
+        alert_rx,
+        Arc::clone(&config),
+        Arc::clone(&alert_shutdown),
+    ), &alert_shutdown);
 
     // Start correlation engine
+    let correlation_monitor = SupervisedMonitor::spawn("Correlation Engine", &correlation_shutdown, {
+        let (process_rx, network_rx, alert_tx) = (process_rx.clone(), network_rx.clone(), alert_tx.clone());
+        let (config, shutdown) = (Arc::clone(&config), Arc::clone(&correlation_shutdown));
+        Box::new(move || start_correlation_engine(
+            process_rx.clone(),
+            network_rx.clone(),
+            alert_tx.clone(),
+            Arc::clone(&config),
+            Arc::new(SystemClock),
+            Arc::clone(&shutdown),
+        ))
+    });
+    drop((process_rx, network_rx));
+
+    // With --record, the monitors feed the recorder, which forwards to the engine
+    let (monitor_process_tx, monitor_network_tx, recorder_monitor) = match recording {
+        Some(options) => {
+            let (recorded_process_tx, recorded_process_rx) = crossbeam_channel::unbounded();
+            let (recorded_network_tx, recorded_network_rx) = crossbeam_channel::unbounded();
+            let handle = start_event_recorder(
+                recorded_process_rx,
+                recorded_network_rx,
+                process_tx.clone(),
+                network_tx.clone(),
+                options,
+                Arc::clone(&recorder_shutdown),
+            );
+            (recorded_process_tx, recorded_network_tx, Some(SupervisedMonitor::watch("Event Recorder", handle, &recorder_shutdown)))
+        }
+        None => (process_tx.clone(), network_tx.clone(), None),
+    };
+
+    // Start monitors with shutdown signals; etw_flags can leave either one off
+    apply_live_settings(&config);
+    let etw_flags = config.etw_flags.clone();
+    let process_monitor = etw_flags.process.then(|| SupervisedMonitor::spawn("Process Monitor", &process_shutdown, {
+        let (tx, shutdown) = (monitor_process_tx.clone(), Arc::clone(&process_shutdown));
+        Box::new(move || start_process_monitor(tx.clone(), Arc::clone(&shutdown)))
+    }));
+    let network_session_needed = monitoring::network::network_session_needed(&etw_flags, &config.etw_providers);
+    let network_monitor = network_session_needed.then(|| SupervisedMonitor::spawn("Network Monitor", &network_shutdown, {
+        let (tx, shutdown) = (monitor_network_tx.clone(), Arc::clone(&network_shutdown));
+        let (etw_flags, providers) = (etw_flags.clone(), config.etw_providers.clone());
+        Box::new(move || start_network_monitor(tx.clone(), etw_flags.clone(), providers.clone(), Arc::clone(&shutdown)))
+    }));
+    // Polling monitors feed the process channel and stop with the process monitor
+    let mut poll_monitors = Vec::new();
+    let mut spawn_poller = |name: &'static str, start: fn(crossbeam_channel::Sender<events::BaseEvent>, Duration, Arc<AtomicBool>) -> std::thread::JoinHandle<()>, interval_secs: u64| {
+        let (tx, shutdown) = (monitor_process_tx.clone(), Arc::clone(&process_shutdown));
+        let interval = Duration::from_secs(interval_secs.max(1));
+        poll_monitors.push(SupervisedMonitor::spawn(name, &process_shutdown, Box::new(move || {
+            start(tx.clone(), interval, Arc::clone(&shutdown))
+        })));
+    };
+    if let Some(registry) = config.registry_monitor.as_ref().filter(|registry| registry.enabled) {
+        spawn_poller("Registry Monitor", start_registry_monitor, registry.poll_interval_secs);
+    }
+    if let Some(pipes) = config.named_pipe_monitor.as_ref().filter(|pipes| pipes.enabled) {
+        spawn_poller("Named Pipe Monitor", start_named_pipe_monitor, pipes.poll_interval_secs);
+    }
+    if let Some(tasks) = config.scheduled_task_monitor.as_ref().filter(|tasks| tasks.enabled) {
+        spawn_poller("Scheduled Task Monitor", start_scheduled_task_monitor, tasks.poll_interval_secs);
+    }
+    if let Some(media) = config.removable_media_monitor.as_ref().filter(|media| media.enabled) {
+        spawn_poller("Removable Media Monitor", start_removable_media_monitor, media.poll_interval_secs);
+    }
+    // Edits to the rules file go through the same reload as the control pipe's reload-config
+    if let Some(reload) = config.config_reload.as_ref().filter(|reload| reload.enabled) {
+        let path = config_path.map_or_else(|| PathBuf::from(config::rules::DEFAULT_CONFIG_PATH), Path::to_path_buf);
+        let interval = Duration::from_secs(reload.poll_interval_secs.max(1));
+        let shutdown = Arc::clone(&process_shutdown);
+        let reload_path = config_path.map(Path::to_path_buf);
+        let reload: Arc<dyn Fn() + Send + Sync> = Arc::new(move || {
+            if let Err(e) = reload_rules(reload_path.as_deref(), min_confidence, "config watcher") {
+                log::warn!("⚠️  Keeping the running rules: {}", e);
+            }
+        });
+        poll_monitors.push(SupervisedMonitor::spawn("Config Watcher", &process_shutdown, Box::new(move || {
+            start_config_watcher(path.clone(), interval, Arc::clone(&reload), Arc::clone(&shutdown))
+        })));
+    }
+    // The heartbeat only reads counters; it stops with the polling monitors
+    if config.heartbeat_interval_secs > 0 {
+        let (interval, shutdown) = (Duration::from_secs(config.heartbeat_interval_secs), Arc::clone(&process_shutdown));
+        poll_monitors.push(SupervisedMonitor::spawn("Heartbeat", &process_shutdown, Box::new(move || {
+            start_heartbeat(interval, Arc::clone(&shutdown))
+        })));
+    }
+    if process_monitor.is_none() {
+        log::warn!("⚠️  Process tracing disabled by etw_flags");
+    }
+    if network_monitor.is_none() {
+        log::warn!("⚠️  Network and DNS tracing disabled by etw_flags and etw_providers");
+    }
+
+    // A monitor whose ETW session fails to start returns right away
+    std::thread::sleep(MONITOR_STARTUP_GRACE);
+    let failed_monitor = [&process_monitor, &network_monitor].into_iter()
+        .flatten()
+        .find(|monitor| monitor.handle.as_ref().is_some_and(|handle| handle.is_finished()))
+        .map(|monitor| monitor.name);
+
+    // Shutdown order: network first, then the pollers and recorder, then
+    // correlation, then process, then alert delivery
+    let mut components: Vec<SupervisedMonitor> = network_monitor.into_iter().collect();
+    components.extend(poll_monitors);
+    components.extend(recorder_monitor);
+    components.push(correlation_monitor);
+    components.extend(process_monitor);
+    components.push(alert_monitor);
+
+    let shutdown_flags = [
+        process_shutdown,
+        network_shutdown,
+        correlation_shutdown,
+        alert_shutdown,
+        recorder_shutdown,
+    ];
+    if let Some(monitor) = failed_monitor {
+        let components = components.into_iter()
+            .map(|monitor| (monitor.name, monitor.handle))
+            .collect();
+        perform_shutdown(&shutdown_flags, process_tx, network_tx, alert_tx, components);
+        return Err(EdrError::EtwStartFailed(monitor));
+    }
+
+    // From here on a monitor thread that dies raises a Critical self-alert and is restarted
+    let supervisor = start_supervisor(
+        components,
+        MONITOR_CHECK_INTERVAL,
+        config.max_monitor_restarts,
+        alert_tx.clone(),
+    );
+
+    log::info!(target: CONSOLE_TARGET, "=========================================");
+    log::info!(target: CONSOLE_TARGET, "       EDR System Running");
+    log::info!(target: CONSOLE_TARGET, "=========================================");
+    log::info!(target: CONSOLE_TARGET, "📊 Monitoring:");
+    log::info!(target: CONSOLE_TARGET, "  • Process creation/termination");
+    log::info!(target: CONSOLE_TARGET, "  • Network connections");
+    if config.registry_monitor.as_ref().is_some_and(|registry| registry.enabled) {
+        log::info!(target: CONSOLE_TARGET, "  • Per-user COM registrations and Run keys");
+    }
+    if config.named_pipe_monitor.as_ref().is_some_and(|pipes| pipes.enabled) {
+        log::info!(target: CONSOLE_TARGET, "  • Named pipe creation");
+    }
+    if config.scheduled_task_monitor.as_ref().is_some_and(|tasks| tasks.enabled) {
+        log::info!(target: CONSOLE_TARGET, "  • Scheduled task files");
+    }
+    if config.removable_media_monitor.as_ref().is_some_and(|media| media.enabled) {
+        log::info!(target: CONSOLE_TARGET, "  • Removable drive insertion");
+    }
+    log::info!(target: CONSOLE_TARGET, "  • Suspicious activity correlation");
+    log::info!(target: CONSOLE_TARGET, "=========================================");
+
+    // Admin commands for a running instance, which has no stdin as a service
+    let control_pipe = config.control_pipe.as_ref()
+        .filter(|control| control.enabled)
+        .and_then(|control| {
+            let config_path = config_path.map(Path::to_path_buf);
+            start_control_pipe(control, Arc::new(move |command: &str| {
+                handle_control_command(command, config_path.as_deref(), min_confidence)
+            }))
+        });
+
+    if interactive {
+        run_console_loop();
+    } else {
+        log::info!("🛑 Running as a service — stop it from the Services console or with 'sc stop'");
+        service::report_running();
+        while RUNNING.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    if let Some(control_pipe) = control_pipe {
+        control_pipe.stop();
+    }
+
+    // ========== SINGLE SHUTDOWN SEQUENCE ==========
+    // The supervisor goes first so stopping monitors aren't mistaken for crashes
+    let components = supervisor.stop();
+    perform_shutdown(&shutdown_flags, process_tx, network_tx, alert_tx, components);
+
+    Ok(())
+}
+
+/// Replays `--record` segments through the correlation engine and alert outputs
+/// without starting ETW, then shuts down once the engine has caught up.
+fn replay(
+    config_path: Option<&Path>,
+    min_confidence: Option<u8>,
+    compact: bool,
+    segments: &[PathBuf],
+    timing: ReplayTiming,
+) -> Result<(), EdrError> {
+    let config = load_config(config_path, min_confidence, compact)?;
+
+    let (process_tx, process_rx) = crossbeam_channel::unbounded();
+    let (network_tx, network_rx) = crossbeam_channel::unbounded();
+    let (alert_tx, alert_rx) = crossbeam_channel::unbounded();
+
+    let correlation_shutdown = Arc::new(AtomicBool::new(true));
+    let alert_shutdown = Arc::new(AtomicBool::new(true));
+
+    let alert_handle = start_alert_handler(
+        alert_rx,
+        Arc::clone(&config),
+        Arc::clone(&alert_shutdown),
+    );
     let correlation_handle = start_correlation_engine(
         process_rx,
         network_rx,
         alert_tx.clone(),
         Arc::clone(&config),
+        // Engine time follows the recorded timestamps, so windows and cooldowns
+        // behave as they did live even under --replay-fast
+        Arc::new(ManualClock::default()),
         Arc::clone(&correlation_shutdown),
     );
 
-    // Start monitors with shutdown signals
-    let process_handle = start_process_monitor(
-        process_tx.clone(), 
-        Arc::clone(&process_shutdown)
-    );
-    let network_handle = start_network_monitor(
-        network_tx.clone(), 
-        Arc::clone(&network_shutdown)
+    ctrlc::set_handler(|| request_shutdown("Ctrl+C"))
+        .expect("Failed to set Ctrl+C handler");
+
+    match segments {
+        [recording] => log::info!("⏯️  Replaying {} ({:?} timing)", recording.display(), timing),
+        _ => log::info!("⏯️  Replaying {} segments ({:?} timing)", segments.len(), timing),
+    }
+    let result = replay_events(segments, timing, &process_tx, &network_tx, &RUNNING);
+    if let Ok(sent) = &result {
+        log::info!("⏯️  Replayed {} events; waiting for the engine to drain", sent);
+        // Let the engine empty its queues and run one more temporal-correlation pass
+        while RUNNING.load(Ordering::Relaxed)
+            && !(process_tx.is_empty() && network_tx.is_empty() && alert_tx.is_empty())
+        {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+
+    perform_shutdown(
+        &[correlation_shutdown, alert_shutdown],
+        process_tx,
+        network_tx,
+        alert_tx,
+        vec![
+            ("Correlation Engine", Some(correlation_handle)),
+            ("Alert Handler", Some(alert_handle)),
+        ],
     );
 
-    log::info!("=========================================");
-    log::info!("       EDR System Running");
-    log::info!("=========================================");
-    log::info!("📊 Monitoring:");
-    log::info!("  • Process creation/termination");
-    log::info!("  • Network connections");
-    log::info!("  • Suspicious activity correlation");
-    log::info!("");
-    log::info!("🛑 To stop:");
-    log::info!("  1. Press Ctrl+C");
-    log::info!("  2. OR Type 'q' then press Enter");
-    log::info!("  3. OR Type 'stop' then press Enter");
-    log::info!("=========================================");
+    result.map(|_| ())
+}
+
+/// Settings the monitors and callbacks read live, at startup and on every reload.
+fn apply_live_settings(config: &config::rules::Config) {
+    utils::common::set_etw_drop_patterns(&config.etw_drop_patterns);
+    utils::common::set_credential_store_paths(&config.credential_store_paths);
+    // Document changes are only traced while ransomware detection is on
+    utils::common::set_document_dirs(config.ransomware_detection.as_ref()
+        .filter(|ransomware| ransomware.enabled)
+        .map_or(&[], |ransomware| ransomware.document_dirs.as_slice()));
+    monitoring::etw_watchdog::set_etw_watchdog_secs(config.etw_watchdog_secs);
+    monitoring::network::set_ephemeral_port_start(config.ephemeral_port_start);
+}
+
+/// Re-reads the rules file for `reload-config` and the config watcher. A file that
+/// fails to parse or validate, including any regex, changes nothing. Live settings
+/// change in place and the correlation engine takes its detection settings after
+/// its current event; outputs, monitors and ETW sessions keep their startup
+/// settings. Returns what was applied.
+fn reload_rules(config_path: Option<&Path>, min_confidence: Option<u8>, trigger: &str) -> Result<Vec<&'static str>, EdrError> {
+    let mut reloaded = config::rules::load_rules(config_path)?;
+    config::rules::validate_patterns(&reloaded).map_err(|reason| EdrError::InvalidConfig {
+        path: config_path.map_or_else(|| config::rules::DEFAULT_CONFIG_PATH.to_string(), |path| path.display().to_string()),
+        reason,
+    })?;
+    if let Some(min_confidence) = min_confidence {
+        reloaded.min_confidence = min_confidence;
+    }
+
+    let mut applied = vec![
+        "detection rules", "etw_drop_patterns", "credential_store_paths", "etw_watchdog_secs", "ephemeral_port_start",
+    ];
+    if !LOG_LEVEL_FROM_CLI.load(Ordering::Relaxed) {
+        set_log_filter(reloaded.log_level.as_deref()
+            .and_then(|filter| filter.parse().ok())
+            .unwrap_or_default());
+        applied.push("log_level");
+    }
+    apply_live_settings(&reloaded);
+    reload_engine_config(Arc::new(reloaded));
+    log::info!("🔄 Rules file reloaded via {} ({})", trigger, applied.join(", "));
+    Ok(applied)
+}
+
+/// Runs one control-pipe command and builds its JSON reply. Commands map onto the
+/// same operations as the console and startup: the rules file, the log filter,
+/// the engine's process contexts and the event and rule-hit counters.
+fn handle_control_command(command: &str, config_path: Option<&Path>, min_confidence: Option<u8>) -> serde_json::Value {
+    let (verb, argument) = command.split_once(char::is_whitespace)
+        .map_or((command, ""), |(verb, argument)| (verb, argument.trim()));
+    match verb.to_lowercase().as_str() {
+        "reload-config" => match reload_rules(config_path, min_confidence, "control pipe") {
+            Ok(applied) => serde_json::json!({
+                "ok": true,
+                "applied": applied,
+                "note": "outputs, monitors and ETW sessions take effect after a restart",
+            }),
+            Err(e) => serde_json::json!({ "ok": false, "error": e.to_string() }),
+        },
+        "dump-contexts" => match request_context_dump(CONTEXT_DUMP_TIMEOUT) {
+            Some(contexts) => serde_json::json!({ "ok": true, "count": contexts.len(), "contexts": contexts }),
+            None => serde_json::json!({ "ok": false, "error": "correlation engine did not respond" }),
+        },
+        "set-level" => match argument.parse::<LogFilter>() {
+            Ok(filter) if !argument.is_empty() => {
+                set_log_filter(filter);
+                // Stays in force until the next reload-config or restart
+                LOG_LEVEL_FROM_CLI.store(true, Ordering::Relaxed);
+                log::info!(target: CONSOLE_TARGET, "🎚️  Log level set to '{}' via control pipe", argument);
+                serde_json::json!({ "ok": true, "log_level": argument })
+            }
+            Ok(_) => serde_json::json!({ "ok": false, "error": "usage: set-level <FILTER>" }),
+            Err(e) => serde_json::json!({ "ok": false, "error": e }),
+        },
+        "stats" => serde_json::json!({
+            "ok": true,
+            "events": {
+                "process_etw": PROCESS_HEARTBEAT.events(),
+                "network_etw": NETWORK_HEARTBEAT.events(),
+                "engine": EVENTS_PROCESSED.load(Ordering::Relaxed),
+            },
+            "alerts_raised": ALERTS_RAISED.load(Ordering::Relaxed),
+            "tracked": {
+                "contexts": TRACKED_CONTEXTS.load(Ordering::Relaxed),
+                "connections": TRACKED_CONNECTIONS.load(Ordering::Relaxed),
+                "events": TRACKED_EVENTS.load(Ordering::Relaxed),
+                "contexts_evicted": CONTEXTS_EVICTED.load(Ordering::Relaxed),
+            },
+            "monitors": monitor_status(),
+            "monitor_restarts": MONITOR_RESTARTS.load(Ordering::Relaxed),
+            "etw_degraded": {
+                "process": PROCESS_HEARTBEAT.is_degraded(),
+                "network": NETWORK_HEARTBEAT.is_degraded(),
+            },
+            "command_line_lookups_degraded": utils::common::POWERSHELL_DEGRADED.load(Ordering::Relaxed),
+            "top_rules": utils::common::top_rule_hits(15).into_iter()
+                .map(|(rule, count)| serde_json::json!({ "rule": rule, "hits": count }))
+                .collect::<Vec<_>>(),
+            "alerts_by_user": alerts_by_user(15).into_iter()
+                .map(|(user, count)| serde_json::json!({ "user": user, "alerts": count }))
+                .collect::<Vec<_>>(),
+        }),
+        "rule-hits" => serde_json::json!({
+            "ok": true,
+            "rules": utils::common::top_rule_hits(usize::MAX).into_iter()
+                .map(|(rule, count)| (rule, serde_json::Value::from(count)))
+                .collect::<serde_json::Map<_, _>>(),
+        }),
+        _ => serde_json::json!({
+            "ok": false,
+            "error": format!("unknown command '{}'", verb),
+            "commands": ["reload-config", "dump-contexts", "set-level <FILTER>", "stats", "rule-hits"],
+        }),
+    }
+}
+
+/// `--control`: sends one command to the running instance's control pipe and
+/// prints the reply.
+fn send_control(config_path: Option<&Path>, command: &str) -> Result<(), EdrError> {
+    let config = config::rules::load_rules(config_path)?;
+    let pipe_name = config.control_pipe
+        .filter(|control| control.enabled)
+        .map(|control| control.pipe_name)
+        .ok_or_else(|| EdrError::Control("control_pipe is not enabled in the rules file".to_string()))?;
+    let reply = send_control_command(&pipe_name, command).map_err(EdrError::Control)?;
+    println!("{}", reply);
+    Ok(())
+}
+
+/// Interactive console mode: Ctrl+C plus the stdin command loop.
+fn run_console_loop() {
+    log::info!(target: CONSOLE_TARGET, "");
+    log::info!(target: CONSOLE_TARGET, "🛑 To stop:");
+    log::info!(target: CONSOLE_TARGET, "  1. Press Ctrl+C");
+    log::info!(target: CONSOLE_TARGET, "  2. OR Type 'q' then press Enter");
+    log::info!(target: CONSOLE_TARGET, "  3. OR Type 'stop' then press Enter");
+    log::info!(target: CONSOLE_TARGET, "=========================================");
 
     // Setup Ctrl+C handler with protection against multiple triggers
     ctrlc::set_handler({
@@ -93,91 +861,128 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     })
     .expect("Failed to set Ctrl+C handler");
 
-    // Main loop - check both Ctrl+C and manual commands
-    let mut input_buffer = String::new();
-    while RUNNING.load(Ordering::Relaxed) {
+    // Main loop - check both Ctrl+C and manual commands. Bytes are kept until a
+    // line ending arrives, so a read may complete several commands or only part of one.
+    let mut input_buffer: Vec<u8> = Vec::new();
+    'input: while RUNNING.load(Ordering::Relaxed) {
         // Check for manual commands (non-blocking)
         let mut buffer = [0u8; 1024];
         if let Ok(n) = io::stdin().read(&mut buffer)
             && n > 0
         {
-            input_buffer.push_str(&String::from_utf8_lossy(&buffer[..n]));
-            
-            // Check if we have a complete line
-            if input_buffer.contains('\n') || input_buffer.contains('\r') {
-                    let command = input_buffer.trim().to_lowercase();
-                    input_buffer.clear();
-                    
-                    if command == "q" || command == "quit" || command == "exit" || command == "stop" {
-                        SHUTDOWN_ONCE.call_once(|| {
-                            log::info!("🛑 Manual shutdown requested via command: '{}'", command);
-                            RUNNING.store(false, Ordering::Relaxed);
-                        });
-                        break;
-                    } else if command == "status" || command == "info" {
-                        log::info!("📊 System Status: RUNNING");
-                        log::info!("  Components: Process Monitor, Network Monitor, Correlation Engine");
-                        log::info!("  Type 'q', 'quit', 'exit', or 'stop' to shutdown");
-                    } else if !command.is_empty() {
-                        log::info!("❓ Unknown command: '{}'", command);
-                        log::info!("   Available commands: q, quit, exit, stop, status");
-                    }
+            input_buffer.extend_from_slice(&buffer[..n]);
+
+            while let Some(end) = input_buffer.iter().position(|&byte| byte == b'\n' || byte == b'\r') {
+                let line: Vec<u8> = input_buffer.drain(..=end).collect();
+                let command = String::from_utf8_lossy(&line).trim().to_lowercase();
+                if !handle_console_command(&command) {
+                    break 'input;
                 }
+            }
         }
         
         // Small sleep to prevent CPU spinning
         std::thread::sleep(Duration::from_millis(50));
     }
+}
 
-    // ========== SINGLE SHUTDOWN SEQUENCE ==========
-    perform_shutdown(
-        process_shutdown,
-        network_shutdown,
-        correlation_shutdown,
-        process_tx,
-        network_tx,
-        alert_tx,
-        process_handle,
-        network_handle,
-        correlation_handle,
-    );
-
-    Ok(())
+/// Runs one console command; returns false once the user asked to stop.
+fn handle_console_command(command: &str) -> bool {
+    if command == "q" || command == "quit" || command == "exit" || command == "stop" {
+        SHUTDOWN_ONCE.call_once(|| {
+            log::info!("🛑 Manual shutdown requested via command: '{}'", command);
+            RUNNING.store(false, Ordering::Relaxed);
+        });
+        return false;
+    } else if command == "status" || command == "info" {
+        let dead = dead_monitors();
+        let status = if dead.is_empty() { "RUNNING" } else { "DEGRADED" };
+        log::info!(target: CONSOLE_TARGET, "📊 System Status: {}", status);
+        let components: Vec<&str> = monitor_status().into_iter().map(|monitor| monitor.name).collect();
+        log::info!(target: CONSOLE_TARGET, "  Components: {}", components.join(", "));
+        log::info!(target: CONSOLE_TARGET, "  Events: process ETW {}, network ETW {}, engine {}; alerts raised: {}",
+            PROCESS_HEARTBEAT.events(), NETWORK_HEARTBEAT.events(),
+            EVENTS_PROCESSED.load(Ordering::Relaxed), ALERTS_RAISED.load(Ordering::Relaxed));
+        log::info!(target: CONSOLE_TARGET, "  Tracked: {} process contexts, {} connections, {} recent events ({} contexts evicted)",
+            TRACKED_CONTEXTS.load(Ordering::Relaxed), TRACKED_CONNECTIONS.load(Ordering::Relaxed),
+            TRACKED_EVENTS.load(Ordering::Relaxed), CONTEXTS_EVICTED.load(Ordering::Relaxed));
+        let restarts = MONITOR_RESTARTS.load(Ordering::Relaxed);
+        if restarts > 0 {
+            log::warn!(target: CONSOLE_TARGET, "  Monitor restarts: {}", restarts);
+        }
+        if !dead.is_empty() {
+            log::error!(target: CONSOLE_TARGET, "  Monitors DOWN: {}", dead.join(", "));
+        }
+        if utils::common::POWERSHELL_DEGRADED.load(Ordering::Relaxed) {
+            log::warn!(target: CONSOLE_TARGET, "  Command-line lookups: DEGRADED (PowerShell unavailable)");
+        }
+        let backfill_dropped = utils::common::BACKFILL_DROPPED.load(Ordering::Relaxed);
+        if backfill_dropped > 0 {
+            log::warn!(target: CONSOLE_TARGET, "  Command-line backfill: {} lookups dropped (queue full)", backfill_dropped);
+        }
+        let hashes_dropped = monitoring::process::IMAGE_HASH_DROPPED.load(Ordering::Relaxed);
+        if hashes_dropped > 0 {
+            log::warn!(target: CONSOLE_TARGET, "  Image hashing: {} images skipped (queue full)", hashes_dropped);
+        }
+        if PROCESS_HEARTBEAT.is_degraded() {
+            log::warn!(target: CONSOLE_TARGET, "  Process ETW: DEGRADED (no events for {}s)",
+                PROCESS_HEARTBEAT.seconds_silent());
+        }
+        if NETWORK_HEARTBEAT.is_degraded() {
+            log::warn!(target: CONSOLE_TARGET, "  Network ETW: DEGRADED (no events for {}s, polling TCP table)",
+                NETWORK_HEARTBEAT.seconds_silent());
+        }
+        log::info!(target: CONSOLE_TARGET, "  Type 'q', 'quit', 'exit', or 'stop' to shutdown");
+    } else if command == "top-rules" || command == "stats" {
+        let top = utils::common::top_rule_hits(15);
+        if top.is_empty() {
+            log::info!(target: CONSOLE_TARGET, "📈 No rule hits recorded yet");
+        } else {
+            log::info!(target: CONSOLE_TARGET, "📈 Rule hits (lifetime):");
+            for (rule, count) in top {
+                log::info!(target: CONSOLE_TARGET, "  {:>6}  {}", count, rule);
+            }
+        }
+        let users = alerts_by_user(10);
+        if command == "stats" && !users.is_empty() {
+            log::info!(target: CONSOLE_TARGET, "👤 Alerts by user (lifetime):");
+            for (user, count) in users {
+                log::info!(target: CONSOLE_TARGET, "  {:>6}  {}", count, user);
+            }
+        }
+    } else if !command.is_empty() {
+        log::info!(target: CONSOLE_TARGET, "❓ Unknown command: '{}'", command);
+        log::info!(target: CONSOLE_TARGET, "   Available commands: q, quit, exit, stop, status, top-rules");
+    }
+    true
 }
 
 fn perform_shutdown(
-    process_shutdown: Arc<AtomicBool>,
-    network_shutdown: Arc<AtomicBool>,
-    correlation_shutdown: Arc<AtomicBool>,
+    shutdown_flags: &[Arc<AtomicBool>],
     process_tx: crossbeam_channel::Sender<crate::events::BaseEvent>,
     network_tx: crossbeam_channel::Sender<crate::events::BaseEvent>,
     alert_tx: crossbeam_channel::Sender<crate::events::Alert>,
-    process_handle: std::thread::JoinHandle<()>,
-    network_handle: std::thread::JoinHandle<()>,
-    correlation_handle: std::thread::JoinHandle<()>,
+    components: Vec<(&'static str, Option<std::thread::JoinHandle<()>>)>,
 ) {
     log::info!("");
     log::info!("=========================================");
     log::info!("       Initiating Graceful Shutdown");
     log::info!("=========================================");
     
-    process_shutdown.store(false, Ordering::Relaxed);
-    network_shutdown.store(false, Ordering::Relaxed);
-    correlation_shutdown.store(false, Ordering::Relaxed);
+    for flag in shutdown_flags {
+        flag.store(false, Ordering::Relaxed);
+    }
 
     // Close channels to unblock threads
     drop(process_tx);
     drop(network_tx);
     drop(alert_tx);
     
-    // Define shutdown order (network first, then correlation, then process)
-    let components = vec![
-        ("Network Monitor", network_handle),
-        ("Correlation Engine", correlation_handle),
-        ("Process Monitor", process_handle),
-    ];
-    
+    // Components arrive in shutdown order
     for (name, handle) in components {
+        let Some(handle) = handle else {
+            continue;
+        };
         log::info!("  Waiting for {}...", name);
         match join_with_timeout(handle, Duration::from_secs(5)) {
             Ok(()) => log::info!("  ✅ {} stopped gracefully", name),
@@ -0,0 +1,189 @@
+use crate::config::rules::{AlertRoute, Config, RuleMetrics};
+use crate::events::Alert;
+use crate::monitoring::notifier::{start_syslog_notifier, start_webhook_notifier, SyslogNotifier, WebhookNotifier};
+use crate::monitoring::pipe_server::{start_pipe_server, PipeServer};
+#[cfg(feature = "sqlite")]
+use crate::monitoring::sqlite_sink::{open_sqlite_sink, SqliteSink};
+use crate::utils::common::{write_file_atomically, RULE_HIT_COUNTS};
+use std::collections::HashMap;
+use std::path::Path;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Every enabled alert output, opened once when the handler starts.
+struct AlertSinks {
+    pipe_server: Option<PipeServer>,
+    webhook: Option<WebhookNotifier>,
+    syslog: Option<SyslogNotifier>,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<SqliteSink>,
+}
+
+pub fn start_alert_handler(
+    alert_rx: Receiver<Alert>,
+    config: Arc<Config>,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_alert_handler(alert_rx, config, shutdown);
+    })
+}
+
+pub fn run_alert_handler(
+    alert_rx: Receiver<Alert>,
+    config: Arc<Config>,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut sinks = open_sinks(&config);
+
+    let rule_metrics = config.rule_metrics.as_ref().filter(|metrics| metrics.enabled);
+    if let Some(metrics) = rule_metrics {
+        load_rule_metrics(metrics);
+    }
+    let mut last_persist = Instant::now();
+
+    while shutdown.load(Ordering::Relaxed) {
+        match alert_rx.recv_timeout(Duration::from_millis(100)) {
+            // min_severity and min_confidence were applied where the alert was
+            // raised, against the live settings a reload updates
+            Ok(alert) => deliver_alert(&alert, &mut sinks, &config.alert_routes),
+            Err(RecvTimeoutError::Timeout) => {
+                #[cfg(feature = "sqlite")]
+                if let Some(sqlite) = sinks.sqlite.as_mut() {
+                    sqlite.flush_pending();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let Some(metrics) = rule_metrics
+            && last_persist.elapsed() >= Duration::from_secs(metrics.persist_interval_secs.max(1))
+        {
+            persist_rule_metrics(metrics);
+            last_persist = Instant::now();
+        }
+    }
+
+    if let Some(metrics) = rule_metrics {
+        persist_rule_metrics(metrics);
+    }
+    if let Some(pipe_server) = sinks.pipe_server {
+        pipe_server.stop();
+    }
+    if let Some(webhook) = sinks.webhook {
+        webhook.stop();
+    }
+    if let Some(syslog) = sinks.syslog {
+        syslog.stop();
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(sqlite) = sinks.sqlite {
+        sqlite.close();
+    }
+}
+
+fn open_sinks(config: &Config) -> AlertSinks {
+    let pipe_server = config.pipe_output
+        .as_ref()
+        .filter(|pipe| pipe.enabled)
+        .and_then(start_pipe_server);
+
+    let webhook = config.webhook_output
+        .as_ref()
+        .filter(|webhook| webhook.enabled)
+        .and_then(start_webhook_notifier);
+    let syslog = config.syslog_output
+        .as_ref()
+        .filter(|syslog| syslog.enabled)
+        .and_then(start_syslog_notifier);
+
+    let sqlite_output = config.sqlite_output.as_ref().filter(|sqlite| sqlite.enabled);
+    #[cfg(not(feature = "sqlite"))]
+    if sqlite_output.is_some() {
+        log::warn!("sqlite_output is enabled but this build lacks the 'sqlite' feature; ignoring");
+    }
+
+    AlertSinks {
+        pipe_server,
+        webhook,
+        syslog,
+        #[cfg(feature = "sqlite")]
+        sqlite: sqlite_output.and_then(open_sqlite_sink),
+    }
+}
+
+fn load_rule_metrics(metrics: &RuleMetrics) {
+    if metrics.reset_on_start {
+        log::info!("Rule hit counters reset on start");
+        return;
+    }
+    let Ok(content) = std::fs::read_to_string(&metrics.persist_path) else {
+        return;
+    };
+    match serde_json::from_str::<HashMap<String, u64>>(&content) {
+        Ok(saved) => {
+            if let Ok(mut counts) = RULE_HIT_COUNTS.lock() {
+                for (rule, count) in saved {
+                    *counts.entry(rule).or_insert(0) += count;
+                }
+                log::info!("Loaded hit counters for {} rules from {}", counts.len(), metrics.persist_path);
+            }
+        }
+        Err(e) => log::warn!("Ignoring unreadable rule metrics file {}: {}", metrics.persist_path, e),
+    }
+}
+
+fn persist_rule_metrics(metrics: &RuleMetrics) {
+    let snapshot = match RULE_HIT_COUNTS.lock() {
+        Ok(counts) => counts.clone(),
+        Err(_) => return,
+    };
+    let result = serde_json::to_string_pretty(&snapshot)
+        .map_err(|e| e.to_string())
+        .and_then(|json| write_file_atomically(Path::new(&metrics.persist_path), json.as_bytes()));
+    if let Err(e) = result {
+        log::warn!("Failed to persist rule metrics: {}", e);
+    }
+}
+
+/// Whether `sink` should receive `alert`: decided by the first matching route,
+/// or every sink when no route matches.
+fn is_routed_to(alert: &Alert, sink: &str, routes: &[AlertRoute]) -> bool {
+    routes.iter()
+        .find(|route| route.matches(&alert.severity, &alert.rule_name))
+        .is_none_or(|route| route.sinks.iter().any(|name| name == sink))
+}
+
+fn deliver_alert(alert: &Alert, sinks: &mut AlertSinks, routes: &[AlertRoute]) {
+    let line = match serde_json::to_string(alert) {
+        Ok(line) => line,
+        Err(e) => {
+            log::error!("Failed to serialize alert for output: {}", e);
+            return;
+        }
+    };
+
+    if let Some(pipe_server) = &sinks.pipe_server
+        && is_routed_to(alert, "pipe", routes)
+    {
+        pipe_server.broadcast(&line);
+    }
+    if let Some(webhook) = &sinks.webhook
+        && is_routed_to(alert, "webhook", routes)
+    {
+        webhook.notify(&line);
+    }
+    if let Some(syslog) = &sinks.syslog
+        && is_routed_to(alert, "syslog", routes)
+    {
+        syslog.notify(alert);
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(sqlite) = sinks.sqlite.as_mut()
+        && is_routed_to(alert, "sqlite", routes)
+    {
+        sqlite.insert(alert, &line);
+    }
+}
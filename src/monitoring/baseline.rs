@@ -0,0 +1,239 @@
+use crate::config::rules::{default_baseline_path, Baseline};
+use crate::error::EdrError;
+use crate::events::alert::AlertSeverity;
+use crate::utils::common::write_file_atomically;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Learned entries are written at most this often while learning, and on shutdown
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+static STATE: Mutex<Option<BaselineState>> = Mutex::new(None);
+
+/// The baseline file: what the detectors would have alerted on while learning.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BaselineFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub learning_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub entries: Vec<BaselineEntry>,
+}
+
+/// One rule matching one process under one parent during learning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub rule: String,
+    pub process: String,
+    pub parent: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub count: u64,
+}
+
+/// `--baseline` actions.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum BaselineAction {
+    Show,
+    Clear,
+}
+
+/// What the baseline decided about an alert that was about to be raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaselineMatch {
+    /// Learning is on: the alert was recorded instead of raised.
+    Learned,
+    /// Learning is over and the alert matches a learned entry.
+    Known,
+}
+
+struct BaselineState {
+    path: String,
+    learning_until: Option<DateTime<Utc>>,
+    entries: HashMap<(String, String, String), BaselineEntry>,
+    dirty: bool,
+    last_save: Instant,
+    save_failing: bool,
+    announced_end: bool,
+}
+
+impl BaselineState {
+    fn learning(&self) -> bool {
+        self.learning_until.is_some_and(|until| Utc::now() < until)
+    }
+}
+
+/// Reads a baseline file. A missing file is an empty baseline.
+pub fn read_baseline_file(path: &str) -> Result<BaselineFile, EdrError> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| EdrError::Baseline(format!("{} is not a baseline file: {}", path, e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BaselineFile::default()),
+        Err(e) => Err(EdrError::Baseline(format!("cannot read {}: {}", path, e))),
+    }
+}
+
+/// Loads the configured baseline, or turns it off. A configured `learning_until`
+/// (or `--learn`) replaces the deadline saved in the file. Called when the
+/// correlation engine starts.
+pub fn open_baseline(config: Option<&Baseline>) {
+    let Some(config) = config.filter(|config| config.enabled) else {
+        if let Ok(mut state) = STATE.lock() {
+            *state = None;
+        }
+        return;
+    };
+    let saved = read_baseline_file(&config.path).unwrap_or_else(|e| {
+        log::warn!("Starting an empty baseline: {}", e);
+        BaselineFile::default()
+    });
+    let learning_until = config.learning_until.or(saved.learning_until);
+    let entries: HashMap<_, _> = saved.entries.into_iter()
+        .map(|entry| ((entry.rule.clone(), entry.process.clone(), entry.parent.clone()), entry))
+        .collect();
+
+    let state = BaselineState {
+        path: config.path.clone(),
+        learning_until,
+        entries,
+        // Persist a deadline that came from --learn or the rules file
+        dirty: learning_until != saved.learning_until,
+        last_save: Instant::now(),
+        save_failing: false,
+        announced_end: false,
+    };
+    if state.learning() {
+        log::info!(
+            "📚 Learning baseline until {} ({} entries so far); alerts below Critical are recorded to {} instead of raised",
+            learning_until.map(|until| until.to_rfc3339()).unwrap_or_default(), state.entries.len(), state.path
+        );
+    } else {
+        log::info!("📚 Baseline loaded from {}: {} learned entries suppress matching alerts", state.path, state.entries.len());
+    }
+    if let Ok(mut current) = STATE.lock() {
+        *current = Some(state);
+    }
+}
+
+/// Checks an alert against the baseline. While learning, records it and returns
+/// `Learned`; afterwards returns `Known` when the same rule already fired for the
+/// same process and parent while learning. Critical alerts always go through and
+/// are never learned. `None` means raise the alert.
+pub fn check_baseline(rule: &str, process_name: &str, parent_name: &str, severity: &AlertSeverity) -> Option<BaselineMatch> {
+    if *severity >= AlertSeverity::Critical {
+        return None;
+    }
+    let mut guard = STATE.lock().ok()?;
+    let state = guard.as_mut()?;
+    let key = (rule.to_string(), process_name.to_lowercase(), parent_name.to_lowercase());
+
+    if state.learning() {
+        let now = Utc::now();
+        let entry = state.entries.entry(key).or_insert_with(|| BaselineEntry {
+            rule: rule.to_string(),
+            process: process_name.to_lowercase(),
+            parent: parent_name.to_lowercase(),
+            first_seen: now,
+            last_seen: now,
+            count: 0,
+        });
+        entry.last_seen = now;
+        entry.count += 1;
+        state.dirty = true;
+        return Some(BaselineMatch::Learned);
+    }
+    if state.learning_until.is_some() && !state.announced_end {
+        state.announced_end = true;
+        log::info!("📚 Baseline learning ended with {} entries; only deviations alert from now on", state.entries.len());
+    }
+    state.entries.contains_key(&key).then_some(BaselineMatch::Known)
+}
+
+/// Writes the baseline if it changed and the save interval has passed, or
+/// unconditionally with `force` (shutdown).
+pub fn save_baseline(force: bool) {
+    let Ok(mut guard) = STATE.lock() else { return };
+    let Some(state) = guard.as_mut() else { return };
+    if !state.dirty || (!force && state.last_save.elapsed() < SAVE_INTERVAL) {
+        return;
+    }
+    let mut entries: Vec<BaselineEntry> = state.entries.values().cloned().collect();
+    entries.sort_by(|a, b| (&a.rule, &a.process, &a.parent).cmp(&(&b.rule, &b.process, &b.parent)));
+    let file = BaselineFile { learning_until: state.learning_until, entries };
+    let result = serde_json::to_string_pretty(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|json| write_file_atomically(Path::new(&state.path), json.as_bytes()));
+    match result {
+        Ok(()) => {
+            if state.save_failing {
+                log::info!("📚 Baseline saved to {} again", state.path);
+            }
+            state.dirty = false;
+            state.save_failing = false;
+        }
+        // Retried every interval; warn once rather than every minute
+        Err(e) if state.save_failing => log::debug!("Baseline still not saved: {}", e),
+        Err(e) => {
+            log::warn!("Failed to save baseline, learned entries are kept in memory and retried: {}", e);
+            state.save_failing = true;
+        }
+    }
+    state.last_save = Instant::now();
+}
+
+/// `--baseline show|clear` against the baseline file named in the rules file.
+pub fn run_baseline_command(config_path: Option<&std::path::Path>, action: BaselineAction) -> Result<(), EdrError> {
+    let config = crate::config::rules::load_rules(config_path)?;
+    match action {
+        BaselineAction::Show => print_baseline(config.baseline.as_ref()),
+        BaselineAction::Clear => clear_baseline(config.baseline.as_ref()),
+    }
+}
+
+/// `--baseline show`: the learning deadline and every learned entry.
+fn print_baseline(config: Option<&Baseline>) -> Result<(), EdrError> {
+    let path = baseline_path(config);
+    let file = read_baseline_file(&path)?;
+    match file.learning_until {
+        Some(until) if Utc::now() < until => println!("Learning until {} ({})", until.to_rfc3339(), path),
+        Some(until) => println!("Learning ended {} ({})", until.to_rfc3339(), path),
+        None => println!("No learning period set ({})", path),
+    }
+    if file.entries.is_empty() {
+        println!("No learned entries");
+        return Ok(());
+    }
+    let mut entries = file.entries;
+    entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.rule.cmp(&b.rule)));
+    for entry in &entries {
+        println!(
+            "{:>7}  {}  {} (parent {})  last seen {}",
+            entry.count,
+            entry.rule,
+            entry.process,
+            entry.parent,
+            entry.last_seen.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    println!("{} learned entries", entries.len());
+    Ok(())
+}
+
+/// `--baseline clear`: deletes the baseline file. The running EDR keeps its
+/// in-memory baseline (and rewrites it while learning) until restarted.
+fn clear_baseline(config: Option<&Baseline>) -> Result<(), EdrError> {
+    let path = baseline_path(config);
+    match std::fs::remove_file(&path) {
+        Ok(()) => println!("Cleared baseline {}", path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("No baseline at {}", path),
+        Err(e) => return Err(EdrError::Baseline(format!("cannot delete {}: {}", path, e))),
+    }
+    Ok(())
+}
+
+fn baseline_path(config: Option<&Baseline>) -> String {
+    config.map_or_else(default_baseline_path, |config| config.path.clone())
+}
@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+
+/// Source of "now" for the correlation engine's time windows (rapid connections,
+/// process age, cooldowns, context TTLs).
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Called with each event's timestamp before the engine processes it.
+    fn observe(&self, _event_time: DateTime<Utc>) {}
+}
+
+/// Wall-clock time, used for live monitoring.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Time that only moves when set. It is driven by the events it observes, so a
+/// replay sees the gaps that were recorded however fast it is fed. Time never
+/// runs backwards; until the first `set` it reads as wall-clock time.
+#[derive(Default)]
+pub struct ManualClock {
+    current: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn set(&self, time: DateTime<Utc>) {
+        if let Ok(mut current) = self.current.lock()
+            && current.is_none_or(|now| time > now)
+        {
+            *current = Some(time);
+        }
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.current.lock().ok().and_then(|current| *current).unwrap_or_else(Utc::now)
+    }
+
+    fn observe(&self, event_time: DateTime<Utc>) {
+        self.set(event_time);
+    }
+}
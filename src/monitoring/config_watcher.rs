@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls the rules file and calls `reload` once it has changed and stopped
+/// changing, so an editor's save in several writes is reloaded once, complete.
+/// `reload` parses and validates the file and keeps the running rules when it
+/// is malformed; a missing file is waited out the same way.
+pub fn start_config_watcher(
+    path: PathBuf,
+    poll_interval: Duration,
+    reload: Arc<dyn Fn() + Send + Sync>,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_config_watcher(path, poll_interval, reload, shutdown);
+    })
+}
+
+fn run_config_watcher(path: PathBuf, poll_interval: Duration, reload: Arc<dyn Fn() + Send + Sync>, shutdown: Arc<AtomicBool>) {
+    log::info!("✅ Config Watcher checking {} every {}s", path.display(), poll_interval.as_secs());
+
+    // The first read is the baseline: the running rules came from this version
+    let mut loaded = file_version(&path);
+    let mut pending: Option<(SystemTime, u64)> = None;
+    let mut last_check: Option<Instant> = None;
+    while shutdown.load(Ordering::Relaxed) {
+        if last_check.is_none_or(|checked| checked.elapsed() >= poll_interval) {
+            let current = file_version(&path);
+            match current {
+                Some(version) if Some(version) == loaded => pending = None,
+                // Unchanged since the last poll: the write has settled
+                Some(version) if pending == Some(version) => {
+                    log::info!("🔄 {} changed, reloading", path.display());
+                    reload();
+                    loaded = Some(version);
+                    pending = None;
+                }
+                Some(version) => pending = Some(version),
+                // Mid-rename or deleted; keep the running rules until it is back
+                None => pending = None,
+            }
+            last_check = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    log::info!("✅ Config Watcher stopped");
+}
+
+/// Modification time and length, which together change on every save.
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -0,0 +1,271 @@
+use crate::config::rules::ControlPipe;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{
+    CloseHandle, LocalFree, ERROR_PIPE_CONNECTED, GENERIC_READ, GENERIC_WRITE, HANDLE, HLOCAL,
+};
+use windows::Win32::Security::Authorization::{
+    ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+};
+use windows::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, ReadFile, WriteFile, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_NONE, OPEN_EXISTING,
+    PIPE_ACCESS_DUPLEX,
+};
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_REJECT_REMOTE_CLIENTS,
+    PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+
+const PIPE_BUFFER_SIZE: u32 = 16 * 1024;
+// Longest command line accepted; a client sending more is disconnected
+const MAX_COMMAND_LEN: usize = 4096;
+// Protected DACL: full access for SYSTEM and the Administrators group only
+pub(crate) const ADMIN_ONLY_SDDL: &str = "D:P(A;;GA;;;SY)(A;;GA;;;BA)";
+
+/// Runs one control command and returns its JSON reply.
+pub type ControlHandler = Arc<dyn Fn(&str) -> serde_json::Value + Send + Sync>;
+
+// Pipe handles are only ever used by the single thread that owns them.
+struct PipeHandle(HANDLE);
+unsafe impl Send for PipeHandle {}
+
+/// Accepts newline-terminated commands on a local named pipe and writes one
+/// JSON line back per command, so a running service can be reconfigured and
+/// inspected without stdin.
+pub struct ControlPipeServer {
+    pipe_name: widestring::U16CString,
+    running: Arc<AtomicBool>,
+    accept_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+pub fn start_control_pipe(config: &ControlPipe, handler: ControlHandler) -> Option<ControlPipeServer> {
+    let pipe_name = match widestring::U16CString::from_str(&config.pipe_name) {
+        Ok(name) => name,
+        Err(e) => {
+            log::error!("Invalid control pipe name '{}': {}", config.pipe_name, e);
+            return None;
+        }
+    };
+    let Some(security) = PipeSecurity::new(ADMIN_ONLY_SDDL) else {
+        // Never fall back to the default DACL, which lets any local user connect
+        log::error!("Failed to build control pipe ACL: {:?}", windows::core::Error::from_thread());
+        return None;
+    };
+
+    let running = Arc::new(AtomicBool::new(true));
+    let accept_handle = {
+        let pipe_name = pipe_name.clone();
+        let running = Arc::clone(&running);
+        std::thread::spawn(move || {
+            run_accept_loop(pipe_name, security, handler, running);
+        })
+    };
+
+    log::info!("✅ Control pipe listening on {} (Administrators only)", config.pipe_name);
+
+    Some(ControlPipeServer {
+        pipe_name,
+        running,
+        accept_handle: Some(accept_handle),
+    })
+}
+
+impl ControlPipeServer {
+    pub fn stop(mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        // The accept thread is parked in ConnectNamedPipe; connect to ourselves to wake it.
+        unsafe {
+            if let Ok(handle) = CreateFileW(
+                PCWSTR(self.pipe_name.as_ptr()),
+                GENERIC_READ.0,
+                FILE_SHARE_NONE,
+                None,
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                None,
+            ) {
+                let _ = CloseHandle(handle);
+            }
+        }
+
+        if let Some(handle) = self.accept_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Security descriptor built from an SDDL string, applied to every instance
+/// of a named pipe so it never falls back to the default DACL.
+pub(crate) struct PipeSecurity(PSECURITY_DESCRIPTOR);
+// Read-only after creation and freed once, by the accept thread that owns it.
+unsafe impl Send for PipeSecurity {}
+
+impl PipeSecurity {
+    pub(crate) fn new(sddl: &str) -> Option<Self> {
+        let sddl = widestring::U16CString::from_str(sddl).ok()?;
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                PCWSTR(sddl.as_ptr()),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                None,
+            )
+        }
+        .ok()?;
+        Some(Self(descriptor))
+    }
+
+    pub(crate) fn attributes(&self) -> SECURITY_ATTRIBUTES {
+        SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.0.0,
+            bInheritHandle: false.into(),
+        }
+    }
+}
+
+impl Drop for PipeSecurity {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = LocalFree(Some(HLOCAL(self.0.0)));
+        }
+    }
+}
+
+fn run_accept_loop(
+    pipe_name: widestring::U16CString,
+    security: PipeSecurity,
+    handler: ControlHandler,
+    running: Arc<AtomicBool>,
+) {
+    let attributes = security.attributes();
+    while running.load(Ordering::Relaxed) {
+        let handle = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(pipe_name.as_ptr()),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                PIPE_BUFFER_SIZE,
+                PIPE_BUFFER_SIZE,
+                0,
+                Some(&attributes),
+            )
+        };
+        if handle.is_invalid() {
+            log::error!("CreateNamedPipeW failed for control pipe: {:?}", windows::core::Error::from_thread());
+            std::thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+
+        // ERROR_PIPE_CONNECTED means the client raced us between create and connect.
+        let connected = match unsafe { ConnectNamedPipe(handle, None) } {
+            Ok(()) => true,
+            Err(e) => e.code() == ERROR_PIPE_CONNECTED.to_hresult(),
+        };
+
+        if !running.load(Ordering::Relaxed) || !connected {
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+            continue;
+        }
+
+        log::debug!("Control pipe client connected");
+        let pipe = PipeHandle(handle);
+        let handler = Arc::clone(&handler);
+        std::thread::spawn(move || {
+            run_control_client(pipe, handler);
+        });
+    }
+}
+
+fn run_control_client(pipe: PipeHandle, handler: ControlHandler) {
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buffer = [0u8; 1024];
+    'client: loop {
+        let mut read = 0u32;
+        let result = unsafe { ReadFile(pipe.0, Some(&mut buffer), Some(&mut read), None) };
+        if result.is_err() || read == 0 {
+            break;
+        }
+        pending.extend_from_slice(&buffer[..read as usize]);
+
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let command = String::from_utf8_lossy(&line);
+            let command = command.trim();
+            if command.is_empty() {
+                continue;
+            }
+            log::info!("🎛️  Control command: {}", command);
+            if !write_reply(&pipe, &handler(command)) {
+                break 'client;
+            }
+        }
+        if pending.len() > MAX_COMMAND_LEN {
+            let reply = serde_json::json!({ "ok": false, "error": "command too long" });
+            write_reply(&pipe, &reply);
+            break;
+        }
+    }
+    unsafe {
+        let _ = DisconnectNamedPipe(pipe.0);
+        let _ = CloseHandle(pipe.0);
+    }
+}
+
+fn write_reply(pipe: &PipeHandle, reply: &serde_json::Value) -> bool {
+    let mut line = reply.to_string();
+    line.push('\n');
+    let mut written = 0u32;
+    unsafe { WriteFile(pipe.0, Some(line.as_bytes()), Some(&mut written), None) }.is_ok()
+}
+
+/// Sends one command to a running instance's control pipe and returns the reply line.
+pub fn send_control_command(pipe_name: &str, command: &str) -> Result<String, String> {
+    let name = widestring::U16CString::from_str(pipe_name).map_err(|e| e.to_string())?;
+    let handle = unsafe {
+        CreateFileW(
+            PCWSTR(name.as_ptr()),
+            GENERIC_READ.0 | GENERIC_WRITE.0,
+            FILE_SHARE_NONE,
+            None,
+            OPEN_EXISTING,
+            FILE_ATTRIBUTE_NORMAL,
+            None,
+        )
+    }
+    .map_err(|e| format!("cannot open {} (is the EDR running with control_pipe enabled?): {}", pipe_name, e))?;
+    let pipe = PipeHandle(handle);
+
+    let result = (|| {
+        let request = format!("{}\n", command);
+        let mut written = 0u32;
+        unsafe { WriteFile(pipe.0, Some(request.as_bytes()), Some(&mut written), None) }
+            .map_err(|e| format!("write failed: {}", e))?;
+
+        let mut reply = Vec::new();
+        let mut buffer = [0u8; 4096];
+        while !reply.contains(&b'\n') {
+            let mut read = 0u32;
+            unsafe { ReadFile(pipe.0, Some(&mut buffer), Some(&mut read), None) }
+                .map_err(|e| format!("read failed: {}", e))?;
+            if read == 0 {
+                break;
+            }
+            reply.extend_from_slice(&buffer[..read as usize]);
+        }
+        Ok(String::from_utf8_lossy(&reply).trim_end().to_string())
+    })();
+
+    unsafe {
+        let _ = CloseHandle(pipe.0);
+    }
+    result
+}
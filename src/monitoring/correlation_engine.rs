@@ -1,17 +1,37 @@
-use crate::config::rules::Config;
-use crate::events::{Alert, BaseEvent, EventType};
+use crate::config::rules::{AlertLogFormat, Config};
+use crate::config::expression::{Expression, Field, FieldValue};
+use crate::utils::reputation::ReputationList;
+use crate::utils::privilege::{inspect_process_token, query_process_image_path, suspend_process};
+use crate::monitoring::clock::Clock;
+use crate::monitoring::process::{process_image_hash, record_image_hash};
+use crate::monitoring::baseline::{check_baseline, open_baseline, save_baseline, BaselineMatch};
+use crate::monitoring::decision_log::{open_decision_log, record_fired, record_suppressed, Suppression};
+use crate::events::{Alert, BaseEvent, EventType, ProcessSecurity};
+use crate::events::file::FileWatch;
+use crate::events::registry::RegistryWatch;
+use crate::events::alert::{SOURCE_CORRELATION, SOURCE_ETW, SOURCE_PIPE_POLL, SOURCE_REGISTRY_POLL, SOURCE_TASK_POLL};
 use crate::utils::common::{
-    get_command_line_cached, get_parent_process_info,
+    get_command_line_cached, cached_command_line, request_command_line_backfill,
+    get_parent_process_info,
     analyze_command_line, is_suspicious_domain,
     is_high_risk_port, describe_port,
-    is_network_aware_process, is_scripting_engine,
+    is_network_aware_process, is_scripting_engine, is_system_process, is_browser_related_process,
     is_known_good_process, is_suspicious_parent_process,
-    identify_lolbas_abuse, is_private_or_local, truncate_string,
+    identify_lolbas_abuse, identify_signed_binary_proxy, identify_dotnet_lolbas, identify_inline_script, url_hosts, identify_recovery_inhibition, identify_uac_bypass,
+    identify_security_service_tamper, detect_encoded_payload, has_encoding_markers,
+    identify_powershell_downgrade,
+    identify_script_host_dropper, identify_office_ancestor_shell, identify_server_process_shell, identify_remote_execution_child, identify_reflective_loading,
+    identify_raw_socket_tunnel, ICMP_TUNNEL_TOOL, identify_bits_abuse, identify_ads_reference, is_signed_executable, is_anomalous_svchost_parent,
+    identify_clipboard_hijack, is_user_writable_script_location, identify_system_binary_relocation, identify_image_command_line_mismatch, identify_lsass_access, identify_suspicious_autorun,
+    image_path_from_command_line, is_unc_path, drive_letter, is_system_drive,
+    is_private_or_local, truncate_string,
+    parse_allowlist_entry, is_allowlisted_destination, AllowlistEntry,
+    record_rule_hits, rule_label, dga_score, registrable_domain, byte_entropy,
 };
 use crossbeam_channel::{Receiver, Sender};
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering};
 use std::time::Duration;
 
 // Suspicion scoring weights
@@ -27,6 +47,231 @@ const WEIGHT_HIGH_RISK_PORT: u32 = 2;
 const WEIGHT_RAPID_CONNECTIONS: u32 = 2;
 const WEIGHT_IMMEDIATE_C2: u32 = 4;
 const WEIGHT_LOLBAS: u32 = 2;
+const WEIGHT_PROXY_EXECUTION: u32 = SUSPICION_THRESHOLD;            // Medium on its own
+const WEIGHT_PROXY_EXECUTION_REMOTE: u32 = SUSPICION_THRESHOLD + 2; // High when the payload is remote
+const WEIGHT_DOTNET_LOLBAS: u32 = SUSPICION_THRESHOLD + 2;          // High on its own
+const WEIGHT_INLINE_SCRIPT: u32 = SUSPICION_THRESHOLD + 2;          // High on its own
+const WEIGHT_ENCODED_PAYLOAD: u32 = 2;
+const WEIGHT_UNEXPECTED_LISTENER: u32 = 4;
+const WEIGHT_INHIBIT_RECOVERY: u32 = SUSPICION_THRESHOLD * 2; // Always Critical on its own
+const WEIGHT_LSASS_ACCESS: u32 = SUSPICION_THRESHOLD + 2;     // High on its own
+const WEIGHT_UAC_BYPASS: u32 = SUSPICION_THRESHOLD + 2;       // High on its own
+const WEIGHT_SCRIPT_DROPPER: u32 = SUSPICION_THRESHOLD + 2;   // High on its own
+const WEIGHT_REFLECTIVE_LOADING: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_RAW_SOCKET: u32 = SUSPICION_THRESHOLD;             // Medium on its own
+const WEIGHT_ICMP_TUNNEL_TOOL: u32 = SUSPICION_THRESHOLD + 2;   // High on its own
+const WEIGHT_ADS_EXECUTION: u32 = SUSPICION_THRESHOLD + 2;      // High on its own
+const WEIGHT_TOKEN_PRIVILEGE: u32 = SUSPICION_THRESHOLD;         // Medium: SeImpersonate
+const WEIGHT_TOKEN_PRIVILEGE_SYSTEM: u32 = SUSPICION_THRESHOLD + 2; // High: SeDebug / SeTcb
+const WEIGHT_REMOVABLE_LAUNCH: u32 = SUSPICION_THRESHOLD;        // Medium: Explorer or drive root
+const WEIGHT_REMOVABLE_AUTORUN: u32 = SUSPICION_THRESHOLD + 2;   // High: Explorer launching the drive root
+const WEIGHT_OFFICE_CHILD_SHELL: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_SERVER_SHELL: u32 = SUSPICION_THRESHOLD + 2;      // High on its own
+const WEIGHT_REMOTE_EXECUTION: u32 = SUSPICION_THRESHOLD + 2;  // High on its own
+const WEIGHT_SVCHOST_MASQUERADE: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_IMAGE_COMMAND_LINE_MISMATCH: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_SYSTEM_BINARY_RELOCATED: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_CLIPBOARD_HIJACK: u32 = SUSPICION_THRESHOLD + 2; // High when run from a user-writable path
+const WEIGHT_SECURITY_TAMPER: u32 = SUSPICION_THRESHOLD + 2;  // High on its own
+const WEIGHT_POWERSHELL_DOWNGRADE: u32 = SUSPICION_THRESHOLD + 2; // High on its own, Critical with an AMSI bypass
+const WEIGHT_RUN_KEY_PAYLOAD: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_COM_HIJACK: u32 = SUSPICION_THRESHOLD + 2;      // High when it shadows a machine-wide CLSID
+const WEIGHT_C2_PIPE: u32 = SUSPICION_THRESHOLD + 2;         // High on its own
+const WEIGHT_TASK_PAYLOAD: u32 = SUSPICION_THRESHOLD + 2;    // High on its own
+const WEIGHT_REMOTE_IMAGE: u32 = SUSPICION_THRESHOLD;         // Medium on its own, High for script hosts
+const WEIGHT_RAW_IP_C2: u32 = SUSPICION_THRESHOLD;            // Medium on its own
+const WEIGHT_SPAWN_STORM: u32 = SUSPICION_THRESHOLD;          // Medium on its own
+const WEIGHT_CREDENTIAL_STORE: u32 = SUSPICION_THRESHOLD + 2; // High on its own
+const WEIGHT_BITS_JOB: u32 = SUSPICION_THRESHOLD;             // Medium: a job with no URL or notify command
+const WEIGHT_BITS_JOB_PAYLOAD: u32 = SUSPICION_THRESHOLD + 2; // High: a download or a notify command
+const WEIGHT_HOSTS_FILE: u32 = SUSPICION_THRESHOLD + 2;       // High on its own
+const WEIGHT_DNS_SERVER: u32 = SUSPICION_THRESHOLD + 2;       // High on its own
+const WEIGHT_DGA: u32 = SUSPICION_THRESHOLD;                  // Medium on its own
+const WEIGHT_DNS_TUNNEL: u32 = SUSPICION_THRESHOLD + 2;       // High on its own
+const WEIGHT_RANSOMWARE: u32 = SUSPICION_THRESHOLD * 2;       // Always Critical on its own
+const WEIGHT_EXFIL_VOLUME: u32 = SUSPICION_THRESHOLD + 2;     // High on its own
+
+// Domains whose redirection in the hosts file blinds updates or security tooling
+const SECURITY_DOMAIN_MARKERS: &[&str] = &[
+    "windowsupdate", "update.microsoft", "defender", "wdcp.microsoft", "smartscreen",
+    "virustotal", "malwarebytes", "kaspersky", "eset", "sophos", "mcafee", "norton",
+    "symantec", "crowdstrike", "sentinelone", "bitdefender", "avast", "trendmicro",
+];
+// A hosts-file write open this long before the registry monitor's poll still
+// counts as the writer, on top of the poll interval itself
+const HOSTS_WRITER_SLACK_SECS: i64 = 30;
+const MAX_HOSTS_WRITERS: usize = 64;
+// Generated-looking domains kept per process as alert samples
+const MAX_DGA_SAMPLES: usize = 5;
+// Document changes are re-assessed every this many new files once past min_files,
+// since the assessment reads sample files from disk
+const RANSOMWARE_REASSESS_STEP: usize = 10;
+const MAX_RANSOMWARE_SAMPLES: usize = 5;
+// Files read for the entropy check, and how much of each
+const MAX_ENTROPY_SAMPLES: usize = 3;
+const ENTROPY_SAMPLE_BYTES: usize = 4096;
+// Bits per byte above which a plain-text or uncompressed file looks encrypted
+const ENCRYPTED_ENTROPY: f64 = 7.5;
+// Formats that are low-entropy when intact, so high entropy means encryption
+const LOW_ENTROPY_EXTENSIONS: &[&str] = &[
+    "txt", "csv", "log", "rtf", "xml", "json", "html", "htm", "md", "ini", "bmp", "wav", "doc", "xls", "ppt",
+];
+// Suffixes editors, browsers and sync clients append while saving or downloading
+const BENIGN_APPENDED_EXTENSIONS: &[&str] = &[
+    "tmp", "temp", "bak", "old", "orig", "part", "partial", "crdownload", "download", "swp", "lnk",
+];
+// User documents an encryptor renames to name.<doc>.<new>; only these count as the inner extension
+const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "doc", "docx", "docm", "xls", "xlsx", "xlsm", "ppt", "pptx", "odt", "ods", "odp", "rtf", "txt", "csv", "pdf",
+    "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "heic", "raw", "psd", "mp3", "wav", "mp4", "mov", "avi",
+    "zip", "rar", "7z", "sql", "mdb", "accdb", "pst", "eml", "msg", "dwg", "vsdx",
+];
+// File types that legitimately follow another dot in a name (app.min.js, types.d.ts,
+// report.pdf.json), so they are never taken for a ransom suffix
+const KNOWN_FILE_EXTENSIONS: &[&str] = &[
+    "js", "mjs", "cjs", "ts", "tsx", "jsx", "json", "map", "css", "scss", "html", "htm", "md", "xml", "yml",
+    "yaml", "py", "pyc", "rb", "go", "rs", "java", "class", "c", "h", "cpp", "cs", "sh", "ps1", "bat", "cmd",
+    "exe", "dll", "ini", "cfg", "log", "lock", "gz", "tar", "bz2", "xz", "svg", "ico", "woff", "woff2", "ttf",
+    "webp", "url", "sig", "asc", "sha256", "md5",
+];
+
+// IP reputation: the feed score (0–100) picks the weight
+const REPUTATION_SCORE_HIGH: u8 = 80;   // e.g. known C2 — High on its own
+const REPUTATION_SCORE_MEDIUM: u8 = 50; // e.g. known scanner — Medium on its own
+const WEIGHT_REPUTATION_HIGH: u32 = SUSPICION_THRESHOLD + 2;
+const WEIGHT_REPUTATION_MEDIUM: u32 = SUSPICION_THRESHOLD;
+const WEIGHT_REPUTATION_LOW: u32 = 1;
+
+// Ports a process may legitimately accept connections on without being a backdoor
+const STANDARD_LISTENER_PORTS: &[u16] = &[
+    21, 22, 25, 53, 80, 135, 139, 443, 445, 3389, 5985, 5986,
+];
+
+/// Events the engine has handled since startup, for the heartbeat and status.
+pub static EVENTS_PROCESSED: AtomicU64 = AtomicU64::new(0);
+/// Alerts that passed the severity/confidence filters since startup.
+pub static ALERTS_RAISED: AtomicU64 = AtomicU64::new(0);
+/// Process contexts currently held, refreshed on every cleanup pass.
+pub static TRACKED_CONTEXTS: AtomicUsize = AtomicUsize::new(0);
+/// Connections held across all contexts' histories, refreshed on every cleanup pass.
+pub static TRACKED_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+/// Events held in the cross-correlation history, refreshed on every cleanup pass.
+pub static TRACKED_EVENTS: AtomicUsize = AtomicUsize::new(0);
+/// Contexts dropped early because `max_contexts` was reached.
+pub static CONTEXTS_EVICTED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    // Token details captured at process start, attached to that process's alerts
+    static ref PROCESS_SECURITY: Mutex<HashMap<u32, ProcessSecurity>> = Mutex::new(HashMap::new());
+    // Alerts raised per account, for `stats`
+    static ref ALERTS_BY_USER: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+/// Accounts ordered by alerts raised for their processes, most first. Alerts for
+/// processes whose start wasn't traced count under "unknown".
+pub fn alerts_by_user(limit: usize) -> Vec<(String, u64)> {
+    let mut counts: Vec<(String, u64)> = match ALERTS_BY_USER.lock() {
+        Ok(counts) => counts.iter().map(|(user, count)| (user.clone(), *count)).collect(),
+        Err(_) => Vec::new(),
+    };
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    counts
+}
+
+// Callers of request_context_dump waiting for the engine loop to answer
+static CONTEXT_DUMP_REQUESTS: Mutex<Vec<Sender<Vec<ContextSummary>>>> = Mutex::new(Vec::new());
+static CONTEXT_DUMP_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// One tracked process as reported by the control pipe's `dump-contexts`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ContextSummary {
+    pub pid: u32,
+    pub process_name: String,
+    pub parent_pid: u32,
+    pub parent_name: String,
+    pub command_line: String,
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    pub suspicion_score: u32,
+    pub alert_reasons: Vec<String>,
+    pub network_connections: usize,
+    pub alerted: bool,
+    pub exited: bool,
+}
+
+/// Snapshot of the engine's process contexts, highest suspicion first. The
+/// engine answers after its current event; `None` if it doesn't within `timeout`.
+pub fn request_context_dump(timeout: Duration) -> Option<Vec<ContextSummary>> {
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    CONTEXT_DUMP_REQUESTS.lock().ok()?.push(tx);
+    CONTEXT_DUMP_PENDING.store(true, Ordering::Release);
+    rx.recv_timeout(timeout).ok()
+}
+
+fn answer_context_dumps(process_contexts: &HashMap<u32, ProcessContext>) {
+    if !CONTEXT_DUMP_PENDING.swap(false, Ordering::Acquire) {
+        return;
+    }
+    let requests = match CONTEXT_DUMP_REQUESTS.lock() {
+        Ok(mut requests) if !requests.is_empty() => std::mem::take(&mut *requests),
+        _ => return,
+    };
+    let mut summaries: Vec<ContextSummary> = process_contexts.values()
+        .map(|context| ContextSummary {
+            pid: context.pid,
+            process_name: context.process_name.clone(),
+            parent_pid: context.parent_pid,
+            parent_name: context.parent_name.clone(),
+            command_line: truncate_string(&context.command_line, 300),
+            start_time: context.start_time,
+            suspicion_score: context.suspicion_score,
+            alert_reasons: context.alert_reasons.clone(),
+            network_connections: context.network_connections.len(),
+            alerted: context.alerted,
+            exited: context.exited_at.is_some(),
+        })
+        .collect();
+    summaries.sort_by_key(|summary| (std::cmp::Reverse(summary.suspicion_score), summary.pid));
+    for request in requests {
+        let _ = request.send(summaries.clone());
+    }
+}
+
+// Read by generate_alert; the thresholds follow reloads, the log format is set once
+static MIN_SEVERITY: RwLock<crate::events::alert::AlertSeverity> = RwLock::new(crate::events::alert::AlertSeverity::Low);
+static MIN_CONFIDENCE: AtomicU8 = AtomicU8::new(0);
+static ALERT_LOG_FORMAT: OnceLock<AlertLogFormat> = OnceLock::new();
+
+// The rules file as last reloaded, picked up by the engine between events
+static RELOADED_CONFIG: Mutex<Option<Arc<Config>>> = Mutex::new(None);
+static CONFIG_RELOAD_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Hands a reloaded rules file to the correlation engine. It applies the
+/// detection settings, allowlists, IOCs and thresholds after its current event,
+/// keeping every tracked process, cooldown and window.
+pub fn reload_engine_config(config: Arc<Config>) {
+    if let Ok(mut reloaded) = RELOADED_CONFIG.lock() {
+        *reloaded = Some(config);
+        CONFIG_RELOAD_PENDING.store(true, Ordering::Release);
+    }
+}
+
+fn reloaded_config() -> Option<Arc<Config>> {
+    RELOADED_CONFIG.lock().ok()?.clone()
+}
+
+// Per-process connection history used by the rate and beaconing rules. Entries older
+// than the window are dropped, and the list is capped (max_network_history) so a
+// busy process stays bounded.
+const CONNECTION_HISTORY_SECS: i64 = 300;
+// Repeated connections to one target only count as beaconing inside this window
+const BEACON_WINDOW_SECS: i64 = 120;
+
+// How long a DNS answer is trusted for attributing a later connection to a domain
+const DNS_RESOLUTION_TTL_SECS: i64 = 600;
+// A connection's last reported send total is kept this long after its last send; one
+// idle for longer counts its whole total again if it resumes
+const EXFIL_CONNECTION_IDLE_SECS: i64 = 600;
 
 #[derive(Clone, Debug)]
 struct ProcessContext {
@@ -45,8 +290,16 @@ struct ProcessContext {
     process_age_at_first_network: Option<Duration>,
     suspicion_score: u32,
     alert_reasons: Vec<String>,
+    /// Extra `Key = Value` lines carried into the alert details.
+    evidence: Vec<String>,
     alerted: bool,
     webhook_alerted: bool,
+    /// Shared by every alert raised for this process, so an escalation groups
+    /// with the alert it escalates. Assigned when the first alert fires.
+    incident_id: Option<String>,
+    /// Set on ProcessEnd; the context lingers for the exit grace period so late
+    /// network events can still be correlated with it.
+    exited_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Clone, Debug)]
@@ -56,10 +309,83 @@ struct NetworkConnection {
     remote_addr: String,
     remote_port: u16,
     remote_domain: Option<String>,
+    local_port: u16,
+    is_listening: bool,
     is_external: bool,
     data_size: Option<u64>,
 }
 
+/// An enabled `alert_rules` entry with an expression, compiled once at startup.
+struct ExpressionRule {
+    name: String,
+    description: String,
+    severity: crate::events::alert::AlertSeverity,
+    cooldown: Duration,
+    expression: Expression,
+    // Tests a network field, so it is evaluated on connections rather than process starts
+    on_network: bool,
+}
+
+/// `dga_detection` with the window as a duration and the allowlist lowercased.
+struct DgaSettings {
+    min_score: u8,
+    min_domains: usize,
+    max_unique_subdomains: usize,
+    window: chrono::Duration,
+    allowlist: Vec<String>,
+}
+
+/// A generated-looking domain one process looked up.
+struct GeneratedDomain {
+    seen_at: chrono::DateTime<chrono::Utc>,
+    domain: String,
+    score: u8,
+    entropy: f64,
+    failed: bool,
+}
+
+/// `ransomware_detection` with the window as a duration and the lists lowercased.
+struct RansomwareSettings {
+    min_files: usize,
+    window: chrono::Duration,
+    ransom_extensions: Vec<String>,
+    exempt_processes: HashSet<String>,
+    suspend_process: bool,
+}
+
+/// Recent document overwrites, new files and renames by one process.
+#[derive(Default)]
+struct DocumentActivity {
+    changes: VecDeque<(chrono::DateTime<chrono::Utc>, String, String)>, // (seen at, path, change)
+    // Distinct files at the last assessment, so disk reads happen in steps
+    assessed_at: usize,
+}
+
+/// `exfil_detection` with the limit in bytes, the window as a duration and the
+/// allowlist lowercased.
+struct ExfilSettings {
+    max_sent_bytes: u64,
+    window: chrono::Duration,
+    allowlist: HashSet<String>,
+}
+
+/// Recent bytes one process sent to external hosts.
+#[derive(Default)]
+struct SentVolume {
+    sends: VecDeque<(chrono::DateTime<chrono::Utc>, u64)>, // (seen at, bytes added)
+    window_bytes: u64,
+    // (local port, remote address, remote port) → (send total last reported, when)
+    connections: HashMap<(u16, String, u16), (u64, chrono::DateTime<chrono::Utc>)>,
+}
+
+/// Recent lookups under one parent domain, with a count per distinct name so
+/// the unique total stays cheap as queries expire.
+#[derive(Default)]
+struct SubdomainWindow {
+    queries: VecDeque<(chrono::DateTime<chrono::Utc>, String)>,
+    unique: HashMap<String, usize>,
+}
+
 struct AlertState {
     recent_alerts: HashMap<String, chrono::DateTime<chrono::Utc>>,
     verified_processes: HashSet<u32>,
@@ -68,8 +394,85 @@ struct AlertState {
     known_malicious_ips: HashSet<String>,
     known_malicious_domains: HashSet<String>,
     known_malicious_ports: HashSet<u16>,
+    destination_allowlist: Vec<AllowlistEntry>,
+    ip_reputation: ReputationList,
+    suspicious_pipe_patterns: Vec<regex::Regex>,
+    protected_services: Vec<String>,
+    server_processes: HashSet<String>, // Lowercased `server_processes` names
+    expression_rules: Vec<ExpressionRule>,
+    exit_grace: chrono::Duration,
     dns_webhook_observations: HashMap<String, (&'static str, chrono::DateTime<chrono::Utc>, u32)>, // Recorded webhook domain DNS queries for correlation.
+    dns_resolutions: HashMap<u32, HashMap<String, (String, chrono::DateTime<chrono::Utc>)>>, // pid → resolved IP → (domain, when)
+    dns_traced: bool, // Without DNS tracing every connection looks unresolved
+    trusted_processes: HashSet<String>, // Lowercased `trusted_processes` names
+    image_signatures: HashMap<u32, Option<bool>>, // pid → Authenticode result for its image, checked once
     recent_events: VecDeque<(chrono::DateTime<chrono::Utc>, u32, String, String)>,
+    removable_arrivals: HashMap<char, (crate::events::RemovableMediaEvent, chrono::DateTime<chrono::Utc>)>, // drive letter → mount
+    removable_launch_window: chrono::Duration,
+    hosts_writers: VecDeque<(chrono::DateTime<chrono::Utc>, u32, String)>, // recent write opens of the hosts file
+    hosts_writer_window: chrono::Duration,
+    child_spawns: HashMap<u32, VecDeque<(chrono::DateTime<chrono::Utc>, String)>>, // parent pid → recent child starts
+    spawn_storm: Option<(usize, chrono::Duration)>, // more than this many children within the window
+    spawn_storm_allowlist: HashSet<String>, // Lowercased parent names never flagged
+    dga: Option<DgaSettings>,
+    dga_domains: HashMap<u32, VecDeque<GeneratedDomain>>, // pid → recent generated-looking lookups
+    dns_subdomains: HashMap<(u32, String), SubdomainWindow>, // (pid, parent domain) → recent lookups
+    ransomware: Option<RansomwareSettings>,
+    document_activity: HashMap<u32, DocumentActivity>,
+    exfil: Option<ExfilSettings>,
+    sent_volume: HashMap<u32, SentVolume>, // pid → recent sends to external hosts
+    max_process_history: usize,
+    max_network_history: usize,
+    max_contexts: usize,
+    max_spawn_chain_depth: usize, // Ancestors walked for a spawn chain before it is cut off
+    clock: Arc<dyn Clock>,
+}
+
+impl AlertState {
+    /// Empty state on `clock`; `apply_engine_config` fills in the settings.
+    fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+    recent_alerts: HashMap::new(),
+    verified_processes: HashSet::new(),
+    process_start_times: HashMap::new(),
+    evaluated_processes: HashSet::new(),
+    known_malicious_ips: HashSet::new(),
+    known_malicious_domains: HashSet::new(),
+    known_malicious_ports: HashSet::new(),
+    destination_allowlist: Vec::new(),
+    ip_reputation: ReputationList::default(),
+    suspicious_pipe_patterns: Vec::new(),
+    protected_services: Vec::new(),
+    server_processes: HashSet::new(),
+    expression_rules: Vec::new(),
+    exit_grace: chrono::Duration::zero(),
+    recent_events: VecDeque::new(),
+    removable_arrivals: HashMap::new(),
+    removable_launch_window: chrono::Duration::zero(),
+    hosts_writers: VecDeque::new(),
+    hosts_writer_window: chrono::Duration::zero(),
+    child_spawns: HashMap::new(),
+    spawn_storm: None,
+    spawn_storm_allowlist: HashSet::new(),
+    dga: None,
+    dga_domains: HashMap::new(),
+    dns_subdomains: HashMap::new(),
+    ransomware: None,
+    document_activity: HashMap::new(),
+    exfil: None,
+    sent_volume: HashMap::new(),
+    max_process_history: 0,
+    max_network_history: 0,
+    max_contexts: 0,
+    max_spawn_chain_depth: 0,
+    dns_webhook_observations: HashMap::new(),
+    dns_resolutions: HashMap::new(),
+    dns_traced: false,
+    trusted_processes: HashSet::new(),
+    image_signatures: HashMap::new(),
+    clock,
+        }
+    }
 }
 
 pub fn start_correlation_engine(
@@ -77,10 +480,11 @@ pub fn start_correlation_engine(
     network_rx: Receiver<BaseEvent>,
     alert_tx: Sender<Alert>,
     config: Arc<Config>,
+    clock: Arc<dyn Clock>,
     shutdown: Arc<AtomicBool>,
 ) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
-        run_correlation_engine(process_rx, network_rx, alert_tx, config, shutdown);
+        run_correlation_engine(process_rx, network_rx, alert_tx, config, clock, shutdown);
     })
 }
 
@@ -89,23 +493,17 @@ pub fn run_correlation_engine(
     network_rx: Receiver<BaseEvent>,
     alert_tx: Sender<Alert>,
     config: Arc<Config>,
+    clock: Arc<dyn Clock>,
     shutdown: Arc<AtomicBool>,
 ) {
+    // The latest reload wins over the startup config, so a restarted engine keeps it
+    let config = reloaded_config().unwrap_or(config);
     let mut process_contexts: HashMap<u32, ProcessContext> = HashMap::new();
-    let mut alert_state = AlertState {
-        recent_alerts: HashMap::new(),
-        verified_processes: HashSet::new(),
-        process_start_times: HashMap::new(),
-        evaluated_processes: HashSet::new(),
-        known_malicious_ips: HashSet::new(),
-        known_malicious_domains: HashSet::new(),
-        known_malicious_ports: HashSet::new(),
-        recent_events: VecDeque::with_capacity(1000),
-        dns_webhook_observations: HashMap::new(),
-    };
-
-    // Load initial IOCs from config
-    load_initial_iocs(&mut alert_state, &config);
+    let mut alert_state = AlertState::new(clock);
+    apply_engine_config(&mut alert_state, &config);
+    let _ = ALERT_LOG_FORMAT.set(config.alert_log_format);
+    open_decision_log(config.decision_log.as_ref());
+    open_baseline(config.baseline.as_ref());
 
     while shutdown.load(Ordering::Relaxed) {
         crossbeam_channel::select! {
@@ -122,9 +520,18 @@ pub fn run_correlation_engine(
             recv(crossbeam_channel::after(Duration::from_millis(100))) -> _ => {
                 cleanup_old_contexts(&mut process_contexts, &mut alert_state);
                 check_temporal_correlations(&mut process_contexts, &mut alert_state, &alert_tx);
+                save_baseline(false);
             }
         }
+        answer_context_dumps(&process_contexts);
+        if CONFIG_RELOAD_PENDING.swap(false, Ordering::Acquire)
+            && let Some(config) = reloaded_config()
+        {
+            apply_engine_config(&mut alert_state, &config);
+            log::info!("🔄 Correlation engine is using the reloaded rules");
+        }
     }
+    save_baseline(true);
 }
 
 fn process_event(
@@ -133,19 +540,29 @@ fn process_event(
     alert_state: &mut AlertState,
     alert_tx: &Sender<Alert>,
 ) {
+    EVENTS_PROCESSED.fetch_add(1, Ordering::Relaxed);
+    alert_state.clock.observe(event.timestamp);
+
     // Store event for cross-correlation
     let (pid, process_name) = match &event.event_type {
         EventType::ProcessStart(process_event) => (process_event.pid, process_event.process_name.clone()),
         EventType::ProcessEnd(process_event) => (process_event.pid, process_event.process_name.clone()),
         EventType::NetworkConnection(network_event) => (network_event.pid, network_event.process_name.clone()),
+        EventType::DnsQuery(dns_event) => (dns_event.pid, dns_event.process_name.clone()),
+        EventType::FileAccess(file_event) => (file_event.pid, file_event.process_name.clone()),
         _ => (0, String::from("Unknown")),
     };
-    alert_state.recent_events.push_back((
-        chrono::Utc::now(),
-        pid,
-        process_name,
-        format!("{:?}", event.event_type)
-    ));
+    if alert_state.max_process_history > 0 {
+        if alert_state.recent_events.len() >= alert_state.max_process_history {
+            alert_state.recent_events.pop_front();
+        }
+        alert_state.recent_events.push_back((
+            alert_state.clock.now(),
+            pid,
+            process_name,
+            format!("{:?}", event.event_type)
+        ));
+    }
 
     match &event.event_type {
         EventType::ProcessStart(process_event) => {
@@ -163,158 +580,1588 @@ fn process_event(
                     if !resolved_ip.is_empty() && resolved_ip != "0.0.0.0" {
                         alert_state.dns_webhook_observations.insert(
                             resolved_ip.clone(),
-                            (service, chrono::Utc::now(), network_event.pid),
+                            (service, alert_state.clock.now(), network_event.pid),
                         );
                     }
                 }
             }
             handle_network_connection(network_event, process_contexts, alert_state, alert_tx);
         }
+        EventType::DnsQuery(dns_event) => {
+            handle_dns_query(dns_event, process_contexts, alert_state, alert_tx);
+        }
+        EventType::RegistryChange(registry_event) => {
+            handle_registry_change(registry_event, alert_state, alert_tx);
+        }
+        EventType::NamedPipeCreated(pipe_event) => {
+            handle_named_pipe(pipe_event, alert_state, alert_tx);
+        }
+        EventType::FileAccess(file_event) => match file_event.watch {
+            FileWatch::CredentialStore => handle_credential_file_access(file_event, process_contexts, alert_state, alert_tx),
+            FileWatch::Document => handle_document_change(file_event, process_contexts, alert_state, alert_tx),
+            FileWatch::HostsFile => {
+                // Editors reopen the file on every save; a handful of writers is plenty
+                if alert_state.hosts_writers.len() >= MAX_HOSTS_WRITERS {
+                    alert_state.hosts_writers.pop_front();
+                }
+                let now = alert_state.clock.now();
+                alert_state.hosts_writers.push_back((now, file_event.pid, file_event.process_name.clone()));
+            }
+        },
+        EventType::HostsFileChanged(hosts_event) => {
+            handle_hosts_file_change(hosts_event, process_contexts, alert_state, alert_tx);
+        }
+        EventType::ScheduledTaskChanged(task_event) => {
+            handle_scheduled_task(task_event, alert_state, alert_tx);
+        }
+        EventType::RemovableMediaArrived(media_event) => {
+            if let Some(letter) = drive_letter(&media_event.drive) {
+                let now = alert_state.clock.now();
+                alert_state.removable_arrivals.insert(letter, (media_event.clone(), now));
+            }
+        }
         _ => {}
     }
 }
 
-fn handle_process_start(
-    process_event: &crate::events::process::ProcessEvent,
-    process_contexts: &mut HashMap<u32, ProcessContext>,
+fn handle_dns_query(
+    dns_event: &crate::events::DnsEvent,
+    process_contexts: &HashMap<u32, ProcessContext>,
     alert_state: &mut AlertState,
     alert_tx: &Sender<Alert>,
 ) {
-    let process_name = &process_event.process_name;
-    let pid = process_event.pid;
-    let parent_pid = process_event.parent_pid;
+    // Scored before the status check: most generated domains never resolve
+    check_generated_domains(dns_event, process_contexts, alert_state, alert_tx);
 
-    // ETW sometimes fires the process-start event twice for the same PID.
-    // Guard first to avoid any work or double-scoring.
-    if process_contexts.contains_key(&pid) {
+    if dns_event.query_status != 0 {
         return;
     }
 
-    // Get command line immediately — it may disappear when the process exits.
-    let command_line = get_command_line_cached(pid).unwrap_or_default();
-    let (parent_name, _) = get_parent_process_info(parent_pid);
-    let cmd_analysis = analyze_command_line(&command_line);
+    let now = alert_state.clock.now();
+    let webhook_service = identify_webhook_service_by_domain(&dns_event.query_name);
+    let resolutions = alert_state.dns_resolutions.entry(dns_event.pid).or_default();
 
-    process_contexts.insert(pid, ProcessContext {
-        start_time: chrono::Utc::now(),
-        process_name: process_name.clone(),
-        pid,
-        parent_pid,
-        parent_name,
-        command_line: command_line.clone(),
-        first_network_event_time: None,
-        network_connections: Vec::new(),
-        last_alert_time: None,
-        is_known_good: is_known_good_process(process_name, &command_line),
-        is_scripting_engine: is_scripting_engine(process_name, &command_line),
-        suspicious_flags: cmd_analysis.flags.clone(),
-        process_age_at_first_network: None,
-        suspicion_score: 0,
-        alert_reasons: Vec::new(),
-        alerted: false,
-        webhook_alerted: false,
-    });
-    alert_state.process_start_times.insert(pid, chrono::Utc::now());
+    for address in &dns_event.resolved_addresses {
+        resolutions.insert(address.clone(), (dns_event.query_name.clone(), now));
 
-    let context = process_contexts.get_mut(&pid).unwrap();
+        if let Some(service) = webhook_service {
+            alert_state.dns_webhook_observations.insert(
+                address.clone(),
+                (service, now, dns_event.pid),
+            );
+        }
+    }
+}
 
-    // For each suspicious command-line flag found, add 1 to the suspicion score.
-    for _flag in &cmd_analysis.flags {
-        context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG;
-        let flags_joined = cmd_analysis.flags.join(", ");
-        context.alert_reasons.push(format!("Suspicious command flags: {}", flags_joined));
+/// Domain generation algorithms (T1568.002) and DNS tunneling (T1071.004). A
+/// process looking up `min_domains` names whose registrable label scores at least
+/// `min_score` is Medium; more than `max_unique_subdomains` distinct names under
+/// one parent domain within the window is High. Allowlisted domains, browsers,
+/// system processes and `trusted_processes` are exempt.
+fn check_generated_domains(
+    dns_event: &crate::events::DnsEvent,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let Some(settings) = alert_state.dga.as_ref() else {
+        return;
+    };
+    let domain = dns_event.query_name.trim_end_matches('.').to_lowercase();
+    if settings.allowlist.iter().any(|entry| domain == *entry || domain.ends_with(&format!(".{}", entry))) {
+        return;
+    }
+    let Some((_, parent)) = registrable_domain(&domain) else {
+        return;
+    };
+    let score = dga_score(&domain).filter(|score| score.score >= settings.min_score);
+    let is_subdomain = domain.len() > parent.len();
+    if score.is_none() && !is_subdomain {
+        return;
+    }
+    let (min_domains, max_unique_subdomains, window) =
+        (settings.min_domains, settings.max_unique_subdomains, settings.window);
+
+    let pid = dns_event.pid;
+    let process_name = &dns_event.process_name;
+    let exemption = if is_browser_related_process(pid, process_name) {
+        Some(Suppression::BrowserProcess)
+    } else if is_system_process(process_name) {
+        Some(Suppression::SystemProcess)
+    } else if alert_state.trusted_processes.contains(&process_name.to_lowercase()) {
+        Some(Suppression::TrustedProcess)
+    } else {
+        None
+    };
+    if let Some(exemption) = exemption {
+        if let Some(score) = &score {
+            let detail = format!("{} (score {})", domain, score.score);
+            record_suppressed("DGA domain queries", process_name, pid, exemption, &detail);
+        }
+        return;
     }
 
-    // Check for LOLBAS abuse patterns
-    if let Some(pattern) = identify_lolbas_abuse(process_name, &command_line) {
-        context.suspicion_score += WEIGHT_LOLBAS;
-        context.alert_reasons.push(format!("LOLBAS pattern: {}", pattern));
+    let now = alert_state.clock.now();
+    if let Some(score) = score {
+        let domains = alert_state.dga_domains.entry(pid).or_default();
+        if !domains.iter().any(|seen| seen.domain == domain) {
+            domains.push_back(GeneratedDomain {
+                seen_at: now,
+                domain: domain.clone(),
+                score: score.score,
+                entropy: score.entropy,
+                failed: dns_event.query_status != 0,
+            });
+        }
+        while domains.front().is_some_and(|seen| now - seen.seen_at > window) {
+            domains.pop_front();
+        }
+        if domains.len() >= min_domains {
+            raise_dga_alert(pid, process_name, process_contexts, alert_state, alert_tx);
+        }
     }
 
-    // Scans the contents of a script file referenced in the command line.
-    if context.is_scripting_engine && !command_line.is_empty() {
-        let (matched_names, total_weight) = scan_script_file_for_apis(&command_line);
-        if !matched_names.is_empty() {
-            context.suspicion_score += total_weight;
-            context.alert_reasons.push(format!(
-                "Script scan found [\n    {}\n]",
-                matched_names.join(",\n    ")
-            ));
+    if is_subdomain && max_unique_subdomains > 0 {
+        let lookups = alert_state.dns_subdomains.entry((pid, parent.clone())).or_default();
+        *lookups.unique.entry(domain.clone()).or_default() += 1;
+        lookups.queries.push_back((now, domain));
+        // A tunnel keeps going long after the alert; cap what one parent can hold
+        while lookups.queries.front().is_some_and(|(seen_at, _)| now - *seen_at > window)
+            || lookups.queries.len() > max_unique_subdomains * 4
+        {
+            let (_, expired) = lookups.queries.pop_front().unwrap();
+            if let Some(count) = lookups.unique.get_mut(&expired) {
+                *count -= 1;
+                if *count == 0 {
+                    lookups.unique.remove(&expired);
+                }
+            }
+        }
+        if lookups.unique.len() > max_unique_subdomains {
+            raise_dns_tunnel_alert(pid, process_name, &parent, process_contexts, alert_state, alert_tx);
         }
     }
 }
 
-fn handle_process_end(
-    process_event: &crate::events::process::ProcessEvent,
-    process_contexts: &mut HashMap<u32, ProcessContext>,
+fn raise_dga_alert(
+    pid: u32,
+    process_name: &str,
+    process_contexts: &HashMap<u32, ProcessContext>,
     alert_state: &mut AlertState,
     alert_tx: &Sender<Alert>,
 ) {
-    let exiting_pid = process_event.pid;
+    let (Some(settings), Some(domains)) = (alert_state.dga.as_ref(), alert_state.dga_domains.get(&pid)) else {
+        return;
+    };
+    let mut samples: Vec<&GeneratedDomain> = domains.iter().collect();
+    samples.sort_by_key(|seen| std::cmp::Reverse(seen.score));
+    let top = samples.first().map_or((0, 0.0), |seen| (seen.score, seen.entropy));
+    let failed = domains.iter().filter(|seen| seen.failed).count();
+    let mut details = vec![
+        format!("DGA Score = {} (threshold {}, entropy {:.2} bits)", top.0, settings.min_score, top.1),
+        format!(
+            "Generated Domains = {} in {}s (limit {})",
+            domains.len(), settings.window.num_seconds(), settings.min_domains
+        ),
+        format!("Failed Lookups = {} of {}", failed, domains.len()),
+    ];
+    details.extend(samples.iter().take(MAX_DGA_SAMPLES)
+        .map(|seen| format!("Sample Domain = {} (score {})", seen.domain, seen.score)));
+    let reasons = vec![format!(
+        "DGA-like domain queries (T1568.002): {} (PID {}) looked up {} generated-looking domains in {}s, e.g. {}",
+        process_name, pid, domains.len(), settings.window.num_seconds(),
+        samples.first().map_or("", |seen| seen.domain.as_str())
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("dga:{}", pid);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
 
-    let exit_time = chrono::Utc::now();
+    let context = process_contexts.get(&pid);
+    generate_alert(
+        crate::events::alert::AlertSeverity::Medium,
+        "DgaDomainQueries",
+        "A process looked up several algorithmically generated-looking domains",
+        process_name,
+        pid,
+        context.map_or(0, |context| context.parent_pid),
+        context.map_or("N/A", |context| context.parent_name.as_str()),
+        "N/A",
+        context.map_or("", |context| context.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_DGA + failed.min(2) as u32)),
+        SOURCE_ETW,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
 
-    // ── Evasion pattern: spawn-and-exit ─────────────────────────────────────
-    // The parent process is often still "Unknown" if it quickly exits because ETW name
-    // resolution races the exit event — so `is_scripting_engine` on the parent
-    // is unreliable. Instead we use TWO independent child-detection signals:
-    // Signal A – parent_pid match (works when name resolved in time)
-    // Signal B – time-window match: any scripting engine that started within 3 s of this exit (catches the unresolved-name race)
-    // Both paths require the exiting process to be short-lived (≤ 5 s) AND the
-    // candidate child to carry at least one evasion flag, to limit FPs.
+fn raise_dns_tunnel_alert(
+    pid: u32,
+    process_name: &str,
+    parent: &str,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let key = (pid, parent.to_string());
+    let (Some(settings), Some(lookups)) = (alert_state.dga.as_ref(), alert_state.dns_subdomains.get(&key)) else {
+        return;
+    };
+    let unique = lookups.unique.len();
+    let longest_label = lookups.unique.keys()
+        .flat_map(|name| name.split('.'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let mut details = vec![
+        format!("Parent Domain = {}", parent),
+        format!(
+            "Unique Subdomains = {} in {}s (limit {})",
+            unique, settings.window.num_seconds(), settings.max_unique_subdomains
+        ),
+        format!("Longest Label = {} characters", longest_label),
+    ];
+    let mut samples: Vec<&str> = Vec::new();
+    for (_, name) in lookups.queries.iter().rev() {
+        if samples.len() == MAX_DGA_SAMPLES {
+            break;
+        }
+        if !samples.contains(&name.as_str()) {
+            samples.push(name);
+        }
+    }
+    details.extend(samples.iter().map(|name| format!("Sample Query = {}", truncate_string(name, 120))));
+    let reasons = vec![format!(
+        "DNS tunneling (T1071.004): {} (PID {}) looked up {} unique subdomains of {} in {}s",
+        process_name, pid, unique, parent, settings.window.num_seconds()
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("dns_tunnel:{}:{}", pid, parent);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
 
-    let parent_lifetime = process_contexts
-        .get(&exiting_pid)
-        .map(|ctx| exit_time - ctx.start_time);
+    let context = process_contexts.get(&pid);
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "DnsTunneling",
+        "A process looked up an abnormal number of unique subdomains of one domain",
+        process_name,
+        pid,
+        context.map_or(0, |context| context.parent_pid),
+        context.map_or("N/A", |context| context.parent_name.as_str()),
+        "N/A",
+        context.map_or("", |context| context.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_DNS_TUNNEL)),
+        SOURCE_ETW,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
 
-    let is_short_lived = parent_lifetime
-        .map(|lt| lt <= chrono::Duration::seconds(5))
-        .unwrap_or(true); // unknown start time → treat conservatively as short-lived
+/// COM hijack (T1546.015): a per-user `InprocServer32` or `TreatAs` key that now
+/// loads a DLL from a user-writable path. High when it shadows a machine-wide CLSID,
+/// since that is what redirects existing COM clients; Medium for a new per-user CLSID.
+fn handle_registry_change(
+    registry_event: &crate::events::RegistryEvent,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    match registry_event.watch {
+        RegistryWatch::ComRegistration => handle_com_registration(registry_event, alert_state, alert_tx),
+        RegistryWatch::RunKey => handle_run_key_value(registry_event, alert_state, alert_tx),
+        RegistryWatch::DnsServer => handle_dns_server_change(registry_event, alert_state, alert_tx),
+    }
+}
 
-    if is_short_lived {
-        let parent_name = process_contexts
-            .get(&exiting_pid)
-            .map(|ctx| ctx.process_name.clone())
-            .unwrap_or_else(|| process_event.process_name.clone());
+/// DNS settings tampering (T1565.001, T1205): a static resolver set or changed on
+/// the TCP/IP stack or an interface, which routes every lookup through a server
+/// the attacker picks. Polling can't tell who wrote it.
+fn handle_dns_server_change(
+    registry_event: &crate::events::RegistryEvent,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let change = match registry_event.change {
+        crate::events::registry::RegistryChange::Created => "set",
+        crate::events::registry::RegistryChange::Modified => "changed",
+    };
+    let reasons = vec![format!(
+        "DNS server tampering (T1565.001, T1205): {} {} to {}",
+        registry_event.key_path, change, registry_event.value_data
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("dns_server:{}:{}", registry_event.key_path, registry_event.value_data);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
 
-        let lifetime_ms = parent_lifetime
-            .map(|lt| lt.num_milliseconds())
-            .unwrap_or(0);
+    let details = vec![
+        format!("Registry Key = {}", registry_event.key_path),
+        format!("Value Name = {}", registry_event.value_name),
+        format!("Name Servers = {}", registry_event.value_data),
+    ];
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "DnsServerTampering",
+        "Static DNS servers were set or changed",
+        "Registry",
+        0,
+        0,
+        "N/A",
+        "N/A",
+        "",
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_DNS_SERVER)),
+        SOURCE_REGISTRY_POLL,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
 
-        // Signal A: explicit parent_pid linkage
-        let children_by_parent: Vec<u32> = process_contexts
-            .iter()
-            .filter(|(pid, ctx)| **pid != exiting_pid && ctx.parent_pid == exiting_pid)
-            .map(|(pid, _)| *pid)
-            .collect();
+/// Hosts file tampering (T1565.001, T1205): entries added, repointed or removed,
+/// typically to sinkhole security and update domains or redirect a login page.
+/// Attributed to the processes that opened the file for writing since the last
+/// poll; a change made only by system processes or `trusted_processes` is exempt.
+fn handle_hosts_file_change(
+    hosts_event: &crate::events::HostsFileEvent,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let now = alert_state.clock.now();
+    let window = alert_state.hosts_writer_window;
+    let mut writers: Vec<(u32, String)> = Vec::new();
+    for (opened_at, pid, name) in alert_state.hosts_writers.drain(..) {
+        if now - opened_at <= window && !writers.iter().any(|(known, _)| *known == pid) {
+            writers.push((pid, name));
+        }
+    }
 
-        // Signal B: scripting engines that started within 3 s of this exit event
-        // and haven't been alerted yet — catches the unresolved-name race.
-        let children_by_time: Vec<u32> = process_contexts
-            .iter()
-            .filter(|(pid, ctx)| {
-                **pid != exiting_pid
-                    && ctx.is_scripting_engine
-                    && exit_time - ctx.start_time <= chrono::Duration::seconds(3)
-            })
-            .map(|(pid, _)| *pid)
-            .collect();
+    let changes: Vec<String> = hosts_event.changes.iter()
+        .map(|change| match (&change.old_address, &change.new_address) {
+            (None, Some(new)) => format!("added {} → {}", change.host, new),
+            (Some(old), Some(new)) => format!("changed {} {} → {}", change.host, old, new),
+            (Some(old), None) => format!("removed {} → {}", change.host, old),
+            (None, None) => change.host.clone(),
+        })
+        .collect();
+    let summary = truncate_string(&changes.join("; "), 300);
 
-        // Merge and deduplicate
-        let mut candidate_pids = children_by_parent;
-        for pid in children_by_time {
-            if !candidate_pids.contains(&pid) {
-                candidate_pids.push(pid);
-            }
-        }
+    let suspects: Vec<&(u32, String)> = writers.iter()
+        .filter(|(_, name)| {
+            !is_system_process(name) && !alert_state.trusted_processes.contains(&name.to_lowercase())
+        })
+        .collect();
+    if !writers.is_empty() && suspects.is_empty() {
+        let (pid, name) = &writers[0];
+        let exemption = if is_system_process(name) { Suppression::SystemProcess } else { Suppression::TrustedProcess };
+        record_suppressed("Hosts file tampering", name, *pid, exemption, &summary);
+        return;
+    }
+    let (pid, process_name) = suspects.first()
+        .map_or((0, "Unknown".to_string()), |(pid, name)| (*pid, name.clone()));
 
-        for child_pid in candidate_pids {
-            if let Some(child_ctx) = process_contexts.get_mut(&child_pid) {
+    let writer = match pid {
+        0 => "unknown process".to_string(),
+        _ => format!("{} (PID {})", process_name, pid),
+    };
+    let reasons = vec![format!("Hosts file tampering (T1565.001, T1205): {} edited {}", writer, summary)];
+    record_rule_hits(&reasons);
 
-                let already_scored_spawn = child_ctx.alert_reasons.iter()
-                    .any(|r| r.contains("spawn-and-exit evasion pattern"));
+    let alert_key = format!("hosts_file:{}", summary);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
 
-                if !already_scored_spawn {
+    let security_hosts: Vec<&str> = hosts_event.changes.iter()
+        .filter(|change| change.new_address.is_some())
+        .map(|change| change.host.as_str())
+        .filter(|host| SECURITY_DOMAIN_MARKERS.iter().any(|marker| host.contains(marker)))
+        .collect();
+    let mut details = vec![
+        format!("Hosts File = {}", hosts_event.path),
+        format!("Writer = {}", writer),
+    ];
+    details.extend(changes.iter().take(20).map(|change| format!("Entry = {}", change)));
+    if changes.len() > 20 {
+        details.push(format!("Entries Not Shown = {}", changes.len() - 20));
+    }
+    if !security_hosts.is_empty() {
+        details.push(format!("Security Domains Redirected = {}", security_hosts.join(", ")));
+    }
+    let context = process_contexts.get(&pid).filter(|_| pid != 0);
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "HostsFileTampering",
+        "Hosts file entries were added, changed or removed by a non-system process",
+        &process_name,
+        pid,
+        context.map_or(0, |context| context.parent_pid),
+        context.map_or("N/A", |context| context.parent_name.as_str()),
+        "N/A",
+        context.map_or("", |context| context.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_HOSTS_FILE + security_hosts.len().min(5) as u32)),
+        SOURCE_REGISTRY_POLL,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+fn handle_com_registration(
+    registry_event: &crate::events::RegistryEvent,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let Some(server) = registry_event.resolved_server.as_deref() else {
+        return;
+    };
+    if !is_user_writable_script_location(server) {
+        return;
+    }
+
+    let change = match registry_event.change {
+        crate::events::registry::RegistryChange::Created => "created",
+        crate::events::registry::RegistryChange::Modified => "modified",
+    };
+    let reasons = vec![format!(
+        "COM hijack persistence (T1546.015): {} {} → {}",
+        registry_event.key_path, change, server
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("com_hijack:{}:{}", registry_event.key_path, server.to_lowercase());
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
+
+    let (severity, score) = if registry_event.shadows_machine_key {
+        (crate::events::alert::AlertSeverity::High, WEIGHT_COM_HIJACK)
+    } else {
+        (crate::events::alert::AlertSeverity::Medium, SUSPICION_THRESHOLD)
+    };
+    let details = vec![
+        format!("Registry Key = {}", registry_event.key_path),
+        format!("Value Data = {}", registry_event.value_data),
+        format!("COM Server = {}", server),
+        format!("Shadows Machine CLSID = {}", registry_event.shadows_machine_key),
+    ];
+    generate_alert(
+        severity,
+        "ComHijackPersistence",
+        "Per-user COM registration points at a DLL in a user-writable path",
+        "Registry",
+        0,
+        0,
+        "N/A",
+        "N/A",
+        "",
+        &reasons,
+        Some(alert_confidence(&reasons, score)),
+        SOURCE_REGISTRY_POLL,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+/// Registry Run-key persistence (T1547.001): a new or changed autostart value that
+/// launches a script, an encoded command, something in a user-writable path or an
+/// unsigned executable. Installers writing signed programs stay quiet.
+fn handle_run_key_value(
+    registry_event: &crate::events::RegistryEvent,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let findings = identify_suspicious_autorun(&registry_event.value_data);
+    if findings.is_empty() {
+        return;
+    }
+
+    let change = match registry_event.change {
+        crate::events::registry::RegistryChange::Created => "created",
+        crate::events::registry::RegistryChange::Modified => "modified",
+    };
+    let reasons = vec![format!(
+        "Run key persistence (T1547.001): {} value '{}' {} → {} [{}]",
+        registry_event.key_path, registry_event.value_name, change,
+        truncate_string(&registry_event.value_data, 150), findings.join(", ")
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!(
+        "run_key:{}:{}:{}",
+        registry_event.key_path, registry_event.value_name, registry_event.value_data.to_lowercase()
+    );
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
+
+    let mut details = vec![
+        format!("Registry Key = {}", registry_event.key_path),
+        format!("Value Name = {}", registry_event.value_name),
+        format!("Value Data = {}", registry_event.value_data),
+        format!("Findings = {}", findings.join(", ")),
+    ];
+    if let Some(payload) = detect_encoded_payload(&registry_event.value_data) {
+        details.push(format!(
+            "Decoded Payload ({}) = {}",
+            payload.encoding,
+            truncate_string(&payload.decoded, 500)
+        ));
+    }
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "RunKeyPersistence",
+        "Run/RunOnce value launches a suspicious payload at logon",
+        "Registry",
+        0,
+        0,
+        "N/A",
+        "N/A",
+        &registry_event.value_data,
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_RUN_KEY_PAYLOAD + findings.len() as u32 - 1)),
+        SOURCE_REGISTRY_POLL,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+/// Counts a process's overwrites, new files and renames in user document folders
+/// and raises Critical ransomware when enough distinct files change within the
+/// window and they carry ransom extensions, one appended extension, or
+/// encrypted-looking content (T1486). Bulk changes without those are recorded
+/// as below threshold, so copies and photo imports stay quiet.
+fn handle_document_change(
+    file_event: &crate::events::FileAccessEvent,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let Some(settings) = alert_state.ransomware.as_ref() else {
+        return;
+    };
+    let (pid, process_name) = (file_event.pid, file_event.process_name.as_str());
+    if settings.exempt_processes.contains(&process_name.to_lowercase()) {
+        return;
+    }
+    let (min_files, window, suspend) = (settings.min_files, settings.window, settings.suspend_process);
+
+    let now = alert_state.clock.now();
+    let activity = alert_state.document_activity.entry(pid).or_default();
+    activity.changes.push_back((now, file_event.path.clone(), file_event.matched.clone()));
+    // A running encryptor keeps going long after the alert; cap what one process can hold
+    while activity.changes.front().is_some_and(|(seen_at, _, _)| now - *seen_at > window)
+        || activity.changes.len() > min_files * 8
+    {
+        activity.changes.pop_front();
+    }
+    let distinct: HashSet<&str> = activity.changes.iter().map(|(_, path, _)| path.as_str()).collect();
+    let file_count = distinct.len();
+    if file_count < activity.assessed_at {
+        activity.assessed_at = 0;
+    }
+    if file_count < min_files
+        || (activity.assessed_at > 0 && file_count < activity.assessed_at + RANSOMWARE_REASSESS_STEP)
+    {
+        return;
+    }
+    activity.assessed_at = file_count;
+
+    let paths: Vec<String> = distinct.into_iter().map(str::to_string).collect();
+    let signals = ransomware_signals(&paths, &settings.ransom_extensions);
+    if signals.is_empty() {
+        let detail = format!(
+            "{} document changes in {}s without ransom extensions or encrypted content",
+            file_count, window.num_seconds()
+        );
+        record_suppressed("Ransomware file encryption", process_name, pid, Suppression::BelowThreshold, &detail);
+        return;
+    }
+
+    let changes = &alert_state.document_activity[&pid].changes;
+    let count_of = |kind: &str| changes.iter().filter(|(_, _, change)| change == kind).count();
+    let breakdown = format!(
+        "Changes = {} overwritten, {} created, {} renamed",
+        count_of("overwrite"), count_of("create"), count_of("rename")
+    );
+    let reasons = vec![format!(
+        "Ransomware file encryption (T1486): {} (PID {}) changed {} user documents in {}s — {}",
+        process_name, pid, file_count, window.num_seconds(), signals.join(", ")
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("ransomware:{}", pid);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(600)) {
+        return;
+    }
+
+    let mut details = vec![
+        format!("File Count = {} in {}s (limit {})", file_count, window.num_seconds(), min_files),
+        breakdown,
+        format!("Signals = {}", signals.join(", ")),
+    ];
+    let recent: Vec<&str> = alert_state.document_activity[&pid].changes.iter().rev()
+        .map(|(_, path, _)| path.as_str())
+        .collect();
+    let mut samples: Vec<&str> = Vec::new();
+    for path in recent {
+        if samples.len() < MAX_RANSOMWARE_SAMPLES && !samples.contains(&path) {
+            samples.push(path);
+        }
+    }
+    details.extend(samples.iter().map(|path| format!("Sample Path = {}", path)));
+
+    if suspend {
+        if is_system_process(process_name) {
+            details.push("Response = not suspended (system process)".to_string());
+        } else {
+            match suspend_process(pid) {
+                Ok(threads) => {
+                    log::warn!("⏸️  Suspended {} (PID {}): {} threads", process_name, pid, threads);
+                    details.push(format!("Response = suspended {} threads", threads));
+                }
+                Err(e) => {
+                    log::error!("Failed to suspend {} (PID {}): {}", process_name, pid, e);
+                    details.push(format!("Response = suspend failed: {}", e));
+                }
+            }
+        }
+    }
+
+    let context = process_contexts.get(&pid);
+    generate_alert(
+        crate::events::alert::AlertSeverity::Critical,
+        "RansomwareFileEncryption",
+        "A process rewrote many user documents and left ransom extensions or encrypted content behind",
+        process_name,
+        pid,
+        context.map_or(0, |context| context.parent_pid),
+        context.map_or("N/A", |context| context.parent_name.as_str()),
+        "N/A",
+        context.map_or("", |context| context.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_RANSOMWARE)),
+        SOURCE_ETW,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+/// What makes a burst of document changes look like encryption: known ransom
+/// extensions, one unfamiliar extension appended to most documents, or near-random
+/// content in files that are low-entropy when intact. Empty when none apply.
+fn ransomware_signals(paths: &[String], ransom_extensions: &[String]) -> Vec<String> {
+    let mut signals = Vec::new();
+    let names: Vec<String> = paths.iter()
+        .map(|path| path.rsplit('\\').next().unwrap_or(path).to_lowercase())
+        .collect();
+
+    let mut ransom_hits: Vec<&str> = ransom_extensions.iter()
+        .filter(|ext| names.iter().any(|name| name.ends_with(ext.as_str())))
+        .map(String::as_str)
+        .collect();
+    ransom_hits.dedup();
+    if !ransom_hits.is_empty() {
+        signals.push(format!("ransom extension {}", ransom_hits.join(" ")));
+    }
+
+    // name.docx.x7k2q: the same unfamiliar extension after a document's own on most files.
+    // A source tree or package (app.min.js, index.d.ts) never has a document extension inside
+    let mut appended: HashMap<&str, usize> = HashMap::new();
+    for name in &names {
+        let mut parts = name.rsplitn(3, '.');
+        if let (Some(last), Some(inner), Some(stem)) = (parts.next(), parts.next(), parts.next())
+            && !stem.is_empty()
+            && DOCUMENT_EXTENSIONS.contains(&inner)
+            && !DOCUMENT_EXTENSIONS.contains(&last)
+            && !KNOWN_FILE_EXTENSIONS.contains(&last)
+            && !BENIGN_APPENDED_EXTENSIONS.contains(&last)
+            && !ransom_extensions.iter().any(|ext| ext.trim_start_matches('.') == last)
+        {
+            *appended.entry(last).or_default() += 1;
+        }
+    }
+    if let Some((ext, count)) = appended.into_iter().max_by_key(|(_, count)| *count)
+        && count * 2 >= names.len()
+    {
+        signals.push(format!("appended extension .{} on {} files", ext, count));
+    }
+
+    let mut highest: Option<(f64, &str)> = None;
+    let candidates = paths.iter().zip(&names)
+        .filter(|(_, name)| {
+            name.split('.').skip(1).any(|ext| LOW_ENTROPY_EXTENSIONS.contains(&ext))
+        })
+        .take(MAX_ENTROPY_SAMPLES);
+    for (path, name) in candidates {
+        let Some(bytes) = read_file_prefix(path, ENTROPY_SAMPLE_BYTES) else {
+            continue;
+        };
+        let entropy = byte_entropy(&bytes);
+        if bytes.len() >= 256 && entropy >= ENCRYPTED_ENTROPY && highest.is_none_or(|(top, _)| entropy > top) {
+            highest = Some((entropy, name.as_str()));
+        }
+    }
+    if let Some((entropy, name)) = highest {
+        signals.push(format!("encrypted content ({:.2} bits/byte in {})", entropy, name));
+    }
+    signals
+}
+
+/// Up to `limit` bytes from the start of a traced file. Kernel-File paths are NT
+/// device paths (`\Device\HarddiskVolume3\...`), opened through `\\?\GLOBALROOT`.
+fn read_file_prefix(path: &str, limit: usize) -> Option<Vec<u8>> {
+    use std::io::Read;
+
+    let openable = if path.starts_with("\\Device\\") {
+        format!("\\\\?\\GLOBALROOT{}", path)
+    } else {
+        path.to_string()
+    };
+    let file = std::fs::File::open(openable).ok()?;
+    let mut bytes = Vec::with_capacity(limit);
+    file.take(limit as u64).read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// Credentials from web browsers (T1555.003): an unsigned process other than a
+/// browser opening a browser password database or a Windows credential store.
+/// System processes and `trusted_processes` are exempt.
+fn handle_credential_file_access(
+    file_event: &crate::events::FileAccessEvent,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let pid = file_event.pid;
+    let process_name = &file_event.process_name;
+    let exemption = if is_browser_related_process(pid, process_name) {
+        Some(Suppression::BrowserProcess)
+    } else if is_system_process(process_name) {
+        Some(Suppression::SystemProcess)
+    } else if alert_state.trusted_processes.contains(&process_name.to_lowercase()) {
+        Some(Suppression::TrustedProcess)
+    } else {
+        None
+    };
+    if let Some(exemption) = exemption {
+        record_suppressed("Credential store access", process_name, pid, exemption, &file_event.path);
+        return;
+    }
+
+    let context = process_contexts.get(&pid);
+    let image_path = query_process_image_path(pid)
+        .or_else(|| context.map(|context| image_path_from_command_line(&context.command_line).to_string()))
+        .unwrap_or_default();
+    let unsigned = *alert_state.image_signatures.entry(pid)
+        .or_insert_with(|| is_signed_executable(&image_path)) == Some(false);
+    if !unsigned {
+        record_suppressed("Credential store access", process_name, pid, Suppression::SignedImage, &file_event.path);
+        return;
+    }
+
+    let reasons = vec![format!(
+        "Credential store access (T1555.003): unsigned {} (PID {}) opened {}",
+        process_name, pid, file_event.path
+    )];
+    record_rule_hits(&reasons);
+
+    // One alert per process and store, however many files of it are read
+    let alert_key = format!("credential_store:{}:{}", pid, file_event.matched);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
+
+    let details = vec![
+        format!("File Path = {}", file_event.path),
+        format!("Credential Store = {}", file_event.matched),
+        format!("Image Path = {}", image_path),
+    ];
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "CredentialStoreAccess",
+        "Unsigned non-browser process opened a browser or Windows credential store",
+        process_name,
+        pid,
+        context.map_or(0, |context| context.parent_pid),
+        context.map_or("N/A", |context| context.parent_name.as_str()),
+        "N/A",
+        context.map_or("", |context| context.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_CREDENTIAL_STORE)),
+        SOURCE_ETW,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+/// C2 named pipe (T1071): a new pipe whose name matches a configured framework
+/// default such as Cobalt Strike's `msagent_##` or `postex_####`.
+fn handle_named_pipe(
+    pipe_event: &crate::events::NamedPipeEvent,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let Some(pattern) = alert_state.suspicious_pipe_patterns.iter()
+        .find(|pattern| pattern.is_match(&pipe_event.pipe_name))
+    else {
+        return;
+    };
+
+    let pipe_path = format!("\\\\.\\pipe\\{}", pipe_event.pipe_name);
+    let reasons = vec![format!(
+        "C2 named pipe (T1071): {} matches '{}'",
+        pipe_path,
+        pattern.as_str().trim_start_matches("(?i)")
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("c2_pipe:{}", pipe_event.pipe_name.to_lowercase());
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+        return;
+    }
+
+    let details = vec![
+        format!("Pipe = {}", pipe_path),
+        "Creator = unknown (found by namespace enumeration)".to_string(),
+    ];
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "SuspiciousNamedPipe",
+        "Named pipe matching a C2 framework naming pattern was created",
+        "Named Pipe",
+        0,
+        0,
+        "N/A",
+        "N/A",
+        "",
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_C2_PIPE)),
+        SOURCE_PIPE_POLL,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+/// Scheduled-task persistence (T1053.005) written straight into the tasks folder:
+/// a new or rewritten task whose action runs a script, an encoded command, a
+/// payload from a user-writable path, an unsigned executable or a proxy binary.
+fn handle_scheduled_task(
+    task_event: &crate::events::ScheduledTaskEvent,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let change = match task_event.change {
+        crate::events::task::TaskFileChange::Created => "created",
+        crate::events::task::TaskFileChange::Modified => "modified",
+    };
+    for action in &task_event.actions {
+        // Quote the image so a path with spaces still parses as one token
+        let command = if action.command.contains(' ') && !action.command.starts_with('"') {
+            format!("\"{}\"", action.command)
+        } else {
+            action.command.clone()
+        };
+        let command_line = format!("{} {}", command, action.arguments).trim_end().to_string();
+
+        let mut findings = identify_suspicious_autorun(&command_line);
+        let image_name = image_path_from_command_line(&command_line)
+            .rsplit(['\\', '/'])
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        if let Some(proxy) = identify_signed_binary_proxy(&image_name, &command_line)
+            .or_else(|| identify_dotnet_lolbas(&image_name, &command_line))
+        {
+            findings.push(proxy.pattern);
+        }
+        if findings.is_empty() {
+            continue;
+        }
+
+        let reasons = vec![format!(
+            "Scheduled task persistence (T1053.005): {} {} → {} [{}]",
+            task_event.task_name, change, truncate_string(&command_line, 150), findings.join(", ")
+        )];
+        record_rule_hits(&reasons);
+
+        let alert_key = format!("scheduled_task:{}:{}", task_event.task_name, command_line.to_lowercase());
+        if !should_alert(&alert_key, alert_state, Duration::from_secs(3600)) {
+            continue;
+        }
+
+        let mut details = vec![
+            format!("Task Name = {}", task_event.task_name),
+            format!("Task File = {}", task_event.file_path),
+            format!("Legacy at Job = {}", task_event.legacy_job),
+            format!("Command = {}", action.command),
+            format!("Arguments = {}", action.arguments),
+            format!("Findings = {}", findings.join(", ")),
+        ];
+        if let Some(payload) = detect_encoded_payload(&command_line) {
+            details.push(format!(
+                "Decoded Payload ({}) = {}",
+                payload.encoding,
+                truncate_string(&payload.decoded, 500)
+            ));
+        }
+        generate_alert(
+            crate::events::alert::AlertSeverity::High,
+            "ScheduledTaskPersistence",
+            "Scheduled task file launches a suspicious payload",
+            "Task Scheduler",
+            0,
+            0,
+            "N/A",
+            "N/A",
+            &command_line,
+            &reasons,
+            Some(alert_confidence(&reasons, WEIGHT_TASK_PAYLOAD)),
+            SOURCE_TASK_POLL,
+            None,
+            false,
+            alert_tx,
+            details,
+        );
+    }
+}
+
+/// Domain this process most recently resolved to `remote_addr`, if still fresh.
+fn resolved_domain_for(alert_state: &AlertState, pid: u32, remote_addr: &str) -> Option<String> {
+    let (domain, resolved_at) = alert_state.dns_resolutions.get(&pid)?.get(remote_addr)?;
+    if alert_state.clock.now() - *resolved_at < chrono::Duration::seconds(DNS_RESOLUTION_TTL_SECS) {
+        Some(domain.clone())
+    } else {
+        None
+    }
+}
+
+fn handle_process_start(
+    process_event: &crate::events::process::ProcessEvent,
+    process_contexts: &mut HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let process_name = &process_event.process_name;
+    let pid = process_event.pid;
+    let parent_pid = process_event.parent_pid;
+
+    // ETW sometimes fires the process-start event twice for the same PID.
+    // Guard first to avoid any work or double-scoring. A context that is only
+    // lingering after exit belongs to an earlier process that reused the PID.
+    match process_contexts.get(&pid) {
+        Some(existing) if existing.exited_at.is_some() => forget_process(pid, process_contexts, alert_state),
+        Some(_) => return,
+        None => {}
+    }
+
+    // Get command line immediately — it may disappear when the process exits.
+    let command_line = get_command_line_cached(pid).unwrap_or_default();
+    let (parent_name, live_parent_command_line) = get_parent_process_info(parent_pid);
+    // Prefer the command line captured when the parent started; it outlives the parent
+    let parent_command_line = process_contexts.get(&parent_pid)
+        .map(|parent| parent.command_line.clone())
+        .filter(|cmdline| !cmdline.is_empty())
+        .unwrap_or(live_parent_command_line);
+    let cmd_analysis = analyze_command_line(&command_line);
+    if let Some(security) = &process_event.security
+        && let Ok(mut known) = PROCESS_SECURITY.lock()
+    {
+        known.insert(pid, security.clone());
+    }
+    // Live starts (stamped with their ETW create time) record it from the ETW callback;
+    // replayed ones carry it on the event
+    if process_event.create_time.is_none()
+        && let Some(hash) = &process_event.sha256
+    {
+        record_image_hash(pid, hash.clone());
+    }
+
+    process_contexts.insert(pid, ProcessContext {
+        start_time: alert_state.clock.now(),
+        process_name: process_name.clone(),
+        pid,
+        parent_pid,
+        parent_name,
+        command_line: command_line.clone(),
+        first_network_event_time: None,
+        network_connections: Vec::new(),
+        last_alert_time: None,
+        is_known_good: is_known_good_process(process_name, &command_line),
+        is_scripting_engine: is_scripting_engine(process_name, &command_line),
+        suspicious_flags: cmd_analysis.flags.clone(),
+        process_age_at_first_network: None,
+        suspicion_score: 0,
+        alert_reasons: Vec::new(),
+        evidence: Vec::new(),
+        alerted: false,
+        webhook_alerted: false,
+        incident_id: None,
+        exited_at: None,
+    });
+    alert_state.process_start_times.insert(pid, alert_state.clock.now());
+
+    check_spawn_storm(pid, process_contexts, alert_state, alert_tx);
+    check_expression_rules(&process_contexts[&pid], Some(&parent_command_line), None, alert_state, alert_tx);
+
+    // The whole ancestry goes into the evidence, so it is walked before the context is borrowed
+    let server_shell = identify_server_process_shell(&process_contexts[&pid].parent_name, process_name, &alert_state.server_processes)
+        .map(|kind| (kind, describe_spawn_chain(pid, process_contexts, alert_state.max_spawn_chain_depth)));
+    // Macros often go through cmd.exe or a script host, so every ancestor counts, not just the parent
+    let ancestry = process_ancestry(pid, process_contexts, alert_state.max_spawn_chain_depth);
+    let ancestor_names: Vec<String> = ancestry.iter().map(|(_, name)| name.clone()).collect();
+    let office_shell = identify_office_ancestor_shell(&ancestor_names, process_name).map(|(app, generation)| {
+        (app, &ancestry[..generation], describe_spawn_chain(pid, process_contexts, alert_state.max_spawn_chain_depth))
+    });
+
+    let context = process_contexts.get_mut(&pid).unwrap();
+
+    // For each suspicious command-line flag found, add 1 to the suspicion score.
+    for _flag in &cmd_analysis.flags {
+        context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG;
+        let flags_joined = cmd_analysis.flags.join(", ");
+        context.alert_reasons.push(format!("Suspicious command flags: {}", flags_joined));
+    }
+
+    // Check for LOLBAS abuse patterns
+    if let Some(pattern) = identify_lolbas_abuse(process_name, &command_line) {
+        context.suspicion_score += WEIGHT_LOLBAS;
+        context.alert_reasons.push(format!("LOLBAS pattern: {}", pattern));
+    }
+
+    // Signed binaries (rundll32, regsvr32, mshta, ...) proxying attacker code
+    if let Some(proxy) = identify_signed_binary_proxy(process_name, &command_line) {
+        context.suspicion_score += if proxy.remote { WEIGHT_PROXY_EXECUTION_REMOTE } else { WEIGHT_PROXY_EXECUTION };
+        if let Some(target) = &proxy.target {
+            context.evidence.push(format!("Proxy Target = {}", target));
+        }
+        context.alert_reasons.push(format!(
+            "Signed binary proxy execution ({}): {} — {}",
+            proxy.technique,
+            proxy.pattern,
+            truncate_string(&command_line, 150)
+        ));
+    }
+
+    // MSBuild inline tasks, InstallUtil/RegAsm/RegSvcs compiling or loading untrusted .NET code
+    if let Some(lolbas) = identify_dotnet_lolbas(process_name, &command_line) {
+        context.suspicion_score += WEIGHT_DOTNET_LOLBAS;
+        if let Some(target) = &lolbas.target {
+            let kind = if process_name.eq_ignore_ascii_case("msbuild.exe") { "Project" } else { "Assembly" };
+            context.evidence.push(format!("Target {} = {}", kind, target));
+        }
+        context.alert_reasons.push(format!(
+            ".NET developer utility code execution ({}): {} — {}",
+            lolbas.technique,
+            lolbas.pattern,
+            truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Script text on the mshta command line, or wscript/cscript forced onto another engine
+    if let Some(inline) = identify_inline_script(process_name, &command_line) {
+        let mut indicators: Vec<String> = inline.indicators.iter().map(|label| label.to_string()).collect();
+        if let Some(body) = inline.body.as_deref() {
+            if process_name.eq_ignore_ascii_case("mshta.exe") {
+                context.evidence.push(format!("Inline Script = {}", truncate_string(body, 300)));
+                indicators.extend(analyze_command_line(body).flags);
+                indicators.extend(scan_script_content_for_apis(body).0);
+            } else {
+                context.evidence.push(format!("Script File = {}", body));
+            }
+        }
+        for host in url_hosts(&command_line) {
+            if let Some(service) = identify_webhook_service_by_domain(&host) {
+                indicators.push(format!("{} webhook", service));
+            }
+        }
+        indicators.dedup();
+        if !indicators.is_empty() {
+            context.evidence.push(format!("Script Indicators = {}", indicators.join(", ")));
+        }
+        context.suspicion_score += WEIGHT_INLINE_SCRIPT;
+        context.alert_reasons.push(format!(
+            "Inline script execution ({}): {}{} — {}",
+            inline.technique,
+            inline.pattern,
+            if indicators.is_empty() { String::new() } else { format!(" [{}]", indicators.join(", ")) },
+            truncate_string(&command_line, 150)
+        ));
+    }
+
+    if let Some(stream) = identify_ads_reference(&command_line) {
+        context.suspicion_score += WEIGHT_ADS_EXECUTION;
+        context.evidence.push(format!("ADS Stream = {}", stream));
+        context.alert_reasons.push(format!(
+            "Alternate data stream execution (T1564.004): {} — {}",
+            stream,
+            truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Dangerous privileges already enabled in an unsigned, non-service process's token.
+    // The signature check only runs once a privilege is found.
+    if !context.is_known_good
+        && let Some(token) = inspect_process_token(pid)
+        && !token.service_account
+        && !token.enabled_privileges.is_empty()
+        && let Some(image_path) = token.image_path
+        && is_signed_executable(&image_path) == Some(false)
+    {
+        let system_level = token.enabled_privileges.iter()
+            .any(|privilege| matches!(*privilege, "SeDebugPrivilege" | "SeTcbPrivilege"));
+        context.suspicion_score += if system_level { WEIGHT_TOKEN_PRIVILEGE_SYSTEM } else { WEIGHT_TOKEN_PRIVILEGE };
+        context.evidence.push(format!("Enabled Privileges = {}", token.enabled_privileges.join(", ")));
+        context.evidence.push(format!("Image Path = {}", image_path));
+        context.alert_reasons.push(format!(
+            "Dangerous token privilege enabled (T1134): {} in unsigned {}",
+            token.enabled_privileges.join(", "), process_name
+        ));
+    }
+
+    // In-memory loading/injection patterns, from the command line, its decoded payload
+    // and the script it runs; reported once below
+    let mut reflective_findings = identify_reflective_loading(&command_line);
+    // Raw-socket / ICMP channels, gathered from the same three places
+    let mut raw_socket_findings = identify_raw_socket_tunnel(&command_line);
+
+    // Encoded/compressed payloads: decode and re-scan regardless of the host process
+    if !context.is_known_good
+        && let Some(payload) = detect_encoded_payload(&command_line)
+    {
+        reflective_findings.extend(identify_reflective_loading(&payload.decoded));
+        raw_socket_findings.extend(identify_raw_socket_tunnel(&payload.decoded));
+        let mut findings = analyze_command_line(&payload.decoded).flags;
+        let (api_names, api_weight) = scan_script_content_for_apis(&payload.decoded);
+        context.suspicion_score += WEIGHT_ENCODED_PAYLOAD
+            + WEIGHT_SUSPICIOUS_FLAG * findings.len() as u32
+            + api_weight;
+        findings.extend(api_names);
+        context.alert_reasons.push(format!(
+            "Encoded payload (T1027/T1140): {} in {} — decoded: {}{}",
+            payload.encoding,
+            process_name,
+            truncate_string(&payload.decoded, 150),
+            if findings.is_empty() { String::new() } else { format!(" — findings: {}", findings.join(", ")) }
+        ));
+    }
+
+    // BITS downloads and notify-command persistence, including cmdlets inside an encoded command
+    let bits_abuse = identify_bits_abuse(process_name, &command_line).or_else(|| {
+        detect_encoded_payload(&command_line)
+            .and_then(|payload| identify_bits_abuse(process_name, &payload.decoded))
+    });
+    if let Some(bits) = bits_abuse {
+        let temp_destination = bits.writes_to_user_writable_path();
+        context.suspicion_score += if bits.url.is_some() || bits.notify_command.is_some() {
+            WEIGHT_BITS_JOB_PAYLOAD
+        } else {
+            WEIGHT_BITS_JOB
+        };
+        if temp_destination {
+            context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG;
+        }
+        if let Some(url) = &bits.url {
+            context.evidence.push(format!("BITS URL = {}", url));
+        }
+        if let Some(destination) = &bits.destination {
+            context.evidence.push(format!("BITS Destination = {}", destination));
+        }
+        if let Some(notify_command) = &bits.notify_command {
+            context.evidence.push(format!("BITS Notify Command = {}", notify_command));
+        }
+        context.alert_reasons.push(format!(
+            "BITS job abuse (T1197): {}{} — {}",
+            bits.pattern,
+            if temp_destination { " into a user-writable path" } else { "" },
+            truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Shadow-copy / backup deletion is one of the last steps before encryption
+    if let Some(pattern) = identify_recovery_inhibition(&command_line) {
+        context.suspicion_score += WEIGHT_INHIBIT_RECOVERY;
+        context.alert_reasons.push(format!(
+            "Inhibit system recovery (T1490): {} — {}",
+            pattern, truncate_string(&command_line, 150)
+        ));
+    }
+
+    // PowerShell v2 has no AMSI or script-block logging
+    if let Some(pattern) = identify_powershell_downgrade(process_name, &command_line) {
+        context.suspicion_score += WEIGHT_POWERSHELL_DOWNGRADE;
+        if pattern.contains("AMSI") {
+            context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG * 3;
+        }
+        context.alert_reasons.push(format!(
+            "Defense evasion via downgrade (T1059.001/T1562.001): {} — {}",
+            pattern, truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Turning off the firewall, AV/EDR services or boot integrity checks ahead of the payload
+    if let Some(detail) = identify_security_service_tamper(&command_line, &alert_state.protected_services) {
+        context.suspicion_score += WEIGHT_SECURITY_TAMPER;
+        context.alert_reasons.push(format!(
+            "Impair defenses (T1562): {} — {}",
+            detail, truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Script hosts opened from a browser/mail/archive or fed a downloaded script
+    if let Some(pattern) = identify_script_host_dropper(process_name, &context.parent_name, &command_line) {
+        context.suspicion_score += WEIGHT_SCRIPT_DROPPER;
+        context.alert_reasons.push(format!(
+            "Initial-access script (T1059.005/T1218.005): {} — parent {} — {}",
+            pattern, context.parent_name, truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Images launched straight from a network share or a removable/secondary drive
+    if !context.is_known_good {
+        let image = image_path_from_command_line(&command_line);
+        let location = if is_unc_path(image) {
+            Some("network share (T1021.002)")
+        } else if drive_letter(image).is_some_and(|drive| !is_system_drive(drive)) {
+            Some("non-system drive (T1091)")
+        } else {
+            None
+        };
+        if let Some(location) = location {
+            context.suspicion_score += WEIGHT_REMOTE_IMAGE;
+            if context.is_scripting_engine {
+                context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG * 2;
+            }
+            context.alert_reasons.push(format!("Execution from {}: {}", location, image));
+        }
+    }
+
+    // Explorer (a click, or an autorun-style LNK) launching from a drive that was
+    // just plugged in, or anything launched from such a drive's root (T1091)
+    if !context.is_known_good {
+        let image = image_path_from_command_line(&command_line);
+        let arrival = drive_letter(image)
+            .and_then(|drive| alert_state.removable_arrivals.get(&drive))
+            .filter(|(_, mounted_at)| alert_state.clock.now() - *mounted_at <= alert_state.removable_launch_window);
+        if let Some((media, mounted_at)) = arrival {
+            let from_explorer = context.parent_name.eq_ignore_ascii_case("explorer.exe");
+            let at_root = image.get(3..).is_some_and(|rest| !rest.is_empty() && !rest.contains('\\'));
+            if from_explorer || at_root {
+                context.suspicion_score += if from_explorer && at_root { WEIGHT_REMOVABLE_AUTORUN } else { WEIGHT_REMOVABLE_LAUNCH };
+                let seconds = (alert_state.clock.now() - *mounted_at).num_seconds();
+                context.alert_reasons.push(format!(
+                    "Removable media execution (T1091): {} launched by {} {}s after {} was inserted",
+                    image, context.parent_name, seconds, media.drive
+                ));
+                context.evidence.push(format!("Device ID = {}", media.volume_id));
+                context.evidence.push(format!("Volume = {} '{}' (serial {:08X})", media.drive, media.label, media.serial_number));
+                context.evidence.push(format!("Image Path = {}", image));
+            }
+        }
+    }
+
+    // Macro-style execution: an Office document handing off to a shell, directly or not
+    if let Some((app, lineage, chain)) = office_shell {
+        context.suspicion_score += WEIGHT_OFFICE_CHILD_SHELL;
+        let (office_pid, office_name) = &lineage[lineage.len() - 1];
+        let via = if lineage.len() > 1 {
+            let intermediates: Vec<&str> = lineage[..lineage.len() - 1].iter().rev().map(|(_, name)| name.as_str()).collect();
+            format!(" via {}", intermediates.join(" → "))
+        } else {
+            String::new()
+        };
+        context.alert_reasons.push(format!(
+            "Office application spawned shell (T1204.002/T1059.001): {} ({}, PID {}) spawned {}{} — {}",
+            app, office_name, office_pid, process_name, via, truncate_string(&command_line, 150)
+        ));
+        context.evidence.push(format!("Spawn Chain = {}", chain));
+        if !parent_command_line.is_empty() {
+            context.evidence.push(format!(
+                "Parent Command Line = {}",
+                truncate_string(&parent_command_line, 200)
+            ));
+        }
+        if !process_name.eq_ignore_ascii_case("cmd.exe")
+            && let Some(payload) = detect_encoded_payload(&command_line)
+        {
+            context.evidence.push(format!(
+                "Decoded Payload ({}) = {}",
+                payload.encoding,
+                truncate_string(&payload.decoded, 500)
+            ));
+        }
+    }
+
+    // Webshell or exploited service: a web, application or database server handing off to a shell
+    if let Some((kind, chain)) = server_shell {
+        context.suspicion_score += WEIGHT_SERVER_SHELL;
+        context.alert_reasons.push(format!(
+            "Server process spawned {} (T1505.003/T1190): {} (PID {}) spawned {} — {}",
+            kind, context.parent_name, parent_pid, process_name, truncate_string(&command_line, 150)
+        ));
+        context.evidence.push(format!("Spawn Chain = {}", chain));
+        // For w3wp this names the application pool
+        if !parent_command_line.is_empty() {
+            context.evidence.push(format!(
+                "Parent Command Line = {}",
+                truncate_string(&parent_command_line, 200)
+            ));
+        }
+        if let Some(payload) = detect_encoded_payload(&command_line) {
+            context.evidence.push(format!(
+                "Decoded Payload ({}) = {}",
+                payload.encoding,
+                truncate_string(&payload.decoded, 500)
+            ));
+        }
+    }
+
+    // Inbound lateral movement: WMI or WinRM executing code on this host
+    let image = image_path_from_command_line(&command_line);
+    if let Some((channel, technique)) = identify_remote_execution_child(&context.parent_name, process_name, image) {
+        context.suspicion_score += WEIGHT_REMOTE_EXECUTION;
+        context.alert_reasons.push(format!(
+            "Remote execution via {} ({}): {} (PID {}) spawned {} — {}",
+            channel, technique, context.parent_name, parent_pid, process_name,
+            truncate_string(&command_line, 150)
+        ));
+        if let Some(payload) = detect_encoded_payload(&command_line) {
+            context.evidence.push(format!(
+                "Decoded Payload ({}) = {}",
+                payload.encoding,
+                truncate_string(&payload.decoded, 500)
+            ));
+        }
+    }
+
+    // svchost.exe is only ever started by services.exe
+    if is_anomalous_svchost_parent(process_name, &context.parent_name) {
+        context.suspicion_score += WEIGHT_SVCHOST_MASQUERADE;
+        context.alert_reasons.push(format!(
+            "Svchost masquerade (T1036.005): {} (PID {}) launched by {} (PID {}), expected services.exe",
+            process_name, pid, context.parent_name, parent_pid
+        ));
+    }
+
+    // A system binary's name running from outside the folder Windows ships it in
+    let mapped_image = query_process_image_path(pid);
+    let image_path = mapped_image.clone()
+        .unwrap_or_else(|| image_path_from_command_line(&command_line).to_string());
+    if let Some(expected) = identify_system_binary_relocation(process_name, &image_path) {
+        context.suspicion_score += WEIGHT_SYSTEM_BINARY_RELOCATED;
+        context.evidence.push(format!("Expected Path = {}", expected));
+        context.evidence.push(format!("Image Path = {}", image_path));
+        context.alert_reasons.push(format!(
+            "System binary outside its directory (T1036): {} (PID {}) running from {}",
+            process_name, pid, image_path
+        ));
+    }
+
+    // The command line still names the original executable after the image was swapped.
+    // Trusted and known-good processes are exempt: updaters and launchers re-exec themselves.
+    if let Some(mapped_image) = &mapped_image
+        && !context.is_known_good
+        && !alert_state.trusted_processes.contains(&process_name.to_lowercase())
+        && let Some(claimed) = identify_image_command_line_mismatch(mapped_image, &command_line)
+    {
+        context.suspicion_score += WEIGHT_IMAGE_COMMAND_LINE_MISMATCH;
+        context.evidence.push(format!("Image Path = {}", mapped_image));
+        context.evidence.push(format!("Command Line Image = {}", claimed));
+        context.alert_reasons.push(format!(
+            "Image/command-line mismatch (T1055.012): {} (PID {}) runs {} but its command line names {}",
+            process_name, pid, mapped_image, claimed
+        ));
+    }
+
+    // Credential dumping aimed at LSASS process memory
+    if let Some(indicator) = identify_lsass_access(&command_line, &image_path) {
+        context.suspicion_score += WEIGHT_LSASS_ACCESS;
+        context.evidence.push(format!("LSASS Indicator = {}", indicator));
+        context.alert_reasons.push(format!(
+            "LSASS credential dumping (T1003.001): {} — {} (PID {}) — {}",
+            indicator, process_name, pid, truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Auto-elevating binaries launching an unexpected child run it at high integrity
+    if let Some(pattern) = identify_uac_bypass(&context.parent_name, process_name) {
+        context.suspicion_score += WEIGHT_UAC_BYPASS;
+        context.alert_reasons.push(format!(
+            "UAC bypass via auto-elevate (T1548.002): {} — {} (PID {}) spawned {} — {}",
+            pattern, context.parent_name, parent_pid, process_name,
+            truncate_string(&command_line, 150)
+        ));
+    }
+
+    // Scans the contents of a script file referenced in the command line.
+    if context.is_scripting_engine && !command_line.is_empty() {
+        let script_path = script_path_from_command_line(&command_line);
+        let script_content = script_path.as_deref()
+            .and_then(|path| std::fs::read_to_string(path).ok());
+
+        if let Some(content) = &script_content {
+            reflective_findings.extend(identify_reflective_loading(content));
+            raw_socket_findings.extend(identify_raw_socket_tunnel(content));
+            let (matched_names, total_weight) = scan_script_content_for_apis(content);
+            if !matched_names.is_empty() {
+                context.suspicion_score += total_weight;
+                context.alert_reasons.push(format!(
+                    "Script scan found [\n    {}\n]",
+                    matched_names.join(",\n    ")
+                ));
+            }
+        }
+
+        // Clipboard read/replace loops (wallet swappers) in the inline command or the script
+        let inspected = match &script_content {
+            Some(content) => format!("{}\n{}", command_line, content),
+            None => command_line.clone(),
+        };
+        if let Some(pattern) = identify_clipboard_hijack(&inspected) {
+            let from_suspicious_path = is_user_writable_script_location(
+                script_path.as_deref().unwrap_or(&command_line),
+            );
+            context.suspicion_score += if from_suspicious_path {
+                WEIGHT_CLIPBOARD_HIJACK
+            } else {
+                WEIGHT_SUSPICIOUS_DOMAIN
+            };
+            context.alert_reasons.push(format!(
+                "Clipboard hijack (T1115): {}{}",
+                pattern,
+                if from_suspicious_path { " — script runs from a user-writable path" } else { "" }
+            ));
+        }
+    }
+
+    if !reflective_findings.is_empty() && !context.is_known_good {
+        reflective_findings.sort_unstable();
+        reflective_findings.dedup();
+        context.suspicion_score += WEIGHT_REFLECTIVE_LOADING;
+        context.alert_reasons.push(format!(
+            "Process injection / reflective loading (T1055): {} in {}",
+            reflective_findings.join(", "), process_name
+        ));
+    }
+
+    if !raw_socket_findings.is_empty() && !context.is_known_good {
+        raw_socket_findings.sort_unstable();
+        raw_socket_findings.dedup();
+        context.suspicion_score += if raw_socket_findings.contains(&ICMP_TUNNEL_TOOL) {
+            WEIGHT_ICMP_TUNNEL_TOOL
+        } else {
+            WEIGHT_RAW_SOCKET
+        };
+        context.alert_reasons.push(format!(
+            "Non-standard protocol channel (T1095): {} in {}",
+            raw_socket_findings.join(", "), process_name
+        ));
+        context.evidence.push("Protocol = ICMP / raw IP".to_string());
+    }
+}
+
+fn handle_process_end(
+    process_event: &crate::events::process::ProcessEvent,
+    process_contexts: &mut HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let exiting_pid = process_event.pid;
+
+    let exit_time = alert_state.clock.now();
+
+    // Duplicate ProcessEnd for a context already in its grace period
+    if process_contexts.get(&exiting_pid).is_some_and(|ctx| ctx.exited_at.is_some()) {
+        return;
+    }
+
+    // ── Evasion pattern: spawn-and-exit ─────────────────────────────────────
+    // The parent process is often still "Unknown" if it quickly exits because ETW name
+    // resolution races the exit event — so `is_scripting_engine` on the parent
+    // is unreliable. Instead we use TWO independent child-detection signals:
+    // Signal A – parent_pid match (works when name resolved in time)
+    // Signal B – time-window match: any scripting engine that started within 3 s of this exit (catches the unresolved-name race)
+    // Both paths require the exiting process to be short-lived (≤ 5 s) AND the
+    // candidate child to carry at least one evasion flag, to limit FPs.
+
+    let parent_lifetime = process_contexts
+        .get(&exiting_pid)
+        .map(|ctx| exit_time - ctx.start_time);
+
+    let is_short_lived = parent_lifetime
+        .map(|lt| lt <= chrono::Duration::seconds(5))
+        .unwrap_or(true); // unknown start time → treat conservatively as short-lived
+
+    if is_short_lived {
+        let parent_name = process_contexts
+            .get(&exiting_pid)
+            .map(|ctx| ctx.process_name.clone())
+            .unwrap_or_else(|| process_event.process_name.clone());
+
+        let lifetime_ms = parent_lifetime
+            .map(|lt| lt.num_milliseconds())
+            .unwrap_or(0);
+
+        // Signal A: explicit parent_pid linkage
+        let children_by_parent: Vec<u32> = process_contexts
+            .iter()
+            .filter(|(pid, ctx)| **pid != exiting_pid && ctx.parent_pid == exiting_pid)
+            .map(|(pid, _)| *pid)
+            .collect();
+
+        // Signal B: scripting engines that started within 3 s of this exit event
+        // and haven't been alerted yet — catches the unresolved-name race.
+        let children_by_time: Vec<u32> = process_contexts
+            .iter()
+            .filter(|(pid, ctx)| {
+                **pid != exiting_pid
+                    && ctx.is_scripting_engine
+                    && exit_time - ctx.start_time <= chrono::Duration::seconds(3)
+            })
+            .map(|(pid, _)| *pid)
+            .collect();
+
+        // Merge and deduplicate
+        let mut candidate_pids = children_by_parent;
+        for pid in children_by_time {
+            if !candidate_pids.contains(&pid) {
+                candidate_pids.push(pid);
+            }
+        }
+
+        for child_pid in candidate_pids {
+            if let Some(child_ctx) = process_contexts.get_mut(&child_pid) {
+
+                let already_scored_spawn = child_ctx.alert_reasons.iter()
+                    .any(|r| r.contains("spawn-and-exit evasion pattern"));
+
+                if !already_scored_spawn {
                     child_ctx.suspicion_score += WEIGHT_SUSPICIOUS_SHORT_LIVED_PROCESS;
                     child_ctx.alert_reasons.push(format!(
                         "Spawned by short-lived process '{}' (PID: {}, lived {}ms) \
@@ -331,15 +2178,395 @@ fn handle_process_end(
                 if child_ctx.parent_name.is_empty() || child_ctx.parent_name == "Unknown" {
                     child_ctx.parent_name = parent_name.clone();
                 }
-                maybe_alert(child_ctx, alert_tx);
+                maybe_alert(child_ctx, exit_time, alert_tx);
             }
         }
     }
 
-    process_contexts.remove(&exiting_pid);
-    alert_state.process_start_times.remove(&exiting_pid);
-    alert_state.verified_processes.remove(&exiting_pid);
-    alert_state.evaluated_processes.remove(&exiting_pid);
+    // Keep the context (and its DNS answers) through the grace period; late TCP
+    // events for a process that exited quickly still correlate with what it did.
+    // cleanup_old_contexts forgets it afterwards.
+    match process_contexts.get_mut(&exiting_pid) {
+        Some(ctx) => ctx.exited_at = Some(exit_time),
+        None => forget_process(exiting_pid, process_contexts, alert_state),
+    }
+}
+
+/// Drops a process context and everything the engine tracks for its PID.
+fn forget_process(
+    pid: u32,
+    process_contexts: &mut HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+) {
+    if let Some(context) = process_contexts.remove(&pid)
+        && context.suspicion_score > 0
+        && !context.alerted
+    {
+        let detail = format!(
+            "score {}/{}: {}",
+            context.suspicion_score, SUSPICION_THRESHOLD, context.alert_reasons.join("; ")
+        );
+        record_suppressed("Suspicion score", &context.process_name, pid, Suppression::BelowThreshold, &detail);
+    }
+    alert_state.process_start_times.remove(&pid);
+    alert_state.verified_processes.remove(&pid);
+    alert_state.evaluated_processes.remove(&pid);
+    alert_state.dns_resolutions.remove(&pid);
+    alert_state.image_signatures.remove(&pid);
+    alert_state.child_spawns.remove(&pid);
+    alert_state.dga_domains.remove(&pid);
+    alert_state.dns_subdomains.retain(|(owner, _), _| *owner != pid);
+    alert_state.document_activity.remove(&pid);
+    alert_state.sent_volume.remove(&pid);
+    if let Ok(mut known) = PROCESS_SECURITY.lock() {
+        known.remove(&pid);
+    }
+}
+
+/// Compiles the enabled `alert_rules` that carry an expression. `load_rules` has
+/// already rejected bad expressions and severities; a rule that still fails is skipped.
+fn compile_expression_rules(config: &Config) -> Vec<ExpressionRule> {
+    use clap::ValueEnum;
+
+    let mut rules = Vec::new();
+    for rule in config.alert_rules.iter().filter(|rule| rule.enabled) {
+        let Some(source) = &rule.expression else {
+            continue;
+        };
+        let compiled = source.parse::<Expression>().and_then(|expression| {
+            crate::events::alert::AlertSeverity::from_str(&rule.severity, true)
+                .map(|severity| (expression, severity))
+        });
+        match compiled {
+            Ok((expression, severity)) => rules.push(ExpressionRule {
+                name: rule.name.clone(),
+                description: rule.description.clone(),
+                severity,
+                cooldown: Duration::from_secs(rule.cooldown_seconds),
+                on_network: expression.needs_network(),
+                expression,
+            }),
+            Err(e) => log::warn!("Skipping alert rule '{}': {}", rule.name, e),
+        }
+    }
+    if !rules.is_empty() {
+        log::info!("📐 {} expression rule(s) loaded", rules.len());
+    }
+    rules
+}
+
+/// Evaluates the `alert_rules` expressions against a process start (`network` is
+/// `None`) or one of its connections, and raises each rule that matches at most
+/// once per process in its cooldown. The evidence lists the value of every field
+/// the expression tests.
+fn check_expression_rules(
+    context: &ProcessContext,
+    parent_command_line: Option<&str>,
+    network: Option<(&crate::events::network::NetworkEvent, &NetworkConnection)>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    if !alert_state.expression_rules.iter().any(|rule| rule.on_network == network.is_some()) {
+        return;
+    }
+    let pid = context.pid;
+    let parent_command_line = parent_command_line.map(str::to_string)
+        .or_else(|| cached_command_line(context.parent_pid))
+        .unwrap_or_default();
+    let security = PROCESS_SECURITY.lock().ok().and_then(|known| known.get(&pid).cloned());
+    let lookup = |field: Field| -> Option<FieldValue> {
+        let text = |value: &str| Some(FieldValue::Text(value.to_string()));
+        match field {
+            Field::Image => text(&context.process_name),
+            Field::Pid => Some(FieldValue::Number(pid as u64)),
+            Field::CommandLine => text(&context.command_line),
+            Field::ParentImage => text(&context.parent_name),
+            Field::ParentPid => Some(FieldValue::Number(context.parent_pid as u64)),
+            Field::ParentCommandLine => text(&parent_command_line),
+            Field::User => security.as_ref().and_then(|security| text(&security.user)),
+            Field::Elevated => security.as_ref().map(|security| FieldValue::Bool(security.elevated)),
+            Field::Integrity => security.as_ref().and_then(|security| text(&security.integrity_level)),
+            Field::RemoteAddress => network.and_then(|(_, connection)| text(&connection.remote_addr)),
+            Field::RemotePort => network.map(|(_, connection)| FieldValue::Number(connection.remote_port as u64)),
+            Field::LocalPort => network.map(|(_, connection)| FieldValue::Number(connection.local_port as u64)),
+            Field::Protocol => network.and_then(|(_, connection)| text(&connection.protocol)),
+            Field::Direction => network.and_then(|(event, _)| text(&format!("{:?}", event.direction))),
+            Field::Domain => network.and_then(|(_, connection)| connection.remote_domain.as_deref()).and_then(text),
+            Field::IsExternal => network.map(|(_, connection)| FieldValue::Bool(connection.is_external)),
+        }
+    };
+
+    let matched: Vec<usize> = alert_state.expression_rules.iter()
+        .enumerate()
+        .filter(|(_, rule)| rule.on_network == network.is_some() && rule.expression.evaluate(&lookup))
+        .map(|(index, _)| index)
+        .collect();
+    for index in matched {
+        let rule = &alert_state.expression_rules[index];
+        let (name, description, severity, cooldown) =
+            (rule.name.clone(), rule.description.clone(), rule.severity.clone(), rule.cooldown);
+        let values: Vec<String> = rule.expression.fields().into_iter()
+            .filter_map(|field| lookup(field).map(|value| format!("{}={}", field.name(), truncate_string(&value.to_string(), 150))))
+            .collect();
+        let mut details = vec![format!("Expression = {}", rule.expression)];
+        details.extend(values.iter().map(|value| format!("Matched Field = {}", value)));
+        let reasons = vec![format!("Expression rule {}: {}", name, values.join(", "))];
+        record_rule_hits(&reasons);
+
+        let alert_key = format!("expression:{}:{}", name, pid);
+        if !should_alert(&alert_key, alert_state, cooldown) {
+            continue;
+        }
+
+        let weight = match severity {
+            crate::events::alert::AlertSeverity::Critical => SUSPICION_THRESHOLD * 2,
+            crate::events::alert::AlertSeverity::High => SUSPICION_THRESHOLD + 2,
+            crate::events::alert::AlertSeverity::Medium => SUSPICION_THRESHOLD,
+            crate::events::alert::AlertSeverity::Low => 1,
+        };
+        let network_line = network.map_or_else(
+            || "N/A".to_string(),
+            |(_, connection)| format!("{}:{} ({})", connection.remote_addr, connection.remote_port, connection.protocol),
+        );
+        generate_alert(
+            severity,
+            &name,
+            &description,
+            &context.process_name,
+            pid,
+            context.parent_pid,
+            if context.parent_name.is_empty() { "N/A" } else { &context.parent_name },
+            &network_line,
+            &context.command_line,
+            &reasons,
+            Some(alert_confidence(&reasons, weight)),
+            SOURCE_ETW,
+            None,
+            false,
+            alert_tx,
+            details,
+        );
+    }
+}
+
+/// Ancestors of `pid`, nearest first, as `(pid, image name)`, walking tracked
+/// contexts up to `max_depth` generations. The first untracked ancestor is still
+/// named from its child's context. A parent context that started after its child
+/// belongs to a process that reused the PID, and a PID seen twice ends the walk,
+/// so it always terminates.
+fn process_ancestry(pid: u32, process_contexts: &HashMap<u32, ProcessContext>, max_depth: usize) -> Vec<(u32, String)> {
+    let mut ancestors = Vec::new();
+    let mut visited = HashSet::from([pid]);
+    let mut current = process_contexts.get(&pid);
+    while let Some(context) = current {
+        if ancestors.len() >= max_depth || context.parent_pid == 0 || !visited.insert(context.parent_pid) {
+            break;
+        }
+        current = process_contexts.get(&context.parent_pid)
+            .filter(|parent| parent.start_time <= context.start_time);
+        let parent_name = match current {
+            Some(parent) => parent.process_name.clone(),
+            None if context.parent_name.is_empty() => "Unknown".to_string(),
+            None => context.parent_name.clone(),
+        };
+        ancestors.push((context.parent_pid, parent_name));
+    }
+    ancestors
+}
+
+/// Ancestry of `pid`, oldest first, as far back as `process_ancestry` goes:
+/// `services.exe (PID 712) → w3wp.exe (PID 4120) → cmd.exe (PID 5012)`.
+fn describe_spawn_chain(pid: u32, process_contexts: &HashMap<u32, ProcessContext>, max_depth: usize) -> String {
+    let Some(context) = process_contexts.get(&pid) else {
+        return String::new();
+    };
+    let mut chain: Vec<String> = process_ancestry(pid, process_contexts, max_depth)
+        .iter()
+        .rev()
+        .map(|(ancestor_pid, name)| format!("{} (PID {})", name, ancestor_pid))
+        .collect();
+    chain.push(format!("{} (PID {})", context.process_name, context.pid));
+    chain.join(" → ")
+}
+
+/// Process spawn storm (T1059): one parent starting more than `max_children`
+/// processes within the window, as droppers, fork bombs and per-file encryptors do.
+/// Reported against the parent, once per burst.
+fn check_spawn_storm(
+    pid: u32,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let Some((max_children, window)) = alert_state.spawn_storm else {
+        return;
+    };
+    let child = &process_contexts[&pid];
+    if child.parent_pid == 0 {
+        return;
+    }
+
+    let now = alert_state.clock.now();
+    let spawns = alert_state.child_spawns.entry(child.parent_pid).or_default();
+    spawns.push_back((now, child.process_name.clone()));
+    while spawns.front().is_some_and(|(started, _)| now - *started > window) {
+        spawns.pop_front();
+    }
+    if spawns.len() <= max_children {
+        return;
+    }
+    // Allowlisted parents are still counted so the decision log can show their bursts, once each
+    if alert_state.spawn_storm_allowlist.contains(&child.parent_name.to_lowercase()) {
+        if spawns.len() == max_children + 1 {
+            let detail = format!("{} processes in {}s", spawns.len(), window.num_seconds());
+            record_suppressed("Process spawn storm", &child.parent_name, child.parent_pid, Suppression::Allowlisted, &detail);
+        }
+        return;
+    }
+
+    let child_count = spawns.len();
+    let mut children: Vec<(String, usize)> = Vec::new();
+    for (_, name) in spawns.iter() {
+        match children.iter_mut().find(|(seen, _)| seen.eq_ignore_ascii_case(name)) {
+            Some((_, count)) => *count += 1,
+            None => children.push((name.clone(), 1)),
+        }
+    }
+    children.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let parent_pid = child.parent_pid;
+    let parent_name = child.parent_name.clone();
+    let reasons = vec![format!(
+        "Process spawn storm (T1059): {} (PID {}) started {} processes in {}s",
+        parent_name, parent_pid, child_count, window.num_seconds()
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("spawn_storm:{}", parent_pid);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(600)) {
+        return;
+    }
+
+    let parent = process_contexts.get(&parent_pid);
+    let parent_image = query_process_image_path(parent_pid).unwrap_or_else(|| parent_name.clone());
+    let details = vec![
+        format!("Parent Image = {}", parent_image),
+        format!("Child Count = {} in {}s (limit {})", child_count, window.num_seconds(), max_children),
+        format!("Children = {}", children.iter()
+            .map(|(name, count)| format!("{} x{}", name, count))
+            .collect::<Vec<_>>()
+            .join(", ")),
+    ];
+    generate_alert(
+        crate::events::alert::AlertSeverity::Medium,
+        "ProcessSpawnStorm",
+        "A single process started an abnormal number of child processes in a short time",
+        &parent_name,
+        parent_pid,
+        parent.map_or(0, |parent| parent.parent_pid),
+        parent.map_or("N/A", |parent| parent.parent_name.as_str()),
+        "N/A",
+        parent.map_or("", |parent| parent.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_SPAWN_STORM)),
+        SOURCE_ETW,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
+}
+
+/// High-volume exfiltration (T1041): one process sending more than
+/// `max_sent_bytes` to external hosts within the window. Each TCP event carries its
+/// connection's running send total, so only the growth since the last event counts.
+/// Reported once per process every ten minutes.
+fn check_exfil_volume(
+    network_event: &crate::events::network::NetworkEvent,
+    process_contexts: &HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+    alert_tx: &Sender<Alert>,
+) {
+    let Some(exfil) = &alert_state.exfil else {
+        return;
+    };
+    if network_event.bytes_sent == 0
+        || is_private_or_local(&network_event.remote_address)
+        || exfil.allowlist.contains(&network_event.process_name.to_lowercase())
+    {
+        return;
+    }
+    let (max_sent_bytes, window) = (exfil.max_sent_bytes, exfil.window);
+
+    let now = alert_state.clock.now();
+    let pid = network_event.pid;
+    let volume = alert_state.sent_volume.entry(pid).or_default();
+    let key = (network_event.local_port, network_event.remote_address.clone(), network_event.remote_port);
+    let previous = volume.connections.insert(key, (network_event.bytes_sent, now)).map_or(0, |(total, _)| total);
+    // A smaller total is a new connection on the same ports
+    let added = if network_event.bytes_sent >= previous { network_event.bytes_sent - previous } else { network_event.bytes_sent };
+    if added == 0 {
+        return;
+    }
+    volume.sends.push_back((now, added));
+    volume.window_bytes += added;
+    while volume.sends.front().is_some_and(|(seen_at, _)| now - *seen_at > window) {
+        if let Some((_, bytes)) = volume.sends.pop_front() {
+            volume.window_bytes -= bytes;
+        }
+    }
+    if volume.window_bytes <= max_sent_bytes {
+        return;
+    }
+
+    let sent_mb = volume.window_bytes as f64 / (1024.0 * 1024.0);
+    let mut destinations: Vec<(&(u16, String, u16), u64)> = volume.connections.iter()
+        .map(|(connection, (total, _))| (connection, *total))
+        .collect();
+    destinations.sort_by_key(|(_, total)| std::cmp::Reverse(*total));
+    let destinations = destinations.iter()
+        .take(5)
+        .map(|((_, remote_addr, remote_port), total)| {
+            format!("{}:{} ({:.1} MB)", remote_addr, remote_port, *total as f64 / (1024.0 * 1024.0))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let process_name = &network_event.process_name;
+    let reasons = vec![format!(
+        "High-volume exfiltration (T1041): {} (PID {}) sent {:.1} MB to external hosts in {}s",
+        process_name, pid, sent_mb, window.num_seconds()
+    )];
+    record_rule_hits(&reasons);
+
+    let alert_key = format!("exfil_volume:{}", pid);
+    if !should_alert(&alert_key, alert_state, Duration::from_secs(600)) {
+        return;
+    }
+
+    let context = process_contexts.get(&pid);
+    let details = vec![
+        format!("Sent = {:.1} MB in {}s (limit {} MB)", sent_mb, window.num_seconds(), max_sent_bytes / (1024 * 1024)),
+        format!("Destinations = {}", destinations),
+    ];
+    generate_alert(
+        crate::events::alert::AlertSeverity::High,
+        "HighVolumeExfiltration",
+        "A single process sent an abnormal amount of data to external hosts in a short time",
+        process_name,
+        pid,
+        context.map_or(0, |context| context.parent_pid),
+        context.map_or("N/A", |context| context.parent_name.as_str()),
+        &format!("{}:{}", network_event.remote_address, network_event.remote_port),
+        context.map_or("", |context| context.command_line.as_str()),
+        &reasons,
+        Some(alert_confidence(&reasons, WEIGHT_EXFIL_VOLUME)),
+        SOURCE_ETW,
+        None,
+        false,
+        alert_tx,
+        details,
+    );
 }
 
 fn handle_network_connection(
@@ -348,19 +2575,38 @@ fn handle_network_connection(
     alert_state: &mut AlertState,
     alert_tx: &Sender<Alert>,
 ) {
+    // Teardown seen by the TCP table poll; the connection was scored when it opened
+    if matches!(network_event.connection_state, crate::events::network::ConnectionState::Closed) {
+        return;
+    }
+
+    let now = alert_state.clock.now();
     let pid = network_event.pid;
     let process_name = &network_event.process_name;
     let remote_addr = &network_event.remote_address;
     let remote_port = network_event.remote_port;
 
+    // Attach the domain this process resolved to the IP, so domain rules apply to raw connections.
+    let remote_domain = network_event.domain.clone()
+        .or_else(|| resolved_domain_for(alert_state, pid, remote_addr));
+
+    // Operator-allowlisted destinations (update servers, telemetry) are never scored.
+    if is_allowlisted_destination(
+        &alert_state.destination_allowlist,
+        remote_addr,
+        remote_domain.as_deref(),
+    ) {
+        return;
+    }
+
+    check_exfil_volume(network_event, process_contexts, alert_state, alert_tx);
+
     let is_external_https = remote_port == 443
         && !is_private_or_local(remote_addr)
         && remote_addr != "0.0.0.0";
 
     if is_external_https {
         if let Some(service) = identify_webhook_service_by_ip(remote_addr) {
-            let now = chrono::Utc::now();
-
             // ── Step 1: Does a viable scripting candidate exist? ─────────────
             // Only proceed if there is a recently-alerted scripting engine that
             // has not yet received a webhook escalation.
@@ -443,16 +2689,18 @@ fn handle_network_connection(
                 if is_tree_related && !connecting_is_scripting {
                     let attributed_conn = NetworkConnection {
                         timestamp: now,
-                        protocol: "TCP".to_string(),
+                        protocol: network_event.protocol.to_string(),
                         remote_addr: remote_addr.to_string(),
                         remote_port,
-                        remote_domain: network_event.domain.clone(),
+                        remote_domain: remote_domain.clone(),
+                        local_port: network_event.local_port,
+                        is_listening: false,
                         is_external: true,
                         data_size: network_event.data_size,
                     };
 
                     if let Some(ctx) = process_contexts.get_mut(&cpid) {
-                        ctx.network_connections.push(attributed_conn);
+                        record_connection(ctx, attributed_conn, now, alert_state.max_network_history);
 
                         let already = ctx.alert_reasons.iter()
                             .any(|r| r.to_lowercase().contains("webhook"));
@@ -463,9 +2711,9 @@ fn handle_network_connection(
                                  {} → {} (IP: {})",
                                 pid, process_name, ctx.process_name, service, remote_addr
                             ));
-                            maybe_alert(ctx, alert_tx);
+                            maybe_alert(ctx, now, alert_tx);
                         } else {
-                            maybe_alert(ctx, alert_tx);
+                            maybe_alert(ctx, now, alert_tx);
                         }
                     }
                 }
@@ -477,18 +2725,24 @@ fn handle_network_connection(
     let context = if let Some(ctx) = process_contexts.get_mut(&pid) {
         ctx
     } else {
-        let command_line = get_command_line_cached(pid).unwrap_or_default();
+        // Processes started before the EDR (or missed by ETW) have no cached command
+        // line. Looking it up spawns PowerShell, so it's backfilled off this thread
+        // and picked up on a later connection.
+        let command_line = cached_command_line(pid).unwrap_or_default();
+        if command_line.is_empty() {
+            request_command_line_backfill(pid);
+        }
         let is_known_good = is_known_good_process(process_name, &command_line);
         let is_scripting_engine = is_scripting_engine(process_name, &command_line);
         let cmd_analysis = analyze_command_line(&command_line);
         let ctx = ProcessContext {
-            start_time: chrono::Utc::now(),
+            start_time: now,
             process_name: process_name.clone(),
             pid,
             parent_pid: 0,
             parent_name: String::new(),
             command_line,
-            first_network_event_time: Some(chrono::Utc::now()),
+            first_network_event_time: Some(now),
             network_connections: Vec::new(),
             last_alert_time: None,
             is_known_good,
@@ -497,18 +2751,30 @@ fn handle_network_connection(
             process_age_at_first_network: None,
             suspicion_score: 0,
             alert_reasons: Vec::new(),
+            evidence: Vec::new(),
             alerted: false,
             webhook_alerted: false,
+            incident_id: None,
+        exited_at: None,
         };
 
         process_contexts.insert(pid, ctx);
         process_contexts.get_mut(&pid).unwrap()
     };
 
+    if context.command_line.is_empty()
+        && let Some(command_line) = cached_command_line(pid)
+    {
+        context.is_known_good = is_known_good_process(&context.process_name, &command_line);
+        context.is_scripting_engine = is_scripting_engine(&context.process_name, &command_line);
+        context.suspicious_flags = analyze_command_line(&command_line).flags;
+        context.command_line = command_line;
+    }
+
     // Update first network event time if not set
     if context.first_network_event_time.is_none() {
-        context.first_network_event_time = Some(chrono::Utc::now());
-        let time_delta = chrono::Utc::now() - context.start_time;
+        context.first_network_event_time = Some(now);
+        let time_delta = now - context.start_time;
         context.process_age_at_first_network = time_delta.to_std().ok();
     }
 
@@ -516,24 +2782,24 @@ fn handle_network_connection(
     let is_private_destination = is_private_or_local(&network_event.remote_address);
     let is_external = !is_private_destination && network_event.remote_address != "0.0.0.0";
 
-    let remote_domain = network_event.domain.clone();
-
     let connection = NetworkConnection {
-        timestamp: chrono::Utc::now(),
-        protocol: match &network_event.protocol {
-            crate::events::network::Protocol::TCP => "TCP".to_string(),
-            crate::events::network::Protocol::UDP => "UDP".to_string(),
-            crate::events::network::Protocol::QUIC => "QUIC".to_string(),
-            crate::events::network::Protocol::Other(proto) => proto.clone(),
-        },
+        timestamp: now,
+        protocol: network_event.protocol.to_string(),
         remote_addr: network_event.remote_address.clone(),
         remote_port: network_event.remote_port,
-        remote_domain,
+        remote_domain: remote_domain.clone(),
+        local_port: network_event.local_port,
+        // Accepted on a local listener, whichever way the data flowed
+        is_listening: matches!(
+            network_event.direction,
+            crate::events::network::NetworkDirection::Inbound | crate::events::network::NetworkDirection::Listening
+        ),
         is_external,
         data_size: network_event.data_size,
     };
 
-    context.network_connections.push(connection.clone());
+    record_connection(context, connection.clone(), connection.timestamp, alert_state.max_network_history);
+    check_expression_rules(context, None, Some((network_event, &connection)), alert_state, alert_tx);
 
     // Run detection functions in order of importance
     // 1. Check for webhook exfiltration (highest priority for scripting engines)
@@ -541,28 +2807,31 @@ fn handle_network_connection(
         context,
         &network_event.remote_address,
         network_event.remote_port,
-        network_event.domain.as_deref(),
+        remote_domain.as_deref(),
         alert_state,
     );
 
     // 2. Check for immediate threats (IOCs, malicious IPs/domains, high-risk ports)
     check_immediate_threats(&connection, context, alert_state);
 
-    // 3. Evaluate network behavior patterns
+    // 2b. Hardcoded-IP C2: an unsigned binary or script host connecting to an IP it never resolved
+    check_raw_ip_connection(&connection, context, alert_state);
+
+    // 3. Check for backdoor-style listeners accepting inbound connections
+    check_unexpected_listener(&connection, context, alert_state);
+
+    // 4. Evaluate network behavior patterns
     evaluate_network_alert(context, &connection, alert_state);
 
-    // 4. Check if we've reached the threshold to alert
-    maybe_alert(context, alert_tx);
+    // 5. Check if we've reached the threshold to alert
+    maybe_alert(context, now, alert_tx);
 
     // Mark as evaluated to prevent repeated processing
     alert_state.evaluated_processes.insert(pid);
 }
 
-// Scans the contents of a script file referenced in the command line.
-fn scan_script_file_for_apis(command_line: &str) -> (Vec<String>, u32) {
-    let mut matched: Vec<String> = Vec::new();
-    let mut total_score: u32 = 0;
-
+// Extracts the script path passed via -File / -f, if any.
+fn script_path_from_command_line(command_line: &str) -> Option<String> {
     let lower_cmd = command_line.to_lowercase();
     let file_arg_pos = lower_cmd.find(" -file ").or_else(|| lower_cmd.find(" -f "));
     let script_path = file_arg_pos.and_then(|pos| {
@@ -579,15 +2848,13 @@ fn scan_script_file_for_apis(command_line: &str) -> (Vec<String>, u32) {
         Some(path.to_string())
     });
 
-    let path = match script_path {
-        Some(p) if !p.is_empty() => p,
-        _ => return (matched, total_score),
-    };
+    script_path.filter(|p| !p.is_empty())
+}
 
-    let content = match std::fs::read_to_string(&path) {
-        Ok(c) => c,
-        Err(_) => return (matched, total_score),
-    };
+// Scans the contents of a script file referenced in the command line.
+fn scan_script_content_for_apis(content: &str) -> (Vec<String>, u32) {
+    let mut matched: Vec<String> = Vec::new();
+    let mut total_score: u32 = 0;
 
     let lower = content.to_lowercase();
 
@@ -773,7 +3040,7 @@ fn check_webhook_exfiltration(
         if let Some((service, obs_time, _obs_pid)) =
             alert_state.dns_webhook_observations.get(remote_addr)
         {
-            let age = chrono::Utc::now() - *obs_time;
+            let age = alert_state.clock.now() - *obs_time;
             if age < chrono::Duration::seconds(60) {
                 if !already_webhook_scored {
                     context.suspicion_score += WEIGHT_WEBHOOK;
@@ -840,6 +3107,24 @@ fn check_immediate_threats(
         context.alert_reasons.push(format!("Connection to known malicious IP: {}", connection.remote_addr));
     }
 
+    if connection.is_external
+        && let Some(reputation) = alert_state.ip_reputation.lookup(&connection.remote_addr)
+    {
+        let reason = format!(
+            "IP reputation: {} listed as {} (score {}/100)",
+            connection.remote_addr, reputation.category, reputation.score
+        );
+        // Scored once per address per process, however many connections follow
+        if !context.alert_reasons.contains(&reason) {
+            context.suspicion_score += match reputation.score {
+                score if score >= REPUTATION_SCORE_HIGH => WEIGHT_REPUTATION_HIGH,
+                score if score >= REPUTATION_SCORE_MEDIUM => WEIGHT_REPUTATION_MEDIUM,
+                _ => WEIGHT_REPUTATION_LOW,
+            };
+            context.alert_reasons.push(reason);
+        }
+    }
+
     if let Some(domain) = &connection.remote_domain {
         if alert_state.known_malicious_domains.contains(domain) {
             context.suspicion_score += WEIGHT_SUSPICIOUS_DOMAIN;
@@ -867,12 +3152,129 @@ fn check_immediate_threats(
     }
 }
 
+/// Outbound external connection to an address this process never looked up,
+/// from an unsigned image or a script host (T1071). Browsers, chat clients and
+/// `trusted_processes` are exempt. Scored once per process.
+fn check_raw_ip_connection(
+    connection: &NetworkConnection,
+    context: &mut ProcessContext,
+    alert_state: &mut AlertState,
+) {
+    if !alert_state.dns_traced
+        || !connection.is_external
+        || connection.is_listening
+        || connection.remote_domain.is_some()
+        || context.is_known_good
+        || is_network_aware_process(&context.process_name)
+        || is_system_process(&context.process_name)
+    {
+        return;
+    }
+    // A process already running when tracing started may have resolved the address earlier
+    if context.parent_name.is_empty() {
+        return;
+    }
+    let resolved_by_process = alert_state.dns_resolutions.get(&context.pid)
+        .is_some_and(|resolutions| resolutions.contains_key(&connection.remote_addr));
+    if resolved_by_process || context.alert_reasons.iter().any(|r| r.starts_with("Raw-IP connection")) {
+        return;
+    }
+    let unresolved = format!("connection to {} with no DNS lookup", connection.remote_addr);
+    if alert_state.trusted_processes.contains(&context.process_name.to_lowercase()) {
+        record_suppressed("Raw-IP connection", &context.process_name, context.pid, Suppression::TrustedProcess, &unresolved);
+        return;
+    }
+
+    let pid = context.pid;
+    let command_line = context.command_line.clone();
+    let unsigned = *alert_state.image_signatures.entry(pid).or_insert_with(|| {
+        let image_path = query_process_image_path(pid)
+            .unwrap_or_else(|| image_path_from_command_line(&command_line).to_string());
+        is_signed_executable(&image_path)
+    }) == Some(false);
+    if !unsigned && !context.is_scripting_engine {
+        record_suppressed("Raw-IP connection", &context.process_name, pid, Suppression::SignedImage, &unresolved);
+        return;
+    }
+
+    // Another process resolving the address suggests a shared resolver rather than a hardcoded IP,
+    // but it's still worth recording
+    let resolved_elsewhere = alert_state.dns_resolutions.iter()
+        .find_map(|(resolver, resolutions)| {
+            resolutions.get(&connection.remote_addr).map(|(domain, _)| (*resolver, domain.clone()))
+        });
+    context.suspicion_score += WEIGHT_RAW_IP_C2;
+    context.evidence.push(match resolved_elsewhere {
+        Some((resolver, domain)) => format!(
+            "DNS Resolution = none by this process (PID {} resolved {} as {})",
+            resolver, connection.remote_addr, domain
+        ),
+        None => "DNS Resolution = none".to_string(),
+    });
+    context.alert_reasons.push(format!(
+        "Raw-IP connection (T1071): {} {} connected to {}:{} without resolving it",
+        if unsigned { "unsigned" } else { "script host" },
+        context.process_name, connection.remote_addr, connection.remote_port
+    ));
+}
+
+fn check_unexpected_listener(
+    connection: &NetworkConnection,
+    context: &mut ProcessContext,
+    alert_state: &mut AlertState,
+) {
+    if !connection.is_listening || STANDARD_LISTENER_PORTS.contains(&connection.local_port) {
+        return;
+    }
+
+    // Browsers, chat clients etc. legitimately accept peer connections.
+    let is_expected_listener = context.is_known_good || is_network_aware_process(&context.process_name);
+    if !context.is_scripting_engine && is_expected_listener {
+        return;
+    }
+
+    let already_scored = context.alert_reasons.iter()
+        .any(|r| r.starts_with(&format!("Unexpected listener on port {}", connection.local_port)));
+    if already_scored {
+        return;
+    }
+
+    // Signed software with its own listener (sync clients, license servers) is left
+    // alone, like the other unsigned-process rules; a script host is flagged either way
+    let pid = context.pid;
+    let command_line = context.command_line.clone();
+    let unsigned = *alert_state.image_signatures.entry(pid).or_insert_with(|| {
+        let image_path = query_process_image_path(pid)
+            .unwrap_or_else(|| image_path_from_command_line(&command_line).to_string());
+        is_signed_executable(&image_path)
+    }) == Some(false);
+    if !unsigned && !context.is_scripting_engine {
+        let detail = format!("listener on port {} accepted {}", connection.local_port, connection.remote_addr);
+        record_suppressed("Unexpected listener", &context.process_name, pid, Suppression::SignedImage, &detail);
+        return;
+    }
+
+    if context.is_scripting_engine {
+        context.suspicion_score += WEIGHT_UNEXPECTED_LISTENER;
+    } else {
+        context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG * 2;
+    }
+    if is_high_risk_port(connection.local_port) {
+        context.suspicion_score += WEIGHT_HIGH_RISK_PORT;
+    }
+    context.alert_reasons.push(format!(
+        "Unexpected listener on port {} ({}) accepted inbound connection from {}:{} (T1571/T1059)",
+        connection.local_port, describe_port(connection.local_port),
+        connection.remote_addr, connection.remote_port
+    ));
+}
+
 fn evaluate_network_alert(
     context: &mut ProcessContext,
     connection: &NetworkConnection,
     alert_state: &mut AlertState,
 ) {
-    let now = chrono::Utc::now();
+    let now = alert_state.clock.now();
 
     // Don't evaluate non-external connections for non-scripting processes
     if !connection.is_external && !context.is_scripting_engine {
@@ -886,13 +3288,20 @@ fn evaluate_network_alert(
         now - context.start_time
     };
 
-    // ── Rule 1: Scripting engine obfuscation ────────────────────────────────
-    if context.is_scripting_engine && connection.is_external {
-        let lower_cmd = context.command_line.to_lowercase();
-        if lower_cmd.contains("frombase64string") || lower_cmd.contains("[convert]::") {
-            context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG;
-            context.alert_reasons.push("PowerShell obfuscation patterns detected".to_string());
-        }
+    // ── Rule 1: Encoded command line talking out (any host process) ─────────
+    // Scored once per process; the decoded content was already scored at start.
+    let already_scored = context.alert_reasons.iter()
+        .any(|r| r.starts_with("Encoded/obfuscated command line"));
+    if connection.is_external
+        && !already_scored
+        && !context.is_known_good
+        && has_encoding_markers(&context.command_line)
+    {
+        context.suspicion_score += WEIGHT_SUSPICIOUS_FLAG;
+        context.alert_reasons.push(format!(
+            "Encoded/obfuscated command line in {} making external connection",
+            context.process_name
+        ));
     }
 
     // Rule 2: Unexpected process making multiple rapid connections (possible scanning)
@@ -967,32 +3376,50 @@ fn evaluate_network_alert(
         }
     }
 
-    // Rule 6: Connection pattern analysis
-    if context.network_connections.len() >= 3 {
-        let first_remote = &context.network_connections[0].remote_addr;
-        let same_target_count = context.network_connections
-            .iter()
-            .filter(|c| &c.remote_addr == first_remote)
-            .count();
-
-        if same_target_count >= 3 && context.network_connections.len() <= 5 {
-            if !is_known_good_process(&context.process_name, &context.command_line) {
-                context.suspicion_score += WEIGHT_RAPID_CONNECTIONS;
-                context.alert_reasons.push(format!("Beaconing pattern: {} connections to same target", same_target_count));
-            }
-        }
+    // Rule 6: Connection pattern analysis — several recent connections to one target
+    // from a process that isn't otherwise chatty
+    if let Some(same_target_count) = beacon_target_count(&context.network_connections, &connection.remote_addr, now)
+        && !is_known_good_process(&context.process_name, &context.command_line)
+    {
+        context.suspicion_score += WEIGHT_RAPID_CONNECTIONS;
+        context.alert_reasons.push(format!(
+            "Beaconing pattern: {} connections to same target in {}s",
+            same_target_count, BEACON_WINDOW_SECS
+        ));
     }
 
     // Mark as evaluated
     alert_state.evaluated_processes.insert(context.pid);
 }
 
+/// Connections to `remote_addr` within the beacon window, when at least three of
+/// the process's three to five recent connections went there.
+fn beacon_target_count(
+    connections: &[NetworkConnection],
+    remote_addr: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<usize> {
+    let beacon_window = chrono::Duration::seconds(BEACON_WINDOW_SECS);
+    let recent_conns: Vec<&NetworkConnection> = connections
+        .iter()
+        .filter(|c| now - c.timestamp < beacon_window)
+        .collect();
+    if recent_conns.len() < 3 || recent_conns.len() > 5 {
+        return None;
+    }
+    let same_target_count = recent_conns
+        .iter()
+        .filter(|c| c.remote_addr == remote_addr)
+        .count();
+    (same_target_count >= 3).then_some(same_target_count)
+}
+
 fn check_temporal_correlations(
     process_contexts: &mut HashMap<u32, ProcessContext>,
     alert_state: &mut AlertState,
     alert_tx: &Sender<Alert>,
 ) {
-    let now = chrono::Utc::now();
+    let now = alert_state.clock.now();
 
     // Fallback: alert any process that scored above threshold at process-start
     // but never received a ProcessEnd event to trigger maybe_alert.
@@ -1007,7 +3434,7 @@ fn check_temporal_correlations(
         .collect();
     for pid in unalerted_pids {
         if let Some(ctx) = process_contexts.get_mut(&pid) {
-            maybe_alert(ctx, alert_tx);
+            maybe_alert(ctx, now, alert_tx);
         }
     }
 
@@ -1054,6 +3481,9 @@ fn check_temporal_correlations(
                     "N/A",
                     "",
                     &indicator_reasons,
+                    None,
+                    SOURCE_CORRELATION,
+                    None,
                     false,
                     alert_tx,
                     detail_items,
@@ -1100,16 +3530,18 @@ fn check_temporal_correlations(
                 );
             }
 
-            maybe_alert(ctx, alert_tx);
+            maybe_alert(ctx, now, alert_tx);
         }
     }
 }
 
 fn should_alert(alert_key: &str, alert_state: &mut AlertState, cooldown: Duration) -> bool {
-    let now = chrono::Utc::now();
+    let now = alert_state.clock.now();
 
     if let Some(last_alert) = alert_state.recent_alerts.get(alert_key) {
         if now - *last_alert < chrono::Duration::from_std(cooldown).unwrap() {
+            let detail = format!("{}s since the previous alert, cooldown {}s", (now - *last_alert).num_seconds(), cooldown.as_secs());
+            record_suppressed(alert_key, "N/A", 0, Suppression::Cooldown, &detail);
             return false; // Still in cooldown
         }
     }
@@ -1124,13 +3556,13 @@ fn should_alert(alert_key: &str, alert_state: &mut AlertState, cooldown: Duratio
     true
 }
 
-fn maybe_alert(context: &mut ProcessContext, alert_tx: &Sender<Alert>) {
+fn maybe_alert(context: &mut ProcessContext, now: chrono::DateTime<chrono::Utc>, alert_tx: &Sender<Alert>) {
     if context.suspicion_score < SUSPICION_THRESHOLD {
         return;
     }
 
     if !context.alerted {
-        context.last_alert_time = Some(chrono::Utc::now());
+        context.last_alert_time = Some(now);
         fire_alert(context, alert_tx);
         context.alerted = true;
         return;
@@ -1160,8 +3592,12 @@ fn fire_alert(context: &mut ProcessContext, alert_tx: &Sender<Alert>) {
     let mut reasons = context.alert_reasons.clone();
     reasons.sort();
     reasons.dedup();
+    record_rule_hits(&reasons);
 
     let is_escalation = context.alerted;
+    let correlation_id = context.incident_id
+        .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+        .clone();
     let rule_name = if is_escalation {
         "WebhookExfiltrationConfirmed"
     } else {
@@ -1198,10 +3634,14 @@ fn fire_alert(context: &mut ProcessContext, alert_tx: &Sender<Alert>) {
     } else {
         let conn = &external_conns[0];
         let proto_label = if conn.protocol == "QUIC" { "QUIC/HTTP3" } else { &conn.protocol };
+        let remote_label = match &conn.remote_domain {
+            Some(domain) => format!("{}:{} ({})", conn.remote_addr, conn.remote_port, domain),
+            None => format!("{}:{}", conn.remote_addr, conn.remote_port),
+        };
         let mut line = format!(
-            "{} total ({} external to {}:{} via {})",
+            "{} total ({} external to {} via {})",
             total_conns, external_conns.len(),
-            conn.remote_addr, conn.remote_port, proto_label
+            remote_label, proto_label
         );
         if quic_conns > 0 {
             line.push_str(" [QUIC: multiple reqs/event]");
@@ -1223,6 +3663,7 @@ fn fire_alert(context: &mut ProcessContext, alert_tx: &Sender<Alert>) {
             truncate_string(&context.command_line, 200)
         ));
     }
+    all_details.extend(context.evidence.iter().cloned());
     all_details.push(format!("Indicators Detected ({}):", reasons.len()));
     for (i, reason) in reasons.iter().take(10).enumerate() {
         all_details.push(format!("  {}. {}", i + 1, reason));
@@ -1250,12 +3691,29 @@ fn fire_alert(context: &mut ProcessContext, alert_tx: &Sender<Alert>) {
         &network_events_line,
         context.command_line.as_str(),
         &reasons,
+        Some(alert_confidence(&reasons, context.suspicion_score)),
+        SOURCE_ETW,
+        Some(&correlation_id),
         is_escalation,
         alert_tx,
         all_details,
     );
 }
 
+/// Scores how well an alert's signals corroborate each other: each distinct rule
+/// that contributed adds 25 (up to 75), and score beyond the threshold adds up to 20.
+/// Three independent signals with a strong score land in the 90s; a lone rule that
+/// barely crossed the threshold stays near 25.
+fn alert_confidence(reasons: &[String], suspicion_score: u32) -> u8 {
+    let mut rules: Vec<&str> = reasons.iter().map(|reason| rule_label(reason)).collect();
+    rules.sort_unstable();
+    rules.dedup();
+
+    let corroboration = (rules.len() as u32 * 25).min(75);
+    let margin = (suspicion_score.saturating_sub(SUSPICION_THRESHOLD) * 2).min(20);
+    (corroboration + margin).clamp(5, 100) as u8
+}
+
 fn generate_alert(
     severity: crate::events::alert::AlertSeverity,
     rule_name: &str,
@@ -1267,20 +3725,123 @@ fn generate_alert(
     network_events_line: &str,
     command_line: &str,
     indicators: &[String],
+    confidence: Option<u8>,
+    source: &str,
+    correlation_id: Option<&str>,
     is_escalation: bool,
     alert_tx: &Sender<Alert>,
     details: Vec<String>,
 ) {
-    let alert = Alert::new(
+    // Rule hits are already counted; below-threshold alerts stop here
+    let min_severity = MIN_SEVERITY.read().map(|min| min.clone()).unwrap_or_default();
+    if severity < min_severity {
+        log::debug!("Suppressed {:?} alert '{}' for {} (below min_severity)", severity, rule_name, process_name);
+        let detail = format!("{:?} below min_severity {:?}", severity, min_severity);
+        record_suppressed(rule_name, process_name, pid, Suppression::BelowMinSeverity, &detail);
+        return;
+    }
+
+    let mut details = details;
+    // Hashed in the background at start, so it may still be missing for a fresh process
+    if let Some(hash) = process_image_hash(pid) {
+        details.push(format!("Image SHA-256 = {}", hash));
+    }
+    let mut alert = Alert::new(
         &severity,
         rule_name,
         description,
         process_name,
         pid,
         &details,
-    );
+    )
+    .with_source(source);
+    if let Some(confidence) = confidence {
+        alert = alert.with_confidence(confidence);
+    }
+    if let Some(correlation_id) = correlation_id {
+        alert = alert.with_correlation_id(correlation_id);
+    }
+    if !parent_name.is_empty() && parent_name != "Unknown" {
+        alert = alert.with_parent_name(parent_name);
+    }
+    if !command_line.is_empty() {
+        alert = alert.with_command_line(command_line);
+    }
+    let security = PROCESS_SECURITY.lock().ok().and_then(|known| known.get(&pid).cloned());
+    if let Some(security) = security.clone() {
+        alert = alert.with_security(security);
+    }
+    let min_confidence = MIN_CONFIDENCE.load(Ordering::Relaxed);
+    if alert.confidence() < min_confidence {
+        log::debug!(
+            "Suppressed alert '{}' for {} (confidence {}% below min_confidence)",
+            rule_name, process_name, alert.confidence()
+        );
+        let detail = format!("confidence {}% below min_confidence {}%", alert.confidence(), min_confidence);
+        record_suppressed(rule_name, process_name, pid, Suppression::BelowMinConfidence, &detail);
+        return;
+    }
+    if let Some(baseline) = check_baseline(rule_name, process_name, parent_name, &severity) {
+        log::debug!("Suppressed alert '{}' for {} ({:?} baseline)", rule_name, process_name, baseline);
+        let suppression = match baseline {
+            BaselineMatch::Learned => Suppression::Learning,
+            BaselineMatch::Known => Suppression::Baseline,
+        };
+        let detail = format!("{:?} under parent {}: {}", severity, parent_name, indicators.join("; "));
+        record_suppressed(rule_name, process_name, pid, suppression, &detail);
+        return;
+    }
+    let confidence_label = format!("{}%", alert.confidence());
+    let alert_id = alert.id.clone();
 
+    ALERTS_RAISED.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut counts) = ALERTS_BY_USER.lock() {
+        let user = security.as_ref().map_or("unknown", |security| security.user.as_str());
+        *counts.entry(user.to_string()).or_insert(0) += 1;
+    }
     let _ = alert_tx.send(alert);
+    record_fired(rule_name, process_name, pid, &format!("{:?}, confidence {}: {}", severity, confidence_label, indicators.join("; ")));
+
+    if ALERT_LOG_FORMAT.get() == Some(&AlertLogFormat::Compact) {
+        let mut line = format!(
+            "{} {:?} {} {} {} {} | confidence={} source={} ppid={} alert={}",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            severity,
+            quote_log_field(rule_name),
+            pid,
+            quote_log_field(process_name),
+            quote_log_field(parent_name),
+            confidence_label,
+            source,
+            parent_pid,
+            alert_id,
+        );
+        if let Some(id) = correlation_id {
+            line.push_str(&format!(" incident={}", id));
+        }
+        if let Some(security) = &security {
+            line.push_str(&format!(
+                " user={} elevated={} integrity={}",
+                quote_log_field(&security.user), security.elevated, security.integrity_level,
+            ));
+        }
+        if is_escalation {
+            line.push_str(" escalation=true");
+        }
+        if let Some(score) = details.first() {
+            line.push_str(&format!(" score={}", quote_log_field(score)));
+        }
+        line.push_str(&format!(" network={}", quote_log_field(network_events_line)));
+        if !command_line.is_empty() {
+            line.push_str(&format!(" cmd={}", quote_log_field(&truncate_string(command_line, 200))));
+        }
+        let indicators: Vec<String> = indicators.iter()
+            .map(|reason| reason.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        line.push_str(&format!(" indicators={}", quote_log_field(&indicators.join("; "))));
+        log::warn!("{}", line);
+        return;
+    }
 
     const BORDER: &str = "╔═════════════════════════════════════════════════════════════";
     const DIVIDER: &str = "╠═════════════════════════════════════════════════════════════";
@@ -1324,11 +3885,14 @@ fn generate_alert(
         {header}\n\
         {DIVIDER}\n\
         ║ Severity = {severity_label}\n\
+        ║ Confidence = {confidence_label}\n\
+        ║ Source   = {source}\n\
         ║ Process  = {process_name}\n\
         ║ PID      = {pid}\n\
         ║ Parent   = {parent_name}\n\
-        ║ PPID     = {parent_pid}\n\
+        ║ PPID     = {parent_pid}{user_line}\n\
         ║ Rule     = {rule_name}\n\
+        ║ Alert ID = {alert_id}{incident_line}\n\
         ║ Details:\n\
         ║   Total Suspicion Score = {description_score}\n\
         ║   Network Events = {network_events_line}{cmd_line}\n\
@@ -1338,11 +3902,23 @@ fn generate_alert(
         header = header,
         DIVIDER = DIVIDER,
         severity_label = severity_label,
+        confidence_label = confidence_label,
+        source = source,
         process_name = process_name,
         pid = pid,
         parent_name = parent_name,
         parent_pid = parent_pid,
+        user_line = security.as_ref()
+            .map(|security| format!(
+                "\n║ User     = {} ({}, {} integrity)",
+                security.user,
+                if security.elevated { "elevated" } else { "not elevated" },
+                security.integrity_level,
+            ))
+            .unwrap_or_default(),
         rule_name = rule_name,
+        alert_id = alert_id,
+        incident_line = correlation_id.map(|id| format!("\n║ Incident = {}", id)).unwrap_or_default(),
         description_score = {
             // Extract "Score: X/Y" from description or fall back to details[0]
             details.first().cloned().unwrap_or_default()
@@ -1354,11 +3930,74 @@ fn generate_alert(
     );
 }
 
+/// A compact-format field: bare when it is a single plain word, otherwise quoted
+/// with inner quotes escaped and line breaks flattened, so the line splits cleanly.
+/// Backslashes are left alone to keep Windows paths readable.
+fn quote_log_field(value: &str) -> String {
+    let plain = !value.is_empty()
+        && !value.chars().any(|c| c.is_whitespace() || matches!(c, '"' | '|' | '='));
+    if plain {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('"', "\\\"")
+        .replace(['\r', '\n', '\t'], " ");
+    format!("\"{}\"", escaped)
+}
+
+/// Appends to the process's connection history, dropping entries outside the
+/// history window and the oldest ones past the cap.
+fn record_connection(
+    context: &mut ProcessContext,
+    connection: NetworkConnection,
+    now: chrono::DateTime<chrono::Utc>,
+    max_connections: usize,
+) {
+    context.network_connections.push(connection);
+    prune_connection_history(context, now, max_connections);
+}
+
+fn prune_connection_history(context: &mut ProcessContext, now: chrono::DateTime<chrono::Utc>, max_connections: usize) {
+    let history = chrono::Duration::seconds(CONNECTION_HISTORY_SECS);
+    context.network_connections.retain(|c| now - c.timestamp < history);
+    if context.network_connections.len() > max_connections {
+        let excess = context.network_connections.len() - max_connections;
+        context.network_connections.drain(..excess);
+    }
+}
+
+/// Drops contexts past `max_contexts`, exited processes first and then the oldest
+/// running ones, so a process storm can't grow the table without bound.
+fn evict_excess_contexts(
+    process_contexts: &mut HashMap<u32, ProcessContext>,
+    alert_state: &mut AlertState,
+) {
+    let excess = process_contexts.len().saturating_sub(alert_state.max_contexts);
+    if excess == 0 {
+        return;
+    }
+    let mut candidates: Vec<(bool, chrono::DateTime<chrono::Utc>, u32)> = process_contexts
+        .iter()
+        .map(|(&pid, context)| (context.exited_at.is_none(), context.start_time, pid))
+        .collect();
+    // Only the victims need ordering, not the whole table
+    candidates.select_nth_unstable(excess - 1);
+    for &(_, _, pid) in &candidates[..excess] {
+        forget_process(pid, process_contexts, alert_state);
+    }
+    // Warn once; a sustained storm would otherwise log on every cleanup pass
+    if CONTEXTS_EVICTED.fetch_add(excess as u64, Ordering::Relaxed) == 0 {
+        log::warn!("⚠️  Context table full ({} max): evicting oldest process contexts (see status for totals)", alert_state.max_contexts);
+    } else {
+        log::debug!("Evicted {} process contexts over max_contexts", excess);
+    }
+}
+
 fn cleanup_old_contexts(
     process_contexts: &mut HashMap<u32, ProcessContext>,
     alert_state: &mut AlertState,
 ) {
-    let now = chrono::Utc::now();
+    let now = alert_state.clock.now();
 
     // Remove old process contexts (older than 1 hour)
     let old_pids: Vec<u32> = process_contexts
@@ -1371,6 +4010,57 @@ fn cleanup_old_contexts(
         process_contexts.remove(&pid);
     }
 
+    // Exited processes whose grace period is over
+    let expired_pids: Vec<u32> = process_contexts
+        .iter()
+        .filter(|(_, context)| context.exited_at.is_some_and(|exited| now - exited > alert_state.exit_grace))
+        .map(|(&pid, _)| pid)
+        .collect();
+    for pid in expired_pids {
+        forget_process(pid, process_contexts, alert_state);
+    }
+
+    evict_excess_contexts(process_contexts, alert_state);
+
+    if let Some((_, window)) = alert_state.spawn_storm {
+        alert_state.child_spawns.retain(|_, spawns| {
+            spawns.back().is_some_and(|(started, _)| now - *started <= window)
+        });
+    }
+
+    if let Some(window) = alert_state.dga.as_ref().map(|dga| dga.window) {
+        alert_state.dga_domains.retain(|_, domains| {
+            domains.back().is_some_and(|seen| now - seen.seen_at <= window)
+        });
+        alert_state.dns_subdomains.retain(|_, lookups| {
+            lookups.queries.back().is_some_and(|(seen_at, _)| now - *seen_at <= window)
+        });
+    }
+    if let Some(window) = alert_state.ransomware.as_ref().map(|ransomware| ransomware.window) {
+        alert_state.document_activity.retain(|_, activity| {
+            activity.changes.back().is_some_and(|(seen_at, _, _)| now - *seen_at <= window)
+        });
+    }
+    if let Some(window) = alert_state.exfil.as_ref().map(|exfil| exfil.window) {
+        let idle = chrono::Duration::seconds(EXFIL_CONNECTION_IDLE_SECS);
+        alert_state.sent_volume.retain(|_, volume| {
+            while volume.sends.front().is_some_and(|(seen_at, _)| now - *seen_at > window) {
+                if let Some((_, bytes)) = volume.sends.pop_front() {
+                    volume.window_bytes -= bytes;
+                }
+            }
+            volume.connections.retain(|_, (_, seen_at)| now - *seen_at <= idle);
+            !volume.sends.is_empty() || !volume.connections.is_empty()
+        });
+    }
+
+    // Idle processes never hit record_connection, so age their history out here
+    let mut tracked_connections = 0;
+    for context in process_contexts.values_mut() {
+        prune_connection_history(context, now, alert_state.max_network_history);
+        tracked_connections += context.network_connections.len();
+    }
+
     // Clean up old start times
     alert_state.process_start_times.retain(|_, time| {
         now - *time < chrono::Duration::hours(1)
@@ -1381,21 +4071,377 @@ fn cleanup_old_contexts(
         process_contexts.contains_key(&pid)
     });
 
-    // Clean up old events (keep last 1000)
-    while alert_state.recent_events.len() > 1000 {
-        alert_state.recent_events.pop_front();
+    TRACKED_CONTEXTS.store(process_contexts.len(), Ordering::Relaxed);
+    TRACKED_CONNECTIONS.store(tracked_connections, Ordering::Relaxed);
+    TRACKED_EVENTS.store(alert_state.recent_events.len(), Ordering::Relaxed);
+
+    let launch_window = alert_state.removable_launch_window;
+    alert_state.removable_arrivals.retain(|_, (_, mounted_at)| now - *mounted_at <= launch_window);
+    let hosts_writer_window = alert_state.hosts_writer_window;
+    while alert_state.hosts_writers.front().is_some_and(|(opened_at, _, _)| now - *opened_at > hosts_writer_window) {
+        alert_state.hosts_writers.pop_front();
     }
 
     // Clean up stale DNS webhook observations (older than 120 s)
     alert_state.dns_webhook_observations.retain(|_, (_, obs_time, _)| {
         now - *obs_time < chrono::Duration::seconds(120)
     });
+
+    // Expire stale DNS answers and forget processes with none left
+    for resolutions in alert_state.dns_resolutions.values_mut() {
+        resolutions.retain(|_, (_, resolved_at)| {
+            now - *resolved_at < chrono::Duration::seconds(DNS_RESOLUTION_TTL_SECS)
+        });
+    }
+    alert_state.dns_resolutions.retain(|_, resolutions| !resolutions.is_empty());
+}
+
+/// Sets everything the engine derives from the rules file, at startup and on
+/// every reload. State the engine has built up (contexts, cooldowns, windows) is
+/// kept, except for detectors a reload turned off.
+fn apply_engine_config(alert_state: &mut AlertState, config: &Config) {
+    alert_state.protected_services = config.protected_services.clone();
+    alert_state.server_processes = config.server_processes.iter().map(|name| name.to_lowercase()).collect();
+    alert_state.expression_rules = compile_expression_rules(config);
+    alert_state.exit_grace = chrono::Duration::seconds(config.process_exit_grace_secs as i64);
+    alert_state.removable_launch_window = chrono::Duration::seconds(config.removable_media_monitor.as_ref()
+        .map_or(0, |media| media.launch_window_secs) as i64);
+    alert_state.hosts_writer_window = chrono::Duration::seconds(config.registry_monitor.as_ref()
+        .map_or(0, |registry| registry.poll_interval_secs) as i64 + HOSTS_WRITER_SLACK_SECS);
+    alert_state.spawn_storm = config.spawn_storm.as_ref()
+        .filter(|storm| storm.enabled)
+        .map(|storm| (storm.max_children, chrono::Duration::seconds(storm.window_secs as i64)));
+    alert_state.spawn_storm_allowlist = config.spawn_storm.as_ref()
+        .map(|storm| storm.allowlist.iter().map(|name| name.to_lowercase()).collect())
+        .unwrap_or_default();
+    alert_state.dga = config.dga_detection.as_ref()
+        .filter(|dga| dga.enabled)
+        .map(|dga| DgaSettings {
+            min_score: dga.min_score,
+            min_domains: dga.min_domains.max(1),
+            max_unique_subdomains: dga.max_unique_subdomains,
+            window: chrono::Duration::seconds(dga.window_secs as i64),
+            allowlist: dga.allowlist.iter().map(|domain| domain.trim_matches('.').to_lowercase()).collect(),
+        });
+    alert_state.ransomware = config.ransomware_detection.as_ref()
+        .filter(|ransomware| ransomware.enabled)
+        .map(|ransomware| RansomwareSettings {
+            min_files: ransomware.min_files.max(1),
+            window: chrono::Duration::seconds(ransomware.window_secs as i64),
+            ransom_extensions: ransomware.ransom_extensions.iter()
+                .map(|ext| format!(".{}", ext.trim_start_matches('.').to_lowercase()))
+                .collect(),
+            exempt_processes: ransomware.exempt_processes.iter().map(|name| name.to_lowercase()).collect(),
+            suspend_process: ransomware.suspend_process,
+        });
+    alert_state.exfil = config.exfil_detection.as_ref()
+        .filter(|exfil| exfil.enabled)
+        .map(|exfil| ExfilSettings {
+            max_sent_bytes: exfil.max_sent_mb.saturating_mul(1024 * 1024),
+            window: chrono::Duration::seconds(exfil.window_secs as i64),
+            allowlist: exfil.allowlist.iter().map(|name| name.to_lowercase()).collect(),
+        });
+    // Windows of a detector that is now off would never be pruned
+    if alert_state.spawn_storm.is_none() {
+        alert_state.child_spawns.clear();
+    }
+    if alert_state.dga.is_none() {
+        alert_state.dga_domains.clear();
+        alert_state.dns_subdomains.clear();
+    }
+    if alert_state.ransomware.is_none() {
+        alert_state.document_activity.clear();
+    }
+    if alert_state.exfil.is_none() {
+        alert_state.sent_volume.clear();
+    }
+    alert_state.max_process_history = config.max_process_history;
+    alert_state.max_network_history = config.max_network_history;
+    alert_state.max_contexts = config.max_contexts;
+    alert_state.max_spawn_chain_depth = config.max_spawn_chain_depth.max(1);
+    alert_state.dns_traced = config.etw_flags.dns;
+    alert_state.trusted_processes = config.trusted_processes.iter().map(|name| name.to_lowercase()).collect();
+    load_initial_iocs(alert_state, config);
+
+    if let Ok(mut min_severity) = MIN_SEVERITY.write() {
+        *min_severity = config.min_severity.clone();
+    }
+    MIN_CONFIDENCE.store(config.min_confidence, Ordering::Relaxed);
 }
 
 fn load_initial_iocs(alert_state: &mut AlertState, config: &Config) {
+    alert_state.known_malicious_ips.clear();
+    alert_state.known_malicious_domains.clear();
+    alert_state.known_malicious_ports.clear();
+    alert_state.destination_allowlist.clear();
+    alert_state.suspicious_pipe_patterns.clear();
+    alert_state.ip_reputation = ReputationList::default();
     if let Some(iocs) = &config.known_malicious_iocs {
         alert_state.known_malicious_ips.extend(iocs.ips.iter().cloned());
         alert_state.known_malicious_domains.extend(iocs.domains.iter().cloned());
         alert_state.known_malicious_ports.extend(iocs.ports.iter().cloned());
     }
-}
\ No newline at end of file
+
+    for entry in &config.destination_allowlist {
+        match parse_allowlist_entry(entry) {
+            Some(parsed) => alert_state.destination_allowlist.push(parsed),
+            None => log::warn!("Ignoring invalid destination_allowlist entry: '{}'", entry),
+        }
+    }
+
+    if let Some(pipes) = config.named_pipe_monitor.as_ref().filter(|pipes| pipes.enabled) {
+        for pattern in &pipes.suspicious_patterns {
+            match crate::utils::common::compile_config_pattern(pattern) {
+                Ok(re) => alert_state.suspicious_pipe_patterns.push(re),
+                Err(e) => log::warn!("Ignoring invalid named_pipe_monitor pattern '{}': {}", pattern, e),
+            }
+        }
+    }
+
+    if let Some(reputation) = config.ip_reputation.as_ref().filter(|reputation| reputation.enabled) {
+        match std::fs::read_to_string(&reputation.path) {
+            Ok(content) => {
+                let (list, skipped) = ReputationList::parse(&content);
+                if list.is_empty() {
+                    log::warn!("⚠️  IP reputation list {} has no usable entries", reputation.path);
+                } else {
+                    log::info!("✅ Loaded {} IP reputation entries from {}", list.len(), reputation.path);
+                }
+                if skipped > 0 {
+                    log::warn!("Ignored {} malformed lines in {}", skipped, reputation.path);
+                }
+                alert_state.ip_reputation = list;
+            }
+            Err(e) => log::error!("Failed to read IP reputation list {}: {}", reputation.path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitoring::clock::ManualClock;
+
+    fn start() -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z").unwrap().with_timezone(&chrono::Utc)
+    }
+
+    fn state_at(time: chrono::DateTime<chrono::Utc>) -> (Arc<ManualClock>, AlertState) {
+        let clock = Arc::new(ManualClock::default());
+        clock.set(time);
+        let state = AlertState::new(clock.clone());
+        (clock, state)
+    }
+
+    fn connection(timestamp: chrono::DateTime<chrono::Utc>, remote_addr: &str) -> NetworkConnection {
+        NetworkConnection {
+            timestamp,
+            protocol: "TCP".to_string(),
+            remote_addr: remote_addr.to_string(),
+            remote_port: 443,
+            remote_domain: None,
+            local_port: 50000,
+            is_listening: false,
+            is_external: true,
+            data_size: None,
+        }
+    }
+
+    #[test]
+    fn cooldown_holds_until_the_window_has_fully_passed() {
+        let t0 = start();
+        let (clock, mut state) = state_at(t0);
+        let cooldown = Duration::from_secs(60);
+
+        assert!(should_alert("rule:1", &mut state, cooldown));
+        clock.set(t0 + chrono::Duration::seconds(59));
+        assert!(!should_alert("rule:1", &mut state, cooldown));
+        clock.set(t0 + chrono::Duration::seconds(60));
+        assert!(should_alert("rule:1", &mut state, cooldown));
+        // The cooldown restarts from the alert that just fired
+        clock.set(t0 + chrono::Duration::seconds(61));
+        assert!(!should_alert("rule:1", &mut state, cooldown));
+    }
+
+    #[test]
+    fn cooldown_is_per_key() {
+        let (_clock, mut state) = state_at(start());
+        let cooldown = Duration::from_secs(60);
+
+        assert!(should_alert("rule:1", &mut state, cooldown));
+        assert!(should_alert("rule:2", &mut state, cooldown));
+        assert!(!should_alert("rule:1", &mut state, cooldown));
+    }
+
+    #[test]
+    fn beacon_window_excludes_its_lower_edge() {
+        let t0 = start();
+        let (clock, _state) = state_at(t0 + chrono::Duration::seconds(BEACON_WINDOW_SECS));
+        let now = clock.now();
+
+        let inside = [
+            connection(now - chrono::Duration::seconds(BEACON_WINDOW_SECS - 1), "203.0.113.7"),
+            connection(now - chrono::Duration::seconds(30), "203.0.113.7"),
+            connection(now, "203.0.113.7"),
+        ];
+        assert_eq!(beacon_target_count(&inside, "203.0.113.7", now), Some(3));
+
+        let edge = [
+            connection(now - chrono::Duration::seconds(BEACON_WINDOW_SECS), "203.0.113.7"),
+            connection(now - chrono::Duration::seconds(30), "203.0.113.7"),
+            connection(now, "203.0.113.7"),
+        ];
+        assert_eq!(beacon_target_count(&edge, "203.0.113.7", now), None);
+    }
+
+    #[test]
+    fn beacon_ignores_chatty_processes_and_other_targets() {
+        let now = start();
+        let chatty: Vec<NetworkConnection> = (0..6)
+            .map(|secs| connection(now - chrono::Duration::seconds(secs), "203.0.113.7"))
+            .collect();
+        assert_eq!(beacon_target_count(&chatty, "203.0.113.7", now), None);
+
+        let mixed = [
+            connection(now, "203.0.113.7"),
+            connection(now, "203.0.113.7"),
+            connection(now, "198.51.100.1"),
+        ];
+        assert_eq!(beacon_target_count(&mixed, "203.0.113.7", now), None);
+    }
+
+    #[test]
+    fn manual_clock_never_runs_backwards() {
+        let t0 = start();
+        let (clock, _state) = state_at(t0 + chrono::Duration::seconds(10));
+        clock.observe(t0);
+        assert_eq!(clock.now(), t0 + chrono::Duration::seconds(10));
+    }
+
+    fn documents(names: &[String]) -> Vec<String> {
+        names.iter().map(|name| format!(r"C:\Users\bob\Documents\{}", name)).collect()
+    }
+
+    #[test]
+    fn ransomware_flags_one_suffix_appended_to_documents() {
+        let names: Vec<String> = (0..20)
+            .map(|i| format!("file{}.{}.x7k2q", i, ["docx", "xlsx", "pdf", "jpg"][i % 4]))
+            .collect();
+        assert_eq!(ransomware_signals(&documents(&names), &[]), vec!["appended extension .x7k2q on 20 files".to_string()]);
+
+        let locked: Vec<String> = (0..5).map(|i| format!("a{}.docx.locked", i)).collect();
+        let signals = ransomware_signals(&documents(&locked), &[".locked".to_string()]);
+        assert_eq!(signals, vec!["ransom extension .locked".to_string()]);
+    }
+
+    #[test]
+    fn ransomware_ignores_package_and_source_trees() {
+        let names: Vec<String> = (0..30)
+            .flat_map(|i| {
+                [
+                    format!("module{}.min.js", i),
+                    format!("index{}.d.ts", i),
+                    format!("util{}.test.js", i),
+                    format!("bundle{}.js.map", i),
+                ]
+            })
+            .collect();
+        assert!(ransomware_signals(&documents(&names), &[]).is_empty());
+        // Documents saved next to sidecar files of known types
+        let sidecars: Vec<String> = (0..10).map(|i| format!("photo{}.jpg.json", i)).collect();
+        assert!(ransomware_signals(&documents(&sidecars), &[]).is_empty());
+        // Downloads and editor backups
+        let partial: Vec<String> = (0..10).map(|i| format!("report{}.pdf.crdownload", i)).collect();
+        assert!(ransomware_signals(&documents(&partial), &[]).is_empty());
+    }
+
+    #[test]
+    fn ransomware_needs_the_suffix_on_half_the_files() {
+        let mut names: Vec<String> = (0..4).map(|i| format!("a{}.docx.x7k2q", i)).collect();
+        names.extend((0..6).map(|i| format!("b{}.docx", i)));
+        assert!(ransomware_signals(&documents(&names), &[]).is_empty());
+    }
+
+    fn process_context(pid: u32, name: &str, parent_pid: u32, parent_name: &str, start_time: chrono::DateTime<chrono::Utc>) -> ProcessContext {
+        ProcessContext {
+            start_time,
+            process_name: name.to_string(),
+            pid,
+            parent_pid,
+            parent_name: parent_name.to_string(),
+            command_line: String::new(),
+            first_network_event_time: None,
+            network_connections: Vec::new(),
+            last_alert_time: None,
+            is_known_good: false,
+            is_scripting_engine: false,
+            suspicious_flags: Vec::new(),
+            process_age_at_first_network: None,
+            suspicion_score: 0,
+            alert_reasons: Vec::new(),
+            evidence: Vec::new(),
+            alerted: false,
+            webhook_alerted: false,
+            incident_id: None,
+            exited_at: None,
+        }
+    }
+
+    fn contexts(list: Vec<ProcessContext>) -> HashMap<u32, ProcessContext> {
+        list.into_iter().map(|context| (context.pid, context)).collect()
+    }
+
+    #[test]
+    fn ancestry_walks_tracked_parents_up_to_the_depth() {
+        let t0 = start();
+        let tree = contexts(vec![
+            process_context(10, "services.exe", 0, "", t0),
+            process_context(20, "w3wp.exe", 10, "services.exe", t0),
+            process_context(30, "cmd.exe", 20, "w3wp.exe", t0),
+        ]);
+        assert_eq!(
+            process_ancestry(30, &tree, 8),
+            vec![(20, "w3wp.exe".to_string()), (10, "services.exe".to_string())]
+        );
+        assert_eq!(process_ancestry(30, &tree, 1), vec![(20, "w3wp.exe".to_string())]);
+        assert_eq!(describe_spawn_chain(30, &tree, 8), "services.exe (PID 10) → w3wp.exe (PID 20) → cmd.exe (PID 30)");
+    }
+
+    #[test]
+    fn ancestry_names_an_untracked_parent_from_the_child() {
+        let t0 = start();
+        let tree = contexts(vec![
+            process_context(30, "cmd.exe", 20, "explorer.exe", t0),
+            process_context(31, "cmd.exe", 21, "", t0),
+        ]);
+        assert_eq!(process_ancestry(30, &tree, 8), vec![(20, "explorer.exe".to_string())]);
+        assert_eq!(process_ancestry(31, &tree, 8), vec![(21, "Unknown".to_string())]);
+        assert!(process_ancestry(99, &tree, 8).is_empty());
+    }
+
+    #[test]
+    fn ancestry_stops_at_a_pid_cycle() {
+        let t0 = start();
+        let tree = contexts(vec![
+            process_context(1, "a.exe", 2, "b.exe", t0),
+            process_context(2, "b.exe", 3, "c.exe", t0),
+            process_context(3, "c.exe", 1, "a.exe", t0),
+        ]);
+        assert_eq!(
+            process_ancestry(1, &tree, 100),
+            vec![(2, "b.exe".to_string()), (3, "c.exe".to_string())]
+        );
+    }
+
+    #[test]
+    fn ancestry_ignores_a_parent_pid_reused_after_the_child_started() {
+        let t0 = start();
+        let tree = contexts(vec![
+            process_context(10, "services.exe", 0, "", t0),
+            // PID 40 was winword.exe when 50 started; notepad.exe took it over later
+            process_context(40, "notepad.exe", 10, "services.exe", t0 + chrono::Duration::seconds(10)),
+            process_context(50, "powershell.exe", 40, "winword.exe", t0),
+        ]);
+        assert_eq!(process_ancestry(50, &tree, 8), vec![(40, "winword.exe".to_string())]);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::config::rules::DecisionLog;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Checked before any formatting, so a disabled log costs one load per decision
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static WRITER: Mutex<Option<ActiveLog>> = Mutex::new(None);
+
+/// The open decision log and how far it is from rotating. Every write and
+/// rotation happens under `WRITER`, so lines are never split across files.
+struct ActiveLog {
+    writer: LineWriter<File>,
+    path: PathBuf,
+    written: u64,
+    max_bytes: u64,
+    max_files: u32,
+}
+
+impl ActiveLog {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let line_len = line.len() as u64 + 1;
+        if self.max_bytes > 0 && self.written > 0 && self.written + line_len > self.max_bytes {
+            self.rotate();
+        }
+        writeln!(self.writer, "{}", line)?;
+        self.written += line_len;
+        Ok(())
+    }
+
+    /// Moves the full log to `path.1`, shifting older files up and deleting the
+    /// oldest. A file on disk smaller than what was written to it means another
+    /// writer has rotated it already, so it is only reopened. A rotation that
+    /// fails (the file held open elsewhere) keeps appending and is retried after
+    /// another `max_mb`.
+    fn rotate(&mut self) {
+        let on_disk = std::fs::metadata(&self.path).map_or(0, |metadata| metadata.len());
+        if on_disk < self.written {
+            match open_append(&self.path) {
+                Ok(file) => {
+                    self.writer = LineWriter::new(file);
+                    self.written = on_disk;
+                }
+                Err(e) => {
+                    log::warn!("Could not reopen decision log {}: {}", self.path.display(), e);
+                    self.written = 0;
+                }
+            }
+            return;
+        }
+        let _ = self.writer.flush();
+        match shift_rotated_logs(&self.path, self.max_files).and_then(|()| open_append(&self.path)) {
+            Ok(file) => {
+                self.writer = LineWriter::new(file);
+                self.written = 0;
+                log::info!("📝 Decision log {} rotated", self.path.display());
+            }
+            Err(e) => {
+                log::warn!("Could not rotate decision log {}, still appending: {}", self.path.display(), e);
+                self.written = 0;
+            }
+        }
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `decisions.jsonl` → `decisions.jsonl.1` → … → `decisions.jsonl.<max_files>`,
+/// deleting what falls off the end. With `max_files` 0 the log is deleted.
+fn shift_rotated_logs(path: &Path, max_files: u32) -> std::io::Result<()> {
+    let oldest = if max_files == 0 { path.to_path_buf() } else { rotated_path(path, max_files) };
+    match std::fs::remove_file(&oldest) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for index in (1..max_files).rev() {
+        let older = rotated_path(path, index);
+        if older.exists() {
+            std::fs::rename(&older, rotated_path(path, index + 1))?;
+        }
+    }
+    if max_files > 0 {
+        std::fs::rename(path, rotated_path(path, 1))?;
+    }
+    Ok(())
+}
+
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(format!(".{}", index));
+    rotated.into()
+}
+
+/// Why a rule that matched did not raise an alert.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Suppression {
+    SignedImage,
+    TrustedProcess,
+    BrowserProcess,
+    SystemProcess,
+    Allowlisted,
+    BelowThreshold,
+    Cooldown,
+    BelowMinSeverity,
+    BelowMinConfidence,
+    /// Recorded into the baseline while learning.
+    Learning,
+    /// Matches an entry learned during the baseline period.
+    Baseline,
+}
+
+#[derive(Serialize)]
+struct Decision<'a> {
+    timestamp: chrono::DateTime<chrono::Utc>,
+    rule: &'a str,
+    process: &'a str,
+    pid: u32,
+    fired: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suppressed_by: Option<Suppression>,
+    detail: &'a str,
+}
+
+/// Opens (appending) the configured decision log, or turns logging off. Called
+/// when the correlation engine starts.
+pub fn open_decision_log(config: Option<&DecisionLog>) {
+    let Some(config) = config.filter(|config| config.enabled) else {
+        ENABLED.store(false, Ordering::Relaxed);
+        return;
+    };
+    match open_append(Path::new(&config.path)) {
+        Ok(file) => {
+            let written = file.metadata().map_or(0, |metadata| metadata.len());
+            if let Ok(mut writer) = WRITER.lock() {
+                *writer = Some(ActiveLog {
+                    writer: LineWriter::new(file),
+                    path: PathBuf::from(&config.path),
+                    written,
+                    max_bytes: config.max_mb.saturating_mul(1024 * 1024),
+                    max_files: config.max_files,
+                });
+            }
+            ENABLED.store(true, Ordering::Relaxed);
+            log::info!("📝 Writing detection decisions to {}", config.path);
+        }
+        Err(e) => log::error!("Failed to open decision log {}: {}", config.path, e),
+    }
+}
+
+/// Records that `rule` matched `process_name` but was suppressed, and why.
+pub fn record_suppressed(rule: &str, process_name: &str, pid: u32, suppression: Suppression, detail: &str) {
+    write_decision(rule, process_name, pid, Some(suppression), detail);
+}
+
+/// Records an alert that was raised.
+pub fn record_fired(rule: &str, process_name: &str, pid: u32, detail: &str) {
+    write_decision(rule, process_name, pid, None, detail);
+}
+
+fn write_decision(rule: &str, process_name: &str, pid: u32, suppressed_by: Option<Suppression>, detail: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    match suppressed_by {
+        Some(suppression) => log::debug!("Decision: {} for {} ({}) suppressed by {:?}: {}", rule, process_name, pid, suppression, detail),
+        None => log::debug!("Decision: {} for {} ({}) fired: {}", rule, process_name, pid, detail),
+    }
+    let decision = Decision {
+        timestamp: chrono::Utc::now(),
+        rule,
+        process: process_name,
+        pid,
+        fired: suppressed_by.is_none(),
+        suppressed_by,
+        detail,
+    };
+    let Ok(line) = serde_json::to_string(&decision) else {
+        return;
+    };
+    if let Ok(mut writer) = WRITER.lock()
+        && let Some(active) = writer.as_mut()
+        && let Err(e) = active.write_line(&line)
+    {
+        log::error!("Decision log stopped: {}", e);
+        *writer = None;
+        ENABLED.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_log(name: &str, max_bytes: u64, max_files: u32) -> (PathBuf, ActiveLog) {
+        let dir = std::env::temp_dir().join(format!("edr-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("decisions.jsonl");
+        let log = ActiveLog {
+            writer: LineWriter::new(open_append(&path).unwrap()),
+            path: path.clone(),
+            written: 0,
+            max_bytes,
+            max_files,
+        };
+        (dir, log)
+    }
+
+    // 39 digits and a newline: two lines fit in 100 bytes, a third does not
+    fn line(index: u32) -> String {
+        format!("{:039}", index)
+    }
+
+    fn lines_in(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn rotation_shifts_full_logs_and_drops_the_oldest() {
+        let (dir, mut log) = scratch_log("decision-rotate", 100, 2);
+        for index in 1..=10 {
+            log.write_line(&line(index)).unwrap();
+        }
+        let path = log.path.clone();
+        assert_eq!(lines_in(&path), vec![line(9), line(10)]);
+        assert_eq!(lines_in(&rotated_path(&path, 1)), vec![line(7), line(8)]);
+        assert_eq!(lines_in(&rotated_path(&path, 2)), vec![line(5), line(6)]);
+        assert!(!rotated_path(&path, 3).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rotation_with_no_kept_files_starts_over() {
+        let (dir, mut log) = scratch_log("decision-no-keep", 100, 0);
+        for index in 1..=3 {
+            log.write_line(&line(index)).unwrap();
+        }
+        assert_eq!(lines_in(&log.path), vec![line(3)]);
+        assert!(!rotated_path(&log.path, 1).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn an_oversized_line_is_written_without_rotating_an_empty_log() {
+        let (dir, mut log) = scratch_log("decision-oversized", 10, 1);
+        log.write_line(&line(1)).unwrap();
+        assert_eq!(lines_in(&log.path), vec![line(1)]);
+        assert!(!rotated_path(&log.path, 1).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_log_rotated_by_another_writer_is_only_reopened() {
+        let (dir, mut log) = scratch_log("decision-reopen", 100, 1);
+        log.write_line(&line(1)).unwrap();
+        log.write_line(&line(2)).unwrap();
+        let path = log.path.clone();
+        std::fs::rename(&path, rotated_path(&path, 1)).unwrap();
+        std::fs::write(&path, "").unwrap();
+
+        log.write_line(&line(3)).unwrap();
+        assert_eq!(lines_in(&path), vec![line(3)]);
+        assert_eq!(lines_in(&rotated_path(&path, 1)), vec![line(1), line(2)]);
+        assert!(!rotated_path(&path, 2).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
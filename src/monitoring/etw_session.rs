@@ -0,0 +1,168 @@
+use crate::config::rules::EtwProvider;
+use crate::events::BaseEvent;
+use crate::utils::common::GLOBAL_SENDER;
+use crossbeam_channel::Sender;
+use std::sync::Arc;
+use widestring::U16CString;
+use windows::core::{GUID, PCWSTR, PWSTR};
+use windows::Win32::Foundation::{ERROR_INVALID_PARAMETER, ERROR_SUCCESS, WIN32_ERROR};
+use windows::Win32::System::Diagnostics::Etw::*;
+
+// EVENT_TRACE_PROPERTIES plus room for the logger name that ETW writes after it.
+// Backed by u64s so the struct cast is correctly aligned.
+const PROPERTIES_BUFFER_WORDS: usize =
+    (std::mem::size_of::<EVENT_TRACE_PROPERTIES>() + 1024).div_ceil(std::mem::size_of::<u64>());
+
+/// A running ETW trace session. Dropping it stops the session, so every
+/// early return after a successful start still tears the logger down.
+pub struct EtwSession {
+    handle: CONTROLTRACE_HANDLE,
+    name: U16CString,
+    properties: Vec<u64>,
+}
+
+impl EtwSession {
+    /// Stops any session left running under `name` (e.g. by a crashed run), then
+    /// starts a fresh one. `configure` fills in the mode, flags and GUIDs.
+    pub fn start(
+        name: &str,
+        configure: impl FnOnce(&mut EVENT_TRACE_PROPERTIES),
+    ) -> Result<Self, WIN32_ERROR> {
+        let name = U16CString::from_str(name).map_err(|_| ERROR_INVALID_PARAMETER)?;
+
+        let mut stale = new_properties();
+        let stop_result = unsafe {
+            ControlTraceW(
+                CONTROLTRACE_HANDLE::default(),
+                PCWSTR(name.as_ptr()),
+                properties_ptr(&mut stale),
+                EVENT_TRACE_CONTROL_STOP,
+            )
+        };
+        if stop_result == ERROR_SUCCESS {
+            log::info!("Stopped existing '{}' session", name.to_string_lossy());
+        }
+
+        let mut properties = new_properties();
+        configure(unsafe { &mut *properties_ptr(&mut properties) });
+
+        let mut handle = CONTROLTRACE_HANDLE::default();
+        let status = unsafe {
+            StartTraceW(&mut handle, PCWSTR(name.as_ptr()), properties_ptr(&mut properties))
+        };
+        if status != ERROR_SUCCESS {
+            return Err(status);
+        }
+
+        Ok(Self { handle, name, properties })
+    }
+
+    /// Enables a configured user-mode provider on this session. The config was
+    /// validated at load, so a malformed GUID or mask only shows up as a failure here.
+    pub fn enable_provider(&self, provider: &EtwProvider) -> Result<(), WIN32_ERROR> {
+        let (Ok(guid), Ok(keywords)) = (provider.guid_value(), provider.keyword_mask()) else {
+            return Err(ERROR_INVALID_PARAMETER);
+        };
+        let status = unsafe {
+            EnableTraceEx2(
+                self.handle,
+                &GUID::from_u128(guid),
+                EVENT_CONTROL_CODE_ENABLE_PROVIDER.0,
+                provider.level,
+                keywords,
+                0,
+                0,
+                None,
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return Err(status);
+        }
+        Ok(())
+    }
+
+    /// Logger name for `EVENT_TRACE_LOGFILEW::LoggerName`; valid while the session lives.
+    pub fn logger_name(&self) -> PWSTR {
+        PWSTR(self.name.as_ptr() as *mut u16)
+    }
+}
+
+impl Drop for EtwSession {
+    fn drop(&mut self) {
+        let status = unsafe {
+            ControlTraceW(
+                self.handle,
+                PCWSTR(self.name.as_ptr()),
+                properties_ptr(&mut self.properties),
+                EVENT_TRACE_CONTROL_STOP,
+            )
+        };
+        if status != ERROR_SUCCESS {
+            log::warn!(
+                "Failed to stop '{}' session: 0x{:08X}",
+                self.name.to_string_lossy(),
+                status.0
+            );
+        }
+    }
+}
+
+/// A real-time consumer opened with `OpenTraceW`; dropping it calls `CloseTrace`,
+/// which also makes a blocked `ProcessTrace` return.
+pub struct TraceConsumer {
+    handle: PROCESSTRACE_HANDLE,
+}
+
+impl TraceConsumer {
+    pub fn open(logfile: &mut EVENT_TRACE_LOGFILEW) -> Option<Self> {
+        let handle = unsafe { OpenTraceW(logfile) };
+        if handle.Value == u64::MAX {
+            return None;
+        }
+        Some(Self { handle })
+    }
+
+    pub fn handle(&self) -> PROCESSTRACE_HANDLE {
+        self.handle
+    }
+}
+
+impl Drop for TraceConsumer {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseTrace(self.handle) };
+    }
+}
+
+/// Publishes a monitor's sender to the ETW callbacks for as long as it lives.
+pub struct SenderRegistration;
+
+impl SenderRegistration {
+    pub fn install(tx: &Sender<BaseEvent>) -> Self {
+        if let Ok(mut guard) = GLOBAL_SENDER.lock() {
+            *guard = Some(Arc::new(tx.clone()));
+        }
+        Self
+    }
+}
+
+impl Drop for SenderRegistration {
+    fn drop(&mut self) {
+        if let Ok(mut guard) = GLOBAL_SENDER.lock() {
+            *guard = None;
+        }
+    }
+}
+
+fn new_properties() -> Vec<u64> {
+    let mut buffer = vec![0u64; PROPERTIES_BUFFER_WORDS];
+    let props = properties_ptr(&mut buffer);
+    unsafe {
+        (*props).Wnode.BufferSize = (buffer.len() * std::mem::size_of::<u64>()) as u32;
+        (*props).LoggerNameOffset = std::mem::size_of::<EVENT_TRACE_PROPERTIES>() as u32;
+    }
+    buffer
+}
+
+fn properties_ptr(buffer: &mut [u64]) -> *mut EVENT_TRACE_PROPERTIES {
+    buffer.as_mut_ptr() as *mut EVENT_TRACE_PROPERTIES
+}
@@ -0,0 +1,240 @@
+use crate::monitoring::network::format_ip;
+use crate::utils::common::get_timestamp;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use windows::Win32::Foundation::{CloseHandle, NO_ERROR};
+use windows::Win32::NetworkManagement::IpHelper::{
+    GetExtendedTcpTable, MIB_TCP6ROW_OWNER_PID, MIB_TCP6TABLE_OWNER_PID, MIB_TCPROW_OWNER_PID,
+    MIB_TCPTABLE_OWNER_PID, TCP_TABLE_OWNER_PID_ALL,
+};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+
+const AF_INET: u32 = 2;
+const AF_INET6: u32 = 23;
+const MIB_TCP_STATE_LISTEN: u32 = 2;
+const MIB_TCP_STATE_ESTAB: u32 = 5;
+
+// Seconds a listener may stay silent while the system is active; 0 disables the watchdog.
+static SILENCE_THRESHOLD_SECS: AtomicU64 = AtomicU64::new(120);
+
+pub fn set_etw_watchdog_secs(secs: u64) {
+    SILENCE_THRESHOLD_SECS.store(secs, Ordering::Relaxed);
+}
+
+/// When an ETW listener last delivered a record, how many it has delivered, and
+/// whether it has been judged dead.
+pub struct EtwHeartbeat {
+    name: &'static str,
+    last_event: AtomicU64,
+    events: AtomicU64,
+    degraded: AtomicBool,
+}
+
+pub static PROCESS_HEARTBEAT: EtwHeartbeat = EtwHeartbeat::new("Process Monitor");
+pub static NETWORK_HEARTBEAT: EtwHeartbeat = EtwHeartbeat::new("Network Monitor");
+
+impl EtwHeartbeat {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            last_event: AtomicU64::new(0),
+            events: AtomicU64::new(0),
+            degraded: AtomicBool::new(false),
+        }
+    }
+
+    /// Called for every record the session delivers, before any filtering.
+    pub fn record_event(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.beat();
+    }
+
+    /// Records delivered since startup.
+    pub fn events(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Resets the silence clock; called once when the session opens.
+    pub fn beat(&self) {
+        self.last_event.store(get_timestamp(), Ordering::Relaxed);
+        if self.degraded.load(Ordering::Relaxed) && self.degraded.swap(false, Ordering::Relaxed) {
+            log::info!("✅ {} is receiving ETW events again", self.name);
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    pub fn seconds_silent(&self) -> u64 {
+        get_timestamp().saturating_sub(self.last_event.load(Ordering::Relaxed))
+    }
+
+    /// Marks the listener degraded when it has been silent past the threshold even
+    /// though an independent probe saw activity. Returns the degraded state.
+    pub fn check(&self, system_active: bool) -> bool {
+        let threshold = SILENCE_THRESHOLD_SECS.load(Ordering::Relaxed);
+        if threshold == 0 {
+            return false;
+        }
+        let silent_for = self.seconds_silent();
+        if silent_for >= threshold && system_active && !self.degraded.swap(true, Ordering::Relaxed) {
+            log::warn!("=========================================");
+            log::warn!(
+                "⚠️  {} has delivered no ETW events for {}s while the system is active",
+                self.name, silent_for
+            );
+            log::warn!("   The trace session may be misconfigured, starved or hijacked");
+            log::warn!("=========================================");
+        }
+        self.is_degraded()
+    }
+}
+
+/// PIDs of every running process, from a Toolhelp snapshot.
+pub fn running_process_ids() -> Option<HashSet<u32>> {
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0).ok()?;
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+        let mut pids = HashSet::new();
+        if Process32FirstW(snapshot, &mut entry).is_ok() {
+            loop {
+                pids.insert(entry.th32ProcessID);
+                if Process32NextW(snapshot, &mut entry).is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = CloseHandle(snapshot);
+        Some(pids)
+    }
+}
+
+/// An established TCP connection from the system TCP tables. IPv6 addresses are
+/// in compressed form (`::1`, `fe80::1c2a:5f3e`), as ETW reports them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TcpTableEntry {
+    pub pid: u32,
+    /// Part of the key, so an IPv4 and an IPv6 connection never collide.
+    pub ipv6: bool,
+    pub local_addr: String,
+    pub local_port: u16,
+    pub remote_addr: String,
+    pub remote_port: u16,
+}
+
+/// The IPv4 and IPv6 TCP tables, merged and split into what the network monitor uses.
+pub struct TcpTable {
+    pub established: HashSet<TcpTableEntry>,
+    /// `(pid, port)` of each listening socket, of either family.
+    pub listening: HashSet<(u32, u16)>,
+}
+
+/// Established TCP connections and listening sockets with their owning PIDs, over
+/// IPv4 and IPv6, via GetExtendedTcpTable. A host without IPv6 just has no IPv6 rows.
+pub fn tcp_table() -> Option<TcpTable> {
+    let mut table = TcpTable { established: HashSet::new(), listening: HashSet::new() };
+    // Addresses and ports are stored in network byte order
+    let v4 = extended_tcp_table(AF_INET, std::mem::size_of::<MIB_TCPROW_OWNER_PID>())?;
+    unsafe {
+        let header = &*(v4.as_ptr() as *const MIB_TCPTABLE_OWNER_PID);
+        let rows = std::slice::from_raw_parts(header.table.as_ptr(), header.dwNumEntries as usize);
+        for row in rows {
+            add_tcp_row(&mut table, row.dwState, TcpTableEntry {
+                pid: row.dwOwningPid,
+                ipv6: false,
+                local_addr: table_ipv4(row.dwLocalAddr),
+                local_port: u16::from_be(row.dwLocalPort as u16),
+                remote_addr: table_ipv4(row.dwRemoteAddr),
+                remote_port: u16::from_be(row.dwRemotePort as u16),
+            });
+        }
+    }
+    if let Some(v6) = extended_tcp_table(AF_INET6, std::mem::size_of::<MIB_TCP6ROW_OWNER_PID>()) {
+        unsafe {
+            let header = &*(v6.as_ptr() as *const MIB_TCP6TABLE_OWNER_PID);
+            let rows = std::slice::from_raw_parts(header.table.as_ptr(), header.dwNumEntries as usize);
+            for row in rows {
+                add_tcp_row(&mut table, row.dwState, TcpTableEntry {
+                    pid: row.dwOwningPid,
+                    ipv6: true,
+                    local_addr: table_ipv6(row.ucLocalAddr),
+                    local_port: u16::from_be(row.dwLocalPort as u16),
+                    remote_addr: table_ipv6(row.ucRemoteAddr),
+                    remote_port: u16::from_be(row.dwRemotePort as u16),
+                });
+            }
+        }
+    }
+    Some(table)
+}
+
+// Table rows are diffed against ETW connections by address, so both go through
+// the formatter the ETW parser uses.
+fn table_ipv4(addr: u32) -> String {
+    format_ip(&addr.to_ne_bytes()).unwrap_or_default()
+}
+
+fn table_ipv6(octets: [u8; 16]) -> String {
+    format_ip(&octets).unwrap_or_default()
+}
+
+fn add_tcp_row(table: &mut TcpTable, state: u32, entry: TcpTableEntry) {
+    match state {
+        MIB_TCP_STATE_LISTEN => {
+            table.listening.insert((entry.pid, entry.local_port));
+        }
+        MIB_TCP_STATE_ESTAB => {
+            table.established.insert(entry);
+        }
+        _ => {}
+    }
+}
+
+/// One family's raw GetExtendedTcpTable buffer, u32-aligned for the table header.
+fn extended_tcp_table(family: u32, row_size: usize) -> Option<Vec<u32>> {
+    unsafe {
+        let mut size = 0u32;
+        let _ = GetExtendedTcpTable(None, &mut size, false, family, TCP_TABLE_OWNER_PID_ALL, 0);
+        if size == 0 {
+            return None;
+        }
+        // The table can grow between the two calls; leave some headroom
+        size += 16 * row_size as u32;
+        let mut buffer = vec![0u32; (size as usize).div_ceil(std::mem::size_of::<u32>())];
+        let status = GetExtendedTcpTable(
+            Some(buffer.as_mut_ptr() as *mut _),
+            &mut size,
+            false,
+            family,
+            TCP_TABLE_OWNER_PID_ALL,
+            0,
+        );
+        (status == NO_ERROR.0).then_some(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn table_and_etw_format_addresses_identically() {
+        // A MIB row holds the address bytes in network order, read as a native u32
+        let etw_v4 = [93u8, 184, 216, 34];
+        assert_eq!(table_ipv4(u32::from_ne_bytes(etw_v4)), format_ip(&etw_v4).unwrap());
+        assert_eq!(table_ipv4(u32::from_ne_bytes(etw_v4)), "93.184.216.34");
+
+        for text in ["::1", "fe80::1c2d:3e4f:5a6b:7c8d", "2001:db8:85a3:8d3:1319:8a2e:370:7348", "::ffff:10.0.0.1"] {
+            let octets = text.parse::<Ipv6Addr>().unwrap().octets();
+            assert_eq!(table_ipv6(octets), format_ip(&octets).unwrap());
+            assert_eq!(table_ipv6(octets), text);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use crate::error::EdrError;
+use crate::events::{BaseEvent, EventType};
+use crate::utils::common::{cache_process_start, get_command_line_cached, STRICT_PARSING, UNPARSED_RECORDS};
+use crossbeam_channel::{Receiver, Sender};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Older recordings kept beside the live file: <path>.1 (newest), then gzipped
+// <path>.2.gz … <path>.N.gz
+const ROTATED_RECORDINGS_KEPT: u32 = 3;
+
+/// Where `--record` writes every monitor event, and when the file rotates.
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+}
+
+/// Pace used by `--replay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTiming {
+    /// Sleep for the recorded gap between consecutive events.
+    Original,
+    /// Send events back to back.
+    Fast,
+}
+
+/// Sits between the monitors and the correlation engine: every event is appended
+/// to the recording as one JSON line, then forwarded unchanged.
+pub fn start_event_recorder(
+    process_in: Receiver<BaseEvent>,
+    network_in: Receiver<BaseEvent>,
+    process_out: Sender<BaseEvent>,
+    network_out: Sender<BaseEvent>,
+    options: RecordingOptions,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_event_recorder(process_in, network_in, process_out, network_out, options, shutdown);
+    })
+}
+
+fn run_event_recorder(
+    process_in: Receiver<BaseEvent>,
+    network_in: Receiver<BaseEvent>,
+    process_out: Sender<BaseEvent>,
+    network_out: Sender<BaseEvent>,
+    options: RecordingOptions,
+    shutdown: Arc<AtomicBool>,
+) {
+    // Detection keeps running even if the recording can't be written
+    let mut writer = match RecordingWriter::create(options) {
+        Ok(writer) => {
+            log::info!("⏺️  Recording events to {}", writer.options.path.display());
+            Some(writer)
+        }
+        Err(e) => {
+            log::error!("Failed to open event recording: {}", e);
+            None
+        }
+    };
+
+    while shutdown.load(Ordering::Relaxed) {
+        crossbeam_channel::select! {
+            recv(process_in) -> event => {
+                if let Ok(event) = event {
+                    forward(event, &mut writer, &process_out);
+                }
+            },
+            recv(network_in) -> event => {
+                if let Ok(event) = event {
+                    forward(event, &mut writer, &network_out);
+                }
+            },
+            recv(crossbeam_channel::after(Duration::from_millis(100))) -> _ => {}
+        }
+    }
+
+    // Keep whatever the monitors sent while they were stopping
+    for event in process_in.try_iter() {
+        forward(event, &mut writer, &process_out);
+    }
+    for event in network_in.try_iter() {
+        forward(event, &mut writer, &network_out);
+    }
+    if let Some(writer) = writer.as_mut()
+        && let Err(e) = writer.file.flush()
+    {
+        log::error!("Failed to flush event recording: {}", e);
+    }
+}
+
+fn forward(mut event: BaseEvent, writer: &mut Option<RecordingWriter>, out: &Sender<BaseEvent>) {
+    if let Some(active) = writer.as_mut() {
+        // The process monitor doesn't fill in command lines; capture them now so a
+        // replay on another machine sees what the engine saw. Populates the same
+        // cache the engine reads, so the lookup isn't repeated.
+        if let EventType::ProcessStart(process_event) = &mut event.event_type
+            && process_event.command_line.is_empty()
+        {
+            process_event.command_line = get_command_line_cached(process_event.pid).unwrap_or_default();
+        }
+        if let Err(e) = active.write(&event) {
+            log::error!("Event recording stopped: {}", e);
+            *writer = None;
+        }
+    }
+    let _ = out.send(event);
+}
+
+struct RecordingWriter {
+    options: RecordingOptions,
+    file: BufWriter<File>,
+    written: u64,
+}
+
+impl RecordingWriter {
+    fn create(options: RecordingOptions) -> io::Result<Self> {
+        if let Some(parent) = options.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = BufWriter::new(File::create(&options.path)?);
+        Ok(Self { options, file, written: 0 })
+    }
+
+    fn write(&mut self, event: &BaseEvent) -> io::Result<()> {
+        let line = serde_json::to_string(event)?;
+        let line_len = line.len() as u64 + 1;
+        if self.written > 0 && self.written + line_len > self.options.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.written += line_len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let path = &self.options.path;
+        for index in (2..ROTATED_RECORDINGS_KEPT).rev() {
+            for (older, newer) in [
+                (compressed_path(path, index), compressed_path(path, index + 1)),
+                (rotated_path(path, index), rotated_path(path, index + 1)),
+            ] {
+                if older.exists() {
+                    std::fs::rename(&older, newer)?;
+                }
+            }
+        }
+        // The previous segment is compressed on its way down; if that fails it
+        // is kept as plain text and still replays
+        let previous = rotated_path(path, 1);
+        if previous.exists() {
+            match compress_segment(&previous, &compressed_path(path, 2)) {
+                Ok(()) => std::fs::remove_file(&previous)?,
+                Err(e) => {
+                    log::warn!("Could not compress {}: {}", previous.display(), e);
+                    std::fs::rename(&previous, rotated_path(path, 2))?;
+                }
+            }
+        }
+        std::fs::rename(path, &previous)?;
+        self.file = BufWriter::new(File::create(path)?);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(format!(".{}", index));
+    rotated.into()
+}
+
+fn compressed_path(path: &Path, index: u32) -> PathBuf {
+    let mut compressed = OsString::from(rotated_path(path, index).as_os_str());
+    compressed.push(".gz");
+    compressed.into()
+}
+
+fn compress_segment(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(source)?);
+    let mut encoder = flate2::write::GzEncoder::new(
+        BufWriter::new(File::create(destination)?),
+        flate2::Compression::default(),
+    );
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.flush()
+}
+
+/// The recording and every rotated segment beside it that exists, oldest first,
+/// so `--replay-all` runs them in the order they were written. Segments left
+/// uncompressed by an older build or a failed compression are included too.
+pub fn recording_segments(path: &Path) -> Vec<PathBuf> {
+    let mut segments: Vec<PathBuf> = (1..=ROTATED_RECORDINGS_KEPT).rev()
+        .flat_map(|index| [compressed_path(path, index), rotated_path(path, index)])
+        .filter(|segment| segment.exists())
+        .collect();
+    segments.push(path.to_path_buf());
+    segments
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Feeds recording segments, in the order given, into the engine's channels,
+/// routing each event to the channel its monitor would have used. `.gz` segments
+/// are decompressed on the fly. Stops early once `running` clears. Returns the
+/// number of events sent.
+pub fn replay_events(
+    segments: &[PathBuf],
+    timing: ReplayTiming,
+    process_tx: &Sender<BaseEvent>,
+    network_tx: &Sender<BaseEvent>,
+    running: &AtomicBool,
+) -> Result<usize, EdrError> {
+    let mut previous_timestamp: Option<chrono::DateTime<chrono::Utc>> = None;
+    let mut sent = 0;
+    read_recording(segments, |event| {
+        if !running.load(Ordering::Relaxed) {
+            return false;
+        }
+        if timing == ReplayTiming::Original
+            && let Some(previous) = previous_timestamp
+        {
+            let gap = (event.timestamp - previous).to_std().unwrap_or_default();
+            sleep_while_running(gap, running);
+        }
+        previous_timestamp = Some(event.timestamp);
+
+        let target = match &event.event_type {
+            EventType::ProcessStart(process_event) => {
+                // Stand in for the process monitor so name/command-line lookups hit the recording
+                let command_line = Some(process_event.command_line.clone()).filter(|c| !c.is_empty());
+                cache_process_start(process_event.pid, process_event.parent_pid, &process_event.process_name, command_line);
+                process_tx
+            }
+            EventType::ProcessEnd(_)
+            | EventType::RegistryChange(_)
+            | EventType::NamedPipeCreated(_)
+            | EventType::ScheduledTaskChanged(_)
+            | EventType::RemovableMediaArrived(_)
+            | EventType::HostsFileChanged(_)
+            | EventType::Alert(_) => process_tx,
+            EventType::NetworkConnection(_)
+            | EventType::DnsQuery(_)
+            | EventType::EtwProvider(_)
+            | EventType::FileAccess(_) => network_tx,
+        };
+        if target.send(event).is_err() {
+            return false;
+        }
+        sent += 1;
+        true
+    })?;
+    Ok(sent)
+}
+
+/// Reads recording segments in the order given and hands each event to `visit`
+/// until it returns false. `.gz` segments are decompressed on the fly; malformed
+/// lines are skipped and a segment cut short is read up to the cut, both counted
+/// in `UNPARSED_RECORDS`. Under `--strict` either one is an error instead.
+pub fn read_recording(segments: &[PathBuf], mut visit: impl FnMut(BaseEvent) -> bool) -> Result<(), EdrError> {
+    for segment in segments {
+        let replay_error = |reason: String| EdrError::Replay {
+            path: segment.display().to_string(),
+            reason,
+        };
+        let file = File::open(segment).map_err(|e| replay_error(e.to_string()))?;
+        let reader: Box<dyn BufRead> = if is_compressed(segment) {
+            Box::new(BufReader::new(flate2::read::GzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        if segments.len() > 1 {
+            log::info!("⏯️  Segment {}", segment.display());
+        }
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                // A segment cut short (the EDR stopped mid-write) still replays up to the cut
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    if STRICT_PARSING.load(Ordering::Relaxed) {
+                        return Err(replay_error(format!("ends early after line {}: {}", index, e)));
+                    }
+                    UNPARSED_RECORDS.fetch_add(1, Ordering::Relaxed);
+                    log::warn!("{} ends early after line {}: {}", segment.display(), index, e);
+                    break;
+                }
+                Err(e) => return Err(replay_error(e.to_string())),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: BaseEvent = match serde_json::from_str(&line) {
+                Ok(event) => event,
+                Err(e) => {
+                    if STRICT_PARSING.load(Ordering::Relaxed) {
+                        return Err(replay_error(format!("line {} is not a valid event: {}", index + 1, e)));
+                    }
+                    UNPARSED_RECORDS.fetch_add(1, Ordering::Relaxed);
+                    log::warn!("Skipping malformed event on line {} of {}: {}", index + 1, segment.display(), e);
+                    continue;
+                }
+            };
+            if !visit(event) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn sleep_while_running(duration: Duration, running: &AtomicBool) {
+    let deadline = std::time::Instant::now() + duration;
+    while running.load(Ordering::Relaxed) {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        std::thread::sleep(remaining.min(Duration::from_millis(200)));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::monitoring::correlation_engine::{ALERTS_RAISED, EVENTS_PROCESSED, TRACKED_CONTEXTS};
+use crate::monitoring::etw_watchdog::{EtwHeartbeat, NETWORK_HEARTBEAT, PROCESS_HEARTBEAT};
+use crate::monitoring::supervisor::{dead_monitors, MONITOR_RESTARTS};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Logs an info line every `interval` with uptime, per-monitor event counts and
+/// ETW session health, so a quiet log still shows the agent is alive.
+pub fn start_heartbeat(interval: Duration, shutdown: Arc<AtomicBool>) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        let mut last_beat = Instant::now();
+        while shutdown.load(Ordering::Relaxed) {
+            if last_beat.elapsed() >= interval {
+                log_heartbeat(started.elapsed());
+                last_beat = Instant::now();
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    })
+}
+
+fn log_heartbeat(uptime: Duration) {
+    log::info!(
+        "💓 Heartbeat: up {} | {} | {} | engine {} events, {} alerts, {} contexts",
+        format_uptime(uptime),
+        session_summary("process ETW", &PROCESS_HEARTBEAT),
+        session_summary("network ETW", &NETWORK_HEARTBEAT),
+        EVENTS_PROCESSED.load(Ordering::Relaxed),
+        ALERTS_RAISED.load(Ordering::Relaxed),
+        TRACKED_CONTEXTS.load(Ordering::Relaxed),
+    );
+    let dead = dead_monitors();
+    if !dead.is_empty() {
+        log::error!(
+            "💀 Monitors down: {} ({} restarts so far) — detection is degraded",
+            dead.join(", "),
+            MONITOR_RESTARTS.load(Ordering::Relaxed),
+        );
+    }
+}
+
+fn session_summary(label: &str, heartbeat: &EtwHeartbeat) -> String {
+    let health = if heartbeat.is_degraded() {
+        format!("DEGRADED, silent {}s", heartbeat.seconds_silent())
+    } else {
+        "ok".to_string()
+    };
+    format!("{} {} events ({})", label, heartbeat.events(), health)
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3_600, secs % 3_600 / 60);
+    if days > 0 {
+        format!("{}d {:02}h {:02}m", days, hours, minutes)
+    } else {
+        format!("{}h {:02}m", hours, minutes)
+    }
+}
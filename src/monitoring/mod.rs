@@ -1,7 +1,31 @@
+pub mod baseline;
+pub mod clock;
+pub mod config_watcher;
 pub mod correlation_engine;
+pub mod decision_log;
+pub mod etw_session;
+pub mod etw_watchdog;
+pub mod heartbeat;
 pub mod process;
 pub mod network;
+pub mod registry;
+pub mod named_pipes;
+pub mod scheduled_tasks;
+pub mod removable_media;
+pub mod pipe_server;
+pub mod notifier;
+pub mod control_pipe;
+pub mod alert_handler;
+pub mod event_recorder;
+pub mod supervisor;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_sink;
 
 pub use process::start_process_monitor;
 pub use network::start_network_monitor;
-pub use correlation_engine::start_correlation_engine;
\ No newline at end of file
+pub use registry::start_registry_monitor;
+pub use named_pipes::start_named_pipe_monitor;
+pub use scheduled_tasks::start_scheduled_task_monitor;
+pub use removable_media::start_removable_media_monitor;
+pub use correlation_engine::start_correlation_engine;
+pub use alert_handler::start_alert_handler;
\ No newline at end of file
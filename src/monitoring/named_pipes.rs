@@ -0,0 +1,63 @@
+use crate::events::{BaseEvent, EventType, NamedPipeEvent};
+use crossbeam_channel::Sender;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use windows::Win32::Storage::FileSystem::{FindClose, FindFirstFileW, FindNextFileW, WIN32_FIND_DATAW};
+use windows::core::w;
+
+/// Polls the named-pipe namespace and reports pipes that appeared since the last
+/// scan. Only names are read; pipes are never opened, so servers are unaffected.
+pub fn start_named_pipe_monitor(
+    tx: Sender<BaseEvent>,
+    poll_interval: Duration,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_named_pipe_monitor(tx, poll_interval, shutdown);
+    })
+}
+
+fn run_named_pipe_monitor(tx: Sender<BaseEvent>, poll_interval: Duration, shutdown: Arc<AtomicBool>) {
+    log::info!("✅ Named Pipe Monitor scanning \\\\.\\pipe\\ every {}s", poll_interval.as_secs());
+
+    // The first scan is the baseline; pipes that already exist aren't reported
+    let mut known: Option<HashSet<String>> = None;
+    let mut last_scan: Option<Instant> = None;
+    while shutdown.load(Ordering::Relaxed) {
+        if last_scan.is_none_or(|scanned| scanned.elapsed() >= poll_interval) {
+            if let Some(current) = list_named_pipes() {
+                if let Some(previous) = &known {
+                    for pipe_name in current.difference(previous) {
+                        let event = NamedPipeEvent { pipe_name: pipe_name.clone() };
+                        let _ = tx.send(BaseEvent::new(EventType::NamedPipeCreated(event)));
+                    }
+                }
+                known = Some(current);
+            }
+            last_scan = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    log::info!("✅ Named Pipe Monitor stopped");
+}
+
+fn list_named_pipes() -> Option<HashSet<String>> {
+    let mut data = WIN32_FIND_DATAW::default();
+    let handle = unsafe { FindFirstFileW(w!("\\\\.\\pipe\\*"), &mut data) }.ok()?;
+
+    let mut pipes = HashSet::new();
+    loop {
+        let len = data.cFileName.iter().position(|&c| c == 0).unwrap_or(data.cFileName.len());
+        pipes.insert(String::from_utf16_lossy(&data.cFileName[..len]));
+        if unsafe { FindNextFileW(handle, &mut data) }.is_err() {
+            break;
+        }
+    }
+    unsafe {
+        let _ = FindClose(handle);
+    }
+    Some(pipes)
+}
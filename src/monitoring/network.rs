@@ -1,14 +1,20 @@
 use crate::events::{BaseEvent, EventType};
-use crate::events::network::NetworkEvent;
+use crate::events::network::{ConnectionState, NetworkDirection, NetworkEvent};
+use crate::events::DnsEvent;
+use crate::config::rules::{EtwFlags, EtwProvider};
+use crate::events::{EtwProviderEvent, FileAccessEvent};
+use crate::events::file::FileWatch;
 use crossbeam_channel::Sender;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use crate::utils::common::{
     is_browser_related_process,
     is_system_process,
     get_process_name_cached,
     get_timestamp,
     cleanup_tracking_data,
+    is_etw_dropped_process,
+    credential_store_match, is_user_document_path,
     ConnectionAttempt,
     GLOBAL_SENDER,
     RECENT_CONNECTIONS,
@@ -17,134 +23,316 @@ use crate::utils::common::{
     QUIC_CONNECTIONS,
 };
 use windows::Win32::System::Diagnostics::Etw::*;
-use windows::core::{GUID, PWSTR};
-use windows::Win32::Foundation::ERROR_SUCCESS;
+use crate::monitoring::etw_session::{EtwSession, SenderRegistration, TraceConsumer};
+use crate::monitoring::etw_watchdog::{NETWORK_HEARTBEAT, TcpTable, TcpTableEntry, tcp_table};
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Mutex, RwLock};
 
 const TCPIP_PROVIDER_GUID: u128 = 0x7dd42a49532948328dfd43d979153a88u128;
+// Microsoft-Windows-DNS-Client
+const DNS_CLIENT_PROVIDER_GUID: u128 = 0x1c95126e7eea49a9a3fea378b03ddb4du128;
+// Microsoft-Windows-Kernel-File
+const KERNEL_FILE_PROVIDER_GUID: u128 = 0xedd089279cc44e65b970c2560fb5c289u128;
+// Payload bytes kept (as hex) from events of providers the monitor doesn't parse
+const MAX_PROVIDER_PAYLOAD_BYTES: usize = 256;
+
+// (pid, local address, local port, remote address, remote port) of one TCP connection
+type TcpConnectionKey = (u32, String, u16, String, u16);
+
+lazy_static::lazy_static! {
+    // Extra providers enabled from `etw_providers`, keyed by GUID: (name, GUID as configured)
+    static ref EXTRA_PROVIDERS: RwLock<HashMap<u128, (String, String)>> = RwLock::new(HashMap::new());
+    // Live TCP connections whose connect or accept was traced: (pid, local port, remote address, remote port) → direction
+    static ref TCP_DIRECTIONS: Mutex<HashMap<(u32, u16, String, u16), NetworkDirection>> = Mutex::new(HashMap::new());
+    // Bytes moved on live TCP connections → (sent, received)
+    static ref TCP_BYTE_COUNTS: Mutex<HashMap<TcpConnectionKey, (u64, u64)>> = Mutex::new(HashMap::new());
+    // (pid, port) of the listening sockets in the last TCP table poll
+    static ref LISTENING_PORTS: RwLock<HashSet<(u32, u16)>> = RwLock::new(HashSet::new());
+}
+
+// Connections whose disconnect was missed are dropped with the next TCP table poll;
+// without polling the direction and byte-count maps are cleared past this size
+const MAX_TCP_DIRECTIONS: usize = 10_000;
+
+static EPHEMERAL_PORT_START: AtomicU16 = AtomicU16::new(49152);
+
+pub fn set_ephemeral_port_start(port: u16) {
+    EPHEMERAL_PORT_START.store(port, Ordering::Relaxed);
+}
+
+/// What a TCP event or table row shows about a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpObservation {
+    /// This host called connect (or reconnected).
+    Connected,
+    /// A local listening socket accepted the peer.
+    Accepted,
+    /// Send, receive, disconnect or a table row: traffic on a connection opened earlier.
+    Traffic,
+}
+
+/// Which side opened a TCP connection. A connect is outbound and an accept is
+/// inbound. For traffic, the direction traced at connect or accept is kept. Failing
+/// that, a local port this process listens on means the peer connected in. Failing
+/// that, the side on an ephemeral port (at or above `ephemeral_start`) is the client.
+/// With no better evidence, the connection is taken as outbound.
+pub fn infer_tcp_direction(
+    observation: TcpObservation,
+    traced: Option<NetworkDirection>,
+    local_port_listening: bool,
+    local_port: u16,
+    remote_port: u16,
+    ephemeral_start: u16,
+) -> NetworkDirection {
+    match observation {
+        TcpObservation::Connected => NetworkDirection::Outbound,
+        TcpObservation::Accepted => NetworkDirection::Inbound,
+        TcpObservation::Traffic => {
+            if let Some(direction) = traced {
+                return direction;
+            }
+            let local_ephemeral = local_port >= ephemeral_start;
+            let remote_ephemeral = remote_port >= ephemeral_start;
+            if local_port_listening || (remote_ephemeral && !local_ephemeral) {
+                NetworkDirection::Inbound
+            } else {
+                NetworkDirection::Outbound
+            }
+        }
+    }
+}
+
+/// Direction of one TCP/IP event, remembering it from connect and accept events so
+/// the connection's later traffic keeps it, and forgetting it at disconnect.
+fn tcp_event_direction(pid: u32, event_id: u16, local_port: u16, remote_addr: &str, remote_port: u16) -> NetworkDirection {
+    let observation = match event_id {
+        EVENT_ID_TCPIP_CONNECT | EVENT_ID_TCPIP_RECONNECT => TcpObservation::Connected,
+        EVENT_ID_TCPIP_ACCEPT => TcpObservation::Accepted,
+        _ => TcpObservation::Traffic,
+    };
+    let key = (pid, local_port, remote_addr.to_string(), remote_port);
+    let Ok(mut traced) = TCP_DIRECTIONS.lock() else {
+        return infer_tcp_direction(observation, None, false, local_port, remote_port, EPHEMERAL_PORT_START.load(Ordering::Relaxed));
+    };
+    let listening = LISTENING_PORTS.read().is_ok_and(|listening| listening.contains(&(pid, local_port)));
+    let direction = infer_tcp_direction(
+        observation,
+        traced.get(&key).copied(),
+        listening,
+        local_port,
+        remote_port,
+        EPHEMERAL_PORT_START.load(Ordering::Relaxed),
+    );
+    if event_id == EVENT_ID_TCPIP_DISCONNECT {
+        traced.remove(&key);
+    } else if observation != TcpObservation::Traffic {
+        if traced.len() >= MAX_TCP_DIRECTIONS {
+            traced.clear();
+        }
+        traced.insert(key, direction);
+    }
+    direction
+}
+
+/// Running (sent, received) totals of one TCP connection, adding `size` for a send
+/// or receive event. The totals are dropped at disconnect, after being returned.
+fn tcp_byte_totals(pid: u32, event_id: u16, local_addr: &str, local_port: u16, remote_addr: &str, remote_port: u16, size: u64) -> (u64, u64) {
+    let key = (pid, local_addr.to_string(), local_port, remote_addr.to_string(), remote_port);
+    let Ok(mut counts) = TCP_BYTE_COUNTS.lock() else {
+        return (0, 0);
+    };
+    match event_id {
+        EVENT_ID_TCPIP_SEND | EVENT_ID_TCPIP_RECV => {
+            if counts.len() >= MAX_TCP_DIRECTIONS && !counts.contains_key(&key) {
+                counts.clear();
+            }
+            let totals = counts.entry(key).or_default();
+            if event_id == EVENT_ID_TCPIP_SEND {
+                totals.0 = totals.0.saturating_add(size);
+            } else {
+                totals.1 = totals.1.saturating_add(size);
+            }
+            *totals
+        }
+        EVENT_ID_TCPIP_DISCONNECT => counts.remove(&key).unwrap_or_default(),
+        _ => counts.get(&key).copied().unwrap_or_default(),
+    }
+}
 
 const EVENT_ID_TCPIP_SEND: u16 = 10;
 const EVENT_ID_TCPIP_RECV: u16 = 11;
 const EVENT_ID_TCPIP_CONNECT: u16 = 12;
 const EVENT_ID_TCPIP_DISCONNECT: u16 = 13;
+const EVENT_ID_TCPIP_ACCEPT: u16 = 15;
 const EVENT_ID_TCPIP_RECONNECT: u16 = 16;
 
 const EVENT_ID_UDP_SEND: u16 = 42;
 const EVENT_ID_UDP_RECV: u16 = 43;
 
-#[repr(C)]
-struct TcpIpV4Event {
-    pid: u32,
-    size: u32,
-    daddr: u32,
-    saddr: u32,
-    dport: u16,
-    sport: u16,
-}
+// Each IPv6 event (TcpSendIPV6 = 26 … TcpReconnectIPV6 = 32, UdpSendIPV6 = 58,
+// UdpRecvIPV6 = 59) sits this far above its IPv4 counterpart
+const IPV6_EVENT_ID_OFFSET: u16 = 16;
 
-#[repr(C)]
-struct TcpIpV6Event {
-    pid: u32,
-    size: u32,
-    daddr: [u8; 16],
-    saddr: [u8; 16],
-    dport: u16,
-    sport: u16,
-}
+const EVENT_ID_DNS_QUERY_COMPLETED: u16 = 3008;
+
+const EVENT_ID_FILE_CREATE: u16 = 12;
+const EVENT_ID_FILE_RENAME_PATH: u16 = 27;
+const EVENT_ID_FILE_CREATE_NEW: u16 = 30;
+
+// Kernel-File Create payload: Irp and FileObject (pointers), IssuingThreadId,
+// CreateOptions, CreateAttributes, ShareAccess (u32 each), then FileName (UTF-16)
+const FILE_CREATE_FIXED_FIELDS_LEN: usize = 4 * 4;
+// CreateOptions carries the create disposition in its top byte; FILE_OPEN only
+// opens an existing file, every other disposition can create or overwrite it
+const FILE_OPEN_DISPOSITION: u32 = 1;
+// FILE_SUPERSEDE, FILE_OVERWRITE and FILE_OVERWRITE_IF replace an existing file's contents
+const OVERWRITE_DISPOSITIONS: [u32; 3] = [0, 4, 5];
+// Kernel-File RenamePath payload: Irp, FileObject, FileKey, ExtraInformation
+// (pointers), IssuingThreadId, InfoClass (u32 each), then FilePath (UTF-16)
+const FILE_RENAME_POINTERS: usize = 4;
+const FILE_RENAME_FIXED_FIELDS_LEN: usize = 2 * 4;
+const HOSTS_FILE_SUFFIX: &str = "\\windows\\system32\\drivers\\etc\\hosts";
+
+// TCP/IP payload layout: PID (u32), size (u32), daddr, saddr, dport, sport.
+// Addresses and ports are in network byte order; the size is little-endian.
+const TCPIP_SIZE_OFFSET: usize = 4;
+const TCPIP_ADDR_OFFSET: usize = 8;
+const TCPIP_V4_EVENT_LEN: usize = TCPIP_ADDR_OFFSET + 4 + 4 + 2 + 2;
+const TCPIP_V6_EVENT_LEN: usize = TCPIP_ADDR_OFFSET + 16 + 16 + 2 + 2;
 
+/// Traces the providers in `etw_providers` on one session. TCP/IP and DNS-Client
+/// records feed the network detections and Kernel-File opens of credential stores
+/// the file detection (when their `etw_flags` are on); records from any other
+/// listed provider are forwarded as `EtwProviderEvent`s.
 pub fn start_network_monitor(
     tx: Sender<BaseEvent>,
+    etw_flags: EtwFlags,
+    providers: Vec<EtwProvider>,
     shutdown: Arc<AtomicBool>,
 ) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
-        run_network_monitor(tx, shutdown);
+        run_network_monitor(tx, etw_flags, providers, shutdown);
     })
 }
 
+/// Whether the network session has anything to trace with these settings.
+pub fn network_session_needed(etw_flags: &EtwFlags, providers: &[EtwProvider]) -> bool {
+    providers.iter()
+        .filter(|provider| provider.enabled)
+        .any(|provider| match provider.guid_value() {
+            Ok(TCPIP_PROVIDER_GUID) => etw_flags.network,
+            Ok(DNS_CLIENT_PROVIDER_GUID) => etw_flags.dns,
+            Ok(KERNEL_FILE_PROVIDER_GUID) => etw_flags.file,
+            _ => true,
+        })
+}
+
 pub fn run_network_monitor(
     tx: Sender<BaseEvent>,
+    etw_flags: EtwFlags,
+    providers: Vec<EtwProvider>,
     shutdown: Arc<AtomicBool>,
 ) {
     unsafe {
-        {
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = Some(Arc::new(tx.clone()));
-        }
+        let _sender = SenderRegistration::install(&tx);
 
-        let session_name = widestring::U16CString::from_str("HIDS_NETWORK_MONITOR").unwrap();
-
-        // Stop any existing session before starting a new one
-        let mut stop_buffer = vec![0u8; std::mem::size_of::<EVENT_TRACE_PROPERTIES>() + 1024];
-        let stop_props = stop_buffer.as_mut_ptr() as *mut EVENT_TRACE_PROPERTIES;
-        (*stop_props).Wnode.BufferSize = stop_buffer.len() as u32;
-        (*stop_props).LoggerNameOffset = std::mem::size_of::<EVENT_TRACE_PROPERTIES>() as u32;
-        let stop_result = ControlTraceW(
-            CONTROLTRACE_HANDLE::default(),
-            PWSTR(session_name.as_ptr() as *mut u16),
-            stop_props,
-            EVENT_TRACE_CONTROL_STOP,
-        );
-        if stop_result == ERROR_SUCCESS {
-            log::info!("Stopped Existing Network Monitor Session");
-        }
-
-        let mut buffer = vec![0u8; std::mem::size_of::<EVENT_TRACE_PROPERTIES>() + 1024];
-        let props = buffer.as_mut_ptr() as *mut EVENT_TRACE_PROPERTIES;
-        (*props).Wnode.BufferSize = buffer.len() as u32;
-        (*props).Wnode.ClientContext = 1;
-        (*props).LogFileMode = EVENT_TRACE_REAL_TIME_MODE;
-        (*props).LoggerNameOffset = std::mem::size_of::<EVENT_TRACE_PROPERTIES>() as u32;
-
-        let mut session_handle = CONTROLTRACE_HANDLE::default();
-        let status = StartTraceW(
-            &mut session_handle,
-            PWSTR(session_name.as_ptr() as *mut u16),
-            props,
-        );
-
-        if status != ERROR_SUCCESS {
-            log::error!("Network Monitor StartTraceW Failed: 0x{:08X}", status.0);
-            match status.0 {
-                0x000000B7 => log::error!("Network Monitor already running"),
-                0x00000005 => log::error!("Access denied - run as Administrator"),
-                _ => {}
+        // EtwSession::start stops any leftover session with this name first
+        let session = match EtwSession::start("HIDS_NETWORK_MONITOR", |props| {
+            props.Wnode.ClientContext = 1;
+            props.LogFileMode = EVENT_TRACE_REAL_TIME_MODE;
+        }) {
+            Ok(session) => session,
+            Err(status) => {
+                log::error!("Network Monitor StartTraceW Failed: 0x{:08X}", status.0);
+                match status.0 {
+                    0x000000B7 => log::error!("Network Monitor already running"),
+                    0x00000005 => log::error!("Access denied - run as Administrator"),
+                    _ => {}
+                }
+                return;
             }
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = None;
-            return;
-        }
+        };
         log::info!("✅ Network Monitor session started");
 
-        let provider_guid = GUID::from_u128(TCPIP_PROVIDER_GUID);
-        let enable_result = EnableTraceEx2(
-            session_handle,
-            &provider_guid,
-            EVENT_CONTROL_CODE_ENABLE_PROVIDER.0 as u32,
-            5,
-            0xFFFFFFFF,
-            0,
-            0,
-            None,
-        );
-        if enable_result != ERROR_SUCCESS {
-            log::warn!("EnableTraceEx2 failed: 0x{:08X}", enable_result.0);
-        } else {
-            log::info!("✅ TCP/IP provider enabled");
+        let mut extra_providers = HashMap::new();
+        for provider in providers.iter().filter(|provider| provider.enabled) {
+            let Ok(guid) = provider.guid_value() else {
+                continue;
+            };
+            let wanted = match guid {
+                TCPIP_PROVIDER_GUID => etw_flags.network,
+                DNS_CLIENT_PROVIDER_GUID => etw_flags.dns,
+                KERNEL_FILE_PROVIDER_GUID => etw_flags.file,
+                _ => true,
+            };
+            if !wanted {
+                continue;
+            }
+            match session.enable_provider(provider) {
+                Ok(()) => log::info!("✅ {} provider enabled (level {}, keywords {})", provider.name, provider.level, provider.keywords),
+                Err(status) => {
+                    log::warn!("{} EnableTraceEx2 failed: 0x{:08X}", provider.name, status.0);
+                    continue;
+                }
+            }
+            if !matches!(guid, TCPIP_PROVIDER_GUID | DNS_CLIENT_PROVIDER_GUID | KERNEL_FILE_PROVIDER_GUID) {
+                extra_providers.insert(guid, (provider.name.clone(), provider.guid.clone()));
+            }
+        }
+        if let Ok(mut guard) = EXTRA_PROVIDERS.write() {
+            *guard = extra_providers;
         }
 
         let mut logfile: EVENT_TRACE_LOGFILEW = std::mem::zeroed();
-        logfile.LoggerName = PWSTR(session_name.as_ptr() as *mut u16);
+        logfile.LoggerName = session.logger_name();
         logfile.Anonymous1.ProcessTraceMode = PROCESS_TRACE_MODE_REAL_TIME | PROCESS_TRACE_MODE_EVENT_RECORD;
 
+        // ETW invokes this across an FFI boundary; a panic while parsing a malformed
+        // record must not unwind into the OS, so it is caught, logged and skipped.
         unsafe extern "system" fn tcpip_callback(record: *mut EVENT_RECORD) {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+                handle_tcpip_record(record);
+            }));
+            if let Err(panic) = result {
+                log::error!(
+                    "Network Monitor callback panicked, skipping event record: {}",
+                    crate::monitoring::supervisor::panic_message(panic.as_ref())
+                );
+            }
+        }
+
+        unsafe fn handle_tcpip_record(record: *mut EVENT_RECORD) {
             if record.is_null() {
                 return;
             }
+            NETWORK_HEARTBEAT.record_event();
 
             let rec = unsafe { &*record };
             let header = &rec.EventHeader;
             let pid = header.ProcessId;
             let event_id = header.EventDescriptor.Id;
 
+            let provider_guid = header.ProviderId.to_u128();
+            if provider_guid == KERNEL_FILE_PROVIDER_GUID {
+                if pid > 4 {
+                    match event_id {
+                        EVENT_ID_FILE_CREATE => handle_file_create(rec),
+                        // CreateNewFile shares the Create payload layout
+                        EVENT_ID_FILE_CREATE_NEW => handle_document_change(rec, 2, FILE_CREATE_FIXED_FIELDS_LEN, "create"),
+                        EVENT_ID_FILE_RENAME_PATH => {
+                            handle_document_change(rec, FILE_RENAME_POINTERS, FILE_RENAME_FIXED_FIELDS_LEN, "rename")
+                        }
+                        _ => {}
+                    }
+                }
+                return;
+            }
+            if provider_guid != TCPIP_PROVIDER_GUID && provider_guid != DNS_CLIENT_PROVIDER_GUID {
+                forward_provider_event(rec, provider_guid);
+                return;
+            }
+
             if pid <= 4 {
                 return;
             }
@@ -155,24 +343,44 @@ pub fn run_network_monitor(
             if !is_browser && is_system_process(&process_name) {
                 return;
             }
+            if is_etw_dropped_process(&process_name) {
+                return;
+            }
 
             if rec.UserDataLength == 0 || rec.UserData.is_null() {
                 return;
             }
 
-            let (saddr, daddr, sport, dport, _is_ipv6) = match event_id {
+            // All payload parsing below is bounds-checked against this slice.
+            let data = unsafe {
+                std::slice::from_raw_parts(rec.UserData as *const u8, rec.UserDataLength as usize)
+            };
+
+            if provider_guid == DNS_CLIENT_PROVIDER_GUID {
+                if event_id == EVENT_ID_DNS_QUERY_COMPLETED {
+                    handle_dns_query_completed(pid, &process_name, data);
+                }
+                return;
+            }
+
+            // The IPv6 events carry the same fields with wider addresses; handle them as their IPv4 twins
+            let (event_id, is_ipv6) = match event_id {
+                26..=32 | 58 | 59 => (event_id - IPV6_EVENT_ID_OFFSET, true),
+                _ => (event_id, false),
+            };
+            let (saddr, sport, daddr, dport) = match event_id {
                 EVENT_ID_TCPIP_SEND | EVENT_ID_TCPIP_RECV    |
                 EVENT_ID_TCPIP_CONNECT | EVENT_ID_TCPIP_DISCONNECT |
-                EVENT_ID_TCPIP_RECONNECT | EVENT_ID_UDP_SEND | EVENT_ID_UDP_RECV => {
-                    parse_tcpip_event(rec.UserData, rec.UserDataLength as usize)
+                EVENT_ID_TCPIP_ACCEPT | EVENT_ID_TCPIP_RECONNECT |
+                EVENT_ID_UDP_SEND | EVENT_ID_UDP_RECV => {
+                    match parse_tcpip_userdata(data, is_ipv6) {
+                        Some(endpoints) => endpoints,
+                        None => return,
+                    }
                 }
                 _ => return,
             };
 
-            if saddr.is_empty() || daddr.is_empty() {
-                return;
-            }
-
             let protocol = match event_id {
                 EVENT_ID_UDP_SEND | EVENT_ID_UDP_RECV => "UDP",
                 _                                     => "TCP",
@@ -181,7 +389,7 @@ pub fn run_network_monitor(
             let network_type = classify_network_connection(&saddr, &daddr);
 
             if protocol == "UDP" {
-                handle_udp_event(pid, &process_name, &saddr, sport, &daddr, dport, network_type, event_id);
+                handle_udp_event(pid, &process_name, &saddr, sport, &daddr, dport, network_type);
                 return;
             }
 
@@ -189,21 +397,29 @@ pub fn run_network_monitor(
                 return;
             }
 
+            // An accepted connection means the local port is a listener; LAN peers
+            // matter here too (reverse-shell servers are often reached from the LAN).
+            let is_lan_accept = event_id == EVENT_ID_TCPIP_ACCEPT && network_type == "LocalNetwork";
+
             if network_type != "External"
+                && !is_lan_accept
                 && !is_browser
                 && !is_suspicious_loopback(&process_name, sport, dport)
             {
                 return;
             }
 
-            let net_direction = match event_id {
-                EVENT_ID_TCPIP_SEND | EVENT_ID_TCPIP_CONNECT | EVENT_ID_TCPIP_RECONNECT =>
-                    crate::events::network::NetworkDirection::Outbound,
-                _ =>
-                    crate::events::network::NetworkDirection::Inbound,
-            };
+            let net_direction = tcp_event_direction(pid, event_id, sport, &daddr, dport);
+            // parse_tcpip_userdata has checked the payload covers the fixed fields
+            let size = u32::from_le_bytes([
+                data[TCPIP_SIZE_OFFSET],
+                data[TCPIP_SIZE_OFFSET + 1],
+                data[TCPIP_SIZE_OFFSET + 2],
+                data[TCPIP_SIZE_OFFSET + 3],
+            ]) as u64;
+            let (bytes_sent, bytes_received) = tcp_byte_totals(pid, event_id, &saddr, sport, &daddr, dport, size);
 
-            let net = NetworkEvent::new(
+            let mut net = NetworkEvent::new(
                 pid,
                 process_name,
                 net_direction,
@@ -213,37 +429,34 @@ pub fn run_network_monitor(
                 daddr,
                 dport,
             );
+            net.bytes_sent = bytes_sent;
+            net.bytes_received = bytes_received;
             let base = BaseEvent::new(EventType::NetworkConnection(net));
 
-            if let Ok(guard) = GLOBAL_SENDER.lock() {
-                if let Some(sender) = guard.as_ref() {
-                    let _ = sender.send(base);
-                }
+            if let Ok(guard) = GLOBAL_SENDER.lock()
+                && let Some(sender) = guard.as_ref()
+            {
+                let _ = sender.send(base);
             }
         }
 
         logfile.Anonymous2.EventRecordCallback = Some(tcpip_callback);
 
-        let trace_handle = OpenTraceW(&mut logfile);
-        if trace_handle.Value == u64::MAX {
+        let Some(consumer) = TraceConsumer::open(&mut logfile) else {
             log::error!("Network Monitor OpenTraceW Failed");
-            let _ = ControlTraceW(
-                session_handle,
-                PWSTR(session_name.as_ptr() as *mut u16),
-                props,
-                EVENT_TRACE_CONTROL_STOP,
-            );
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = None;
             return;
-        }
+        };
         log::info!("✅ Network Monitor OpenTraceW Opened");
+        NETWORK_HEARTBEAT.beat();
 
+        let trace_handle = consumer.handle();
         let etw_thread = std::thread::spawn(move || {
             let _ = ProcessTrace(&[trace_handle], None, None);
         });
 
         let mut cleanup_counter = 0u32;
+        let mut watchdog_counter = 0u32;
+        let mut known_connections: Option<HashMap<TcpTableEntry, ConnectionSeen>> = None;
         while shutdown.load(std::sync::atomic::Ordering::Relaxed) {
             std::thread::sleep(std::time::Duration::from_millis(200));
             cleanup_counter += 1;
@@ -252,25 +465,158 @@ pub fn run_network_monitor(
                 cleanup_tracking_data();
                 cleanup_counter = 0;
             }
+            watchdog_counter += 1;
+            if etw_flags.network && watchdog_counter >= 25 {
+                // ~5 seconds
+                poll_tcp_table(&mut known_connections, &tx);
+                watchdog_counter = 0;
+            }
         }
 
-        let _ = CloseTrace(trace_handle);
-        let _ = ControlTraceW(
-            session_handle,
-            PWSTR(session_name.as_ptr() as *mut u16),
-            props,
-            EVENT_TRACE_CONTROL_STOP,
-        );
+        // Closing the consumer unblocks ProcessTrace; the session guard stops the logger
+        drop(consumer);
+        drop(session);
         let _ = etw_thread.join();
 
-        {
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = None;
-        }
         log::info!("✅ Network Monitor session stopped");
     }
 }
 
+/// When the TCP table poll first and last saw a connection.
+#[derive(Debug, Clone, Copy)]
+struct ConnectionSeen {
+    first_seen: chrono::DateTime<chrono::Utc>,
+    last_seen: chrono::DateTime<chrono::Utc>,
+}
+
+/// Diffs the system TCP table against the previous poll. New connections while
+/// the TCP/IP session is silent mean ETW is dead; once it is marked degraded the
+/// new external connections are reported here instead. External connections that
+/// left the table are reported as closed, with how long they were seen open.
+fn poll_tcp_table(known: &mut Option<HashMap<TcpTableEntry, ConnectionSeen>>, tx: &Sender<BaseEvent>) {
+    let Some(TcpTable { established: current, listening }) = tcp_table() else {
+        return;
+    };
+    let now = chrono::Utc::now();
+    // Directions and byte counts traced for connections that have since closed are no longer needed.
+    // The local address is left out, as the table may show a wildcard bind where ETW has the interface
+    let live: HashSet<(u32, u16, &str, u16)> = current.iter()
+        .map(|entry| (entry.pid, entry.local_port, entry.remote_addr.as_str(), entry.remote_port))
+        .collect();
+    if let Ok(mut traced) = TCP_DIRECTIONS.lock() {
+        traced.retain(|(pid, local_port, remote_addr, remote_port), _| {
+            live.contains(&(*pid, *local_port, remote_addr.as_str(), *remote_port))
+        });
+    }
+    if let Ok(mut counts) = TCP_BYTE_COUNTS.lock() {
+        counts.retain(|(pid, _, local_port, remote_addr, remote_port), _| {
+            live.contains(&(*pid, *local_port, remote_addr.as_str(), *remote_port))
+        });
+    }
+    let new_connections: Vec<&TcpTableEntry> = match known.as_ref() {
+        Some(previous) => current.iter().filter(|entry| !previous.contains_key(*entry)).collect(),
+        None => Vec::new(),
+    };
+
+    if NETWORK_HEARTBEAT.check(!new_connections.is_empty()) {
+        for entry in new_connections {
+            if entry.pid <= 4 {
+                continue;
+            }
+            let process_name = get_process_name_cached(entry.pid);
+            let is_browser = is_browser_related_process(entry.pid, &process_name);
+            if (!is_browser && is_system_process(&process_name)) || is_etw_dropped_process(&process_name) {
+                continue;
+            }
+            if classify_network_connection(&entry.local_addr, &entry.remote_addr) != "External" {
+                continue;
+            }
+            // The table doesn't say who initiated; the listening sockets and ports do
+            let direction = infer_tcp_direction(
+                TcpObservation::Traffic,
+                None,
+                listening.contains(&(entry.pid, entry.local_port)),
+                entry.local_port,
+                entry.remote_port,
+                EPHEMERAL_PORT_START.load(Ordering::Relaxed),
+            );
+            let net = NetworkEvent::new(
+                entry.pid,
+                process_name,
+                direction,
+                crate::events::network::Protocol::TCP,
+                entry.local_addr.clone(),
+                entry.local_port,
+                entry.remote_addr.clone(),
+                entry.remote_port,
+            );
+            let _ = tx.send(BaseEvent::new(EventType::NetworkConnection(net)));
+        }
+    }
+
+    let mut seen = known.take().unwrap_or_default();
+    for (entry, lifetime) in seen.iter().filter(|(entry, _)| !current.contains(*entry)) {
+        report_closed_connection(entry, lifetime, &listening, tx);
+    }
+    seen.retain(|entry, _| current.contains(entry));
+    for entry in current {
+        seen.entry(entry)
+            .and_modify(|lifetime| lifetime.last_seen = now)
+            .or_insert(ConnectionSeen { first_seen: now, last_seen: now });
+    }
+    *known = Some(seen);
+    if let Ok(mut ports) = LISTENING_PORTS.write() {
+        *ports = listening;
+    }
+}
+
+/// Sends a `Closed` event for an external connection gone from the TCP table. The
+/// duration runs from the first poll that saw it to the last, so it is accurate to
+/// the poll interval, and connections that open and close between polls are missed.
+fn report_closed_connection(
+    entry: &TcpTableEntry,
+    lifetime: &ConnectionSeen,
+    listening: &HashSet<(u32, u16)>,
+    tx: &Sender<BaseEvent>,
+) {
+    if entry.pid <= 4 || classify_network_connection(&entry.local_addr, &entry.remote_addr) != "External" {
+        return;
+    }
+    let process_name = get_process_name_cached(entry.pid);
+    let is_browser = is_browser_related_process(entry.pid, &process_name);
+    if (!is_browser && is_system_process(&process_name)) || is_etw_dropped_process(&process_name) {
+        return;
+    }
+    let duration_secs = (lifetime.last_seen - lifetime.first_seen).num_seconds().max(0) as u64;
+    log::debug!(
+        "🔌 Connection closed: {} (PID {}) {}:{} → {}:{} after {}s",
+        process_name, entry.pid, entry.local_addr, entry.local_port,
+        entry.remote_addr, entry.remote_port, duration_secs
+    );
+
+    let direction = infer_tcp_direction(
+        TcpObservation::Traffic,
+        None,
+        listening.contains(&(entry.pid, entry.local_port)),
+        entry.local_port,
+        entry.remote_port,
+        EPHEMERAL_PORT_START.load(Ordering::Relaxed),
+    );
+    let mut net = NetworkEvent::new(
+        entry.pid,
+        process_name,
+        direction,
+        crate::events::network::Protocol::TCP,
+        entry.local_addr.clone(),
+        entry.local_port,
+        entry.remote_addr.clone(),
+        entry.remote_port,
+    );
+    net.connection_state = ConnectionState::Closed;
+    net.duration_secs = Some(duration_secs);
+    let _ = tx.send(BaseEvent::new(EventType::NetworkConnection(net)));
+}
+
 fn is_suspicious_loopback(process_name: &str, sport: u16, dport: u16) -> bool {
     const SUSPICIOUS_PROCESSES: &[&str] = &[
         "powershell.exe", "cmd.exe", "wscript.exe", "cscript.exe",
@@ -298,7 +644,6 @@ fn handle_udp_event(
     daddr: &str,
     dport: u16,
     network_type: &str,
-    event_id: u16,
 ) {
     if network_type == "Loopback" {
         return;
@@ -347,26 +692,190 @@ fn handle_udp_event(
 
         if should_log {
 
-            let net_direction = if event_id == EVENT_ID_UDP_SEND {
-                crate::events::network::NetworkDirection::Outbound
-            } else {
-                crate::events::network::NetworkDirection::Inbound
-            };
+            // Remote port 443: this host is the QUIC client whichever way the datagram went
             let net = NetworkEvent::new(
-                pid, process_name.to_string(), net_direction,
-                crate::events::network::Protocol::UDP,
+                pid, process_name.to_string(), NetworkDirection::Outbound,
+                crate::events::network::Protocol::QUIC,
                 saddr.to_string(), sport,
                 daddr.to_string(), dport,
             );
             let base = BaseEvent::new(EventType::NetworkConnection(net));
-            if let Ok(guard) = GLOBAL_SENDER.lock() {
-                if let Some(sender) = guard.as_ref() {
-                    let _ = sender.send(base);
-                }
+            if let Ok(guard) = GLOBAL_SENDER.lock()
+                && let Some(sender) = guard.as_ref()
+            {
+                let _ = sender.send(base);
+            }
+        }
+        return;
+    }
+}
+
+// Event 3008 payload: QueryName (wstring), QueryType (u32), QueryOptions (u64),
+// QueryStatus (u32), QueryResults (wstring, ';'-separated).
+fn handle_dns_query_completed(pid: u32, process_name: &str, data: &[u8]) {
+    let Some((query_name, offset)) = read_utf16z(data, 0) else {
+        return;
+    };
+    let status_offset = offset + 4 + 8;
+    let Some(status_bytes) = data.get(status_offset..status_offset + 4) else {
+        return;
+    };
+    let query_status = u32::from_le_bytes([status_bytes[0], status_bytes[1], status_bytes[2], status_bytes[3]]);
+    let results = read_utf16z(data, status_offset + 4)
+        .map(|(results, _)| results)
+        .unwrap_or_default();
+
+    let query_name = query_name.trim_end_matches('.').to_lowercase();
+    if query_name.is_empty() {
+        return;
+    }
+
+    let resolved_addresses = parse_dns_query_results(&results);
+    if query_status == 0 && resolved_addresses.is_empty() {
+        return;
+    }
+
+    let dns = DnsEvent::new(pid, process_name.to_string(), query_name, query_status, resolved_addresses);
+    let base = BaseEvent::new(EventType::DnsQuery(dns));
+    if let Ok(guard) = GLOBAL_SENDER.lock()
+        && let Some(sender) = guard.as_ref()
+    {
+        let _ = sender.send(base);
+    }
+}
+
+/// Forwards a Kernel-File open of a `credential_store_paths` file, a write-capable
+/// open of the hosts file, or an overwrite of a user document. This runs for every
+/// file open on the system, so the path is matched before any process lookup.
+fn handle_file_create(rec: &EVENT_RECORD) {
+    if rec.UserDataLength == 0 || rec.UserData.is_null() {
+        return;
+    }
+    let data = unsafe { std::slice::from_raw_parts(rec.UserData as *const u8, rec.UserDataLength as usize) };
+    let pointer_size = if rec.EventHeader.Flags as u32 & EVENT_HEADER_FLAG_32_BIT_HEADER != 0 { 4 } else { 8 };
+    let Some((path, _)) = read_utf16z(data, 2 * pointer_size + FILE_CREATE_FIXED_FIELDS_LEN) else {
+        return;
+    };
+    let create_options = data.get(2 * pointer_size + 4..2 * pointer_size + 8)
+        .map_or(0, |bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    let disposition = create_options >> 24;
+    let (matched, watch) = match credential_store_match(&path) {
+        Some(matched) => (matched, FileWatch::CredentialStore),
+        None if path.to_lowercase().ends_with(HOSTS_FILE_SUFFIX) => {
+            // Every resolver reads the hosts file; only opens that can write it matter
+            if disposition == FILE_OPEN_DISPOSITION {
+                return;
             }
+            ("hosts".to_string(), FileWatch::HostsFile)
+        }
+        None if OVERWRITE_DISPOSITIONS.contains(&disposition) && is_user_document_path(&path) => {
+            ("overwrite".to_string(), FileWatch::Document)
         }
+        None => return,
+    };
+    forward_file_access(rec.EventHeader.ProcessId, path, matched, watch);
+}
+
+/// Forwards a Kernel-File new file or rename in a watched document folder. The
+/// path follows `pointers` pointer-sized fields and `fixed_len` bytes of others.
+fn handle_document_change(rec: &EVENT_RECORD, pointers: usize, fixed_len: usize, change: &str) {
+    if rec.UserDataLength == 0 || rec.UserData.is_null() {
+        return;
+    }
+    let data = unsafe { std::slice::from_raw_parts(rec.UserData as *const u8, rec.UserDataLength as usize) };
+    let pointer_size = if rec.EventHeader.Flags as u32 & EVENT_HEADER_FLAG_32_BIT_HEADER != 0 { 4 } else { 8 };
+    let Some((path, _)) = read_utf16z(data, pointers * pointer_size + fixed_len) else {
+        return;
+    };
+    if !is_user_document_path(&path) {
         return;
     }
+    forward_file_access(rec.EventHeader.ProcessId, path, change.to_string(), FileWatch::Document);
+}
+
+fn forward_file_access(pid: u32, path: String, matched: String, watch: FileWatch) {
+    let process_name = get_process_name_cached(pid);
+    if is_etw_dropped_process(&process_name) {
+        return;
+    }
+    let event = FileAccessEvent { pid, process_name, path, matched, watch };
+    let base = BaseEvent::new(EventType::FileAccess(event));
+    if let Ok(guard) = GLOBAL_SENDER.lock()
+        && let Some(sender) = guard.as_ref()
+    {
+        let _ = sender.send(base);
+    }
+}
+
+/// Passes a record from an extra `etw_providers` entry through unparsed. Events
+/// from providers that weren't enabled by this monitor (none expected) are dropped.
+fn forward_provider_event(rec: &EVENT_RECORD, provider_guid: u128) {
+    let Some((provider, guid)) = EXTRA_PROVIDERS.read().ok()
+        .and_then(|providers| providers.get(&provider_guid).cloned())
+    else {
+        return;
+    };
+    let header = &rec.EventHeader;
+    let descriptor = &header.EventDescriptor;
+    let pid = header.ProcessId;
+    let process_name = get_process_name_cached(pid);
+    if is_etw_dropped_process(&process_name) {
+        return;
+    }
+
+    let payload = if rec.UserDataLength == 0 || rec.UserData.is_null() {
+        String::new()
+    } else {
+        let len = (rec.UserDataLength as usize).min(MAX_PROVIDER_PAYLOAD_BYTES);
+        let data = unsafe { std::slice::from_raw_parts(rec.UserData as *const u8, len) };
+        data.iter().map(|byte| format!("{:02x}", byte)).collect()
+    };
+    log::debug!("ETW {} event {} from {} ({})", provider, descriptor.Id, process_name, pid);
+
+    let event = EtwProviderEvent {
+        provider,
+        provider_guid: guid,
+        event_id: descriptor.Id,
+        opcode: descriptor.Opcode,
+        level: descriptor.Level,
+        keyword: descriptor.Keyword,
+        pid,
+        process_name,
+        payload,
+    };
+    let base = BaseEvent::new(EventType::EtwProvider(event));
+    if let Ok(guard) = GLOBAL_SENDER.lock()
+        && let Some(sender) = guard.as_ref()
+    {
+        let _ = sender.send(base);
+    }
+}
+
+/// Extracts IP addresses from a QueryResults string such as
+/// `type:  5 cdn.example.net;::ffff:93.184.216.34;`, skipping CNAME entries.
+fn parse_dns_query_results(results: &str) -> Vec<String> {
+    results
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty() && !entry.starts_with("type:"))
+        .map(|entry| entry.trim_start_matches("::ffff:"))
+        .filter(|entry| entry.parse::<std::net::IpAddr>().is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+fn read_utf16z(data: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut units = Vec::new();
+    let mut pos = offset;
+    while let Some(pair) = data.get(pos..pos + 2) {
+        pos += 2;
+        let unit = u16::from_le_bytes([pair[0], pair[1]]);
+        if unit == 0 {
+            return Some((String::from_utf16_lossy(&units), pos));
+        }
+        units.push(unit);
+    }
+    None
 }
 
 fn classify_network_connection(saddr: &str, daddr: &str) -> &'static str {
@@ -419,36 +928,180 @@ fn is_ipv6_unique_local(addr: &str) -> bool {
     lower.starts_with("fc") || lower.starts_with("fd")
 }
 
-unsafe fn parse_tcpip_event(
-    user_data: *const std::ffi::c_void,
-    data_len: usize,
-) -> (String, String, u16, u16, bool) {
-    if data_len >= std::mem::size_of::<TcpIpV4Event>() {
-        let event = &*(user_data as *const TcpIpV4Event);
-        let saddr_bytes = u32::from_be(event.saddr).to_be_bytes();
-        let daddr_bytes = u32::from_be(event.daddr).to_be_bytes();
-        let saddr = format!("{}.{}.{}.{}", saddr_bytes[0], saddr_bytes[1], saddr_bytes[2], saddr_bytes[3]);
-        let daddr = format!("{}.{}.{}.{}", daddr_bytes[0], daddr_bytes[1], daddr_bytes[2], daddr_bytes[3]);
-        return (saddr, daddr, u16::from_be(event.sport), u16::from_be(event.dport), false);
-    }
-
-    if data_len >= std::mem::size_of::<TcpIpV6Event>() {
-        let event = &*(user_data as *const TcpIpV6Event);
-        return (
-            format_ipv6(&event.saddr),
-            format_ipv6(&event.daddr),
-            u16::from_be(event.sport),
-            u16::from_be(event.dport),
-            true,
-        );
+/// Local address and port, then remote address and port, from a Kernel-Network
+/// TCP or UDP payload. The event ID decides the layout: a short or truncated
+/// record yields `None` rather than an address read from the wrong offset.
+fn parse_tcpip_userdata(data: &[u8], is_ipv6: bool) -> Option<(String, u16, String, u16)> {
+    let (addr_len, event_len) = if is_ipv6 { (16, TCPIP_V6_EVENT_LEN) } else { (4, TCPIP_V4_EVENT_LEN) };
+    if data.len() < event_len {
+        return None;
     }
+    let daddr = &data[TCPIP_ADDR_OFFSET..TCPIP_ADDR_OFFSET + addr_len];
+    let saddr = &data[TCPIP_ADDR_OFFSET + addr_len..TCPIP_ADDR_OFFSET + 2 * addr_len];
+    let ports = TCPIP_ADDR_OFFSET + 2 * addr_len;
+    let port_at = |offset: usize| u16::from_be_bytes([data[offset], data[offset + 1]]);
+    Some((format_ip(saddr)?, port_at(ports + 2), format_ip(daddr)?, port_at(ports)))
+}
 
-    (String::new(), String::new(), 0, 0, false)
+/// Network-order address bytes in the usual text form (`::1`, not eight zero-padded groups).
+pub(crate) fn format_ip(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string()),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(|octets| Ipv6Addr::from(octets).to_string()),
+        _ => None,
+    }
 }
 
-fn format_ipv6(bytes: &[u8; 16]) -> String {
-    bytes.chunks(2)
-        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
-        .collect::<Vec<_>>()
-        .join(":")
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::random_buffer;
+
+    #[test]
+    fn tcpip_userdata_rejects_short_buffers() {
+        for is_ipv6 in [false, true] {
+            let event_len = if is_ipv6 { TCPIP_V6_EVENT_LEN } else { TCPIP_V4_EVENT_LEN };
+            assert_eq!(parse_tcpip_userdata(&[], is_ipv6), None);
+            assert_eq!(parse_tcpip_userdata(&[0u8; 3], is_ipv6), None);
+            // Truncated inside the remote address, the local address and the last port
+            for len in [TCPIP_ADDR_OFFSET + 1, TCPIP_ADDR_OFFSET + 5, event_len - 1] {
+                assert_eq!(parse_tcpip_userdata(&vec![0xAB; len], is_ipv6), None, "len {}", len);
+            }
+        }
+    }
+
+    // PID, size, then daddr, saddr, dport, sport, as the kernel writes them
+    fn tcpip_payload(daddr: &[u8], saddr: &[u8], dport: u16, sport: u16) -> Vec<u8> {
+        let mut data = vec![0u8; TCPIP_ADDR_OFFSET];
+        data[TCPIP_SIZE_OFFSET..TCPIP_SIZE_OFFSET + 4].copy_from_slice(&512u32.to_le_bytes());
+        data.extend_from_slice(daddr);
+        data.extend_from_slice(saddr);
+        data.extend_from_slice(&dport.to_be_bytes());
+        data.extend_from_slice(&sport.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn tcpip_userdata_reads_ipv4_in_network_order() {
+        let data = tcpip_payload(&[93, 184, 216, 34], &[192, 168, 1, 20], 443, 50123);
+        assert_eq!(data.len(), TCPIP_V4_EVENT_LEN);
+        assert_eq!(
+            parse_tcpip_userdata(&data, false),
+            Some(("192.168.1.20".to_string(), 50123, "93.184.216.34".to_string(), 443))
+        );
+    }
+
+    #[test]
+    fn tcpip_userdata_reads_ipv6_in_network_order() {
+        let daddr = "2606:2800:220:1:248:1893:25c8:1946".parse::<Ipv6Addr>().unwrap().octets();
+        let saddr = "fe80::1c2d:3e4f:5a6b:7c8d".parse::<Ipv6Addr>().unwrap().octets();
+        let data = tcpip_payload(&daddr, &saddr, 8443, 0xC350);
+        assert_eq!(data.len(), TCPIP_V6_EVENT_LEN);
+        assert_eq!(
+            parse_tcpip_userdata(&data, true),
+            Some((
+                "fe80::1c2d:3e4f:5a6b:7c8d".to_string(),
+                50000,
+                "2606:2800:220:1:248:1893:25c8:1946".to_string(),
+                8443
+            ))
+        );
+        // The same bytes read as IPv4 pick up the first four address bytes only
+        assert_eq!(parse_tcpip_userdata(&data, false).map(|(_, _, daddr, _)| daddr), Some("38.6.40.0".to_string()));
+    }
+
+    #[test]
+    fn tcpip_userdata_short_by_one_byte_is_none() {
+        let v4 = tcpip_payload(&[8, 8, 8, 8], &[10, 0, 0, 1], 53, 1234);
+        assert_eq!(parse_tcpip_userdata(&v4[..v4.len() - 1], false), None);
+        let v6 = tcpip_payload(&[0; 16], &[0; 16], 53, 1234);
+        assert_eq!(parse_tcpip_userdata(&v6[..v6.len() - 1], true), None);
+    }
+
+    const EPHEMERAL: u16 = 49152;
+
+    #[test]
+    fn connect_and_accept_decide_direction_outright() {
+        use NetworkDirection::{Inbound, Outbound};
+        // Even with every port hint pointing the other way
+        assert_eq!(infer_tcp_direction(TcpObservation::Connected, Some(Inbound), true, 443, 50000, EPHEMERAL), Outbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Accepted, Some(Outbound), false, 50000, 443, EPHEMERAL), Inbound);
+    }
+
+    #[test]
+    fn traffic_keeps_the_traced_direction() {
+        use NetworkDirection::{Inbound, Outbound};
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, Some(Inbound), false, 50000, 443, EPHEMERAL), Inbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, Some(Outbound), true, 4444, 50000, EPHEMERAL), Outbound);
+    }
+
+    #[test]
+    fn traffic_on_a_listening_port_is_inbound() {
+        // A listener on an ephemeral port talking to an ephemeral peer
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, true, 50001, 50002, EPHEMERAL), NetworkDirection::Inbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, true, 4444, 443, EPHEMERAL), NetworkDirection::Inbound);
+    }
+
+    #[test]
+    fn traffic_falls_back_to_the_ephemeral_side() {
+        use NetworkDirection::{Inbound, Outbound};
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 3389, 50000, EPHEMERAL), Inbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 50000, 3389, EPHEMERAL), Outbound);
+        // Both ephemeral, or neither: no evidence, taken as outbound
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 50000, 50001, EPHEMERAL), Outbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 445, 139, EPHEMERAL), Outbound);
+        // The boundary port counts as ephemeral, and the start is configurable
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 80, EPHEMERAL, EPHEMERAL), Inbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 80, 1025, 1025), Inbound);
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 80, 1025, EPHEMERAL), Outbound);
+    }
+
+    #[test]
+    fn quic_shaped_traffic_is_outbound() {
+        // An ephemeral local port talking to 443, as QUIC clients do
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, false, 61000, 443, EPHEMERAL), NetworkDirection::Outbound);
+        // Unless this host serves 443 itself
+        assert_eq!(infer_tcp_direction(TcpObservation::Traffic, None, true, 443, 61000, EPHEMERAL), NetworkDirection::Inbound);
+    }
+
+    #[test]
+    fn format_ip_uses_compressed_ipv6_text() {
+        let mut loopback = [0u8; 16];
+        loopback[15] = 1;
+        assert_eq!(format_ip(&loopback).as_deref(), Some("::1"));
+
+        let mut link_local = [0u8; 16];
+        link_local[..2].copy_from_slice(&[0xFE, 0x80]);
+        link_local[8..].copy_from_slice(&[0x1C, 0x2D, 0x3E, 0x4F, 0x5A, 0x6B, 0x7C, 0x8D]);
+        assert_eq!(format_ip(&link_local).as_deref(), Some("fe80::1c2d:3e4f:5a6b:7c8d"));
+
+        let full = [
+            0x20, 0x01, 0x0D, 0xB8, 0x85, 0xA3, 0x08, 0xD3, 0x13, 0x19, 0x8A, 0x2E, 0x03, 0x70, 0x73, 0x48,
+        ];
+        assert_eq!(format_ip(&full).as_deref(), Some("2001:db8:85a3:8d3:1319:8a2e:370:7348"));
+        // What classify_network_connection compares loopback against
+        assert_eq!(classify_network_connection(&format_ip(&loopback).unwrap(), "::1"), "Loopback");
+        assert!(is_ipv6_link_local(&format_ip(&link_local).unwrap()));
+    }
+
+    #[test]
+    fn format_ip_rejects_other_lengths() {
+        assert_eq!(format_ip(&[]), None);
+        assert_eq!(format_ip(&[127, 0, 0]), None);
+        assert_eq!(format_ip(&[0; 8]), None);
+    }
+
+    #[test]
+    fn tcpip_userdata_never_panics_on_random_buffers() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15u64;
+        for len in 0..=TCPIP_V6_EVENT_LEN + 8 {
+            for _ in 0..32 {
+                let data = random_buffer(&mut seed, len);
+                for is_ipv6 in [false, true] {
+                    let event_len = if is_ipv6 { TCPIP_V6_EVENT_LEN } else { TCPIP_V4_EVENT_LEN };
+                    assert_eq!(parse_tcpip_userdata(&data, is_ipv6).is_some(), len >= event_len);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,501 @@
+use crate::config::rules::{SyslogOutput, SyslogTransport, WebhookOutput};
+use crate::events::alert::{Alert, AlertSeverity};
+use crossbeam_channel::{RecvTimeoutError, Sender, TrySendError};
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Networking::WinHttp::*;
+
+// First retry delay; each later retry waits twice as long as the one before
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const USER_AGENT: &str = "CustomEDR-Webhook/1.0";
+
+/// Where a webhook URL points, split the way WinHTTP wants it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookTarget {
+    pub secure: bool,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, always starting with `/`.
+    pub path: String,
+}
+
+/// Splits an `http://` or `https://` URL into host, port and path. Credentials in
+/// the URL are refused rather than sent in the clear to a log.
+pub fn parse_webhook_url(url: &str) -> Result<WebhookTarget, String> {
+    let (secure, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(format!("'{}' must start with http:// or https://", url));
+    };
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(pos) if rest[pos..].starts_with('?') => (&rest[..pos], format!("/{}", &rest[pos..])),
+        Some(pos) => (&rest[..pos], rest[pos..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    if authority.contains('@') {
+        return Err(format!("'{}' carries credentials; put them in the path or query instead", url));
+    }
+    let default_port = if secure { 443 } else { 80 };
+    // A bracketed IPv6 literal keeps its colons
+    let (host, port) = match authority.rfind(':') {
+        Some(pos) if !authority[pos..].contains(']') => {
+            let port = authority[pos + 1..].parse::<u16>()
+                .map_err(|_| format!("'{}' has an invalid port", url))?;
+            (&authority[..pos], port)
+        }
+        _ => (authority, default_port),
+    };
+    if host.is_empty() {
+        return Err(format!("'{}' has no host", url));
+    }
+    Ok(WebhookTarget { secure, host: host.to_string(), port, path })
+}
+
+/// An alert output that delivers from its own thread. The alert handler only
+/// queues; a slow or unreachable destination fills the bounded queue and further
+/// alerts are dropped for this output instead of stalling the others.
+struct OutputQueue {
+    name: &'static str,
+    queue: Sender<String>,
+    dropped_alerts: AtomicU64,
+    running: Arc<AtomicBool>,
+    worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl OutputQueue {
+    /// Starts the delivery thread, which hands each queued alert to `deliver`
+    /// along with the flag that turns false on shutdown.
+    fn start<F>(name: &'static str, queue_size: usize, mut deliver: F) -> Self
+    where
+        F: FnMut(&str, &AtomicBool) + Send + 'static,
+    {
+        let (queue, pending) = crossbeam_channel::bounded::<String>(queue_size.max(1));
+        let running = Arc::new(AtomicBool::new(true));
+        let worker = {
+            let running = Arc::clone(&running);
+            std::thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    match pending.recv_timeout(Duration::from_millis(100)) {
+                        Ok(line) => deliver(&line, &running),
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            })
+        };
+        Self { name, queue, dropped_alerts: AtomicU64::new(0), running, worker: Some(worker) }
+    }
+
+    /// Queues one alert for delivery. Never blocks.
+    fn push(&self, line: String) {
+        match self.queue.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped_alerts.fetch_add(1, Ordering::Relaxed) + 1;
+                // Warn on the first drop and every 100th after, not once per alert
+                if dropped % 100 == 1 {
+                    log::warn!("{} output is behind; {} alerts dropped so far", self.name, dropped);
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Stops the thread. An alert being delivered is given up; queued ones are dropped.
+    fn stop(mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        let pending = self.queue.len();
+        if pending > 0 {
+            log::warn!("{} output stopped with {} alerts undelivered", self.name, pending);
+        }
+    }
+}
+
+/// Forwards alerts as JSON to a webhook.
+pub struct WebhookNotifier {
+    queue: OutputQueue,
+}
+
+pub fn start_webhook_notifier(config: &WebhookOutput) -> Option<WebhookNotifier> {
+    let target = match parse_webhook_url(&config.url) {
+        Ok(target) => target,
+        Err(e) => {
+            log::error!("Webhook output disabled: {}", e);
+            return None;
+        }
+    };
+    let timeout = Duration::from_secs(config.timeout_secs.max(1));
+    let max_attempts = config.max_attempts.max(1);
+    let queue = OutputQueue::start("Webhook", config.queue_size, move |body, running| {
+        deliver(&target, body, timeout, max_attempts, running)
+    });
+    log::info!("✅ Webhook output forwarding alerts to {}", redact_url(&config.url));
+    Some(WebhookNotifier { queue })
+}
+
+impl WebhookNotifier {
+    /// Queues one alert (a JSON object) for delivery. Never blocks.
+    pub fn notify(&self, line: &str) {
+        self.queue.push(line.to_string());
+    }
+
+    pub fn stop(self) {
+        self.queue.stop();
+    }
+}
+
+/// POSTs one alert, retrying failures with exponential backoff. A 4xx answer other
+/// than 429 means the request itself is wrong, so it is not retried.
+fn deliver(target: &WebhookTarget, body: &str, timeout: Duration, max_attempts: u32, running: &AtomicBool) {
+    let mut delay = RETRY_BASE_DELAY;
+    for attempt in 1..=max_attempts {
+        let error = match post_json(target, body, timeout) {
+            Ok(status) if (200..300).contains(&status) => return,
+            Ok(status) if (400..500).contains(&status) && status != 429 => {
+                log::error!("Webhook {} rejected an alert with HTTP {}; not retrying", target.host, status);
+                return;
+            }
+            Ok(status) => format!("HTTP {}", status),
+            Err(e) => e,
+        };
+        if attempt == max_attempts {
+            log::error!("Webhook {} failed {} times, alert dropped: {}", target.host, max_attempts, error);
+            return;
+        }
+        log::debug!("Webhook {} attempt {} failed ({}), retrying in {}s", target.host, attempt, error, delay.as_secs());
+        let waited = std::time::Instant::now();
+        while waited.elapsed() < delay {
+            if !running.load(Ordering::Relaxed) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        delay *= 2;
+    }
+}
+
+/// Closes a WinHTTP handle when dropped.
+struct InternetHandle(*mut core::ffi::c_void);
+
+impl Drop for InternetHandle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = WinHttpCloseHandle(self.0);
+        }
+    }
+}
+
+fn open_handle(handle: *mut core::ffi::c_void, what: &str) -> Result<InternetHandle, String> {
+    if handle.is_null() {
+        return Err(format!("{} failed: {}", what, windows::core::Error::from_thread()));
+    }
+    Ok(InternetHandle(handle))
+}
+
+/// One POST with `application/json`; returns the HTTP status. `timeout` bounds
+/// each of resolve, connect, send and receive.
+fn post_json(target: &WebhookTarget, body: &str, timeout: Duration) -> Result<u32, String> {
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    unsafe {
+        let session = open_handle(
+            WinHttpOpen(&HSTRING::from(USER_AGENT), WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY, PCWSTR::null(), PCWSTR::null(), 0),
+            "WinHttpOpen",
+        )?;
+        WinHttpSetTimeouts(session.0, timeout_ms, timeout_ms, timeout_ms, timeout_ms)
+            .map_err(|e| format!("WinHttpSetTimeouts failed: {}", e))?;
+        let connection = open_handle(
+            WinHttpConnect(session.0, &HSTRING::from(target.host.trim_start_matches('[').trim_end_matches(']')), target.port, 0),
+            "WinHttpConnect",
+        )?;
+        let flags = if target.secure { WINHTTP_FLAG_SECURE } else { WINHTTP_OPEN_REQUEST_FLAGS(0) };
+        let request = open_handle(
+            WinHttpOpenRequest(
+                connection.0,
+                &HSTRING::from("POST"),
+                &HSTRING::from(target.path.as_str()),
+                PCWSTR::null(),
+                PCWSTR::null(),
+                std::ptr::null(),
+                flags,
+            ),
+            "WinHttpOpenRequest",
+        )?;
+
+        let headers: Vec<u16> = "Content-Type: application/json\r\n".encode_utf16().collect();
+        WinHttpSendRequest(
+            request.0,
+            Some(&headers),
+            Some(body.as_ptr() as *const core::ffi::c_void),
+            body.len() as u32,
+            body.len() as u32,
+            0,
+        )
+        .map_err(|e| format!("send failed: {}", e))?;
+        WinHttpReceiveResponse(request.0, std::ptr::null_mut())
+            .map_err(|e| format!("no response: {}", e))?;
+
+        let mut status = 0u32;
+        let mut status_size = std::mem::size_of::<u32>() as u32;
+        WinHttpQueryHeaders(
+            request.0,
+            WINHTTP_QUERY_STATUS_CODE | WINHTTP_QUERY_FLAG_NUMBER,
+            PCWSTR::null(),
+            Some(&mut status as *mut u32 as *mut core::ffi::c_void),
+            &mut status_size,
+            std::ptr::null_mut(),
+        )
+        .map_err(|e| format!("no status code: {}", e))?;
+        Ok(status)
+    }
+}
+
+/// The URL with its path and query cut off: webhook URLs usually embed their token.
+fn redact_url(url: &str) -> String {
+    match parse_webhook_url(url) {
+        Ok(target) => format!("{}://{}:{}/…", if target.secure { "https" } else { "http" }, target.host, target.port),
+        Err(_) => "an invalid URL".to_string(),
+    }
+}
+
+// RFC 5424 facility local0, and the SD-ID of the alert fields (32473 is the
+// private enterprise number RFC 5612 sets aside for examples and private use)
+const SYSLOG_FACILITY_LOCAL0: u8 = 16;
+const SYSLOG_SD_ID: &str = "edr@32473";
+const SYSLOG_TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Marks MSG as UTF-8 (RFC 5424 §6.4); without it collectors may treat non-ASCII paths as Latin-1
+const SYSLOG_MSG_BOM: char = '\u{FEFF}';
+
+/// Sends alerts to a syslog collector as RFC 5424 messages, over UDP (one
+/// datagram each) or TCP (octet-counted framing, RFC 6587).
+pub struct SyslogNotifier {
+    queue: OutputQueue,
+    hostname: String,
+    app_name: String,
+}
+
+pub fn start_syslog_notifier(config: &SyslogOutput) -> Option<SyslogNotifier> {
+    if let Err(e) = config.server.to_socket_addrs() {
+        log::error!("Syslog output disabled: cannot resolve '{}': {}", config.server, e);
+        return None;
+    }
+    let mut transport = SyslogConnection::new(config.server.clone(), config.transport);
+    let queue = OutputQueue::start("Syslog", config.queue_size, move |message, _running| transport.send(message));
+    log::info!("✅ Syslog output sending alerts to {} over {:?}", config.server, config.transport);
+    Some(SyslogNotifier {
+        queue,
+        // The name events are stamped with (BaseEvent::machine_name)
+        hostname: whoami::fallible::hostname().unwrap_or_else(|_| "-".to_string()),
+        app_name: config.app_name.clone(),
+    })
+}
+
+impl SyslogNotifier {
+    /// Queues one alert for delivery. Never blocks.
+    pub fn notify(&self, alert: &Alert) {
+        self.queue.push(format_syslog_message(alert, &self.hostname, &self.app_name, std::process::id()));
+    }
+
+    pub fn stop(self) {
+        self.queue.stop();
+    }
+}
+
+/// One alert as an RFC 5424 message:
+///
+/// `<131>1 2025-01-01T12:00:00.000Z HOST CustomEDR 4242 ALERT [edr@32473 id="…" rule="…"
+/// process="…" pid="…" severity="High" source="…"] BOMRule: description`
+///
+/// MSG starts with the UTF-8 byte order mark, so collectors decode it as UTF-8.
+/// PRI is facility local0 with the alert's severity mapped onto syslog's:
+/// Critical → crit (2), High → err (3), Medium → warning (4), Low → notice (5).
+pub fn format_syslog_message(alert: &Alert, hostname: &str, app_name: &str, procid: u32) -> String {
+    let syslog_severity = match alert.severity {
+        AlertSeverity::Critical => 2,
+        AlertSeverity::High => 3,
+        AlertSeverity::Medium => 4,
+        AlertSeverity::Low => 5,
+    };
+    let structured_data = [
+        ("id", alert.id.clone()),
+        ("rule", alert.rule_name.clone()),
+        ("process", alert.process_name.clone()),
+        ("pid", alert.pid.to_string()),
+        ("severity", format!("{:?}", alert.severity)),
+        ("source", alert.source.clone()),
+    ]
+    .iter()
+    .map(|(name, value)| format!(" {}=\"{}\"", name, escape_sd_value(value)))
+    .collect::<String>();
+    format!(
+        "<{}>1 {} {} {} {} ALERT [{}{}] {}{}: {}",
+        SYSLOG_FACILITY_LOCAL0 * 8 + syslog_severity,
+        alert.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        header_field(hostname, 255),
+        header_field(app_name, 48),
+        procid,
+        SYSLOG_SD_ID,
+        structured_data,
+        SYSLOG_MSG_BOM,
+        alert.rule_name,
+        alert.description
+    )
+}
+
+/// A header field: printable ASCII without spaces, at most `max_len` characters,
+/// or the NILVALUE `-` when nothing is left.
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value.chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max_len)
+        .collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+/// PARAM-VALUE escaping: `"`, `\` and `]` get a backslash.
+fn escape_sd_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The collector connection, owned by the delivery thread. TCP reconnects
+/// once per message after a failure; UDP re-resolves the server each time.
+struct SyslogConnection {
+    server: String,
+    transport: SyslogTransport,
+    stream: Option<TcpStream>,
+    failing: bool,
+}
+
+impl SyslogConnection {
+    fn new(server: String, transport: SyslogTransport) -> Self {
+        Self { server, transport, stream: None, failing: false }
+    }
+
+    fn send(&mut self, message: &str) {
+        let result = match self.transport {
+            SyslogTransport::Udp => self.send_udp(message),
+            SyslogTransport::Tcp => {
+                // A collector that restarted leaves a dead stream; one fresh connection is tried
+                let framed = format!("{} {}", message.len(), message);
+                self.send_tcp(&framed).or_else(|_| {
+                    self.stream = None;
+                    self.send_tcp(&framed)
+                })
+            }
+        };
+        match result {
+            Ok(()) if self.failing => {
+                log::info!("Syslog output reaching {} again", self.server);
+                self.failing = false;
+            }
+            Ok(()) => {}
+            // Logged once per outage rather than once per alert
+            Err(e) if self.failing => log::debug!("Syslog alert dropped: {}", e),
+            Err(e) => {
+                log::error!("Syslog output cannot reach {}, alerts are dropped until it can: {}", self.server, e);
+                self.failing = true;
+            }
+        }
+    }
+
+    fn send_udp(&self, message: &str) -> std::io::Result<()> {
+        let server = self.server.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address"))?;
+        let local = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        UdpSocket::bind(local)?.send_to(message.as_bytes(), server)?;
+        Ok(())
+    }
+
+    fn send_tcp(&mut self, framed: &str) -> std::io::Result<()> {
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => {
+                let server = self.server.to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address"))?;
+                let stream = TcpStream::connect_timeout(&server, SYSLOG_TCP_CONNECT_TIMEOUT)?;
+                stream.set_write_timeout(Some(SYSLOG_TCP_CONNECT_TIMEOUT))?;
+                self.stream.insert(stream)
+            }
+        };
+        stream.write_all(framed.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(process_name: &str) -> Alert {
+        let mut alert = Alert::new(
+            &AlertSeverity::High,
+            "MultiFactorThreatDetection",
+            "Encoded PowerShell",
+            process_name,
+            4312,
+            &[],
+        )
+        .with_source("correlation");
+        alert.id = "3f2a9c1e".to_string();
+        alert.timestamp = chrono::DateTime::parse_from_rfc3339("2025-01-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        alert
+    }
+
+    #[test]
+    fn syslog_message_wire_format() {
+        assert_eq!(
+            format_syslog_message(&alert("powershell.exe"), "WS01", "CustomEDR", 4242),
+            "<131>1 2025-01-01T12:00:00.000Z WS01 CustomEDR 4242 ALERT [edr@32473 id=\"3f2a9c1e\" \
+             rule=\"MultiFactorThreatDetection\" process=\"powershell.exe\" pid=\"4312\" severity=\"High\" \
+             source=\"correlation\"] \u{FEFF}MultiFactorThreatDetection: Encoded PowerShell"
+        );
+    }
+
+    #[test]
+    fn syslog_severity_sets_pri() {
+        let mut critical = alert("a.exe");
+        critical.severity = AlertSeverity::Critical;
+        let mut low = alert("a.exe");
+        low.severity = AlertSeverity::Low;
+        assert!(format_syslog_message(&critical, "h", "a", 1).starts_with("<130>1 "));
+        assert!(format_syslog_message(&low, "h", "a", 1).starts_with("<133>1 "));
+    }
+
+    #[test]
+    fn sd_values_escape_quote_backslash_and_bracket() {
+        assert_eq!(escape_sd_value(r#"say "hi""#), r#"say \"hi\""#);
+        assert_eq!(escape_sd_value(r"C:\Temp\x.exe"), r"C:\\Temp\\x.exe");
+        assert_eq!(escape_sd_value("a]b"), r"a\]b");
+        assert_eq!(escape_sd_value("[plain]"), r"[plain\]");
+
+        let message = format_syslog_message(&alert(r#"C:\a "b"].exe"#), "WS01", "CustomEDR", 1);
+        assert!(message.contains(r#" process="C:\\a \"b\"\].exe" "#), "{}", message);
+    }
+
+    #[test]
+    fn header_fields_never_contain_spaces_or_go_empty() {
+        let message = format_syslog_message(&alert("a.exe"), "", "", 1);
+        assert!(message.starts_with("<131>1 2025-01-01T12:00:00.000Z - - 1 ALERT ["), "{}", message);
+
+        let message = format_syslog_message(&alert("a.exe"), "my host", "Custom EDR", 1);
+        assert!(message.starts_with("<131>1 2025-01-01T12:00:00.000Z my_host Custom_EDR 1 ALERT ["), "{}", message);
+
+        assert_eq!(header_field(&"h".repeat(300), 255).len(), 255);
+    }
+}
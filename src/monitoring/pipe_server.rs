@@ -0,0 +1,198 @@
+use crate::config::rules::PipeOutput;
+use crate::monitoring::control_pipe::{PipeSecurity, ADMIN_ONLY_SDDL};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, ERROR_PIPE_CONNECTED, GENERIC_READ, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, WriteFile, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_NONE, OPEN_EXISTING,
+    PIPE_ACCESS_OUTBOUND,
+};
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_REJECT_REMOTE_CLIENTS,
+    PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+
+const PIPE_BUFFER_SIZE: u32 = 64 * 1024;
+
+// Pipe handles are only ever used by the single thread that owns them.
+struct PipeHandle(HANDLE);
+unsafe impl Send for PipeHandle {}
+
+/// Broadcasts JSON lines to every client connected to the local named pipe.
+/// Each client gets its own bounded queue; a slow client loses lines instead
+/// of stalling the alert pipeline.
+pub struct PipeServer {
+    pipe_name: widestring::U16CString,
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+    dropped_lines: AtomicU64,
+    running: Arc<AtomicBool>,
+    accept_handle: Option<std::thread::JoinHandle<()>>,
+}
+
+pub fn start_pipe_server(config: &PipeOutput) -> Option<PipeServer> {
+    let pipe_name = match widestring::U16CString::from_str(&config.pipe_name) {
+        Ok(name) => name,
+        Err(e) => {
+            log::error!("Invalid pipe name '{}': {}", config.pipe_name, e);
+            return None;
+        }
+    };
+    let sddl = alert_pipe_sddl(config.reader_sid.as_deref());
+    let Some(security) = PipeSecurity::new(&sddl) else {
+        // Never fall back to the default DACL, which lets any local user read alerts
+        log::error!("Failed to build alert pipe ACL: {:?}", windows::core::Error::from_thread());
+        return None;
+    };
+
+    let clients: Arc<Mutex<Vec<Sender<String>>>> = Arc::new(Mutex::new(Vec::new()));
+    let running = Arc::new(AtomicBool::new(true));
+    let queue_size = config.client_queue_size.max(1);
+
+    let accept_handle = {
+        let pipe_name = pipe_name.clone();
+        let clients = Arc::clone(&clients);
+        let running = Arc::clone(&running);
+        std::thread::spawn(move || {
+            run_accept_loop(pipe_name, security, queue_size, clients, running);
+        })
+    };
+
+    log::info!("✅ Named pipe output listening on {}", config.pipe_name);
+
+    Some(PipeServer {
+        pipe_name,
+        clients,
+        dropped_lines: AtomicU64::new(0),
+        running,
+        accept_handle: Some(accept_handle),
+    })
+}
+
+impl PipeServer {
+    pub fn broadcast(&self, line: &str) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        let mut line = line.to_string();
+        line.push('\n');
+
+        clients.retain(|client| match client.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped_lines.fetch_add(1, Ordering::Relaxed) + 1;
+                log::debug!("Pipe client queue full, dropped line ({} total)", dropped);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    pub fn stop(mut self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        // Dropping the senders ends every client writer thread.
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.clear();
+        }
+
+        // The accept thread is parked in ConnectNamedPipe; connect to ourselves to wake it.
+        unsafe {
+            if let Ok(handle) = CreateFileW(
+                PCWSTR(self.pipe_name.as_ptr()),
+                GENERIC_READ.0,
+                FILE_SHARE_NONE,
+                None,
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                None,
+            ) {
+                let _ = CloseHandle(handle);
+            }
+        }
+
+        if let Some(handle) = self.accept_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// SYSTEM and Administrators always get full access; `reader_sid` (a SID such
+/// as `S-1-5-21-…-1105`) is added with read access so a non-admin UI group
+/// can subscribe to alerts.
+fn alert_pipe_sddl(reader_sid: Option<&str>) -> String {
+    match reader_sid {
+        Some(sid) => format!("{}(A;;GR;;;{})", ADMIN_ONLY_SDDL, sid),
+        None => ADMIN_ONLY_SDDL.to_string(),
+    }
+}
+
+fn run_accept_loop(
+    pipe_name: widestring::U16CString,
+    security: PipeSecurity,
+    queue_size: usize,
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+    running: Arc<AtomicBool>,
+) {
+    let attributes = security.attributes();
+    while running.load(Ordering::Relaxed) {
+        let handle = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(pipe_name.as_ptr()),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                PIPE_BUFFER_SIZE,
+                0,
+                0,
+                Some(&attributes),
+            )
+        };
+        if handle.is_invalid() {
+            log::error!("CreateNamedPipeW failed: {:?}", windows::core::Error::from_thread());
+            std::thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+
+        // ERROR_PIPE_CONNECTED means the client raced us between create and connect.
+        let connected = match unsafe { ConnectNamedPipe(handle, None) } {
+            Ok(()) => true,
+            Err(e) => e.code() == ERROR_PIPE_CONNECTED.to_hresult(),
+        };
+
+        if !running.load(Ordering::Relaxed) || !connected {
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+            continue;
+        }
+
+        let (tx, rx) = crossbeam_channel::bounded(queue_size);
+        if let Ok(mut clients) = clients.lock() {
+            clients.push(tx);
+            log::info!("Pipe client connected ({} active)", clients.len());
+        }
+
+        let pipe = PipeHandle(handle);
+        std::thread::spawn(move || {
+            run_client_writer(pipe, rx);
+        });
+    }
+}
+
+fn run_client_writer(pipe: PipeHandle, rx: Receiver<String>) {
+    for line in rx.iter() {
+        let mut written = 0u32;
+        let result = unsafe { WriteFile(pipe.0, Some(line.as_bytes()), Some(&mut written), None) };
+        if result.is_err() {
+            log::info!("Pipe client disconnected");
+            break;
+        }
+    }
+    unsafe {
+        let _ = DisconnectNamedPipe(pipe.0);
+        let _ = CloseHandle(pipe.0);
+    }
+}
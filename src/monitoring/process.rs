@@ -1,18 +1,48 @@
 use crate::events::{BaseEvent, EventType};
 use crate::events::process::ProcessEvent;
+use crate::utils::privilege::{query_process_image_path, query_process_security};
 use crossbeam_channel::Sender;
-use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
 use crate::utils::common::{
     get_process_name_cached, cache_process_start,
-    is_system_process, cleanup_tracking_data, GLOBAL_SENDER,
+    is_system_process, is_anomalous_svchost_parent, is_etw_dropped_process, is_unc_path,
+    cleanup_tracking_data, GLOBAL_SENDER,
 };
 use windows::Win32::System::Diagnostics::Etw::*;
-use windows::core::PWSTR;
-use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::Win32::Foundation::FILETIME;
+use crate::monitoring::etw_session::{EtwSession, SenderRegistration, TraceConsumer};
+use crate::monitoring::etw_watchdog::{PROCESS_HEARTBEAT, running_process_ids};
 
 const WNODE_FLAG_TRACED_GUID: u32 = 0x00020000;
 const EVENT_TRACE_FLAG_PROCESS: u32 = 0x00000001;
+// Images above this are not hashed
+const MAX_HASH_IMAGE_BYTES: u64 = 64 * 1024 * 1024;
+const HASH_READ_CHUNK: usize = 64 * 1024;
+// Distinct images whose hash is remembered; the cache starts over when full
+const MAX_CACHED_IMAGE_HASHES: usize = 4096;
+// Images are hashed off the ETW callback: reading a large image, or one on a slow
+// share, inside it would hold up the trace until its buffers overflow
+const HASH_WORKERS: usize = 1;
+const HASH_QUEUE_CAPACITY: usize = 256;
+// PIDs whose image hash is kept for alert enrichment; the map starts over when full
+const MAX_PROCESS_HASHES: usize = 8192;
+
+/// Image hash requests dropped because the queue was full.
+pub static IMAGE_HASH_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    // Lowercased image path -> (length, modified, SHA-256), so each image is read once per version
+    static ref IMAGE_HASHES: Mutex<HashMap<String, (u64, SystemTime, String)>> = Mutex::new(HashMap::new());
+    // pid -> (ETW timestamp of its start event, SHA-256 of the image once known). The
+    // timestamp tells a worker result for a process that has gone from one that reused its PID.
+    static ref PROCESS_IMAGE_HASHES: Mutex<HashMap<u32, (i64, Option<String>)>> = Mutex::new(HashMap::new());
+}
+
+static HASH_QUEUE: OnceLock<Sender<(u32, i64, String)>> = OnceLock::new();
 
 pub fn start_process_monitor(
     tx: Sender<BaseEvent>,
@@ -28,64 +58,52 @@ pub fn run_process_monitor(
     shutdown: Arc<AtomicBool>,
 ) {
     unsafe {
-        {
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = Some(Arc::new(tx.clone()));
-        }
+        let _sender = SenderRegistration::install(&tx);
 
-        // Stop any existing kernel logger session before starting a new one
-        let mut stop_buffer = vec![0u8; std::mem::size_of::<EVENT_TRACE_PROPERTIES>() + 1024];
-        let stop_props = stop_buffer.as_mut_ptr() as *mut EVENT_TRACE_PROPERTIES;
-        (*stop_props).Wnode.BufferSize = stop_buffer.len() as u32;
-        (*stop_props).LoggerNameOffset = std::mem::size_of::<EVENT_TRACE_PROPERTIES>() as u32;
-        let stop_result = ControlTraceW(
-            CONTROLTRACE_HANDLE::default(),
-            KERNEL_LOGGER_NAMEW,
-            stop_props,
-            EVENT_TRACE_CONTROL_STOP,
-        );
-        if stop_result == ERROR_SUCCESS {
-            log::info!("Stopped Existing Process Monitor Session");
-        }
-
-        let mut buffer = vec![0u8; std::mem::size_of::<EVENT_TRACE_PROPERTIES>() + 1024];
-        let props = buffer.as_mut_ptr() as *mut EVENT_TRACE_PROPERTIES;
-        (*props).Wnode.BufferSize = buffer.len() as u32;
-        (*props).Wnode.Flags = WNODE_FLAG_TRACED_GUID;
-        (*props).Wnode.Guid = SystemTraceControlGuid;
-        (*props).Wnode.ClientContext = 1;
-        (*props).LogFileMode = EVENT_TRACE_REAL_TIME_MODE;
-        (*props).EnableFlags = EVENT_TRACE_FLAG(EVENT_TRACE_FLAG_PROCESS);
-        (*props).LoggerNameOffset = std::mem::size_of::<EVENT_TRACE_PROPERTIES>() as u32;
-
-        let mut session_handle = CONTROLTRACE_HANDLE::default();
-        let status = StartTraceW(
-            &mut session_handle,
-            KERNEL_LOGGER_NAMEW,
-            props,
-        );
-
-        if status != ERROR_SUCCESS {
-            log::error!("Process Monitor StartTraceW Failed: 0x{:08X}", status.0);
-            match status.0 {
-                0x000000B7 => log::error!("Process Monitor already running"),
-                0x00000005 => log::error!("Access denied - run as Administrator"),
-                _ => {}
+        // EtwSession::start stops any leftover kernel logger before starting a new one
+        let session = match EtwSession::start("NT Kernel Logger", |props| {
+            props.Wnode.Flags = WNODE_FLAG_TRACED_GUID;
+            props.Wnode.Guid = SystemTraceControlGuid;
+            props.Wnode.ClientContext = 1;
+            props.LogFileMode = EVENT_TRACE_REAL_TIME_MODE;
+            props.EnableFlags = EVENT_TRACE_FLAG(EVENT_TRACE_FLAG_PROCESS);
+        }) {
+            Ok(session) => session,
+            Err(status) => {
+                log::error!("Process Monitor StartTraceW Failed: 0x{:08X}", status.0);
+                match status.0 {
+                    0x000000B7 => log::error!("Process Monitor already running"),
+                    0x00000005 => log::error!("Access denied - run as Administrator"),
+                    _ => {}
+                }
+                return;
             }
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = None;
-            return;
-        }
+        };
         log::info!("✅ Process Monitor session started");
 
         let mut logfile: EVENT_TRACE_LOGFILEW = std::mem::zeroed();
-        logfile.LoggerName = PWSTR(KERNEL_LOGGER_NAMEW.as_ptr() as *mut u16);
+        logfile.LoggerName = session.logger_name();
         logfile.Anonymous1.ProcessTraceMode = PROCESS_TRACE_MODE_REAL_TIME | PROCESS_TRACE_MODE_EVENT_RECORD;
 
+        // ETW invokes this across an FFI boundary; a panic while parsing a malformed
+        // record must not unwind into the OS, so it is caught, logged and skipped.
         unsafe extern "system" fn event_callback(record: *mut EVENT_RECORD) {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+                handle_event_record(record);
+            }));
+            if let Err(panic) = result {
+                log::error!(
+                    "Process Monitor callback panicked, skipping event record: {}",
+                    crate::monitoring::supervisor::panic_message(panic.as_ref())
+                );
+            }
+        }
+
+        unsafe fn handle_event_record(record: *mut EVENT_RECORD) {
             if record.is_null() {
                 return;
             }
+            PROCESS_HEARTBEAT.record_event();
 
             let rec = unsafe { &*record };
             let header = &rec.EventHeader;
@@ -98,16 +116,26 @@ pub fn run_process_monitor(
 
             let process_name = get_process_name_cached(pid);
 
-            if is_system_process(&process_name) {
+            // svchost starts bypass the system-process exclusion long enough for the
+            // parentage check below; everything else from the OS is dropped here.
+            let is_svchost_start = opcode == 1 && process_name.eq_ignore_ascii_case("svchost.exe");
+            if is_system_process(&process_name) && !is_svchost_start {
+                return;
+            }
+            if is_etw_dropped_process(&process_name) {
                 return;
             }
 
             let base = match opcode {
                 1 => {
-                    let raw_parent_pid = parse_parent_pid(
-                        rec.UserData as *const u8,
-                        rec.UserDataLength,
-                    );
+                    let data = if rec.UserData.is_null() {
+                        &[][..]
+                    } else {
+                        unsafe {
+                            std::slice::from_raw_parts(rec.UserData as *const u8, rec.UserDataLength as usize)
+                        }
+                    };
+                    let raw_parent_pid = parse_parent_pid(data);
 
                     // Guard against PID reuse: if the kernel has already recycled the
                     // launcher's PID and reassigned it to this very process, the parent
@@ -120,6 +148,12 @@ pub fn run_process_monitor(
                         raw_parent_pid
                     };
 
+                    if is_svchost_start
+                        && !is_anomalous_svchost_parent(&process_name, &get_process_name_cached(parent_pid))
+                    {
+                        return;
+                    }
+
                     // Eagerly resolve and cache the parent name now — the short-lived
                     // launcher may exit before the correlation engine processes this
                     // event, making later lookups return "Unknown".
@@ -134,13 +168,32 @@ pub fn run_process_monitor(
                         None,
                     );
 
-                    let event = ProcessEvent::new_start(
+                    let mut event = ProcessEvent::new_start(
                         pid,
                         parent_pid,
                         process_name.clone(),
                     );
+                    // Read the token and image now, while the process certainly still exists
+                    event.security = query_process_security(pid);
+                    event.create_time = Some(FILETIME {
+                        dwLowDateTime: header.TimeStamp as u32,
+                        dwHighDateTime: (header.TimeStamp >> 32) as u32,
+                    });
+                    if let Some(image_path) = query_process_image_path(pid) {
+                        event.sha256 = request_image_hash(pid, header.TimeStamp, &image_path);
+                        event.image_path = image_path;
+                    }
+                    let user_name = event.security.as_ref().map(|security| {
+                        event.integrity_level = security.integrity_level.clone();
+                        security.user.clone()
+                    });
 
-                    BaseEvent::new(EventType::ProcessStart(event))
+                    let mut base = BaseEvent::new(EventType::ProcessStart(event));
+                    // The process's own account rather than the EDR's
+                    if let Some(user_name) = user_name {
+                        base.user_name = user_name;
+                    }
+                    base
                 }
 
                 2 => {
@@ -160,26 +213,21 @@ pub fn run_process_monitor(
 
         logfile.Anonymous2.EventRecordCallback = Some(event_callback);
 
-        let trace_handle = OpenTraceW(&mut logfile);
-        if trace_handle.Value == u64::MAX {
+        let Some(consumer) = TraceConsumer::open(&mut logfile) else {
             log::error!("Process Monitor OpenTraceW Failed");
-            let _ = ControlTraceW(
-                session_handle,
-                KERNEL_LOGGER_NAMEW,
-                props,
-                EVENT_TRACE_CONTROL_STOP,
-            );
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = None;
             return;
-        }
+        };
         log::info!("✅ Process Monitor OpenTraceW Opened");
+        PROCESS_HEARTBEAT.beat();
 
+        let trace_handle = consumer.handle();
         let etw_thread = std::thread::spawn(move || {
             let _ = ProcessTrace(&[trace_handle], None, None);
         });
 
         let mut cleanup_counter = 0u32;
+        let mut watchdog_counter = 0u32;
+        let mut known_pids = running_process_ids();
         while shutdown.load(std::sync::atomic::Ordering::Relaxed) {
             std::thread::sleep(std::time::Duration::from_millis(200));
             cleanup_counter += 1;
@@ -188,30 +236,224 @@ pub fn run_process_monitor(
                 cleanup_tracking_data();
                 cleanup_counter = 0;
             }
+            watchdog_counter += 1;
+            if watchdog_counter >= 25 {
+                // ~5 seconds: processes appearing while ETW is silent means the session is dead
+                let current_pids = running_process_ids();
+                let active = match (&known_pids, &current_pids) {
+                    (Some(known), Some(current)) => current.difference(known).next().is_some(),
+                    _ => false,
+                };
+                PROCESS_HEARTBEAT.check(active);
+                known_pids = current_pids;
+                watchdog_counter = 0;
+            }
         }
 
-        let _ = CloseTrace(trace_handle);
-        let _ = ControlTraceW(
-            session_handle,
-            KERNEL_LOGGER_NAMEW,
-            props,
-            EVENT_TRACE_CONTROL_STOP,
-        );
+        // Closing the consumer unblocks ProcessTrace; the session guard stops the logger
+        drop(consumer);
+        drop(session);
         let _ = etw_thread.join();
 
-        {
-            let mut guard = GLOBAL_SENDER.lock().unwrap();
-            *guard = None;
-        }
         log::info!("✅ Process Monitor session stopped");
     }
 }
 
-unsafe fn parse_parent_pid(user_data: *const u8, user_data_len: u16) -> u32 {
+fn parse_parent_pid(data: &[u8]) -> u32 {
+    // UniqueProcessKey (pointer), ProcessId (u32), ParentId (u32)
     const PARENT_PID_OFFSET: usize = 8 + 4;
-    if user_data.is_null() || (user_data_len as usize) < PARENT_PID_OFFSET + 4 {
-        return 0;
+    data.get(PARENT_PID_OFFSET..PARENT_PID_OFFSET + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .unwrap_or(0)
+}
+
+fn hash_queue() -> &'static Sender<(u32, i64, String)> {
+    HASH_QUEUE.get_or_init(|| {
+        let (tx, rx) = crossbeam_channel::bounded::<(u32, i64, String)>(HASH_QUEUE_CAPACITY);
+        for _ in 0..HASH_WORKERS {
+            let rx = rx.clone();
+            std::thread::spawn(move || {
+                for (pid, started, path) in rx.iter() {
+                    if let Some(hash) = hash_image(&path) {
+                        finish_image_hash(pid, started, hash);
+                    }
+                }
+            });
+        }
+        tx
+    })
+}
+
+/// The image's hash when the cache already has this version of a local file;
+/// otherwise `None`, and the image is queued for a background worker whose result
+/// `process_image_hash` returns later. Never reads the file or blocks on a share.
+fn request_image_hash(pid: u32, started: i64, path: &str) -> Option<String> {
+    let cached = if is_unc_path(path) { None } else { cached_image_hash(path) };
+    // Replaces whatever a process that used this PID before left behind
+    start_process_hash(pid, started, cached.clone());
+    if cached.is_some() {
+        return cached;
+    }
+    if hash_queue().try_send((pid, started, path.to_string())).is_err() {
+        let dropped = IMAGE_HASH_DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+        log::debug!("Image hash queue full; dropped {} for PID {} ({} dropped so far)", path, pid, dropped);
+    }
+    None
+}
+
+fn start_process_hash(pid: u32, started: i64, hash: Option<String>) {
+    if let Ok(mut hashes) = PROCESS_IMAGE_HASHES.lock() {
+        if hashes.len() >= MAX_PROCESS_HASHES && !hashes.contains_key(&pid) {
+            hashes.clear();
+        }
+        hashes.insert(pid, (started, hash));
     }
-    let ptr = user_data.add(PARENT_PID_OFFSET) as *const u32;
-    ptr.read_unaligned()
-}
\ No newline at end of file
+}
+
+/// Stores a worker's hash, unless the process it was computed for is no longer
+/// the one holding the PID.
+fn finish_image_hash(pid: u32, started: i64, hash: String) {
+    if let Ok(mut hashes) = PROCESS_IMAGE_HASHES.lock() {
+        match hashes.get_mut(&pid) {
+            Some(entry) if entry.0 == started => entry.1 = Some(hash),
+            _ => log::debug!("PID {} was reused before its image hash was ready; dropping it", pid),
+        }
+    }
+}
+
+/// Remembers the hash a replayed start event carries. Replayed events have no
+/// ETW timestamp, and no worker is hashing for them.
+pub fn record_image_hash(pid: u32, hash: String) {
+    start_process_hash(pid, 0, Some(hash));
+}
+
+/// SHA-256 of the image `pid` started from, once it has been computed.
+pub fn process_image_hash(pid: u32) -> Option<String> {
+    PROCESS_IMAGE_HASHES.lock().ok()?.get(&pid)?.1.clone()
+}
+
+/// Length and modification time of a hashable file, `None` when it is missing,
+/// not a regular file or larger than `MAX_HASH_IMAGE_BYTES`.
+fn image_version(path: &str) -> Option<(u64, SystemTime)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_HASH_IMAGE_BYTES {
+        return None;
+    }
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+fn cached_image_hash(path: &str) -> Option<String> {
+    let version = image_version(path)?;
+    let cache = IMAGE_HASHES.lock().ok()?;
+    let (len, modified, hash) = cache.get(&path.to_lowercase())?;
+    ((*len, *modified) == version).then(|| hash.clone())
+}
+
+/// Lowercase hex SHA-256 of an executable, for matching against threat-intel
+/// feeds. `None` when the file can't be read or is larger than
+/// `MAX_HASH_IMAGE_BYTES`. Hashes are cached per path until the file's length
+/// or modification time changes. Reads the whole file, so never call it from an
+/// ETW callback.
+pub fn hash_image(path: &str) -> Option<String> {
+    let version = image_version(path)?;
+    let key = path.to_lowercase();
+    if let Ok(cache) = IMAGE_HASHES.lock()
+        && let Some((len, modified, hash)) = cache.get(&key)
+        && (*len, *modified) == version
+    {
+        return Some(hash.clone());
+    }
+
+    let hash = sha256_file(path)?;
+    if let Ok(mut cache) = IMAGE_HASHES.lock() {
+        if cache.len() >= MAX_CACHED_IMAGE_HASHES {
+            cache.clear();
+        }
+        cache.insert(key, (version.0, version.1, hash.clone()));
+    }
+    Some(hash)
+}
+
+/// Streams the file through the CNG SHA-256 provider.
+fn sha256_file(path: &str) -> Option<String> {
+    use windows::Win32::Security::Cryptography::{
+        BCryptCreateHash, BCryptDestroyHash, BCryptFinishHash, BCryptHashData, BCRYPT_HASH_HANDLE,
+        BCRYPT_SHA256_ALG_HANDLE,
+    };
+
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut buffer = vec![0u8; HASH_READ_CHUNK];
+    let mut digest = [0u8; 32];
+    unsafe {
+        let mut hash = BCRYPT_HASH_HANDLE::default();
+        if BCryptCreateHash(BCRYPT_SHA256_ALG_HANDLE, &mut hash, None, None, 0).is_err() {
+            return None;
+        }
+        let mut complete = false;
+        loop {
+            let read = match file.read(&mut buffer) {
+                Ok(0) => {
+                    complete = true;
+                    break;
+                }
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            if BCryptHashData(hash, &buffer[..read], 0).is_err() {
+                break;
+            }
+        }
+        let finished = complete && BCryptFinishHash(hash, &mut digest, 0).is_ok();
+        let _ = BCryptDestroyHash(hash);
+        if !finished {
+            return None;
+        }
+    }
+    Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::random_buffer;
+
+    #[test]
+    fn parent_pid_falls_back_to_zero_on_short_buffers() {
+        assert_eq!(parse_parent_pid(&[]), 0);
+        assert_eq!(parse_parent_pid(&[0xFF; 7]), 0);
+        // Odd length, and truncated one byte into the ParentId field
+        assert_eq!(parse_parent_pid(&[0xFF; 13]), 0);
+        assert_eq!(parse_parent_pid(&[0xFF; 15]), 0);
+    }
+
+    #[test]
+    fn parent_pid_reads_little_endian_after_pid() {
+        let mut data = vec![0u8; 16];
+        data[8..12].copy_from_slice(&1234u32.to_le_bytes());
+        data[12..16].copy_from_slice(&5678u32.to_le_bytes());
+        assert_eq!(parse_parent_pid(&data), 5678);
+    }
+
+    #[test]
+    fn parent_pid_never_panics_on_random_buffers() {
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        for len in 0..64 {
+            let data = random_buffer(&mut seed, len);
+            let expected = if len >= 16 { u32::from_le_bytes([data[12], data[13], data[14], data[15]]) } else { 0 };
+            assert_eq!(parse_parent_pid(&data), expected);
+        }
+    }
+
+    #[test]
+    fn a_late_image_hash_does_not_reach_a_reused_pid() {
+        // Far above real PIDs, so no other test shares the entry
+        let pid = 0xFFFF_0001;
+        start_process_hash(pid, 100, None);
+        start_process_hash(pid, 200, None);
+        finish_image_hash(pid, 100, "hash of the exited process".to_string());
+        assert_eq!(process_image_hash(pid), None);
+        finish_image_hash(pid, 200, "hash of the new process".to_string());
+        assert_eq!(process_image_hash(pid).as_deref(), Some("hash of the new process"));
+    }
+}
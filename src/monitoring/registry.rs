@@ -0,0 +1,448 @@
+use crate::events::{BaseEvent, EventType, HostsFileEvent, RegistryEvent};
+use crate::events::file::HostsEntryChange;
+use crate::events::registry::{RegistryChange, RegistryWatch};
+use crossbeam_channel::Sender;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::{ERROR_MORE_DATA, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS, FILETIME};
+use windows::Win32::System::Registry::*;
+use windows::core::{HSTRING, PCWSTR, PWSTR};
+
+// Only these values are snapshotted; the rest of the CLSID tree is never read
+const COM_SERVER_SUBKEY: &str = "InprocServer32";
+const COM_TREAT_AS_SUBKEY: &str = "TreatAs";
+// Per-user CLSID trees are normally a few hundred keys; stop enumerating past this
+const MAX_CLSIDS_PER_USER: u32 = 20_000;
+// Run keys hold a handful of values; an enumeration failing part-way can't loop forever
+const MAX_RUN_VALUES: u32 = 1_024;
+const MACHINE_CLSID_ROOT: &str = "SOFTWARE\\Classes\\CLSID";
+// Autostart keys read under each loaded user hive and, with the WOW64 copies, under HKLM
+const USER_RUN_KEYS: &[&str] = &[
+    "Software\\Microsoft\\Windows\\CurrentVersion\\Run",
+    "Software\\Microsoft\\Windows\\CurrentVersion\\RunOnce",
+];
+const MACHINE_RUN_KEYS: &[&str] = &[
+    "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run",
+    "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\RunOnce",
+    "SOFTWARE\\WOW6432Node\\Microsoft\\Windows\\CurrentVersion\\Run",
+    "SOFTWARE\\WOW6432Node\\Microsoft\\Windows\\CurrentVersion\\RunOnce",
+];
+// Static resolvers live in NameServer, globally and per interface; DhcpNameServer
+// is what DHCP handed out and changes on its own
+const TCPIP_PARAMETER_KEYS: &[&str] = &[
+    "SYSTEM\\CurrentControlSet\\Services\\Tcpip\\Parameters",
+    "SYSTEM\\CurrentControlSet\\Services\\Tcpip6\\Parameters",
+];
+const NAME_SERVER_VALUE: &str = "NameServer";
+// Interfaces per stack are normally a handful; stop enumerating past this
+const MAX_INTERFACES: u32 = 1_024;
+
+/// Polls every loaded user's `CLSID` tree (`HKU\<SID>_Classes\CLSID`, which backs
+/// `HKCU\Software\Classes\CLSID`) for COM-hijack persistence, the machine and
+/// per-user `Run`/`RunOnce` keys for new autostart values, and the name-resolution
+/// settings: static DNS servers and the hosts file.
+pub fn start_registry_monitor(
+    tx: Sender<BaseEvent>,
+    poll_interval: Duration,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_registry_monitor(tx, poll_interval, shutdown);
+    })
+}
+
+fn run_registry_monitor(tx: Sender<BaseEvent>, poll_interval: Duration, shutdown: Arc<AtomicBool>) {
+    log::info!(
+        "✅ Registry Monitor watching COM registrations, Run keys, DNS servers and the hosts file every {}s",
+        poll_interval.as_secs()
+    );
+
+    let mut snapshots: HashMap<String, ComSnapshot> = HashMap::new();
+    let mut run_keys = RunKeyState::default();
+    let mut name_servers: Option<HashMap<String, String>> = None;
+    let hosts_path = hosts_file_path();
+    let mut hosts_entries = read_hosts_entries(&hosts_path);
+    let mut last_scan: Option<Instant> = None;
+    while shutdown.load(Ordering::Relaxed) {
+        if last_scan.is_none_or(|scanned| scanned.elapsed() >= poll_interval) {
+            scan_user_hives(&mut snapshots, &tx);
+            scan_run_keys(&mut run_keys, &tx);
+            scan_name_servers(&mut name_servers, &tx);
+            scan_hosts_file(&hosts_path, &mut hosts_entries, &tx);
+            last_scan = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    log::info!("✅ Registry Monitor stopped");
+}
+
+/// Last-seen state of one `{CLSID}\InprocServer32` or `{CLSID}\TreatAs` key.
+struct ComValue {
+    last_write: u64,
+    data: String,
+}
+
+/// Keyed by `{CLSID}\<subkey>` relative to the user's CLSID root.
+type ComSnapshot = HashMap<String, ComValue>;
+
+fn scan_user_hives(snapshots: &mut HashMap<String, ComSnapshot>, tx: &Sender<BaseEvent>) {
+    let Some(users) = RegKey::open(HKEY_USERS, "") else {
+        return;
+    };
+    let hives: Vec<String> = users.subkey_names(u32::MAX)
+        .into_iter()
+        .filter(|name| name.ends_with("_Classes"))
+        .collect();
+
+    for hive in &hives {
+        // A hive seen for the first time (startup, or a user logging on) is baselined
+        // without events; only later creations and modifications are reported.
+        let baseline = !snapshots.contains_key(hive);
+        let snapshot = snapshots.entry(hive.clone()).or_default();
+        scan_user_clsids(hive, snapshot, baseline, tx);
+        if baseline {
+            log::debug!("Registry Monitor baselined {} COM keys for {}", snapshot.len(), hive);
+        }
+    }
+
+    // Forget users that logged off
+    snapshots.retain(|hive, _| hives.contains(hive));
+}
+
+fn scan_user_clsids(hive: &str, snapshot: &mut ComSnapshot, baseline: bool, tx: &Sender<BaseEvent>) {
+    let Some(clsid_root) = RegKey::open(HKEY_USERS, &format!("{}\\CLSID", hive)) else {
+        return;
+    };
+
+    let mut seen = HashSet::new();
+    for clsid in clsid_root.subkey_names(MAX_CLSIDS_PER_USER) {
+        for subkey in [COM_SERVER_SUBKEY, COM_TREAT_AS_SUBKEY] {
+            let relative = format!("{}\\{}", clsid, subkey);
+            let Some(key) = clsid_root.open_subkey(&relative) else {
+                continue;
+            };
+            seen.insert(relative.clone());
+
+            // Incremental: an unchanged last-write time means the value wasn't touched
+            let last_write = key.last_write_time();
+            if snapshot.get(&relative).is_some_and(|known| known.last_write == last_write) {
+                continue;
+            }
+            let data = key.string_value("").unwrap_or_default();
+            let previous = snapshot.insert(relative.clone(), ComValue { last_write, data: data.clone() });
+            if baseline {
+                continue;
+            }
+            let change = match previous {
+                None => RegistryChange::Created,
+                Some(previous) if previous.data != data => RegistryChange::Modified,
+                Some(_) => continue,
+            };
+
+            let resolved_server = if subkey == COM_SERVER_SUBKEY {
+                Some(data.clone())
+            } else {
+                resolve_com_server(&clsid_root, &data)
+            };
+            let event = RegistryEvent {
+                key_path: format!("HKU\\{}\\CLSID\\{}", hive, relative),
+                value_name: String::new(),
+                value_data: data,
+                change,
+                watch: RegistryWatch::ComRegistration,
+                resolved_server,
+                shadows_machine_key: RegKey::open(HKEY_LOCAL_MACHINE, &format!("{}\\{}", MACHINE_CLSID_ROOT, clsid)).is_some(),
+            };
+            let _ = tx.send(BaseEvent::new(EventType::RegistryChange(event)));
+        }
+    }
+
+    snapshot.retain(|relative, _| seen.contains(relative));
+}
+
+/// The DLL a `TreatAs` target CLSID loads, preferring the per-user registration
+/// the same way COM does.
+fn resolve_com_server(user_clsid_root: &RegKey, target_clsid: &str) -> Option<String> {
+    if target_clsid.is_empty() {
+        return None;
+    }
+    let relative = format!("{}\\{}", target_clsid, COM_SERVER_SUBKEY);
+    user_clsid_root.open_subkey(&relative)
+        .or_else(|| RegKey::open(HKEY_LOCAL_MACHINE, &format!("{}\\{}", MACHINE_CLSID_ROOT, relative)))
+        .and_then(|key| key.string_value(""))
+}
+
+/// Last-seen values of one Run/RunOnce key.
+struct RunKeySnapshot {
+    last_write: u64,
+    values: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct RunKeyState {
+    /// `HKLM` and the user SIDs already baselined.
+    roots: HashSet<String>,
+    /// Keyed by the full display path, e.g. `HKU\<SID>\Software\…\Run`.
+    keys: HashMap<String, RunKeySnapshot>,
+}
+
+fn scan_run_keys(state: &mut RunKeyState, tx: &Sender<BaseEvent>) {
+    let mut roots: Vec<(String, HKEY, String, &[&str])> = vec![
+        ("HKLM".to_string(), HKEY_LOCAL_MACHINE, String::new(), MACHINE_RUN_KEYS),
+    ];
+    if let Some(users) = RegKey::open(HKEY_USERS, "") {
+        for sid in users.subkey_names(u32::MAX).into_iter().filter(|name| !name.ends_with("_Classes")) {
+            roots.push((format!("HKU\\{}", sid), HKEY_USERS, format!("{}\\", sid), USER_RUN_KEYS));
+        }
+    }
+
+    for (root, hkey, prefix, paths) in &roots {
+        // The machine keys on the first scan, and a user's keys when they log on, are
+        // baselined; a Run key created later has all of its values reported.
+        let baseline = !state.roots.contains(root);
+        for path in *paths {
+            let display_path = format!("{}\\{}", root, path);
+            let Some(key) = RegKey::open(*hkey, &format!("{}{}", prefix, path)) else {
+                state.keys.remove(&display_path);
+                continue;
+            };
+            let last_write = key.last_write_time();
+            if state.keys.get(&display_path).is_some_and(|known| known.last_write == last_write) {
+                continue;
+            }
+            let values = key.string_values(MAX_RUN_VALUES);
+            let previous = state.keys.insert(display_path.clone(), RunKeySnapshot { last_write, values: values.clone() });
+            if baseline {
+                continue;
+            }
+
+            let previous_values = previous.map(|snapshot| snapshot.values).unwrap_or_default();
+            for (name, data) in values {
+                let change = match previous_values.get(&name) {
+                    None => RegistryChange::Created,
+                    Some(old) if *old != data => RegistryChange::Modified,
+                    Some(_) => continue,
+                };
+                let event = RegistryEvent {
+                    key_path: display_path.clone(),
+                    value_name: name,
+                    value_data: data,
+                    change,
+                    watch: RegistryWatch::RunKey,
+                    resolved_server: None,
+                    shadows_machine_key: false,
+                };
+                let _ = tx.send(BaseEvent::new(EventType::RegistryChange(event)));
+            }
+        }
+    }
+
+    state.roots = roots.into_iter().map(|(root, ..)| root).collect();
+    state.keys.retain(|path, _| state.roots.iter().any(|root| path.starts_with(root.as_str())));
+}
+
+/// Reports static `NameServer` values that were set or changed since the last scan,
+/// keyed by full path. The first scan is the baseline. Clearing a value (back to
+/// DHCP) is not reported.
+fn scan_name_servers(known: &mut Option<HashMap<String, String>>, tx: &Sender<BaseEvent>) {
+    let mut current = HashMap::new();
+    for parameters in TCPIP_PARAMETER_KEYS {
+        let Some(key) = RegKey::open(HKEY_LOCAL_MACHINE, parameters) else {
+            continue;
+        };
+        let mut keys = vec![(format!("HKLM\\{}", parameters), key.string_value(NAME_SERVER_VALUE))];
+        if let Some(interfaces) = key.open_subkey("Interfaces") {
+            for interface in interfaces.subkey_names(MAX_INTERFACES) {
+                let servers = interfaces.open_subkey(&interface).and_then(|key| key.string_value(NAME_SERVER_VALUE));
+                keys.push((format!("HKLM\\{}\\Interfaces\\{}", parameters, interface), servers));
+            }
+        }
+        for (path, servers) in keys {
+            if let Some(servers) = servers.filter(|servers| !servers.trim().is_empty()) {
+                current.insert(path, servers);
+            }
+        }
+    }
+
+    if let Some(previous) = known.as_ref() {
+        for (path, servers) in &current {
+            let change = match previous.get(path) {
+                None => RegistryChange::Created,
+                Some(old) if old != servers => RegistryChange::Modified,
+                Some(_) => continue,
+            };
+            let event = RegistryEvent {
+                key_path: path.clone(),
+                value_name: NAME_SERVER_VALUE.to_string(),
+                value_data: servers.clone(),
+                change,
+                watch: RegistryWatch::DnsServer,
+                resolved_server: None,
+                shadows_machine_key: false,
+            };
+            let _ = tx.send(BaseEvent::new(EventType::RegistryChange(event)));
+        }
+    }
+    *known = Some(current);
+}
+
+fn hosts_file_path() -> String {
+    let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+    format!("{}\\System32\\drivers\\etc\\hosts", system_root)
+}
+
+/// Host name (lowercased) → address, from the hosts file's active lines. A host
+/// listed twice keeps its first address, as the resolver does. A missing or
+/// unreadable file has no entries.
+fn read_hosts_entries(path: &str) -> HashMap<String, String> {
+    let Ok(bytes) = std::fs::read(path) else {
+        return HashMap::new();
+    };
+    let mut entries = HashMap::new();
+    for line in String::from_utf8_lossy(&bytes).lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let Some(address) = fields.next() else {
+            continue;
+        };
+        for host in fields {
+            entries.entry(host.to_lowercase()).or_insert_with(|| address.to_string());
+        }
+    }
+    entries
+}
+
+fn scan_hosts_file(path: &str, known: &mut HashMap<String, String>, tx: &Sender<BaseEvent>) {
+    let current = read_hosts_entries(path);
+    let mut changes: Vec<HostsEntryChange> = current.iter()
+        .filter(|(host, address)| known.get(*host) != Some(*address))
+        .map(|(host, address)| HostsEntryChange {
+            host: host.clone(),
+            old_address: known.get(host).cloned(),
+            new_address: Some(address.clone()),
+        })
+        .chain(known.iter()
+            .filter(|(host, _)| !current.contains_key(*host))
+            .map(|(host, address)| HostsEntryChange {
+                host: host.clone(),
+                old_address: Some(address.clone()),
+                new_address: None,
+            }))
+        .collect();
+    *known = current;
+    if changes.is_empty() {
+        return;
+    }
+    changes.sort_by(|a, b| a.host.cmp(&b.host));
+    let event = HostsFileEvent { path: path.to_string(), changes };
+    let _ = tx.send(BaseEvent::new(EventType::HostsFileChanged(event)));
+}
+
+/// Read-only registry key, closed on drop.
+struct RegKey(HKEY);
+
+impl RegKey {
+    fn open(parent: HKEY, path: &str) -> Option<Self> {
+        let mut key = HKEY::default();
+        let status = unsafe { RegOpenKeyExW(parent, &HSTRING::from(path), None, KEY_READ, &mut key) };
+        (status == ERROR_SUCCESS).then_some(Self(key))
+    }
+
+    fn open_subkey(&self, path: &str) -> Option<Self> {
+        Self::open(self.0, path)
+    }
+
+    fn subkey_names(&self, limit: u32) -> Vec<String> {
+        let mut names = Vec::new();
+        // Key names are at most 255 characters
+        let mut buffer = [0u16; 256];
+        for index in 0..limit {
+            let mut len = buffer.len() as u32;
+            let status = unsafe {
+                RegEnumKeyExW(self.0, index, Some(PWSTR(buffer.as_mut_ptr())), &mut len, None, None, None, None)
+            };
+            if status == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            if status == ERROR_SUCCESS {
+                names.push(String::from_utf16_lossy(&buffer[..len as usize]));
+            }
+        }
+        names
+    }
+
+    fn last_write_time(&self) -> u64 {
+        let mut written = FILETIME::default();
+        let status = unsafe {
+            RegQueryInfoKeyW(self.0, None, None, None, None, None, None, None, None, None, None, Some(&mut written))
+        };
+        if status != ERROR_SUCCESS {
+            return 0;
+        }
+        ((written.dwHighDateTime as u64) << 32) | written.dwLowDateTime as u64
+    }
+
+    /// A string value (`""` for the key's default); REG_EXPAND_SZ is expanded.
+    fn string_value(&self, name: &str) -> Option<String> {
+        let name = HSTRING::from(name);
+        let mut size = 0u32;
+        let status = unsafe {
+            RegGetValueW(self.0, PCWSTR::null(), &name, RRF_RT_REG_SZ, None, None, Some(&mut size))
+        };
+        if status != ERROR_SUCCESS && status != ERROR_MORE_DATA {
+            return None;
+        }
+        let mut buffer = vec![0u16; (size as usize).div_ceil(2)];
+        let status = unsafe {
+            RegGetValueW(
+                self.0,
+                PCWSTR::null(),
+                &name,
+                RRF_RT_REG_SZ,
+                None,
+                Some(buffer.as_mut_ptr() as *mut _),
+                Some(&mut size),
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+        let chars = (size as usize / 2).min(buffer.len());
+        Some(String::from_utf16_lossy(&buffer[..chars]).trim_end_matches('\0').to_string())
+    }
+
+    /// Named string values of the key; other value types are skipped.
+    fn string_values(&self, limit: u32) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        // Value names are at most 16,383 characters
+        let mut buffer = vec![0u16; 16_384];
+        for index in 0..limit {
+            let mut len = buffer.len() as u32;
+            let status = unsafe {
+                RegEnumValueW(self.0, index, Some(PWSTR(buffer.as_mut_ptr())), &mut len, None, None, None, None)
+            };
+            if status == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            if status != ERROR_SUCCESS || len == 0 {
+                continue;
+            }
+            let name = String::from_utf16_lossy(&buffer[..len as usize]);
+            if let Some(data) = self.string_value(&name) {
+                values.insert(name, data);
+            }
+        }
+        values
+    }
+}
+
+impl Drop for RegKey {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = RegCloseKey(self.0);
+        }
+    }
+}
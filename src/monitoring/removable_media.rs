@@ -0,0 +1,89 @@
+use crate::events::device::RemovableMediaEvent;
+use crate::events::{BaseEvent, EventType};
+use crossbeam_channel::Sender;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use windows::Win32::Storage::FileSystem::{
+    GetDriveTypeW, GetLogicalDrives, GetVolumeInformationW, GetVolumeNameForVolumeMountPointW,
+};
+use windows::Win32::System::WindowsProgramming::DRIVE_REMOVABLE;
+use windows::core::HSTRING;
+
+/// Polls the drive letters for newly mounted removable drives, so process starts
+/// from a just-inserted USB stick can be told apart from ones on a long-present drive.
+pub fn start_removable_media_monitor(
+    tx: Sender<BaseEvent>,
+    poll_interval: Duration,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_removable_media_monitor(tx, poll_interval, shutdown);
+    })
+}
+
+fn run_removable_media_monitor(tx: Sender<BaseEvent>, poll_interval: Duration, shutdown: Arc<AtomicBool>) {
+    log::info!("✅ Removable Media Monitor polling drive letters every {}s", poll_interval.as_secs());
+
+    // The first poll is the baseline; drives already plugged in aren't reported
+    let mut known: Option<u32> = None;
+    let mut last_poll: Option<Instant> = None;
+    while shutdown.load(Ordering::Relaxed) {
+        if last_poll.is_none_or(|polled| polled.elapsed() >= poll_interval) {
+            let removable = removable_drive_mask();
+            if let Some(previous) = known {
+                for index in 0..26 {
+                    if removable & !previous & (1 << index) != 0 {
+                        let event = describe_drive((b'A' + index as u8) as char);
+                        log::info!("💾 Removable drive {} mounted (label '{}', {})", event.drive, event.label, event.volume_id);
+                        let _ = tx.send(BaseEvent::new(EventType::RemovableMediaArrived(event)));
+                    }
+                }
+            }
+            known = Some(removable);
+            last_poll = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    log::info!("✅ Removable Media Monitor stopped");
+}
+
+/// Bit n set when drive letter `A + n` is mounted and removable.
+fn removable_drive_mask() -> u32 {
+    let mounted = unsafe { GetLogicalDrives() };
+    (0..26)
+        .filter(|index| mounted & (1 << index) != 0)
+        .filter(|&index| {
+            let root = format!("{}:\\", (b'A' + index as u8) as char);
+            let drive_type = unsafe { GetDriveTypeW(&HSTRING::from(root)) };
+            drive_type == DRIVE_REMOVABLE
+        })
+        .fold(0, |mask, index| mask | (1 << index))
+}
+
+fn describe_drive(letter: char) -> RemovableMediaEvent {
+    let drive = format!("{}:\\", letter);
+    let root = HSTRING::from(drive.as_str());
+
+    let mut volume_name = [0u16; 64];
+    let volume_id = match unsafe { GetVolumeNameForVolumeMountPointW(&root, &mut volume_name) } {
+        Ok(()) => wide_to_string(&volume_name),
+        Err(_) => String::new(),
+    };
+    let mut label = [0u16; 261];
+    let mut serial_number = 0u32;
+    let _ = unsafe { GetVolumeInformationW(&root, Some(&mut label), Some(&mut serial_number), None, None, None) };
+
+    RemovableMediaEvent {
+        drive,
+        volume_id,
+        label: wide_to_string(&label),
+        serial_number,
+    }
+}
+
+fn wide_to_string(buffer: &[u16]) -> String {
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
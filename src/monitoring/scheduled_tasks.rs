@@ -0,0 +1,205 @@
+use crate::events::task::{ScheduledTaskEvent, TaskAction, TaskFileChange};
+use crate::events::{BaseEvent, EventType};
+use crossbeam_channel::Sender;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+// Bounds on a scan, so a flooded folder can't stall the poller
+const MAX_TASK_FILES: usize = 20_000;
+const MAX_FOLDER_DEPTH: usize = 16;
+// Task XML is a few KB; anything far larger isn't a task definition worth parsing
+const MAX_TASK_FILE_BYTES: u64 = 1024 * 1024;
+// FIXDLEN_DATA plus the running-instance count precede a .job's variable-length section
+const JOB_VARIABLE_SECTION_OFFSET: usize = 0x46;
+
+lazy_static::lazy_static! {
+    static ref EXEC_ACTION: regex::Regex = regex::Regex::new(r"(?s)<Exec>(.*?)</Exec>").unwrap();
+    static ref EXEC_COMMAND: regex::Regex = regex::Regex::new(r"(?s)<Command>(.*?)</Command>").unwrap();
+    static ref EXEC_ARGUMENTS: regex::Regex = regex::Regex::new(r"(?s)<Arguments>(.*?)</Arguments>").unwrap();
+    static ref ENVIRONMENT_VARIABLE: regex::Regex = regex::Regex::new(r"%([^%\s]+)%").unwrap();
+}
+
+/// Last-seen state of one task file; a change in either means it was rewritten.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct TaskFileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Polls `%SystemRoot%\System32\Tasks` (task XML) and `%SystemRoot%\Tasks` (legacy
+/// `.job` files) and reports files that appeared or changed, with their actions.
+/// This catches tasks dropped straight onto disk, which never run `schtasks`.
+pub fn start_scheduled_task_monitor(
+    tx: Sender<BaseEvent>,
+    poll_interval: Duration,
+    shutdown: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        run_scheduled_task_monitor(tx, poll_interval, shutdown);
+    })
+}
+
+fn run_scheduled_task_monitor(tx: Sender<BaseEvent>, poll_interval: Duration, shutdown: Arc<AtomicBool>) {
+    let system_root = PathBuf::from(std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".to_string()));
+    let roots = [
+        (system_root.join("System32").join("Tasks"), false),
+        (system_root.join("Tasks"), true),
+    ];
+    log::info!("✅ Scheduled Task Monitor scanning {} every {}s", roots[0].0.display(), poll_interval.as_secs());
+
+    // The first scan is the baseline; tasks that already exist aren't reported
+    let mut known: Option<HashMap<PathBuf, TaskFileStamp>> = None;
+    let mut last_scan: Option<Instant> = None;
+    while shutdown.load(Ordering::Relaxed) {
+        if last_scan.is_none_or(|scanned| scanned.elapsed() >= poll_interval) {
+            let mut current = HashMap::new();
+            for (root, legacy) in &roots {
+                scan_task_folder(root, *legacy, 0, &mut current);
+            }
+            if let Some(previous) = &known {
+                for (path, stamp) in &current {
+                    let change = match previous.get(path) {
+                        None => TaskFileChange::Created,
+                        Some(old) if old != stamp => TaskFileChange::Modified,
+                        Some(_) => continue,
+                    };
+                    let legacy = is_legacy_job(path);
+                    let root = if legacy { &roots[1].0 } else { &roots[0].0 };
+                    if let Some(event) = read_task_file(path, root, legacy, change) {
+                        let _ = tx.send(BaseEvent::new(EventType::ScheduledTaskChanged(event)));
+                    }
+                }
+            }
+            known = Some(current);
+            last_scan = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    log::info!("✅ Scheduled Task Monitor stopped");
+}
+
+fn is_legacy_job(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("job"))
+}
+
+fn scan_task_folder(folder: &Path, legacy: bool, depth: usize, files: &mut HashMap<PathBuf, TaskFileStamp>) {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if files.len() >= MAX_TASK_FILES {
+            return;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        if metadata.is_dir() {
+            // Legacy jobs live flat in %SystemRoot%\Tasks
+            if !legacy && depth < MAX_FOLDER_DEPTH {
+                scan_task_folder(&path, legacy, depth + 1, files);
+            }
+        } else if !legacy || is_legacy_job(&path) {
+            files.insert(path, TaskFileStamp { modified: metadata.modified().ok(), len: metadata.len() });
+        }
+    }
+}
+
+fn read_task_file(path: &Path, root: &Path, legacy: bool, change: TaskFileChange) -> Option<ScheduledTaskEvent> {
+    if std::fs::metadata(path).ok()?.len() > MAX_TASK_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    let actions = if legacy {
+        parse_legacy_job(&bytes).into_iter().collect()
+    } else {
+        parse_task_xml(&decode_task_xml(&bytes))
+    };
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    Some(ScheduledTaskEvent {
+        task_name: format!("\\{}", relative.display()),
+        file_path: path.display().to_string(),
+        change,
+        legacy_job: legacy,
+        actions,
+    })
+}
+
+/// Task XML is normally UTF-16 with a BOM, but UTF-8 files register too.
+fn decode_task_xml(bytes: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], little_endian: bool| {
+        let units: Vec<u16> = bytes.chunks_exact(2)
+            .map(|pair| if little_endian { u16::from_le_bytes([pair[0], pair[1]]) } else { u16::from_be_bytes([pair[0], pair[1]]) })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, true),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, false),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        // UTF-16LE without a BOM starts "<\0"
+        [b'<', 0, ..] => utf16(bytes, true),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// The `Exec` actions of a task definition. COM-handler actions have no command line.
+fn parse_task_xml(xml: &str) -> Vec<TaskAction> {
+    EXEC_ACTION.captures_iter(xml)
+        .filter_map(|exec| {
+            let body = exec.get(1)?.as_str();
+            let command = EXEC_COMMAND.captures(body)?.get(1)?.as_str();
+            let arguments = EXEC_ARGUMENTS.captures(body)
+                .and_then(|arguments| arguments.get(1))
+                .map_or("", |arguments| arguments.as_str());
+            Some(TaskAction {
+                command: expand_environment(&unescape_xml(command.trim())),
+                arguments: expand_environment(&unescape_xml(arguments.trim())),
+            })
+        })
+        .collect()
+}
+
+/// Application name and parameters from a Task Scheduler 1.0 `.job` file: each is a
+/// character count followed by that many UTF-16LE characters, NUL included.
+fn parse_legacy_job(bytes: &[u8]) -> Option<TaskAction> {
+    let read_string = |offset: usize| -> Option<(String, usize)> {
+        let count = u16::from_le_bytes([*bytes.get(offset)?, *bytes.get(offset + 1)?]) as usize;
+        let start = offset + 2;
+        let end = start.checked_add(count * 2)?;
+        let units: Vec<u16> = bytes.get(start..end)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        Some((String::from_utf16_lossy(&units).trim_end_matches('\0').to_string(), end))
+    };
+    let (command, next) = read_string(JOB_VARIABLE_SECTION_OFFSET)?;
+    let arguments = read_string(next).map(|(arguments, _)| arguments).unwrap_or_default();
+    if command.is_empty() {
+        return None;
+    }
+    Some(TaskAction {
+        command: expand_environment(&command),
+        arguments: expand_environment(&arguments),
+    })
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Expands `%VAR%` from this process's environment; unknown variables are kept.
+fn expand_environment(text: &str) -> String {
+    ENVIRONMENT_VARIABLE.replace_all(text, |caps: &regex::Captures| {
+        std::env::var(&caps[1]).unwrap_or_else(|_| caps[0].to_string())
+    })
+    .into_owned()
+}
@@ -0,0 +1,469 @@
+use crate::config::rules::SqliteOutput;
+use crate::events::{Alert, ProcessSecurity};
+use crate::events::alert::SOURCE_UNKNOWN;
+use rusqlite::{params, Connection, ErrorCode, OpenFlags};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Bump when adding a migration step to `migrate`.
+const SCHEMA_VERSION: i32 = 5;
+
+lazy_static::lazy_static! {
+    static ref TECHNIQUE_ID: regex::Regex = regex::Regex::new(r"\bT\d{4}(?:\.\d{3})?\b").unwrap();
+}
+
+// How long an insert waits on a lock held by the CLI (e.g. `--ack`) before spilling
+const SINK_BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+// Alerts held in memory while the database is unwritable; the oldest go first
+const MAX_SPILLED_ALERTS: usize = 1000;
+// Backoff between attempts to flush spilled alerts
+const SPILL_RETRY_MIN: Duration = Duration::from_secs(1);
+const SPILL_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// Writes each alert as a row in an indexed `alerts` table, keeping the full
+/// JSON alongside for anything the columns don't capture.
+pub struct SqliteSink {
+    conn: Connection,
+    host: String,
+    spill: Spill,
+}
+
+/// Alerts that could not be written because the database was locked or
+/// unwritable, kept in order until a retry succeeds.
+struct Spill {
+    pending: VecDeque<(Alert, String)>,
+    dropped: u64,
+    retry_at: Instant,
+    retry_delay: Duration,
+}
+
+pub fn open_sqlite_sink(config: &SqliteOutput) -> Option<SqliteSink> {
+    let conn = match Connection::open(&config.database_path) {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::error!("Failed to open alert database {}: {}", config.database_path, e);
+            return None;
+        }
+    };
+    if let Err(e) = migrate(&conn) {
+        log::error!("Failed to prepare alert database schema: {}", e);
+        return None;
+    }
+    if let Err(e) = conn.busy_timeout(SINK_BUSY_TIMEOUT) {
+        log::warn!("Failed to set alert database busy timeout: {}", e);
+    }
+
+    log::info!("✅ SQLite alert output writing to {}", config.database_path);
+    Some(SqliteSink {
+        conn,
+        host: whoami::fallible::hostname().unwrap_or_else(|_| "unknown".to_string()),
+        spill: Spill {
+            pending: VecDeque::new(),
+            dropped: 0,
+            retry_at: Instant::now(),
+            retry_delay: SPILL_RETRY_MIN,
+        },
+    })
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version < 1 {
+        conn.execute_batch(
+            "BEGIN;
+             CREATE TABLE IF NOT EXISTS alerts (
+                 id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                 timestamp   TEXT    NOT NULL,
+                 severity    TEXT    NOT NULL,
+                 rule_name   TEXT    NOT NULL,
+                 process     TEXT    NOT NULL,
+                 pid         INTEGER NOT NULL,
+                 host        TEXT    NOT NULL,
+                 technique   TEXT,
+                 raw_json    BLOB    NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_alerts_timestamp ON alerts(timestamp);
+             CREATE INDEX IF NOT EXISTS idx_alerts_severity  ON alerts(severity);
+             CREATE INDEX IF NOT EXISTS idx_alerts_rule      ON alerts(rule_name);
+             CREATE INDEX IF NOT EXISTS idx_alerts_process   ON alerts(process);
+             CREATE INDEX IF NOT EXISTS idx_alerts_pid       ON alerts(pid);
+             CREATE INDEX IF NOT EXISTS idx_alerts_host      ON alerts(host);
+             CREATE INDEX IF NOT EXISTS idx_alerts_technique ON alerts(technique);
+             COMMIT;",
+        )?;
+    }
+
+    if version < 2 {
+        conn.execute_batch(
+            "BEGIN;
+             ALTER TABLE alerts ADD COLUMN confidence INTEGER;
+             CREATE INDEX IF NOT EXISTS idx_alerts_confidence ON alerts(confidence);
+             COMMIT;",
+        )?;
+    }
+
+    if version < 3 {
+        conn.execute_batch(
+            "BEGIN;
+             ALTER TABLE alerts ADD COLUMN source TEXT;
+             CREATE INDEX IF NOT EXISTS idx_alerts_source ON alerts(source);
+             COMMIT;",
+        )?;
+    }
+
+    if version < 4 {
+        // Rows from before alert IDs get a stable synthetic one so they can be acknowledged too
+        conn.execute_batch(
+            "BEGIN;
+             ALTER TABLE alerts ADD COLUMN alert_id TEXT;
+             UPDATE alerts SET alert_id = 'legacy-' || id WHERE alert_id IS NULL;
+             CREATE UNIQUE INDEX IF NOT EXISTS idx_alerts_alert_id ON alerts(alert_id);
+             CREATE TABLE IF NOT EXISTS alert_status (
+                 alert_id    TEXT PRIMARY KEY,
+                 status      TEXT NOT NULL,
+                 updated_at  TEXT NOT NULL
+             );
+             COMMIT;",
+        )?;
+    }
+
+    if version < 5 {
+        conn.execute_batch(
+            "BEGIN;
+             ALTER TABLE alerts ADD COLUMN correlation_id TEXT;
+             CREATE INDEX IF NOT EXISTS idx_alerts_correlation ON alerts(correlation_id);
+             COMMIT;",
+        )?;
+    }
+
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+impl SqliteSink {
+    /// Writes the alert, or holds it in memory if the database is locked or
+    /// unwritable. Spilled alerts are written first so the table stays in order.
+    /// While alerts are held, the database is only retried once the backoff has
+    /// passed, so a burst during a lock doesn't wait out the busy timeout per alert.
+    pub fn insert(&mut self, alert: &Alert, raw_json: &str) {
+        if !self.spill.pending.is_empty() {
+            self.flush_spill(false);
+        }
+        if !self.spill.pending.is_empty() {
+            self.spill_alert(alert.clone(), raw_json.to_string());
+            return;
+        }
+        match self.write_row(alert, raw_json) {
+            Ok(()) => {}
+            Err(e) if is_transient(&e) => {
+                log::warn!("⚠️  Alert database unavailable ({}); holding alerts in memory", e);
+                self.spill.retry_delay = SPILL_RETRY_MIN;
+                self.spill.retry_at = Instant::now() + SPILL_RETRY_MIN;
+                self.spill_alert(alert.clone(), raw_json.to_string());
+            }
+            Err(e) => log::error!("Failed to write alert to database: {}", e),
+        }
+    }
+
+    /// Retries spilled alerts once their backoff has elapsed. Called from the
+    /// alert handler's idle loop so they are written even if no new alert arrives.
+    pub fn flush_pending(&mut self) {
+        if !self.spill.pending.is_empty() {
+            self.flush_spill(false);
+        }
+    }
+
+    /// Makes a last attempt to write spilled alerts before shutdown.
+    pub fn close(mut self) {
+        if self.spill.pending.is_empty() {
+            return;
+        }
+        self.flush_spill(true);
+        if !self.spill.pending.is_empty() {
+            log::error!(
+                "❌ {} alert(s) could not be written to the alert database before shutdown",
+                self.spill.pending.len()
+            );
+        }
+    }
+
+    fn flush_spill(&mut self, force: bool) {
+        if !force && Instant::now() < self.spill.retry_at {
+            return;
+        }
+        let mut written = 0;
+        while let Some((alert, raw_json)) = self.spill.pending.front() {
+            match self.write_row(alert, raw_json) {
+                Ok(()) => {
+                    self.spill.pending.pop_front();
+                    written += 1;
+                }
+                Err(e) if is_transient(&e) => {
+                    self.spill.retry_delay = (self.spill.retry_delay * 2).min(SPILL_RETRY_MAX);
+                    self.spill.retry_at = Instant::now() + self.spill.retry_delay;
+                    break;
+                }
+                Err(e) => {
+                    log::error!("Failed to write spilled alert to database: {}", e);
+                    self.spill.pending.pop_front();
+                }
+            }
+        }
+        if self.spill.pending.is_empty() {
+            log::info!(
+                "✅ Alert database writable again; wrote {} held alert(s){}",
+                written,
+                if self.spill.dropped > 0 { format!(", {} dropped while it was unavailable", self.spill.dropped) } else { String::new() }
+            );
+            self.spill.dropped = 0;
+            self.spill.retry_delay = SPILL_RETRY_MIN;
+        }
+    }
+
+    fn spill_alert(&mut self, alert: Alert, raw_json: String) {
+        if self.spill.pending.len() >= MAX_SPILLED_ALERTS
+            && let Some((dropped, _)) = self.spill.pending.pop_front()
+        {
+            self.spill.dropped += 1;
+            log::warn!(
+                "⚠️  Alert spill buffer full ({} alerts); dropped {} [{}]",
+                MAX_SPILLED_ALERTS, dropped.rule_name, dropped.id
+            );
+        }
+        self.spill.pending.push_back((alert, raw_json));
+    }
+
+    fn write_row(&self, alert: &Alert, raw_json: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO alerts (alert_id, timestamp, severity, rule_name, process, pid, host, technique, confidence, source,
+                                 correlation_id, raw_json)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                alert.id,
+                alert.timestamp.to_rfc3339(),
+                format!("{:?}", alert.severity),
+                alert.rule_name,
+                alert.process_name,
+                alert.pid,
+                self.host,
+                techniques(alert),
+                alert.confidence(),
+                alert.source,
+                alert.correlation_id,
+                raw_json.as_bytes(),
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// Errors a later retry can succeed after: the database locked by another
+/// connection, or the file held open or made read-only by another process.
+fn is_transient(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy
+            | ErrorCode::DatabaseLocked
+            | ErrorCode::ReadOnly
+            | ErrorCode::CannotOpen
+            | ErrorCode::SystemIoFailure
+            | ErrorCode::DiskFull)
+    )
+}
+
+/// Status recorded by `acknowledge_alert`.
+const STATUS_ACKNOWLEDGED: &str = "acknowledged";
+
+// How long a CLI write waits for a running sink to release the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An alert row read back for reporting.
+pub struct StoredAlert {
+    /// Row ID in the store. It grows with every insert, including alerts spilled
+    /// earlier and written late, and is never reused.
+    pub row: i64,
+    pub id: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub severity: String,
+    pub rule_name: String,
+    pub process: String,
+    pub pid: u32,
+    pub technique: Option<String>,
+    pub source: String,
+    pub evidence: Vec<String>,
+    /// Incident the alert belongs to; `None` for standalone alerts and rows before schema v5.
+    pub correlation_id: Option<String>,
+    /// When an analyst acknowledged the alert, if they have.
+    pub acknowledged_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Account and token of the alerting process, when its start was traced.
+    pub security: Option<ProcessSecurity>,
+    /// Parent image name and command line, for rows whose raw JSON carries them.
+    pub parent_name: Option<String>,
+    pub command_line: Option<String>,
+}
+
+/// Alerts stored at or after `since`, oldest first, and a description of each row
+/// that could not be parsed. A row with a bad timestamp is left out; one whose raw
+/// JSON is bad is kept without evidence. Opens the database read-only so reporting
+/// never contends with a running sink for the write lock; a database on an older
+/// schema is upgraded first.
+pub fn read_alerts_since(
+    database_path: &str,
+    since: chrono::DateTime<chrono::Utc>,
+) -> rusqlite::Result<(Vec<StoredAlert>, Vec<String>)> {
+    read_alerts(database_path, "a.timestamp >= ?1 ORDER BY a.timestamp", &since.to_rfc3339())
+}
+
+/// Alerts written after row `after`, in insert order, read as `read_alerts_since` does.
+pub fn read_alerts_after_row(
+    database_path: &str,
+    after: i64,
+) -> rusqlite::Result<(Vec<StoredAlert>, Vec<String>)> {
+    read_alerts(database_path, "a.id > ?1 ORDER BY a.id", &after)
+}
+
+/// Highest row ID the store has handed out, 0 before the first alert. It only
+/// drops when the database is replaced.
+pub fn last_alert_row(database_path: &str) -> rusqlite::Result<i64> {
+    let conn = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.query_row(
+        "SELECT COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'alerts'), 0)",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn read_alerts(
+    database_path: &str,
+    filter: &str,
+    bound: &dyn rusqlite::ToSql,
+) -> rusqlite::Result<(Vec<StoredAlert>, Vec<String>)> {
+    let mut conn = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < SCHEMA_VERSION {
+        drop(open_for_update(database_path)?);
+        conn = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    }
+    let mut statement = conn.prepare(&format!(
+        "SELECT a.alert_id, a.timestamp, a.severity, a.rule_name, a.process, a.pid, a.technique, a.source,
+                a.raw_json, s.updated_at, a.correlation_id, a.id
+         FROM alerts a
+         LEFT JOIN alert_status s ON s.alert_id = a.alert_id AND s.status = ?2
+         WHERE {}",
+        filter
+    ))?;
+    let rows = statement.query_map(params![bound, STATUS_ACKNOWLEDGED], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, u32>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<String>>(7)?,
+            row.get::<_, Vec<u8>>(8)?,
+            row.get::<_, Option<String>>(9)?,
+            row.get::<_, Option<String>>(10)?,
+            row.get::<_, i64>(11)?,
+        ))
+    })?;
+
+    let mut alerts = Vec::new();
+    let mut unparsed = Vec::new();
+    for row in rows {
+        let (id, timestamp, severity, rule_name, process, pid, technique, source, raw_json, acknowledged_at, correlation_id, row) = row?;
+        let id = id.unwrap_or_default();
+        let timestamp = match chrono::DateTime::parse_from_rfc3339(&timestamp) {
+            Ok(timestamp) => timestamp,
+            Err(e) => {
+                unparsed.push(format!("alert {} has timestamp '{}': {}", id, timestamp, e));
+                continue;
+            }
+        };
+        let (evidence, security, parent_name, command_line) = match serde_json::from_slice::<Alert>(&raw_json) {
+            Ok(alert) => (alert.evidence, alert.security, alert.parent_name, alert.command_line),
+            Err(e) => {
+                unparsed.push(format!("alert {} has unreadable raw JSON: {}", id, e));
+                Default::default()
+            }
+        };
+        alerts.push(StoredAlert {
+            row,
+            id,
+            timestamp: timestamp.with_timezone(&chrono::Utc),
+            severity,
+            rule_name,
+            process,
+            pid,
+            technique,
+            // Rows written before schema v3 have no source
+            source: source.unwrap_or_else(|| SOURCE_UNKNOWN.to_string()),
+            evidence,
+            correlation_id,
+            acknowledged_at: acknowledged_at
+                .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+                .map(|at| at.with_timezone(&chrono::Utc)),
+            security,
+            parent_name,
+            command_line,
+        });
+    }
+    Ok((alerts, unparsed))
+}
+
+/// Result of `acknowledge_alert`.
+pub enum AckOutcome {
+    Acknowledged(String),
+    AlreadyAcknowledged(String),
+    NotFound,
+    /// The prefix matched more than one alert.
+    Ambiguous,
+}
+
+/// Marks the alert whose ID is, or starts with, `id` as acknowledged. The status
+/// lives in its own table keyed on the alert ID, so it survives restarts and is
+/// untouched by the sink.
+pub fn acknowledge_alert(database_path: &str, id: &str) -> rusqlite::Result<AckOutcome> {
+    let conn = open_for_update(database_path)?;
+    let mut statement = conn.prepare(
+        "SELECT alert_id FROM alerts WHERE substr(alert_id, 1, length(?1)) = ?1 LIMIT 2",
+    )?;
+    let matches: Vec<String> = statement.query_map(params![id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let alert_id = match matches.as_slice() {
+        [] => return Ok(AckOutcome::NotFound),
+        [alert_id] => alert_id.clone(),
+        _ if matches.iter().any(|alert_id| alert_id == id) => id.to_string(),
+        _ => return Ok(AckOutcome::Ambiguous),
+    };
+
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO alert_status (alert_id, status, updated_at) VALUES (?1, ?2, ?3)",
+        params![alert_id, STATUS_ACKNOWLEDGED, chrono::Utc::now().to_rfc3339()],
+    )?;
+    Ok(if inserted == 0 {
+        AckOutcome::AlreadyAcknowledged(alert_id)
+    } else {
+        AckOutcome::Acknowledged(alert_id)
+    })
+}
+
+/// Opens an existing store for writing, upgrading its schema if needed.
+fn open_for_update(database_path: &str) -> rusqlite::Result<Connection> {
+    let conn = Connection::open_with_flags(database_path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    migrate(&conn)?;
+    Ok(conn)
+}
+
+/// Comma-separated MITRE technique IDs cited in the alert's evidence, if any.
+fn techniques(alert: &Alert) -> Option<String> {
+    let mut ids: Vec<&str> = alert.evidence.iter()
+        .flat_map(|line| TECHNIQUE_ID.find_iter(line).map(|m| m.as_str()))
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() { None } else { Some(ids.join(",")) }
+}
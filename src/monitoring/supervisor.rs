@@ -0,0 +1,183 @@
+use crate::events::Alert;
+use crate::events::alert::{AlertSeverity, SOURCE_SUPERVISOR};
+use crossbeam_channel::Sender;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Starts a fresh copy of a monitor thread after the previous one died.
+pub type RestartFn = Box<dyn Fn() -> JoinHandle<()> + Send>;
+
+/// Monitor restarts performed since startup, across all monitors.
+pub static MONITOR_RESTARTS: AtomicU64 = AtomicU64::new(0);
+
+/// Health of one supervised thread, as shown by the console status and `stats`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MonitorStatus {
+    pub name: &'static str,
+    pub running: bool,
+    pub restarts: u32,
+}
+
+static MONITOR_STATUS: Mutex<Vec<MonitorStatus>> = Mutex::new(Vec::new());
+
+/// Snapshot of every supervised thread, in registration order.
+pub fn monitor_status() -> Vec<MonitorStatus> {
+    MONITOR_STATUS.lock().map(|status| status.clone()).unwrap_or_default()
+}
+
+/// Names of supervised threads that are down and won't be restarted.
+pub fn dead_monitors() -> Vec<&'static str> {
+    monitor_status().into_iter()
+        .filter(|status| !status.running)
+        .map(|status| status.name)
+        .collect()
+}
+
+/// A thread to watch: its handle, the shutdown flag it runs under (true = running)
+/// and, optionally, how to start it again.
+pub struct SupervisedMonitor {
+    pub name: &'static str,
+    pub handle: Option<JoinHandle<()>>,
+    pub running: Arc<AtomicBool>,
+    pub restart: Option<RestartFn>,
+}
+
+impl SupervisedMonitor {
+    /// Starts the thread with `restart`, which also restarts it after a crash.
+    pub fn spawn(name: &'static str, running: &Arc<AtomicBool>, restart: RestartFn) -> Self {
+        Self { name, handle: Some(restart()), running: Arc::clone(running), restart: Some(restart) }
+    }
+
+    /// Watches an already started thread that can't be restarted in place.
+    pub fn watch(name: &'static str, handle: JoinHandle<()>, running: &Arc<AtomicBool>) -> Self {
+        Self { name, handle: Some(handle), running: Arc::clone(running), restart: None }
+    }
+}
+
+/// A running supervisor. `stop` ends it and returns the monitors' current threads.
+pub struct Supervisor {
+    monitors: Arc<Mutex<Vec<SupervisedMonitor>>>,
+    shutdown: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Supervisor {
+    /// Stops supervising and hands back each monitor's thread, in the order given
+    /// to `start_supervisor`. The restart closures (and the channel ends they hold)
+    /// are dropped here, so the caller's shutdown can disconnect the channels.
+    pub fn stop(self) -> Vec<(&'static str, Option<JoinHandle<()>>)> {
+        self.shutdown.store(false, Ordering::Relaxed);
+        let _ = self.thread.join();
+        let monitors = std::mem::take(&mut *self.monitors.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        monitors.into_iter()
+            .map(|monitor| (monitor.name, monitor.handle))
+            .collect()
+    }
+}
+
+/// Checks every `check_interval` whether a monitor thread has finished while its
+/// shutdown flag still says run. Each death raises a Critical self-alert; the
+/// thread is restarted up to `max_restarts` times if it has a restart function.
+pub fn start_supervisor(
+    monitors: Vec<SupervisedMonitor>,
+    check_interval: Duration,
+    max_restarts: u32,
+    alert_tx: Sender<Alert>,
+) -> Supervisor {
+    if let Ok(mut status) = MONITOR_STATUS.lock() {
+        *status = monitors.iter()
+            .map(|monitor| MonitorStatus { name: monitor.name, running: monitor.handle.is_some(), restarts: 0 })
+            .collect();
+    }
+    let monitors = Arc::new(Mutex::new(monitors));
+    let shutdown = Arc::new(AtomicBool::new(true));
+
+    let thread = {
+        let monitors = Arc::clone(&monitors);
+        let shutdown = Arc::clone(&shutdown);
+        std::thread::spawn(move || {
+            let mut waited = Duration::ZERO;
+            while shutdown.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(200));
+                waited += Duration::from_millis(200);
+                if waited < check_interval {
+                    continue;
+                }
+                waited = Duration::ZERO;
+                if let Ok(mut monitors) = monitors.lock() {
+                    for (index, monitor) in monitors.iter_mut().enumerate() {
+                        check_monitor(index, monitor, max_restarts, &alert_tx);
+                    }
+                }
+            }
+        })
+    };
+
+    Supervisor { monitors, shutdown, thread }
+}
+
+fn check_monitor(index: usize, monitor: &mut SupervisedMonitor, max_restarts: u32, alert_tx: &Sender<Alert>) {
+    // A thread that finished because it was told to stop isn't a failure
+    if !monitor.running.load(Ordering::Relaxed)
+        || !monitor.handle.as_ref().is_some_and(|handle| handle.is_finished())
+    {
+        return;
+    }
+    let cause = match monitor.handle.take().map(JoinHandle::join) {
+        Some(Err(panic)) => format!("panicked: {}", panic_message(panic.as_ref())),
+        _ => "exited unexpectedly".to_string(),
+    };
+
+    let restarts = MONITOR_STATUS.lock().ok()
+        .and_then(|status| status.get(index).map(|status| status.restarts))
+        .unwrap_or(0);
+    let restart = monitor.restart.as_ref().filter(|_| restarts < max_restarts);
+    let outcome = match restart {
+        Some(_) => format!("restarting (attempt {} of {})", restarts + 1, max_restarts),
+        None if monitor.restart.is_some() => format!("restart limit of {} reached; detection is degraded", max_restarts),
+        None => "not restartable; detection is degraded".to_string(),
+    };
+    log::error!("💀 {} {} — {}", monitor.name, cause, outcome);
+
+    let details = vec![
+        format!("Monitor = {}", monitor.name),
+        format!("Cause = {}", cause),
+        format!("Restarts = {}", restarts),
+        format!("Action = {}", outcome),
+    ];
+    let alert = Alert::new(
+        &AlertSeverity::Critical,
+        "EDR monitor stopped",
+        &format!("{} {}; {}", monitor.name, cause, outcome),
+        env!("CARGO_PKG_NAME"),
+        std::process::id(),
+        &details,
+    )
+    .with_source(SOURCE_SUPERVISOR)
+    // Certain by construction, so min_confidence never hides it
+    .with_confidence(100);
+    let _ = alert_tx.send(alert);
+
+    if let Some(restart) = restart {
+        monitor.handle = Some(restart());
+        MONITOR_RESTARTS.fetch_add(1, Ordering::Relaxed);
+    }
+    if let Ok(mut status) = MONITOR_STATUS.lock()
+        && let Some(status) = status.get_mut(index)
+    {
+        status.running = monitor.handle.is_some();
+        if monitor.handle.is_some() {
+            status.restarts += 1;
+        }
+    }
+}
+
+/// The text a panic was raised with, for logging a caught panic.
+pub(crate) fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic.downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
@@ -0,0 +1,855 @@
+// Without the sqlite feature there is no store to read; the report types stay
+// compiled so the CLI surface is identical, but nothing constructs them.
+#![cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+
+use crate::error::EdrError;
+use crate::events::alert::AlertSeverity;
+use serde::Serialize;
+use std::path::Path;
+
+// How many entries each breakdown lists before the tail is dropped
+const TOP_ENTRIES: usize = 10;
+// How often --watch reads the store, and how long it sleeps between Ctrl+C checks
+const WATCH_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+const WATCH_SLEEP: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Window covered by `--report`.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ReportPeriod {
+    Day,
+    Week,
+}
+
+impl ReportPeriod {
+    pub(crate) fn duration(self) -> chrono::Duration {
+        match self {
+            ReportPeriod::Day => chrono::Duration::days(1),
+            ReportPeriod::Week => chrono::Duration::weeks(1),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ReportPeriod::Day => "day",
+            ReportPeriod::Week => "week",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CountEntry {
+    name: String,
+    count: u64,
+}
+
+/// Standup-friendly digest of the alerts stored during one period.
+#[derive(Debug, Serialize)]
+struct AlertReport {
+    period: &'static str,
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
+    total_alerts: u64,
+    /// Set when the report was limited to one `--source`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_filter: Option<String>,
+    /// Set when acknowledged alerts were left out (`--unacked`).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unacknowledged_only: bool,
+    by_severity: Vec<CountEntry>,
+    by_source: Vec<CountEntry>,
+    by_rule: Vec<CountEntry>,
+    by_indicator: Vec<CountEntry>,
+    by_technique: Vec<CountEntry>,
+    top_processes: Vec<CountEntry>,
+    /// Accounts the alerting processes ran as; "unknown" when the start wasn't traced.
+    by_user: Vec<CountEntry>,
+    /// Processes alerting this period that did not alert in the period before.
+    new_processes: Vec<String>,
+    /// Hour buckets (UTC) with the most alerts.
+    busiest_hours: Vec<CountEntry>,
+    /// Incidents (alerts sharing a `correlation_id`) with more than one alert.
+    incidents: Vec<IncidentEntry>,
+}
+
+/// Alerts grouped by `correlation_id` in `--report`.
+#[derive(Debug, Serialize)]
+struct IncidentEntry {
+    correlation_id: String,
+    alerts: u64,
+    process: String,
+    rules: Vec<String>,
+    first_seen: chrono::DateTime<chrono::Utc>,
+    last_seen: chrono::DateTime<chrono::Utc>,
+}
+
+/// Prints a summary of the alerts in the SQLite store (`sqlite_output.database_path`)
+/// for the last day or week, as text or as JSON. `source` limits it to alerts from one
+/// detector (see `Alert::source`); `unacked` leaves out acknowledged alerts.
+pub fn print_report(
+    config_path: Option<&Path>,
+    period: ReportPeriod,
+    source: Option<&str>,
+    unacked: bool,
+    json: bool,
+) -> Result<(), EdrError> {
+    let database_path = database_path(config_path)?;
+    let report = build_report(&database_path, period, source, unacked)?;
+    if json {
+        let rendered = serde_json::to_string_pretty(&report)
+            .map_err(|e| EdrError::Report(e.to_string()))?;
+        println!("{}", rendered);
+    } else {
+        print_text(&report);
+    }
+    Ok(())
+}
+
+/// One alert as listed by `--alerts`.
+#[derive(Debug, Serialize)]
+struct AlertListEntry {
+    id: String,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    severity: String,
+    rule_name: String,
+    process: String,
+    pid: u32,
+    source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acknowledged_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elevated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command_line: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    evidence: Vec<String>,
+}
+
+/// Lists the stored alerts of the last day or week, oldest first, with their IDs
+/// for `--ack`. `min_severity` leaves out anything less severe. Returns how many
+/// alerts were listed.
+pub fn print_alert_list(
+    config_path: Option<&Path>,
+    period: ReportPeriod,
+    source: Option<&str>,
+    unacked: bool,
+    min_severity: Option<AlertSeverity>,
+    json: bool,
+) -> Result<usize, EdrError> {
+    let database_path = database_path(config_path)?;
+    let alerts = list_alerts(&database_path, period, source, unacked, min_severity)?;
+    if json {
+        let rendered = serde_json::to_string_pretty(&alerts)
+            .map_err(|e| EdrError::Report(e.to_string()))?;
+        println!("{}", rendered);
+        return Ok(alerts.len());
+    }
+
+    if alerts.is_empty() {
+        println!("No {}alerts in the last {}.", if unacked { "unacknowledged " } else { "" }, period.label());
+        return Ok(0);
+    }
+    for alert in &alerts {
+        print_alert_line(alert);
+    }
+    Ok(alerts.len())
+}
+
+/// One `--alerts` / `--watch` line: time, severity, rule, process, source, ID and status.
+fn print_alert_line(alert: &AlertListEntry) {
+    let status = match alert.acknowledged_at {
+        Some(at) => format!("acknowledged {}", at.format("%Y-%m-%d %H:%M")),
+        None => "open".to_string(),
+    };
+    let incident = alert.correlation_id.as_deref()
+        .map(|id| format!("  incident {}", id))
+        .unwrap_or_default();
+    let user = alert.user.as_deref()
+        .map(|user| format!("  user {}{}", user, if alert.elevated == Some(true) { " (elevated)" } else { "" }))
+        .unwrap_or_default();
+    println!(
+        "{}  {:<8}  {}  {} (PID {})  [{}]  {}  {}{}{}",
+        alert.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        alert.severity,
+        alert.rule_name,
+        alert.process,
+        alert.pid,
+        alert.source,
+        alert.id,
+        status,
+        incident,
+        user
+    );
+}
+
+/// `--watch`: prints each alert as the running EDR stores it, from now on, until
+/// Ctrl+C. The store is polled every `WATCH_POLL_INTERVAL`; a database that is
+/// missing, deleted or replaced is waited for and picked up again. `min_severity`
+/// and `source` filter as they do for `--alerts`; `json` prints one JSON object per line.
+pub fn watch_alerts(
+    config_path: Option<&Path>,
+    source: Option<&str>,
+    min_severity: Option<AlertSeverity>,
+    json: bool,
+) -> Result<(), EdrError> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    let database_path = database_path(config_path)?;
+    // A store that isn't there at all (or a build without one) is an error, as for --alerts;
+    // once watching, a missing store is waited out instead
+    let mut last_row = newest_alert_row(&database_path)?;
+    ctrlc::set_handler(|| STOP.store(true, Ordering::Relaxed))
+        .map_err(|e| EdrError::Report(format!("cannot set the Ctrl+C handler: {}", e)))?;
+    eprintln!("Watching {} for new alerts (Ctrl+C to stop)...", database_path);
+
+    // Like tail -f, start at the end. Rows are followed by row ID rather than timestamp:
+    // an alert the sink spilled while the database was locked keeps its original
+    // timestamp but is written with a new, higher row ID.
+    let mut store_missing = false;
+    let mut last_poll: Option<std::time::Instant> = None;
+    while !STOP.load(Ordering::Relaxed) {
+        if last_poll.is_some_and(|polled| polled.elapsed() < WATCH_POLL_INTERVAL) {
+            std::thread::sleep(WATCH_SLEEP);
+            continue;
+        }
+        last_poll = Some(std::time::Instant::now());
+
+        if !Path::new(&database_path).exists() {
+            if !store_missing {
+                eprintln!("{} is gone; waiting for it to come back...", database_path);
+                store_missing = true;
+            }
+            continue;
+        }
+        let newest = match newest_alert_row(&database_path) {
+            Ok(newest) => newest,
+            Err(e) => {
+                log::debug!("Watch poll failed, retrying: {}", e);
+                continue;
+            }
+        };
+        if store_missing {
+            // A store that was deleted and recreated starts its row IDs over
+            eprintln!("{} is back; watching again", database_path);
+            store_missing = false;
+            last_row = 0;
+        } else if newest < last_row {
+            // Row IDs never go down in one database, so this is a different file
+            eprintln!("{} was replaced; watching the new database", database_path);
+            last_row = 0;
+        }
+
+        let (alerts, read_up_to) = match list_alerts_after_row(&database_path, last_row, source, min_severity.clone()) {
+            Ok(read) => read,
+            Err(e) => {
+                log::debug!("Watch poll failed, retrying: {}", e);
+                continue;
+            }
+        };
+        // Rows that could not be parsed are not read again
+        last_row = newest.max(read_up_to);
+        for alert in alerts {
+            if json {
+                let rendered = serde_json::to_string(&alert).map_err(|e| EdrError::Report(e.to_string()))?;
+                println!("{}", rendered);
+            } else {
+                print_alert_line(&alert);
+            }
+        }
+    }
+
+    eprintln!("Stopped watching");
+    Ok(())
+}
+
+/// File format written by `--alerts --export`.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+const CSV_HEADER: &str = "time,severity,rule,process,parent,command_line,details";
+
+/// Writes the same alerts `--alerts` would list, with the same filters, to `output`
+/// for spreadsheets and ticketing tools. Returns how many alerts were written.
+pub fn export_alert_list(
+    config_path: Option<&Path>,
+    period: ReportPeriod,
+    source: Option<&str>,
+    unacked: bool,
+    min_severity: Option<AlertSeverity>,
+    format: ExportFormat,
+    output: &Path,
+) -> Result<usize, EdrError> {
+    let database_path = database_path(config_path)?;
+    let alerts = list_alerts(&database_path, period, source, unacked, min_severity)?;
+    let rendered = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&alerts)
+            .map_err(|e| EdrError::Report(e.to_string()))?,
+        ExportFormat::Csv => {
+            let mut csv = format!("{}\r\n", CSV_HEADER);
+            for alert in &alerts {
+                let fields = [
+                    alert.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                    alert.severity.clone(),
+                    alert.rule_name.clone(),
+                    alert.process.clone(),
+                    alert.parent.clone().unwrap_or_default(),
+                    alert.command_line.clone().unwrap_or_default(),
+                    alert.evidence.join("; "),
+                ];
+                let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                csv.push_str(&row.join(","));
+                csv.push_str("\r\n");
+            }
+            csv
+        }
+    };
+    std::fs::write(output, rendered)
+        .map_err(|e| EdrError::Report(format!("cannot write '{}': {}", output.display(), e)))?;
+    println!("Exported {} alerts to {}", alerts.len(), output.display());
+    Ok(alerts.len())
+}
+
+/// Quotes a CSV field (RFC 4180) when it holds a comma, quote or line break,
+/// doubling any quotes inside. A field a spreadsheet would run as a formula
+/// (a command line such as `=cmd|' /C calc'!A0`) gets a leading `'` and is quoted.
+fn csv_field(value: &str) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("\"'{}\"", value.replace('"', "\"\""))
+    } else if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A stored alert that a tested pattern matches, and what it matched on.
+#[derive(Debug, Serialize)]
+struct PatternHit {
+    id: String,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    severity: String,
+    rule_name: String,
+    process: String,
+    pid: u32,
+    /// `process`, or the evidence key whose value matched.
+    field: String,
+    value: String,
+}
+
+/// `--test-pattern`: compiles `pattern` as the rules file would, shows where it
+/// matches each sample, and with `against` which stored alerts of that period it
+/// would have matched (by process name or evidence value), to gauge noise.
+pub fn print_pattern_test(
+    config_path: Option<&Path>,
+    pattern: &str,
+    samples: &[String],
+    against: Option<ReportPeriod>,
+) -> Result<(), EdrError> {
+    let regex = crate::utils::common::compile_config_pattern(pattern)
+        .map_err(|e| EdrError::Pattern(e.to_string()))?;
+    println!("Pattern compiles (matched case-insensitively, as in the rules file): {}", pattern);
+    for sample in samples {
+        match regex.find(sample) {
+            Some(found) => println!(
+                "  MATCH     {:?}  bytes {}..{}: {:?}",
+                sample, found.start(), found.end(), found.as_str()
+            ),
+            None => println!("  no match  {:?}", sample),
+        }
+    }
+
+    let Some(period) = against else {
+        return Ok(());
+    };
+    let database_path = database_path(config_path)?;
+    let (total, hits) = pattern_hits(&database_path, period, &regex)?;
+    println!();
+    println!("{} of {} stored alerts in the last {} would match:", hits.len(), total, period.label());
+    for hit in &hits {
+        println!(
+            "  {}  {:<8}  {}  {} (PID {})  {} = {}  {}",
+            hit.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            hit.severity,
+            hit.rule_name,
+            hit.process,
+            hit.pid,
+            hit.field,
+            hit.value,
+            hit.id
+        );
+    }
+    Ok(())
+}
+
+/// Marks a stored alert as acknowledged. `id` may be a unique prefix of the alert ID.
+pub fn acknowledge(config_path: Option<&Path>, id: &str) -> Result<(), EdrError> {
+    let database_path = database_path(config_path)?;
+    acknowledge_in_store(&database_path, id.trim())
+}
+
+pub(crate) fn database_path(config_path: Option<&Path>) -> Result<String, EdrError> {
+    let config = crate::config::rules::load_rules(config_path)?;
+    Ok(config.sqlite_output
+        .map(|output| output.database_path)
+        .unwrap_or_else(|| "alerts.db".to_string()))
+}
+
+#[cfg(feature = "sqlite")]
+fn require_store(database_path: &str) -> Result<(), EdrError> {
+    if !Path::new(database_path).exists() {
+        return Err(EdrError::Report(format!(
+            "alert database '{}' does not exist (enable sqlite_output to collect alerts)",
+            database_path
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+pub(crate) fn read_stored_alerts(
+    database_path: &str,
+    since: chrono::DateTime<chrono::Utc>,
+    source: Option<&str>,
+    unacked: bool,
+) -> Result<Vec<crate::monitoring::sqlite_sink::StoredAlert>, EdrError> {
+    require_store(database_path)?;
+    let read = crate::monitoring::sqlite_sink::read_alerts_since(database_path, since);
+    checked_alerts(database_path, read, source, unacked)
+}
+
+/// Drops or counts the rows a read could not parse, then applies the source and
+/// acknowledgement filters.
+#[cfg(feature = "sqlite")]
+fn checked_alerts(
+    database_path: &str,
+    read: rusqlite::Result<(Vec<crate::monitoring::sqlite_sink::StoredAlert>, Vec<String>)>,
+    source: Option<&str>,
+    unacked: bool,
+) -> Result<Vec<crate::monitoring::sqlite_sink::StoredAlert>, EdrError> {
+    use crate::utils::common::{STRICT_PARSING, UNPARSED_RECORDS};
+    use std::sync::atomic::Ordering;
+
+    let (mut stored, unparsed) = read
+        .map_err(|e| EdrError::Report(format!("cannot read '{}': {}", database_path, e)))?;
+    if let Some(first) = unparsed.first() {
+        if STRICT_PARSING.load(Ordering::Relaxed) {
+            return Err(EdrError::Report(format!("'{}': {}", database_path, first)));
+        }
+        for row in &unparsed {
+            log::debug!("Skipping unparseable row in {}: {}", database_path, row);
+        }
+        UNPARSED_RECORDS.fetch_add(unparsed.len() as u64, Ordering::Relaxed);
+    }
+    stored.retain(|alert| {
+        source.is_none_or(|source| alert.source.eq_ignore_ascii_case(source))
+            && !(unacked && alert.acknowledged_at.is_some())
+    });
+    Ok(stored)
+}
+
+fn list_alerts(
+    database_path: &str,
+    period: ReportPeriod,
+    source: Option<&str>,
+    unacked: bool,
+    min_severity: Option<AlertSeverity>,
+) -> Result<Vec<AlertListEntry>, EdrError> {
+    list_alerts_since(database_path, chrono::Utc::now() - period.duration(), source, unacked, min_severity)
+}
+
+#[cfg(feature = "sqlite")]
+fn list_alerts_since(
+    database_path: &str,
+    since: chrono::DateTime<chrono::Utc>,
+    source: Option<&str>,
+    unacked: bool,
+    min_severity: Option<AlertSeverity>,
+) -> Result<Vec<AlertListEntry>, EdrError> {
+    Ok(read_stored_alerts(database_path, since, source, unacked)?
+        .into_iter()
+        .filter(|alert| meets_severity(alert, min_severity.as_ref()))
+        .map(list_entry)
+        .collect())
+}
+
+/// Alerts written after row `after` that pass the filters, and the highest row
+/// read, whether it passed or not.
+#[cfg(feature = "sqlite")]
+fn list_alerts_after_row(
+    database_path: &str,
+    after: i64,
+    source: Option<&str>,
+    min_severity: Option<AlertSeverity>,
+) -> Result<(Vec<AlertListEntry>, i64), EdrError> {
+    require_store(database_path)?;
+    let read = crate::monitoring::sqlite_sink::read_alerts_after_row(database_path, after);
+    let stored = checked_alerts(database_path, read, None, false)?;
+    let read_up_to = stored.iter().map(|alert| alert.row).max().unwrap_or(after);
+    let entries = stored.into_iter()
+        .filter(|alert| {
+            source.is_none_or(|source| alert.source.eq_ignore_ascii_case(source))
+                && meets_severity(alert, min_severity.as_ref())
+        })
+        .map(list_entry)
+        .collect();
+    Ok((entries, read_up_to))
+}
+
+#[cfg(feature = "sqlite")]
+fn newest_alert_row(database_path: &str) -> Result<i64, EdrError> {
+    require_store(database_path)?;
+    crate::monitoring::sqlite_sink::last_alert_row(database_path)
+        .map_err(|e| EdrError::Report(format!("cannot read '{}': {}", database_path, e)))
+}
+
+#[cfg(feature = "sqlite")]
+fn meets_severity(alert: &crate::monitoring::sqlite_sink::StoredAlert, min_severity: Option<&AlertSeverity>) -> bool {
+    use clap::ValueEnum;
+
+    min_severity.is_none_or(|min| {
+        AlertSeverity::from_str(&alert.severity, true).is_ok_and(|severity| severity >= *min)
+    })
+}
+
+#[cfg(feature = "sqlite")]
+fn list_entry(alert: crate::monitoring::sqlite_sink::StoredAlert) -> AlertListEntry {
+    AlertListEntry {
+        id: alert.id,
+        timestamp: alert.timestamp,
+        severity: alert.severity,
+        rule_name: alert.rule_name,
+        process: alert.process,
+        pid: alert.pid,
+        source: alert.source,
+        correlation_id: alert.correlation_id,
+        acknowledged_at: alert.acknowledged_at,
+        user: alert.security.as_ref().map(|security| security.user.clone()),
+        elevated: alert.security.as_ref().map(|security| security.elevated),
+        parent: alert.parent_name,
+        command_line: alert.command_line,
+        evidence: alert.evidence,
+    }
+}
+
+/// How many alerts the period holds, and those the pattern matches.
+#[cfg(feature = "sqlite")]
+fn pattern_hits(
+    database_path: &str,
+    period: ReportPeriod,
+    regex: &regex::Regex,
+) -> Result<(usize, Vec<PatternHit>), EdrError> {
+    let since = chrono::Utc::now() - period.duration();
+    let stored = read_stored_alerts(database_path, since, None, false)?;
+    let total = stored.len();
+    let hits = stored.into_iter()
+        .filter_map(|alert| {
+            let (field, value) = std::iter::once(("process", alert.process.as_str()))
+                .chain(alert.evidence.iter().filter_map(|line| line.split_once(" = ")))
+                .find(|(_, value)| regex.is_match(value))?;
+            let (field, value) = (field.to_string(), value.to_string());
+            Some(PatternHit {
+                id: alert.id,
+                timestamp: alert.timestamp,
+                severity: alert.severity,
+                rule_name: alert.rule_name,
+                process: alert.process,
+                pid: alert.pid,
+                field,
+                value,
+            })
+        })
+        .collect();
+    Ok((total, hits))
+}
+
+#[cfg(feature = "sqlite")]
+fn acknowledge_in_store(database_path: &str, id: &str) -> Result<(), EdrError> {
+    use crate::monitoring::sqlite_sink::{acknowledge_alert, AckOutcome};
+
+    if id.is_empty() {
+        return Err(EdrError::Report("no alert ID given".to_string()));
+    }
+    require_store(database_path)?;
+    match acknowledge_alert(database_path, id)
+        .map_err(|e| EdrError::Report(format!("cannot update '{}': {}", database_path, e)))?
+    {
+        AckOutcome::Acknowledged(alert_id) => println!("Acknowledged alert {}", alert_id),
+        AckOutcome::AlreadyAcknowledged(alert_id) => println!("Alert {} was already acknowledged", alert_id),
+        AckOutcome::NotFound => return Err(EdrError::Report(format!("no stored alert has ID '{}'", id))),
+        AckOutcome::Ambiguous => {
+            return Err(EdrError::Report(format!("'{}' matches more than one alert; give more of the ID", id)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn build_report(database_path: &str, period: ReportPeriod, source: Option<&str>, unacked: bool) -> Result<AlertReport, EdrError> {
+    use crate::utils::common::rule_label;
+    use std::collections::{HashMap, HashSet};
+
+    let to = chrono::Utc::now();
+    let from = to - period.duration();
+    // The previous period is read too, to tell which processes are new
+    let stored = read_stored_alerts(database_path, from - period.duration(), source, false)?;
+    let (mut current, previous): (Vec<_>, Vec<_>) = stored.into_iter().partition(|alert| alert.timestamp >= from);
+    // Acknowledged alerts still count as history when telling new processes apart
+    if unacked {
+        current.retain(|alert| alert.acknowledged_at.is_none());
+    }
+
+    let mut by_severity: HashMap<String, u64> = HashMap::new();
+    let mut by_source: HashMap<String, u64> = HashMap::new();
+    let mut by_rule: HashMap<String, u64> = HashMap::new();
+    let mut by_indicator: HashMap<String, u64> = HashMap::new();
+    let mut by_technique: HashMap<String, u64> = HashMap::new();
+    let mut by_process: HashMap<String, u64> = HashMap::new();
+    let mut by_user: HashMap<String, u64> = HashMap::new();
+    let mut by_hour: HashMap<String, u64> = HashMap::new();
+    let mut by_incident: HashMap<&str, Vec<&crate::monitoring::sqlite_sink::StoredAlert>> = HashMap::new();
+
+    for alert in &current {
+        *by_severity.entry(alert.severity.clone()).or_default() += 1;
+        *by_source.entry(alert.source.clone()).or_default() += 1;
+        *by_rule.entry(alert.rule_name.clone()).or_default() += 1;
+        *by_process.entry(alert.process.clone()).or_default() += 1;
+        let user = alert.security.as_ref().map_or("unknown", |security| security.user.as_str());
+        *by_user.entry(user.to_string()).or_default() += 1;
+        *by_hour.entry(alert.timestamp.format("%Y-%m-%d %H:00").to_string()).or_default() += 1;
+        if let Some(correlation_id) = &alert.correlation_id {
+            by_incident.entry(correlation_id).or_default().push(alert);
+        }
+        for technique in alert.technique.iter().flat_map(|ids| ids.split(',')) {
+            *by_technique.entry(technique.to_string()).or_default() += 1;
+        }
+        // Indicator lines look like "  3. Rapid connections: 7 in 10 seconds"
+        for line in &alert.evidence {
+            if let Some((number, reason)) = line.trim_start().split_once(". ")
+                && number.chars().all(|c| c.is_ascii_digit())
+            {
+                *by_indicator.entry(rule_label(reason).to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let seen_before: HashSet<&str> = previous.iter().map(|alert| alert.process.as_str()).collect();
+    let mut new_processes: Vec<String> = by_process.keys()
+        .filter(|process| !seen_before.contains(process.as_str()))
+        .cloned()
+        .collect();
+    new_processes.sort();
+
+    // Severities in fixed most-to-least severe order rather than by count
+    const SEVERITY_ORDER: &[&str] = &["Critical", "High", "Medium", "Low"];
+    let by_severity = SEVERITY_ORDER.iter()
+        .filter_map(|severity| by_severity.get(*severity).map(|&count| CountEntry { name: severity.to_string(), count }))
+        .collect();
+
+    Ok(AlertReport {
+        period: period.label(),
+        from,
+        to,
+        total_alerts: current.len() as u64,
+        source_filter: source.map(str::to_string),
+        unacknowledged_only: unacked,
+        by_severity,
+        by_source: top_counts(by_source),
+        by_rule: top_counts(by_rule),
+        by_indicator: top_counts(by_indicator),
+        by_technique: top_counts(by_technique),
+        top_processes: top_counts(by_process),
+        by_user: top_counts(by_user),
+        new_processes,
+        busiest_hours: top_counts(by_hour),
+        incidents: incidents(by_incident),
+    })
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn build_report(_database_path: &str, _period: ReportPeriod, _source: Option<&str>, _unacked: bool) -> Result<AlertReport, EdrError> {
+    Err(no_store_support())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn list_alerts_since(
+    _database_path: &str,
+    _since: chrono::DateTime<chrono::Utc>,
+    _source: Option<&str>,
+    _unacked: bool,
+    _min_severity: Option<AlertSeverity>,
+) -> Result<Vec<AlertListEntry>, EdrError> {
+    Err(no_store_support())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn list_alerts_after_row(
+    _database_path: &str,
+    _after: i64,
+    _source: Option<&str>,
+    _min_severity: Option<AlertSeverity>,
+) -> Result<(Vec<AlertListEntry>, i64), EdrError> {
+    Err(no_store_support())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn newest_alert_row(_database_path: &str) -> Result<i64, EdrError> {
+    Err(no_store_support())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn pattern_hits(
+    _database_path: &str,
+    _period: ReportPeriod,
+    _regex: &regex::Regex,
+) -> Result<(usize, Vec<PatternHit>), EdrError> {
+    Err(no_store_support())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn acknowledge_in_store(_database_path: &str, _id: &str) -> Result<(), EdrError> {
+    Err(no_store_support())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub(crate) fn no_store_support() -> EdrError {
+    EdrError::Report("the alert store is SQLite; rebuild with --features sqlite".to_string())
+}
+
+/// Incidents with more than one alert, largest first. Alerts arrive oldest first.
+#[cfg(feature = "sqlite")]
+fn incidents(
+    by_incident: std::collections::HashMap<&str, Vec<&crate::monitoring::sqlite_sink::StoredAlert>>,
+) -> Vec<IncidentEntry> {
+    let mut incidents: Vec<IncidentEntry> = by_incident.into_iter()
+        .filter(|(_, alerts)| alerts.len() > 1)
+        .map(|(correlation_id, alerts)| {
+            let mut rules: Vec<String> = alerts.iter().map(|alert| alert.rule_name.clone()).collect();
+            rules.dedup();
+            IncidentEntry {
+                correlation_id: correlation_id.to_string(),
+                alerts: alerts.len() as u64,
+                process: alerts[0].process.clone(),
+                rules,
+                first_seen: alerts[0].timestamp,
+                last_seen: alerts[alerts.len() - 1].timestamp,
+            }
+        })
+        .collect();
+    incidents.sort_by(|a, b| b.alerts.cmp(&a.alerts).then_with(|| a.first_seen.cmp(&b.first_seen)));
+    incidents.truncate(TOP_ENTRIES);
+    incidents
+}
+
+#[cfg(feature = "sqlite")]
+fn top_counts(counts: std::collections::HashMap<String, u64>) -> Vec<CountEntry> {
+    let mut entries: Vec<CountEntry> = counts.into_iter()
+        .map(|(name, count)| CountEntry { name, count })
+        .collect();
+    entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    entries.truncate(TOP_ENTRIES);
+    entries
+}
+
+fn print_text(report: &AlertReport) {
+    println!("==============================================");
+    println!(" CustomEDR alert summary — last {}", report.period);
+    println!(" {} → {} (UTC)", report.from.format("%Y-%m-%d %H:%M"), report.to.format("%Y-%m-%d %H:%M"));
+    if let Some(source) = &report.source_filter {
+        println!(" Source: {}", source);
+    }
+    if report.unacknowledged_only {
+        println!(" Unacknowledged alerts only");
+    }
+    println!("==============================================");
+
+    if report.total_alerts == 0 {
+        println!("No alerts in this period.");
+        return;
+    }
+
+    println!("Total alerts: {}", report.total_alerts);
+    print_section("By severity", &report.by_severity);
+    print_section("By source", &report.by_source);
+    print_section("By rule", &report.by_rule);
+    print_section("Top indicators", &report.by_indicator);
+    print_section("By MITRE technique", &report.by_technique);
+    print_section("Top processes", &report.top_processes);
+    print_section("By user", &report.by_user);
+
+    println!();
+    if report.new_processes.is_empty() {
+        println!("New processes this {}: none", report.period);
+    } else {
+        println!("New processes this {}:", report.period);
+        for process in report.new_processes.iter().take(TOP_ENTRIES) {
+            println!("  {}", process);
+        }
+        if report.new_processes.len() > TOP_ENTRIES {
+            println!("  ... and {} more", report.new_processes.len() - TOP_ENTRIES);
+        }
+    }
+
+    print_section("Busiest hours", &report.busiest_hours);
+
+    if !report.incidents.is_empty() {
+        println!();
+        println!("Incidents with more than one alert:");
+        for incident in &report.incidents {
+            println!(
+                "  {:>6}  {}  {}  {} → {}  {}",
+                incident.alerts,
+                incident.process,
+                incident.rules.join(" → "),
+                incident.first_seen.format("%Y-%m-%d %H:%M"),
+                incident.last_seen.format("%H:%M"),
+                incident.correlation_id
+            );
+        }
+    }
+}
+
+fn print_section(title: &str, entries: &[CountEntry]) {
+    if entries.is_empty() {
+        return;
+    }
+    println!();
+    println!("{}:", title);
+    for entry in entries {
+        println!("  {:>6}  {}", entry.count, entry.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_per_rfc4180() {
+        assert_eq!(csv_field("powershell.exe"), "powershell.exe");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+    }
+
+    #[test]
+    fn csv_field_neutralises_formulas() {
+        assert_eq!(csv_field("=cmd|' /C calc'!A0"), "\"'=cmd|' /C calc'!A0\"");
+        assert_eq!(csv_field("+1+1"), "\"'+1+1\"");
+        assert_eq!(csv_field("-enc SQBFAFgA"), "\"'-enc SQBFAFgA\"");
+        assert_eq!(csv_field("@SUM(A1)"), "\"'@SUM(A1)\"");
+        assert_eq!(csv_field("\t=1"), "\"'\t=1\"");
+        assert_eq!(csv_field("=HYPERLINK(\"http://x\",\"y\")"), "\"'=HYPERLINK(\"\"http://x\"\",\"\"y\"\")\"");
+        // Only a leading character triggers it
+        assert_eq!(csv_field("a=b"), "a=b");
+    }
+}
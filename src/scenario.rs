@@ -0,0 +1,331 @@
+use crate::config::rules::Config;
+use crate::error::EdrError;
+use crate::events::alert::{Alert, AlertSeverity};
+use crate::events::network::{NetworkDirection, Protocol};
+use crate::events::{BaseEvent, EventType, NetworkEvent, ProcessEvent};
+use crate::monitoring::clock::ManualClock;
+use crate::monitoring::start_correlation_engine;
+use crate::utils::common::cache_process_start;
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{Receiver, Sender};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// After each step the engine gets long enough for one housekeeping tick (100 ms),
+// so temporal correlations run between steps as they would live
+const STEP_SETTLE: Duration = Duration::from_millis(150);
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+// Synthetic PIDs sit far above anything Windows hands out (and above --bench's),
+// and each scenario gets its own block so the shared process caches never mix them
+const FIRST_SCENARIO_PID: u32 = 0x5000_0000;
+const PIDS_PER_SCENARIO: u32 = 0x1000;
+
+const ENCODED_POWERSHELL: &str =
+    "powershell.exe -NoProfile -WindowStyle Hidden -EncodedCommand VwByAGkAdABlAC0ATwB1AHQAcAB1AHQAIAAnAHMAYwBlAG4AYQByAGkAbwAnAA==";
+
+/// A monitor stand-in: events on a scripted timeline, delivered one at a time in
+/// the order they were added. Event timestamps drive the engine's `ManualClock`,
+/// so windows and cooldowns follow the script rather than the wall clock.
+pub struct ScriptedSource {
+    started: DateTime<Utc>,
+    pid_base: u32,
+    steps: Vec<(i64, EventType)>,
+}
+
+impl ScriptedSource {
+    pub fn new(pid_base: u32) -> Self {
+        // Stand in for the process monitor, as replay does, so parent lookups hit the cache
+        cache_process_start(pid_base, 0, "explorer.exe", Some("C:\\Windows\\explorer.exe".to_string()));
+        Self { started: Utc::now(), pid_base, steps: Vec::new() }
+    }
+
+    /// PID `n` of this scenario's block; `pid(0)` is the Explorer every script starts under.
+    pub fn pid(&self, n: u32) -> u32 {
+        self.pid_base + n
+    }
+
+    pub fn process_start(&mut self, at: i64, pid: u32, parent_pid: u32, name: &str, command_line: &str) -> &mut Self {
+        cache_process_start(pid, parent_pid, name, Some(command_line.to_string()));
+        let mut event = ProcessEvent::new_start(pid, parent_pid, name.to_string());
+        event.command_line = command_line.to_string();
+        self.steps.push((at, EventType::ProcessStart(event)));
+        self
+    }
+
+    pub fn process_end(&mut self, at: i64, pid: u32, name: &str) -> &mut Self {
+        self.steps.push((at, EventType::ProcessEnd(ProcessEvent::new_end(pid, name.to_string(), Some(0)))));
+        self
+    }
+
+    /// An outbound TCP connection, with the domain the network monitor would
+    /// have attached from DNS, if any.
+    pub fn connect(&mut self, at: i64, pid: u32, name: &str, remote: &str, port: u16, domain: Option<&str>) -> &mut Self {
+        let mut event = NetworkEvent::new(
+            pid,
+            name.to_string(),
+            NetworkDirection::Outbound,
+            Protocol::TCP,
+            "10.0.0.5".to_string(),
+            49152 + self.steps.len() as u16,
+            remote.to_string(),
+            port,
+        );
+        event.domain = domain.map(str::to_string);
+        self.steps.push((at, EventType::NetworkConnection(event)));
+        self
+    }
+
+    /// Sends every step to the engine, waiting after each one until the engine
+    /// has taken it and had time to act on it. Returns the number of events sent.
+    fn feed(&self, tx: &Sender<BaseEvent>) -> Result<usize, String> {
+        for (at, event_type) in &self.steps {
+            let event = BaseEvent {
+                timestamp: self.started + chrono::Duration::seconds(*at),
+                event_id: uuid::Uuid::new_v4().to_string(),
+                machine_name: "SCENARIO".to_string(),
+                user_name: "scenario".to_string(),
+                event_type: event_type.clone(),
+            };
+            tx.send(event).map_err(|_| "correlation engine stopped".to_string())?;
+
+            let sent_at = Instant::now();
+            while !tx.is_empty() {
+                if sent_at.elapsed() > DRAIN_TIMEOUT {
+                    return Err(format!("engine did not take an event within {}s", DRAIN_TIMEOUT.as_secs()));
+                }
+                std::thread::yield_now();
+            }
+            std::thread::sleep(STEP_SETTLE);
+        }
+        Ok(self.steps.len())
+    }
+}
+
+/// Collects the alerts the engine raises, in the order it raised them.
+pub struct AlertSink {
+    rx: Receiver<Alert>,
+    alerts: Vec<Alert>,
+}
+
+impl AlertSink {
+    pub fn new(rx: Receiver<Alert>) -> Self {
+        Self { rx, alerts: Vec::new() }
+    }
+
+    pub fn collect(&mut self) -> &[Alert] {
+        self.alerts.extend(self.rx.try_iter());
+        &self.alerts
+    }
+}
+
+/// An alert a scenario has to raise, matched in order against what the sink
+/// collected: the rule, the alerting process and the lowest acceptable severity.
+struct Expected {
+    rule: &'static str,
+    process: &'static str,
+    min_severity: AlertSeverity,
+}
+
+impl Expected {
+    fn matches(&self, alert: &Alert) -> bool {
+        alert.rule_name == self.rule
+            && alert.process_name.eq_ignore_ascii_case(self.process)
+            && alert.severity >= self.min_severity
+    }
+}
+
+/// A scripted sequence of events and exactly the alerts it must produce.
+struct Scenario {
+    name: &'static str,
+    script: fn(&mut ScriptedSource),
+    expected: &'static [Expected],
+}
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        // Macro drops to encoded PowerShell, which calls out twice (one alert, not
+        // two) and then reaches a webhook (escalation in the same incident)
+        name: "office-macro-c2",
+        script: office_macro_c2,
+        expected: &[
+            Expected { rule: "MultiFactorThreatDetection", process: "powershell.exe", min_severity: AlertSeverity::High },
+            Expected { rule: "WebhookExfiltrationConfirmed", process: "powershell.exe", min_severity: AlertSeverity::High },
+        ],
+    },
+    Scenario {
+        // Everyday shell use from Explorer must stay quiet
+        name: "benign-shell",
+        script: benign_shell,
+        expected: &[],
+    },
+];
+
+fn office_macro_c2(source: &mut ScriptedSource) {
+    let (explorer, word, powershell) = (source.pid(0), source.pid(1), source.pid(2));
+    source
+        .process_start(
+            0,
+            word,
+            explorer,
+            "WINWORD.EXE",
+            r#""C:\Program Files\Microsoft Office\root\Office16\WINWORD.EXE" /n "C:\Users\scenario\Downloads\invoice.docm""#,
+        )
+        .process_start(2, powershell, word, "powershell.exe", ENCODED_POWERSHELL)
+        .connect(4, powershell, "powershell.exe", "203.0.113.10", 443, None)
+        .connect(5, powershell, "powershell.exe", "203.0.113.10", 443, None)
+        .connect(8, powershell, "powershell.exe", "203.0.113.20", 443, Some("discord.com"));
+}
+
+fn benign_shell(source: &mut ScriptedSource) {
+    let (explorer, cmd) = (source.pid(0), source.pid(1));
+    source
+        .process_start(0, cmd, explorer, "cmd.exe", r"C:\Windows\System32\cmd.exe /c dir C:\Users\scenario")
+        .connect(1, cmd, "cmd.exe", "10.0.0.20", 445, None)
+        .process_end(2, cmd, "cmd.exe");
+}
+
+/// Runs one scenario through a fresh correlation engine and returns the alerts
+/// it raised, in order.
+fn run_scenario(config: &Config, scenario: &Scenario, pid_base: u32) -> Result<Vec<Alert>, String> {
+    let mut config = config.clone();
+    // Scripted alerts must not end up in the learned baseline or the decision log
+    config.baseline = None;
+    config.decision_log = None;
+
+    let (process_tx, process_rx) = crossbeam_channel::unbounded();
+    let (network_tx, network_rx) = crossbeam_channel::unbounded::<BaseEvent>();
+    let (alert_tx, alert_rx) = crossbeam_channel::unbounded();
+    let engine_running = Arc::new(AtomicBool::new(true));
+    let engine = start_correlation_engine(
+        process_rx,
+        network_rx,
+        alert_tx,
+        Arc::new(config),
+        Arc::new(ManualClock::default()),
+        Arc::clone(&engine_running),
+    );
+
+    let mut source = ScriptedSource::new(pid_base);
+    (scenario.script)(&mut source);
+    let mut sink = AlertSink::new(alert_rx);
+    let fed = source.feed(&process_tx);
+
+    engine_running.store(false, Ordering::Relaxed);
+    drop(process_tx);
+    drop(network_tx);
+    let _ = engine.join();
+    fed?;
+    Ok(sink.collect().to_vec())
+}
+
+/// Why the collected alerts differ from the expected ones, or `None` when they
+/// match one for one, in order.
+fn compare_alerts(expected: &[Expected], alerts: &[Alert]) -> Option<String> {
+    for (index, want) in expected.iter().enumerate() {
+        match alerts.get(index) {
+            Some(alert) if want.matches(alert) => {}
+            Some(alert) => {
+                return Some(format!(
+                    "alert {} was {} {:?} for {}, expected {} (at least {:?}) for {}",
+                    index + 1, alert.rule_name, alert.severity, alert.process_name,
+                    want.rule, want.min_severity, want.process
+                ));
+            }
+            None => {
+                return Some(format!(
+                    "only {} alerts, expected {} (at least {:?}) for {} next",
+                    alerts.len(), want.rule, want.min_severity, want.process
+                ));
+            }
+        }
+    }
+    alerts.get(expected.len()).map(|extra| {
+        format!(
+            "unexpected alert {}: {} {:?} for {}",
+            expected.len() + 1, extra.rule_name, extra.severity, extra.process_name
+        )
+    })
+}
+
+/// `--scenarios`: plays each built-in scenario (or those whose name contains
+/// `filter`) through its own correlation engine, with no ETW sessions or alert
+/// outputs, and checks it raised exactly the expected alerts in order.
+pub fn run_scenarios(config_path: Option<&Path>, filter: Option<&str>) -> Result<(), EdrError> {
+    let config = crate::config::rules::load_rules(config_path)?;
+    let selected: Vec<(usize, &Scenario)> = SCENARIOS.iter()
+        .enumerate()
+        .filter(|(_, scenario)| filter.is_none_or(|filter| scenario.name.contains(filter)))
+        .collect();
+    if selected.is_empty() {
+        let names: Vec<&str> = SCENARIOS.iter().map(|scenario| scenario.name).collect();
+        return Err(EdrError::Scenario(format!("no scenario matches; available: {}", names.join(", "))));
+    }
+
+    println!("Running {} scenarios through the correlation engine...", selected.len());
+    println!();
+    println!("{:<20}  {:>6}  RESULT", "SCENARIO", "ALERTS");
+    let mut failed = 0;
+    for (index, scenario) in &selected {
+        let pid_base = FIRST_SCENARIO_PID + *index as u32 * PIDS_PER_SCENARIO;
+        let (count, result) = match run_scenario(&config, scenario, pid_base) {
+            Ok(alerts) => match compare_alerts(scenario.expected, &alerts) {
+                None => (alerts.len().to_string(), "PASS".to_string()),
+                Some(mismatch) => (alerts.len().to_string(), format!("FAIL ({})", mismatch)),
+            },
+            Err(e) => ("-".to_string(), format!("FAIL ({})", e)),
+        };
+        if result != "PASS" {
+            failed += 1;
+        }
+        println!("{:<20}  {:>6}  {}", scenario.name, count, result);
+    }
+
+    if failed > 0 {
+        return Err(EdrError::Scenario(format!("{} of {} scenarios failed", failed, selected.len())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alert(rule: &str, process: &str, severity: AlertSeverity) -> Alert {
+        Alert::new(&severity, rule, "", process, 1, &[])
+    }
+
+    // Every scenario runs through a real engine with the built-in rules, one after
+    // another as `--scenarios` runs them, and must raise exactly its expected alerts
+    #[test]
+    fn every_scenario_raises_its_expected_alerts_in_order() {
+        let config = Config::default();
+        let mut failures = Vec::new();
+        for (index, scenario) in SCENARIOS.iter().enumerate() {
+            let pid_base = FIRST_SCENARIO_PID + index as u32 * PIDS_PER_SCENARIO;
+            match run_scenario(&config, scenario, pid_base) {
+                Ok(alerts) => {
+                    if let Some(mismatch) = compare_alerts(scenario.expected, &alerts) {
+                        failures.push(format!("{}: {}", scenario.name, mismatch));
+                    }
+                }
+                Err(e) => failures.push(format!("{}: {}", scenario.name, e)),
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("; "));
+    }
+
+    #[test]
+    fn compare_alerts_checks_order_and_count() {
+        let expected = SCENARIOS[0].expected;
+        let first = alert("MultiFactorThreatDetection", "PowerShell.exe", AlertSeverity::Critical);
+        let second = alert("WebhookExfiltrationConfirmed", "powershell.exe", AlertSeverity::High);
+
+        assert_eq!(compare_alerts(expected, &[first.clone(), second.clone()]), None);
+        assert!(compare_alerts(expected, &[second.clone(), first.clone()]).unwrap().starts_with("alert 1 was WebhookExfiltrationConfirmed"));
+        assert!(compare_alerts(expected, std::slice::from_ref(&first)).unwrap().starts_with("only 1 alerts"));
+        assert!(compare_alerts(expected, &[first.clone(), second, first]).unwrap().starts_with("unexpected alert 3"));
+        assert!(compare_alerts(expected, &[alert("MultiFactorThreatDetection", "powershell.exe", AlertSeverity::Medium)]).is_some());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::config::rules::Config;
+use crate::error::EdrError;
+use crate::monitoring::control_pipe::send_control_command;
+use base64::Engine;
+use std::collections::HashMap;
+use std::os::windows::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS};
+use windows::Win32::System::Registry::{
+    RegDeleteKeyValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ,
+};
+use windows::core::HSTRING;
+
+// How long every check gets to show up in the running EDR's rule hits; stretched
+// to two registry polls when the registry monitor polls more slowly than this
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+const RULE_HIT_POLL: Duration = Duration::from_millis(500);
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+// Names and paths used by the triggers, so they are recognisable in alerts and logs
+const RUN_VALUE_NAME: &str = "CustomEDR-SelfTest";
+const SELF_TEST_FILE: &str = "customedr-selftest";
+
+/// One harmless action and the rule (as counted by the control pipe's `rule-hits`)
+/// it has to trip.
+struct Check {
+    name: &'static str,
+    rule: &'static str,
+    /// Why the check can't run with this configuration, if it can't.
+    unavailable: fn(&Config) -> Option<&'static str>,
+    trigger: fn() -> Result<(), String>,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "Encoded PowerShell",
+        rule: "Encoded payload",
+        unavailable: needs_process_tracing,
+        trigger: spawn_encoded_powershell,
+    },
+    Check {
+        name: "ADS launch",
+        rule: "Alternate data stream execution",
+        unavailable: needs_process_tracing,
+        trigger: spawn_ads_reference,
+    },
+    Check {
+        name: "Run key value",
+        rule: "Run key persistence",
+        unavailable: needs_registry_monitor,
+        trigger: write_run_key_value,
+    },
+];
+
+enum Outcome {
+    Pending,
+    Detected(Duration),
+    Missed,
+    Failed(String),
+    Skipped(&'static str),
+}
+
+/// `--self-test`: performs benign actions that should trip specific rules, waits
+/// for the running EDR to count them, and prints a pass/fail matrix. Everything
+/// the checks create is removed before returning.
+pub fn run_self_test(config_path: Option<&Path>) -> Result<(), EdrError> {
+    let config = crate::config::rules::load_rules(config_path)?;
+    let pipe_name = config.control_pipe.as_ref()
+        .filter(|control| control.enabled)
+        .map(|control| control.pipe_name.clone())
+        .ok_or_else(|| EdrError::SelfTest("control_pipe must be enabled so results can be read back".to_string()))?;
+    let timeout = config.registry_monitor.as_ref()
+        .filter(|registry| registry.enabled)
+        .map_or(SELF_TEST_TIMEOUT, |registry| SELF_TEST_TIMEOUT.max(Duration::from_secs(registry.poll_interval_secs * 2)));
+
+    let baseline = read_rule_hits(&pipe_name)?;
+    println!("Running {} checks against {} (timeout {}s)...", CHECKS.len(), pipe_name, timeout.as_secs());
+
+    let started = Instant::now();
+    let mut outcomes: Vec<Outcome> = CHECKS.iter()
+        .map(|check| match (check.unavailable)(&config) {
+            Some(reason) => Outcome::Skipped(reason),
+            None => match (check.trigger)() {
+                Ok(()) => Outcome::Pending,
+                Err(e) => Outcome::Failed(e),
+            },
+        })
+        .collect();
+
+    let polled = poll_for_detections(&pipe_name, &baseline, &mut outcomes, started, timeout);
+    remove_run_key_value();
+    polled?;
+
+    println!();
+    println!("{:<20}  {:<34}  RESULT", "CHECK", "RULE");
+    for (check, outcome) in CHECKS.iter().zip(&outcomes) {
+        let result = match outcome {
+            Outcome::Detected(after) => format!("PASS ({:.1}s)", after.as_secs_f64()),
+            Outcome::Missed | Outcome::Pending => format!("FAIL (not detected within {}s)", timeout.as_secs()),
+            Outcome::Failed(e) => format!("FAIL ({})", e),
+            Outcome::Skipped(reason) => format!("SKIPPED ({})", reason),
+        };
+        println!("{:<20}  {:<34}  {}", check.name, check.rule, result);
+    }
+
+    let failed = outcomes.iter()
+        .filter(|outcome| matches!(outcome, Outcome::Missed | Outcome::Pending | Outcome::Failed(_)))
+        .count();
+    if failed > 0 {
+        return Err(EdrError::SelfTest(format!("{} of {} checks failed", failed, CHECKS.len())));
+    }
+    Ok(())
+}
+
+fn poll_for_detections(
+    pipe_name: &str,
+    baseline: &HashMap<String, u64>,
+    outcomes: &mut [Outcome],
+    started: Instant,
+    timeout: Duration,
+) -> Result<(), EdrError> {
+    while outcomes.iter().any(|outcome| matches!(outcome, Outcome::Pending)) {
+        if started.elapsed() >= timeout {
+            for outcome in outcomes.iter_mut().filter(|outcome| matches!(outcome, Outcome::Pending)) {
+                *outcome = Outcome::Missed;
+            }
+            break;
+        }
+        std::thread::sleep(RULE_HIT_POLL);
+
+        let hits = read_rule_hits(pipe_name)?;
+        for (check, outcome) in CHECKS.iter().zip(outcomes.iter_mut()) {
+            let before = baseline.get(check.rule).copied().unwrap_or(0);
+            if matches!(outcome, Outcome::Pending) && hits.get(check.rule).copied().unwrap_or(0) > before {
+                *outcome = Outcome::Detected(started.elapsed());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_rule_hits(pipe_name: &str) -> Result<HashMap<String, u64>, EdrError> {
+    let reply = send_control_command(pipe_name, "rule-hits").map_err(EdrError::SelfTest)?;
+    let reply: serde_json::Value = serde_json::from_str(&reply)
+        .map_err(|e| EdrError::SelfTest(format!("unreadable control pipe reply: {}", e)))?;
+    let Some(rules) = reply.get("rules").and_then(|rules| rules.as_object()) else {
+        let error = reply.get("error").and_then(|error| error.as_str()).unwrap_or("no rule hits in reply");
+        return Err(EdrError::SelfTest(error.to_string()));
+    };
+    Ok(rules.iter()
+        .filter_map(|(rule, count)| Some((rule.clone(), count.as_u64()?)))
+        .collect())
+}
+
+fn needs_process_tracing(config: &Config) -> Option<&'static str> {
+    (!config.etw_flags.process).then_some("etw_flags.process is off")
+}
+
+fn needs_registry_monitor(config: &Config) -> Option<&'static str> {
+    let enabled = config.registry_monitor.as_ref().is_some_and(|registry| registry.enabled);
+    (!enabled).then_some("registry_monitor is disabled")
+}
+
+fn self_test_path(extension: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("{}.{}", SELF_TEST_FILE, extension))
+        .display()
+        .to_string()
+}
+
+fn run_hidden(program: &str, args: &[&str]) -> Result<(), String> {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()
+        .map(|_| ())
+        .map_err(|e| format!("cannot start {}: {}", program, e))
+}
+
+/// PowerShell with the usual stager flags whose encoded command only prints a line.
+fn spawn_encoded_powershell() -> Result<(), String> {
+    let script = format!("Write-Output '{}'", RUN_VALUE_NAME);
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(utf16);
+    run_hidden(
+        "powershell.exe",
+        &["-NoProfile", "-NonInteractive", "-WindowStyle", "Hidden", "-EncodedCommand", &encoded],
+    )
+}
+
+/// `rem` ignores its arguments, so the stream reference is only ever read, never run.
+fn spawn_ads_reference() -> Result<(), String> {
+    let stream = format!("{}:{}.exe", self_test_path("txt"), SELF_TEST_FILE);
+    run_hidden("cmd.exe", &["/d", "/c", "rem", &stream])
+}
+
+/// A per-user Run value pointing at a script in %TEMP% that doesn't exist; it is
+/// deleted again before the self-test returns.
+fn write_run_key_value() -> Result<(), String> {
+    let data = format!("powershell.exe -NoProfile -File \"{}\"", self_test_path("ps1"));
+    let wide: Vec<u16> = data.encode_utf16().chain(std::iter::once(0)).collect();
+    let status = unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(RUN_KEY),
+            &HSTRING::from(RUN_VALUE_NAME),
+            REG_SZ.0,
+            Some(wide.as_ptr().cast()),
+            (wide.len() * 2) as u32,
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return Err(format!("cannot write HKCU\\{}\\{}: {:?}", RUN_KEY, RUN_VALUE_NAME, status));
+    }
+    Ok(())
+}
+
+fn remove_run_key_value() {
+    let status = unsafe {
+        RegDeleteKeyValueW(HKEY_CURRENT_USER, &HSTRING::from(RUN_KEY), &HSTRING::from(RUN_VALUE_NAME))
+    };
+    if status != ERROR_SUCCESS && status != ERROR_FILE_NOT_FOUND {
+        eprintln!("Warning: could not remove HKCU\\{}\\{} ({:?}); delete it by hand", RUN_KEY, RUN_VALUE_NAME, status);
+    }
+}
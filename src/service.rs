@@ -0,0 +1,189 @@
+use crate::error::EdrError;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, OnceLock};
+use windows::core::{w, PCWSTR, PWSTR};
+use windows::Win32::Foundation::ERROR_SERVICE_SPECIFIC_ERROR;
+use windows::Win32::Storage::FileSystem::DELETE;
+use windows::Win32::System::Services::*;
+
+const SERVICE_NAME: PCWSTR = w!("CustomEDR");
+const SERVICE_DISPLAY_NAME: PCWSTR = w!("Custom EDR Threat Detection");
+const SERVICE_DESCRIPTION: &str = "Monitors process and network activity via ETW and raises threat alerts.";
+
+// Handed from run_as_service to service_main, which the SCM calls on its own thread.
+static SERVICE_CONFIG_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+static SERVICE_RESULT: Mutex<Option<EdrError>> = Mutex::new(None);
+static STATUS_HANDLE: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+pub fn executable_dir() -> Option<PathBuf> {
+    std::env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+fn service_error(api: &'static str) -> impl Fn(windows::core::Error) -> EdrError {
+    move |e| EdrError::Service(format!("{} failed: {}", api, e))
+}
+
+/// Registers the current executable as an auto-start LocalSystem service.
+pub fn install_service(config_path: Option<&Path>) -> Result<(), EdrError> {
+    let exe = std::env::current_exe()
+        .map_err(|e| EdrError::Service(format!("cannot locate executable: {}", e)))?;
+
+    let mut command = format!("\"{}\" --service", exe.display());
+    if let Some(path) = config_path {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        command.push_str(&format!(" --config \"{}\"", absolute.display()));
+    }
+    let command = widestring::U16CString::from_str(&command)
+        .map_err(|e| EdrError::Service(format!("invalid service command line: {}", e)))?;
+
+    unsafe {
+        let scm = OpenSCManagerW(PCWSTR::null(), PCWSTR::null(), SC_MANAGER_CREATE_SERVICE)
+            .map_err(service_error("OpenSCManagerW"))?;
+
+        let service = CreateServiceW(
+            scm,
+            SERVICE_NAME,
+            SERVICE_DISPLAY_NAME,
+            SERVICE_ALL_ACCESS,
+            SERVICE_WIN32_OWN_PROCESS,
+            SERVICE_AUTO_START,
+            SERVICE_ERROR_NORMAL,
+            PCWSTR(command.as_ptr()),
+            PCWSTR::null(),
+            None,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            PCWSTR::null(),
+        );
+        let _ = CloseServiceHandle(scm);
+        let service = service.map_err(service_error("CreateServiceW"))?;
+
+        let mut description: Vec<u16> = SERVICE_DESCRIPTION.encode_utf16().chain(Some(0)).collect();
+        let info = SERVICE_DESCRIPTIONW { lpDescription: PWSTR(description.as_mut_ptr()) };
+        let _ = ChangeServiceConfig2W(
+            service,
+            SERVICE_CONFIG_DESCRIPTION,
+            Some(&info as *const _ as *const core::ffi::c_void),
+        );
+        let _ = CloseServiceHandle(service);
+    }
+
+    log::info!("✅ Service installed: {}", command.to_string_lossy());
+    log::info!("   Start it with: sc start CustomEDR");
+    Ok(())
+}
+
+/// Stops the service if it is running and removes it from the SCM.
+pub fn uninstall_service() -> Result<(), EdrError> {
+    unsafe {
+        let scm = OpenSCManagerW(PCWSTR::null(), PCWSTR::null(), SC_MANAGER_CONNECT)
+            .map_err(service_error("OpenSCManagerW"))?;
+        let service = OpenServiceW(scm, SERVICE_NAME, SERVICE_STOP | SERVICE_QUERY_STATUS | DELETE.0);
+        let _ = CloseServiceHandle(scm);
+        let service = service.map_err(service_error("OpenServiceW"))?;
+
+        let mut status = SERVICE_STATUS::default();
+        if ControlService(service, SERVICE_CONTROL_STOP, &mut status).is_ok() {
+            log::info!("🛑 Stop request sent to the running service");
+        }
+
+        let deleted = DeleteService(service);
+        let _ = CloseServiceHandle(service);
+        deleted.map_err(service_error("DeleteService"))?;
+    }
+
+    log::info!("✅ Service removed");
+    Ok(())
+}
+
+/// Hands the process to the SCM dispatcher. Returns once the service has stopped.
+pub fn run_as_service(config_path: Option<PathBuf>) -> Result<(), EdrError> {
+    // The SCM starts services in System32; resolve relative defaults next to the executable.
+    if let Some(dir) = executable_dir() {
+        let _ = std::env::set_current_dir(dir);
+    }
+    let _ = SERVICE_CONFIG_PATH.set(config_path);
+
+    let table = [
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: PWSTR(SERVICE_NAME.as_ptr() as *mut u16),
+            lpServiceProc: Some(service_main),
+        },
+        SERVICE_TABLE_ENTRYW::default(),
+    ];
+    unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) }
+        .map_err(service_error("StartServiceCtrlDispatcherW (was it started by the SCM?)"))?;
+
+    match SERVICE_RESULT.lock().ok().and_then(|mut result| result.take()) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut PWSTR) {
+    let handle = match unsafe { RegisterServiceCtrlHandlerW(SERVICE_NAME, Some(service_control_handler)) } {
+        Ok(handle) => handle,
+        Err(e) => {
+            log::error!("RegisterServiceCtrlHandlerW failed: {}", e);
+            return;
+        }
+    };
+    STATUS_HANDLE.store(handle.0, Ordering::SeqCst);
+
+    // Startup clears stale ETW sessions and opens every trace; `run` reports
+    // SERVICE_RUNNING through `report_running` once the monitors are up
+    report_status(SERVICE_START_PENDING, 0, 30_000);
+
+    let config_path = SERVICE_CONFIG_PATH.get().cloned().flatten();
+    let result = crate::run(config_path.as_deref(), None, false, None, false);
+
+    let exit_code = result.as_ref().err().map(|e| e.code() as u32).unwrap_or(0);
+    if let Err(e) = result
+        && let Ok(mut slot) = SERVICE_RESULT.lock()
+    {
+        *slot = Some(e);
+    }
+    report_status(SERVICE_STOPPED, exit_code, 0);
+}
+
+/// Tells the SCM the service has finished starting. A no-op outside the SCM.
+pub fn report_running() {
+    report_status(SERVICE_RUNNING, 0, 0);
+}
+
+unsafe extern "system" fn service_control_handler(control: u32) {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            // perform_shutdown waits up to 5 s per component
+            report_status(SERVICE_STOP_PENDING, 0, 30_000);
+            crate::request_shutdown("Service Control Manager");
+        }
+        _ => {}
+    }
+}
+
+fn report_status(state: SERVICE_STATUS_CURRENT_STATE, exit_code: u32, wait_hint: u32) {
+    let handle = SERVICE_STATUS_HANDLE(STATUS_HANDLE.load(Ordering::SeqCst));
+    if handle.is_invalid() {
+        return;
+    }
+
+    let controls_accepted = if state == SERVICE_RUNNING {
+        SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+    } else {
+        0
+    };
+    let status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: state,
+        dwControlsAccepted: controls_accepted,
+        dwWin32ExitCode: if exit_code == 0 { 0 } else { ERROR_SERVICE_SPECIFIC_ERROR.0 },
+        dwServiceSpecificExitCode: exit_code,
+        dwCheckPoint: 0,
+        dwWaitHint: wait_hint,
+    };
+    unsafe {
+        let _ = SetServiceStatus(handle, &status);
+    }
+}
@@ -0,0 +1,363 @@
+// Without the sqlite feature only recordings can be read; the alert types stay
+// compiled so the CLI surface is identical.
+#![cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+
+use crate::error::EdrError;
+use crate::events::{BaseEvent, EventType};
+use crate::events::file::FileWatch;
+use crate::monitoring::event_recorder::{read_recording, recording_segments};
+use crate::report::ReportPeriod;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Events still attributed to a process this long after its exit, as the engine's
+// exit grace does for late ETW records
+const LATE_EVENT_GRACE_SECS: i64 = 30;
+// An alert can be raised a little before the recorded start reaches the engine
+const EARLY_ALERT_GRACE_SECS: i64 = 5;
+// Alerts raised this long after an exit still belong to it (temporal correlation)
+const LATE_ALERT_GRACE_SECS: i64 = 300;
+
+/// Which processes `--timeline` reconstructs.
+enum Target {
+    Pid(u32),
+    /// Lowercased image name; `powershell` also matches `powershell.exe`.
+    Name(String),
+}
+
+impl Target {
+    fn parse(target: &str) -> Self {
+        match target.trim().parse() {
+            Ok(pid) => Target::Pid(pid),
+            Err(_) => Target::Name(target.trim().to_lowercase()),
+        }
+    }
+
+    fn matches(&self, pid: u32, process_name: &str) -> bool {
+        match self {
+            Target::Pid(target) => *target == pid,
+            Target::Name(name) => {
+                let process_name = process_name.to_lowercase();
+                process_name == *name || process_name.strip_suffix(".exe") == Some(name.as_str())
+            }
+        }
+    }
+}
+
+/// One thing that happened to or around a process.
+#[derive(Debug, Serialize)]
+struct TimelineEntry {
+    timestamp: DateTime<Utc>,
+    /// `start`, `exit`, `child`, `network`, `dns`, `file`, `alert`, or `registry`,
+    /// `hosts` and `task` for unattributed changes made during the lifetime.
+    kind: &'static str,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert_id: Option<String>,
+}
+
+/// Everything known about one run of a process. A reused PID is a separate run.
+#[derive(Debug, Serialize)]
+struct ProcessTimeline {
+    pid: u32,
+    process: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command_line: Option<String>,
+    /// `None` when the process started before the recording (or there is none).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exited: Option<DateTime<Utc>>,
+    events: Vec<TimelineEntry>,
+}
+
+impl ProcessTimeline {
+    fn new(pid: u32, process: &str) -> Self {
+        Self {
+            pid,
+            process: process.to_string(),
+            parent_pid: None,
+            command_line: None,
+            started: None,
+            exited: None,
+            events: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, timestamp: DateTime<Utc>, kind: &'static str, description: String) {
+        self.events.push(TimelineEntry { timestamp, kind, description, alert_id: None });
+    }
+
+    fn first_seen(&self) -> Option<DateTime<Utc>> {
+        self.started.or_else(|| self.events.iter().map(|entry| entry.timestamp).min())
+    }
+
+    /// Whether something at `at` falls inside this run, allowing `early` before the
+    /// start and `late` after the exit.
+    fn covers(&self, at: DateTime<Utc>, early: i64, late: i64) -> bool {
+        self.started.is_none_or(|started| at >= started - chrono::Duration::seconds(early))
+            && self.exited.is_none_or(|exited| at <= exited + chrono::Duration::seconds(late))
+    }
+}
+
+/// A stored alert as the timeline needs it.
+struct TimelineAlert {
+    id: String,
+    timestamp: DateTime<Utc>,
+    severity: String,
+    rule_name: String,
+    process: String,
+    pid: u32,
+}
+
+/// `--timeline`: everything known about a process, by PID or image name, in
+/// time order. Process starts and exits, child processes, connections, DNS
+/// lookups and file opens come from a `--record` recording (with its rotated
+/// segments); alerts come from the SQLite store for `period`, filtered by
+/// `source` and `unacked` as `--alerts` is. Registry, hosts-file and task changes
+/// can't be attributed, so those made during a run are listed with it. A reused
+/// PID is split into separate runs by start, exit and image name.
+pub fn print_process_timeline(
+    config_path: Option<&Path>,
+    target: &str,
+    period: ReportPeriod,
+    recording: Option<&Path>,
+    source: Option<&str>,
+    unacked: bool,
+    json: bool,
+) -> Result<(), EdrError> {
+    let target = Target::parse(target);
+    let mut timelines = match recording {
+        Some(recording) => timelines_from_recording(&recording_segments(recording), &target)?,
+        None => Vec::new(),
+    };
+
+    let database_path = crate::report::database_path(config_path)?;
+    let since = Utc::now() - period.duration();
+    match stored_alerts(&database_path, since, source, unacked) {
+        Ok(alerts) => attach_alerts(&mut timelines, alerts, &target),
+        // A recording on its own still tells most of the story
+        Err(e) if recording.is_some() => log::warn!("Stored alerts not included: {}", e),
+        Err(e) => return Err(e),
+    }
+
+    timelines.retain(|timeline| !timeline.events.is_empty() || timeline.started.is_some());
+    timelines.sort_by_key(|timeline| timeline.first_seen());
+    for timeline in &mut timelines {
+        timeline.events.sort_by_key(|entry| entry.timestamp);
+    }
+
+    if json {
+        let rendered = serde_json::to_string_pretty(&timelines)
+            .map_err(|e| EdrError::Report(e.to_string()))?;
+        println!("{}", rendered);
+        return Ok(());
+    }
+    if timelines.is_empty() {
+        println!("Nothing recorded for that process.");
+        return Ok(());
+    }
+    for timeline in &timelines {
+        print_timeline(timeline);
+    }
+    Ok(())
+}
+
+fn print_timeline(timeline: &ProcessTimeline) {
+    let format_time = |at: Option<DateTime<Utc>>| {
+        at.map_or_else(|| "?".to_string(), |at| at.format("%Y-%m-%d %H:%M:%S").to_string())
+    };
+    println!("==============================================");
+    println!(" {} (PID {})", timeline.process, timeline.pid);
+    println!(" Started {}  Exited {}", format_time(timeline.started), format_time(timeline.exited));
+    if let Some(parent_pid) = timeline.parent_pid {
+        println!(" Parent PID {}", parent_pid);
+    }
+    if let Some(command_line) = &timeline.command_line {
+        println!(" Command line: {}", command_line);
+    }
+    println!("==============================================");
+    for entry in &timeline.events {
+        println!("{}  {:<8}  {}", entry.timestamp.format("%H:%M:%S%.3f"), entry.kind, entry.description);
+    }
+    println!();
+}
+
+/// Builds one timeline per run of a matching process. Events carrying a PID go to
+/// that PID's current run; a new start for the PID ends the mapping, so PID reuse
+/// never mixes two processes.
+fn timelines_from_recording(segments: &[std::path::PathBuf], target: &Target) -> Result<Vec<ProcessTimeline>, EdrError> {
+    let mut timelines: Vec<ProcessTimeline> = Vec::new();
+    // PID → index of its current run in `timelines` (only runs of the target)
+    let mut current: HashMap<u32, usize> = HashMap::new();
+    // PIDs seen starting without matching, so their later events are skipped
+    let mut other_runs: std::collections::HashSet<u32> = std::collections::HashSet::new();
+    let mut unattributed: Vec<(DateTime<Utc>, &'static str, String)> = Vec::new();
+
+    read_recording(segments, |event: BaseEvent| {
+        let at = event.timestamp;
+        match &event.event_type {
+            EventType::ProcessStart(process) => {
+                if let Some(&parent) = current.get(&process.parent_pid) {
+                    timelines[parent].push(at, "child", format!(
+                        "started {} (PID {}): {}",
+                        process.process_name, process.pid, process.command_line
+                    ));
+                }
+                current.remove(&process.pid);
+                other_runs.remove(&process.pid);
+                if target.matches(process.pid, &process.process_name) {
+                    let mut timeline = ProcessTimeline::new(process.pid, &process.process_name);
+                    timeline.parent_pid = Some(process.parent_pid);
+                    timeline.command_line = Some(process.command_line.clone()).filter(|line| !line.is_empty());
+                    timeline.started = Some(at);
+                    timeline.push(at, "start", format!("parent PID {}", process.parent_pid));
+                    current.insert(process.pid, timelines.len());
+                    timelines.push(timeline);
+                } else {
+                    other_runs.insert(process.pid);
+                }
+            }
+            EventType::RegistryChange(registry) => {
+                let value = if registry.value_name.is_empty() { "(default)" } else { registry.value_name.as_str() };
+                unattributed.push((at, "registry", format!(
+                    "{:?} {}\\{} = {}", registry.change, registry.key_path, value, registry.value_data
+                )));
+            }
+            EventType::HostsFileChanged(hosts) => {
+                let hosts: Vec<&str> = hosts.changes.iter().map(|change| change.host.as_str()).collect();
+                unattributed.push((at, "hosts", format!("hosts file entries changed: {}", hosts.join(", "))));
+            }
+            EventType::ScheduledTaskChanged(task) => {
+                unattributed.push((at, "task", format!("{:?} {}", task.change, task.task_name)));
+            }
+            _ => {
+                let Some((pid, process_name, kind, description)) = describe_process_event(&event.event_type) else {
+                    return true;
+                };
+                let index = match current.get(&pid) {
+                    Some(&index) if timelines[index].covers(at, 0, LATE_EVENT_GRACE_SECS) => Some(index),
+                    Some(_) => None,
+                    // Running before the recording began: its first event opens the run
+                    None if !other_runs.contains(&pid) && target.matches(pid, process_name) => {
+                        current.insert(pid, timelines.len());
+                        timelines.push(ProcessTimeline::new(pid, process_name));
+                        Some(timelines.len() - 1)
+                    }
+                    None => None,
+                };
+                if let Some(index) = index {
+                    if kind == "exit" {
+                        timelines[index].exited = Some(at);
+                    }
+                    timelines[index].push(at, kind, description);
+                }
+            }
+        }
+        true
+    })?;
+
+    for timeline in &mut timelines {
+        for (at, kind, description) in &unattributed {
+            if timeline.started.is_some() && timeline.covers(*at, 0, 0) {
+                timeline.push(*at, kind, format!("{} (during lifetime, not attributed)", description));
+            }
+        }
+    }
+    Ok(timelines)
+}
+
+/// PID, process name, kind and text of an event that belongs to one process.
+fn describe_process_event(event: &EventType) -> Option<(u32, &str, &'static str, String)> {
+    Some(match event {
+        EventType::ProcessEnd(process) => (
+            process.pid,
+            process.process_name.as_str(),
+            "exit",
+            process.exit_code.map_or_else(|| "exited".to_string(), |code| format!("exit code {}", code)),
+        ),
+        EventType::NetworkConnection(network) => {
+            let domain = network.domain.as_deref().map(|domain| format!(" ({})", domain)).unwrap_or_default();
+            let closed = network.duration_secs.map(|secs| format!(", closed after {}s", secs)).unwrap_or_default();
+            (
+                network.pid,
+                network.process_name.as_str(),
+                "network",
+                format!(
+                    "{:?} {} {}:{} → {}:{}{}{}",
+                    network.direction, network.protocol, network.local_address, network.local_port,
+                    network.remote_address, network.remote_port, domain, closed
+                ),
+            )
+        }
+        EventType::DnsQuery(dns) => (
+            dns.pid,
+            dns.process_name.as_str(),
+            "dns",
+            format!("{} → {}", dns.query_name, dns.resolved_addresses.join(", ")),
+        ),
+        EventType::FileAccess(file) => {
+            let action = if file.watch == FileWatch::Document { file.matched.as_str() } else { "opened" };
+            (file.pid, file.process_name.as_str(), "file", format!("{} {}", action, file.path))
+        }
+        _ => return None,
+    })
+}
+
+/// Adds each matching alert to the run it fell in: same PID, same image name and
+/// within the run's lifetime. Alerts with no such run (no recording, or one that
+/// doesn't reach back far enough) get a run of their own per PID and image name.
+fn attach_alerts(timelines: &mut Vec<ProcessTimeline>, alerts: Vec<TimelineAlert>, target: &Target) {
+    for alert in alerts.into_iter().filter(|alert| target.matches(alert.pid, &alert.process)) {
+        let index = timelines.iter()
+            .position(|timeline| {
+                timeline.pid == alert.pid
+                    && timeline.process.eq_ignore_ascii_case(&alert.process)
+                    && timeline.covers(alert.timestamp, EARLY_ALERT_GRACE_SECS, LATE_ALERT_GRACE_SECS)
+            })
+            .unwrap_or_else(|| {
+                timelines.push(ProcessTimeline::new(alert.pid, &alert.process));
+                timelines.len() - 1
+            });
+        timelines[index].events.push(TimelineEntry {
+            timestamp: alert.timestamp,
+            kind: "alert",
+            description: format!("{} {}", alert.severity, alert.rule_name),
+            alert_id: Some(alert.id),
+        });
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn stored_alerts(
+    database_path: &str,
+    since: DateTime<Utc>,
+    source: Option<&str>,
+    unacked: bool,
+) -> Result<Vec<TimelineAlert>, EdrError> {
+    Ok(crate::report::read_stored_alerts(database_path, since, source, unacked)?
+        .into_iter()
+        .map(|alert| TimelineAlert {
+            id: alert.id,
+            timestamp: alert.timestamp,
+            severity: alert.severity,
+            rule_name: alert.rule_name,
+            process: alert.process,
+            pid: alert.pid,
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn stored_alerts(
+    _database_path: &str,
+    _since: DateTime<Utc>,
+    _source: Option<&str>,
+    _unacked: bool,
+) -> Result<Vec<TimelineAlert>, EdrError> {
+    Err(crate::report::no_store_support())
+}
@@ -2,8 +2,10 @@ use crate::events::BaseEvent;
 use crossbeam_channel::Sender;
 use std::sync::Arc;
 use std::collections::{HashSet, HashMap};
+use std::net::IpAddr;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::sync::Mutex;
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use windows::Win32::{
     System::ProcessStatus::GetModuleFileNameExW,
     System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
@@ -29,6 +31,71 @@ pub struct CmdlineAnalysis {
     pub cmd_score: u8,
 }
 
+/// A base64 blob (optionally gzip/deflate-compressed) recovered from a command line.
+pub struct EncodedPayload {
+    pub encoding: &'static str,
+    pub decoded: String,
+}
+
+/// Set once PowerShell-backed lookups have failed to launch.
+pub static POWERSHELL_DEGRADED: AtomicBool = AtomicBool::new(false);
+
+/// Recording lines and stored alerts skipped as unparseable this run.
+pub static UNPARSED_RECORDS: AtomicU64 = AtomicU64::new(0);
+/// `--strict`: readers fail on the first unparseable record instead of skipping it.
+pub static STRICT_PARSING: AtomicBool = AtomicBool::new(false);
+
+/// Warns once, at the end of a command, about the records its readers skipped, so
+/// a writer bug or truncated file shows up instead of quietly thinning the output.
+pub fn warn_unparsed_records() {
+    match UNPARSED_RECORDS.load(Ordering::Relaxed) {
+        0 => {}
+        1 => log::warn!("⚠️  1 line could not be parsed (possibly truncated/corrupt); --strict stops at the first one"),
+        skipped => log::warn!(
+            "⚠️  {} lines could not be parsed (possibly truncated/corrupt); --strict stops at the first one",
+            skipped
+        ),
+    }
+}
+
+/// Replaces `path` with `contents` by writing a temporary file beside it and
+/// renaming it over the original, so a crash or full disk never leaves a
+/// half-written file. Missing parent directories are created. The error names
+/// the file and, for access errors, points at a read-only install directory.
+pub fn write_file_atomically(path: &std::path::Path, contents: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+
+    let describe = |action: &str, target: &std::path::Path, e: std::io::Error| {
+        let hint = if e.kind() == std::io::ErrorKind::PermissionDenied {
+            " (the directory is read-only or this account cannot write to it)"
+        } else {
+            ""
+        };
+        format!("cannot {} {}: {}{}", action, target.display(), e, hint)
+    };
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| describe("create directory", dir, e))?;
+    }
+    let Some(file_name) = path.file_name() else {
+        return Err(format!("cannot write {}: not a file path", path.display()));
+    };
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = std::fs::File::create(&temp_path)
+        .and_then(|mut file| file.write_all(contents).and_then(|()| file.sync_all()));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(describe("write", &temp_path, e));
+    }
+    std::fs::rename(&temp_path, path).map_err(|e| {
+        let _ = std::fs::remove_file(&temp_path);
+        describe("replace", path, e)
+    })
+}
+
 lazy_static::lazy_static! {
     pub static ref GLOBAL_SENDER: Mutex<Option<Arc<Sender<BaseEvent>>>> = Mutex::new(None);
     pub static ref RECENT_CONNECTIONS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
@@ -39,17 +106,94 @@ lazy_static::lazy_static! {
     pub static ref DNS_CACHE: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
     pub static ref COMMAND_LINE_CACHE: Mutex<HashMap<u32, (String, u64)>> = Mutex::new(HashMap::new());
     pub static ref SCRIPTING_ENGINE_CACHE: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+    pub static ref FAILED_NAME_RESOLUTIONS: Mutex<HashMap<u32, u64>> = Mutex::new(HashMap::new());
+    pub static ref RULE_HIT_COUNTS: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+// Process-name regexes whose ETW events are dropped in the callbacks; set at startup
+// and replaced by the control pipe's reload-config.
+static ETW_DROP_PATTERNS: RwLock<Vec<regex::Regex>> = RwLock::new(Vec::new());
+
+/// Compiles a regex from the rules file (`etw_drop_patterns`, pipe patterns) the
+/// way the monitors apply it: case-insensitively. Only called when the rules are
+/// loaded or reloaded; the hot paths match against the compiled `Regex` it returns.
+pub fn compile_config_pattern(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::Regex::new(&format!("(?i){}", pattern))
+}
+
+pub fn set_etw_drop_patterns(patterns: &[String]) {
+    let compiled = patterns.iter()
+        .filter_map(|pattern| match compile_config_pattern(pattern) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                log::warn!("Ignoring invalid etw_drop_patterns entry '{}': {}", pattern, e);
+                None
+            }
+        })
+        .collect();
+    if let Ok(mut current) = ETW_DROP_PATTERNS.write() {
+        *current = compiled;
+    }
+}
+
+/// True if events from this process should be discarded before reaching the engine.
+pub fn is_etw_dropped_process(process_name: &str) -> bool {
+    ETW_DROP_PATTERNS.read()
+        .is_ok_and(|patterns| patterns.iter().any(|re| re.is_match(process_name)))
+}
+
+// Lowercased `credential_store_paths`, set at startup and by reload-config; read
+// in the Kernel-File callback for every file open.
+static CREDENTIAL_STORE_PATHS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+pub fn set_credential_store_paths(paths: &[String]) {
+    if let Ok(mut current) = CREDENTIAL_STORE_PATHS.write() {
+        *current = paths.iter().map(|path| path.to_lowercase()).collect();
+    }
+}
+
+/// The `credential_store_paths` entry (lowercased) this file path contains, if any.
+pub fn credential_store_match(path: &str) -> Option<String> {
+    let lower = path.to_lowercase();
+    CREDENTIAL_STORE_PATHS.read().ok()?
+        .iter()
+        .find(|fragment| lower.contains(fragment.as_str()))
+        .cloned()
+}
+
+// Lowercased `ransomware_detection.document_dirs`, empty when the rule is off; read
+// in the Kernel-File callback for every overwrite, new file and rename.
+static DOCUMENT_DIRS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+pub fn set_document_dirs(dirs: &[String]) {
+    if let Ok(mut current) = DOCUMENT_DIRS.write() {
+        *current = dirs.iter().map(|dir| dir.to_lowercase()).collect();
+    }
+}
+
+/// True for a file in a watched document folder under a user profile. AppData
+/// is never a document folder, whatever the configured fragments match.
+pub fn is_user_document_path(path: &str) -> bool {
+    let Ok(dirs) = DOCUMENT_DIRS.read() else {
+        return false;
+    };
+    if dirs.is_empty() {
+        return false;
+    }
+    let lower = path.to_lowercase();
+    lower.contains("\\users\\")
+        && !lower.contains("\\appdata\\")
+        && dirs.iter().any(|dir| lower.contains(dir.as_str()))
 }
 
+// How long a failed OpenProcess for a PID is remembered before retrying.
+const FAILED_RESOLUTION_TTL_SECS: u64 = 10;
+
 pub fn get_command_line_cached(pid: u32) -> Option<String> {
     let now = get_timestamp();
 
-    if let Ok(cache) = COMMAND_LINE_CACHE.lock() {
-        if let Some((cmdline, cached_at)) = cache.get(&pid) {
-            if now - cached_at < 300 {
-                return Some(cmdline.clone());
-            }
-        }
+    if let Some(cmdline) = cached_command_line(pid) {
+        return Some(cmdline);
     }
 
     // Try PowerShell Get-Process first (fastest)
@@ -72,39 +216,118 @@ pub fn get_command_line_cached(pid: u32) -> Option<String> {
     None
 }
 
+/// The command line if a lookup already cached it, without spawning PowerShell.
+pub fn cached_command_line(pid: u32) -> Option<String> {
+    let now = get_timestamp();
+    let cache = COMMAND_LINE_CACHE.lock().ok()?;
+    let (cmdline, cached_at) = cache.get(&pid)?;
+    (now - cached_at < 300).then(|| cmdline.clone())
+}
+
+// Command-line backfill for PIDs first seen on the network path. Each lookup can
+// spawn two PowerShell processes, so a few workers drain a bounded queue instead
+// of the engine blocking on every unknown PID.
+const BACKFILL_WORKERS: usize = 2;
+const BACKFILL_QUEUE_CAPACITY: usize = 64;
+
+/// Backfill requests dropped because the queue was full.
+pub static BACKFILL_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    // PIDs queued or being looked up, so bursts for one PID queue it once
+    static ref BACKFILL_PENDING: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+}
+
+static BACKFILL_QUEUE: OnceLock<Sender<u32>> = OnceLock::new();
+
+fn backfill_queue() -> &'static Sender<u32> {
+    BACKFILL_QUEUE.get_or_init(|| {
+        let (tx, rx) = crossbeam_channel::bounded::<u32>(BACKFILL_QUEUE_CAPACITY);
+        for _ in 0..BACKFILL_WORKERS {
+            let rx = rx.clone();
+            std::thread::spawn(move || {
+                for pid in rx.iter() {
+                    let _ = get_command_line_cached(pid);
+                    if let Ok(mut pending) = BACKFILL_PENDING.lock() {
+                        pending.remove(&pid);
+                    }
+                }
+            });
+        }
+        tx
+    })
+}
+
+/// Queues a background command-line lookup for `pid`; the result lands in the
+/// command-line cache. Never blocks: duplicates are ignored and requests past the
+/// queue capacity are dropped and counted in `BACKFILL_DROPPED`.
+pub fn request_command_line_backfill(pid: u32) {
+    if cached_command_line(pid).is_some() {
+        return;
+    }
+    match BACKFILL_PENDING.lock() {
+        Ok(mut pending) => {
+            if !pending.insert(pid) {
+                return;
+            }
+        }
+        Err(_) => return,
+    }
+
+    if backfill_queue().try_send(pid).is_err() {
+        if let Ok(mut pending) = BACKFILL_PENDING.lock() {
+            pending.remove(&pid);
+        }
+        let dropped = BACKFILL_DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+        log::debug!("Command-line backfill queue full; dropped PID {} ({} dropped so far)", pid, dropped);
+    }
+}
+
 fn get_command_line_powershell(pid: u32) -> Option<String> {
-    use std::process::Command;
     let ps_cmd = format!(
         "Get-Process -Id {} -ErrorAction SilentlyContinue | Select-Object -ExpandProperty CommandLine",
         pid
     );
-    let output = Command::new("powershell")
-        .args(&["-NoProfile", "-NonInteractive", "-Command", &ps_cmd])
-        .output();
-    match output {
-        Ok(output) if output.status.success() => {
-            let result = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !result.is_empty() && result != "null" { Some(result) } else { None }
-        }
-        _ => None,
-    }
+    run_powershell_query(&ps_cmd)
 }
 
 fn get_command_line_cim(pid: u32) -> Option<String> {
-    use std::process::Command;
     let cim_cmd = format!(
         "Get-CimInstance Win32_Process -Filter \"ProcessId = {}\" | Select-Object -ExpandProperty CommandLine",
         pid
     );
+    run_powershell_query(&cim_cmd)
+}
+
+/// Runs a PowerShell one-liner and returns its trimmed stdout. Failures are logged
+/// rather than swallowed; if PowerShell cannot be launched at all the command-line
+/// lookup path is flagged as degraded so it shows up in `status`.
+fn run_powershell_query(script: &str) -> Option<String> {
+    use std::process::Command;
     let output = Command::new("powershell")
-        .args(&["-NoProfile", "-NonInteractive", "-Command", &cim_cmd])
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
         .output();
     match output {
         Ok(output) if output.status.success() => {
             let result = String::from_utf8_lossy(&output.stdout).trim().to_string();
             if !result.is_empty() && result != "null" { Some(result) } else { None }
         }
-        _ => None,
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::debug!(
+                "PowerShell query exited with {}: {}",
+                output.status,
+                stderr.lines().next().unwrap_or("").trim()
+            );
+            None
+        }
+        Err(e) => {
+            if !POWERSHELL_DEGRADED.swap(true, Ordering::Relaxed) {
+                log::warn!("⚠️  Unable to launch PowerShell for command-line lookups: {}", e);
+                log::warn!("   Command-line based detections will be degraded");
+            }
+            None
+        }
     }
 }
 
@@ -145,16 +368,30 @@ pub fn get_process_name_cached(pid: u32) -> String {
         }
     }
 
+    // Protected or exited processes fail OpenProcess on every event; skip the
+    // syscall while a recent failure for this PID is still remembered.
+    let recently_failed = FAILED_NAME_RESOLUTIONS.lock()
+        .map(|failed| failed.get(&pid).map(|t| now.saturating_sub(*t) < FAILED_RESOLUTION_TTL_SECS).unwrap_or(false))
+        .unwrap_or(false);
+
     // Try to resolve now (process might still be running)
-    if let Some(name) = resolve_process_name(pid) {
-        let info = ProcessInfo { name: name.clone(), cached_at: now, parent_pid: 0 };
-        if let Ok(mut cache) = PROCESS_NAME_CACHE.lock() {
-            cache.insert(pid, info.clone());
+    if !recently_failed {
+        if let Some(name) = resolve_process_name(pid) {
+            let info = ProcessInfo { name: name.clone(), cached_at: now, parent_pid: 0 };
+            if let Ok(mut cache) = PROCESS_NAME_CACHE.lock() {
+                cache.insert(pid, info.clone());
+            }
+            if let Ok(mut recent) = RECENT_PROCESS_STARTS.lock() {
+                recent.insert(pid, info);
+            }
+            if let Ok(mut failed) = FAILED_NAME_RESOLUTIONS.lock() {
+                failed.remove(&pid);
+            }
+            return name;
         }
-        if let Ok(mut recent) = RECENT_PROCESS_STARTS.lock() {
-            recent.insert(pid, info);
+        if let Ok(mut failed) = FAILED_NAME_RESOLUTIONS.lock() {
+            failed.insert(pid, now);
         }
-        return name;
     }
 
     // Last resort: return any stale cached value for fast-exiting processes
@@ -248,6 +485,11 @@ pub fn cleanup_tracking_data() {
         });
     }
 
+    if let Ok(mut failed) = FAILED_NAME_RESOLUTIONS.lock() {
+        // A wall clock stepped backwards must not underflow the age
+        failed.retain(|_, failed_at| now.saturating_sub(*failed_at) < FAILED_RESOLUTION_TTL_SECS);
+    }
+
     if let Ok(mut recent) = RECENT_CONNECTIONS.lock() {
         if recent.len() > 5000 {
             recent.clear();
@@ -255,6 +497,39 @@ pub fn cleanup_tracking_data() {
     }
 }
 
+/// Reduces an alert reason to the rule that produced it by dropping the
+/// per-event details, e.g. "Rapid connections: 7 in 10 seconds" → "Rapid connections".
+pub fn rule_label(reason: &str) -> &str {
+    let end = reason
+        .find(|c: char| c == ':' || c == '(' || c == '[' || c.is_ascii_digit())
+        .unwrap_or(reason.len());
+    reason[..end].trim()
+}
+
+/// Counts one hit for every distinct rule among an alert's reasons.
+pub fn record_rule_hits(reasons: &[String]) {
+    let labels: HashSet<&str> = reasons.iter()
+        .map(|reason| rule_label(reason))
+        .filter(|label| !label.is_empty())
+        .collect();
+    if let Ok(mut counts) = RULE_HIT_COUNTS.lock() {
+        for label in labels {
+            *counts.entry(label.to_string()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Rules ordered by lifetime hit count, noisiest first.
+pub fn top_rule_hits(limit: usize) -> Vec<(String, u64)> {
+    let mut hits: Vec<(String, u64)> = match RULE_HIT_COUNTS.lock() {
+        Ok(counts) => counts.iter().map(|(rule, count)| (rule.clone(), *count)).collect(),
+        Err(_) => Vec::new(),
+    };
+    hits.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    hits.truncate(limit);
+    hits
+}
+
 /// Returns true for OS processes that should be silently ignored by all monitors.
 pub fn is_system_process(process_name: &str) -> bool {
     let lower = process_name.to_lowercase();
@@ -320,6 +595,33 @@ pub fn is_scripting_engine(process_name: &str, command_line: &str) -> bool {
     false
 }
 
+/// Office applications by image name, with the name alerts show for each.
+/// Every rule that asks "is this Office?" uses this one list.
+const OFFICE_APPS: &[(&str, &str)] = &[
+    ("winword.exe", "Word"),
+    ("excel.exe", "Excel"),
+    ("powerpnt.exe", "PowerPoint"),
+    ("outlook.exe", "Outlook"),
+    ("msaccess.exe", "Access"),
+    ("mspub.exe", "Publisher"),
+    ("onenote.exe", "OneNote"),
+    ("visio.exe", "Visio"),
+];
+
+/// The Office application `lower_name` (a lowercased image name) belongs to, if any.
+fn office_app(lower_name: &str) -> Option<&'static str> {
+    OFFICE_APPS.iter().find(|(image, _)| *image == lower_name).map(|(_, app)| *app)
+}
+
+/// Whether any whitespace-separated token of `lower` (already lowercased) has one of
+/// `extensions`, ignoring quotes and trailing punctuation, so `.js` matches `run.js`
+/// and `"C:\dl\run.js"` but not `config.json`.
+fn has_token_extension(lower: &str, extensions: &[&str]) -> bool {
+    lower.split_whitespace()
+        .map(|token| token.trim_start_matches(['"', '\'']).trim_end_matches(|c: char| !c.is_ascii_alphanumeric()))
+        .any(|token| extensions.iter().any(|ext| token.ends_with(ext)))
+}
+
 /// Legitimate, well-known processes that are not expected to be involved in malicious activity.
 /// NOTE: Being known-good suppresses beaconing/scoring heuristics but does NOT suppress IOC hits.
 pub fn is_known_good_process(process_name: &str, command_line: &str) -> bool {
@@ -524,6 +826,81 @@ pub fn analyze_command_line(command_line: &str) -> CmdlineAnalysis {
     CmdlineAnalysis { flags, cmd_score }
 }
 
+lazy_static::lazy_static! {
+    static ref BASE64_BLOB: regex::Regex = regex::Regex::new(r"[A-Za-z0-9+/]{40,}={0,2}").unwrap();
+}
+
+// Cap on decompressed output so a crafted blob can't balloon memory.
+const MAX_DECODED_PAYLOAD: u64 = 256 * 1024;
+
+/// True if the command line carries a usual encode/compress construct or a long
+/// base64 blob, whatever program is running it.
+pub fn has_encoding_markers(command_line: &str) -> bool {
+    const MARKERS: &[&str] = &[
+        "frombase64", "[convert]::", "gzipstream", "deflatestream", "io.compression",
+        "-encodedcommand", "-enc ",
+    ];
+    let lower_cmd = command_line.to_lowercase();
+    MARKERS.iter().any(|marker| lower_cmd.contains(marker)) || BASE64_BLOB.is_match(command_line)
+}
+
+/// Decodes the first base64 blob in the command line that yields readable text,
+/// inflating gzip/deflate streams and UTF-16LE (`-EncodedCommand`) text along the way.
+/// Host-agnostic: cmd.exe, wmic and conhost carry the same payloads as PowerShell.
+pub fn detect_encoded_payload(command_line: &str) -> Option<EncodedPayload> {
+    use base64::Engine;
+    use base64::engine::{GeneralPurpose, GeneralPurposeConfig, DecodePaddingMode};
+
+    if !has_encoding_markers(command_line) {
+        return None;
+    }
+    let lenient = GeneralPurpose::new(
+        &base64::alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+    let deflate_hinted = command_line.to_lowercase().contains("deflatestream");
+
+    BASE64_BLOB.find_iter(command_line).find_map(|blob| {
+        let bytes = lenient.decode(blob.as_str()).ok()?;
+        let (encoding, bytes) = if bytes.starts_with(&[0x1f, 0x8b]) {
+            ("base64+gzip", inflate(flate2::read::GzDecoder::new(&bytes[..]))?)
+        } else if deflate_hinted {
+            match inflate(flate2::read::DeflateDecoder::new(&bytes[..])) {
+                Some(inflated) => ("base64+deflate", inflated),
+                None => ("base64", bytes),
+            }
+        } else {
+            ("base64", bytes)
+        };
+        Some(EncodedPayload { encoding, decoded: decode_payload_text(&bytes)? })
+    })
+}
+
+fn inflate(reader: impl std::io::Read) -> Option<Vec<u8>> {
+    use std::io::Read;
+    let mut inflated = Vec::new();
+    reader.take(MAX_DECODED_PAYLOAD).read_to_end(&mut inflated).ok()?;
+    Some(inflated)
+}
+
+// Accepts UTF-16LE (every second byte zero for ASCII script text) or UTF-8, and
+// rejects anything that isn't mostly printable so hashes and binary blobs are ignored.
+fn decode_payload_text(bytes: &[u8]) -> Option<String> {
+    let zero_high_bytes = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    let text = if bytes.len() >= 4 && zero_high_bytes * 10 >= (bytes.len() / 2) * 9 {
+        let units: Vec<u16> = bytes.chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8(bytes.to_vec()).ok()?
+    };
+
+    let total = text.chars().count();
+    let printable = text.chars().filter(|c| !c.is_control() || c.is_whitespace()).count();
+    (total >= 8 && printable * 10 >= total * 9).then_some(text)
+}
+
 /// Returns a label if the process and command line match a known LOLBAS abuse pattern,
 /// or `None` if no match.
 pub fn identify_lolbas_abuse(process_name: &str, command_line: &str) -> Option<&'static str> {
@@ -531,35 +908,6 @@ pub fn identify_lolbas_abuse(process_name: &str, command_line: &str) -> Option<&
     let lower_cmd = command_line.to_lowercase();
 
     match lower_name.as_str() {
-        "rundll32.exe" => {
-            if lower_cmd.contains(".dll,") &&
-               (lower_cmd.contains("http://") || lower_cmd.contains("https://") ||
-                lower_cmd.contains("regsvr") || lower_cmd.contains("javascript:"))
-            {
-                if lower_cmd.contains("javascript:") {
-                    return Some("Rundll32 JavaScript Execution");
-                }
-                return Some("Rundll32 Remote DLL Load");
-            }
-        }
-        "regsvr32.exe" => {
-            if lower_cmd.contains("/s") &&
-               (lower_cmd.contains("http://") || lower_cmd.contains("https://") ||
-                lower_cmd.contains(".sct") || lower_cmd.contains(".scrobj"))
-            {
-                if lower_cmd.contains(".sct") {
-                    return Some("Regsvr32 SCT Scriptlet Execution");
-                }
-                return Some("Regsvr32 Remote Script Execution");
-            }
-        }
-        "mshta.exe" => {
-            if lower_cmd.contains("http://") || lower_cmd.contains("https://") ||
-               lower_cmd.contains("javascript:") || lower_cmd.contains("vbscript:")
-            {
-                return Some("Mshta Remote Script Execution");
-            }
-        }
         "certutil.exe" => {
             if lower_cmd.contains("-urlcache") || lower_cmd.contains("-split") ||
                lower_cmd.contains("-decode") || lower_cmd.contains("-encode")
@@ -570,13 +918,6 @@ pub fn identify_lolbas_abuse(process_name: &str, command_line: &str) -> Option<&
                 return Some("Certutil Encode/Decode Abuse");
             }
         }
-        "bitsadmin.exe" => {
-            if lower_cmd.contains("/transfer") || lower_cmd.contains("/create") ||
-               lower_cmd.contains("/addfile") || lower_cmd.contains("/setnotifycmdline")
-            {
-                return Some("Bitsadmin File Transfer");
-            }
-        }
         "wmic.exe" => {
             if lower_cmd.contains("process call create") ||
                (lower_cmd.contains("/node:") && lower_cmd.contains("process create"))
@@ -589,71 +930,1325 @@ pub fn identify_lolbas_abuse(process_name: &str, command_line: &str) -> Option<&
     None
 }
 
-/// Returns true if the address is a loopback, RFC-1918 private, link-local, or
-/// unspecified address that should not be treated as an external connection.
-pub fn is_private_or_local(addr: &str) -> bool {
-    if addr.starts_with("127.") || addr.starts_with("192.168.") {
-        return true;
-    }
-    if addr.starts_with("10.") {
-        return true;
+lazy_static::lazy_static! {
+    static ref URL_IN_TEXT: regex::Regex = regex::Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s"']+"#).unwrap();
+}
+
+/// Host names of the URLs in the text, lowercased.
+pub fn url_hosts(text: &str) -> Vec<String> {
+    URL_IN_TEXT.find_iter(text)
+        .filter_map(|url| {
+            let rest = url.as_str().split_once("://")?.1;
+            let authority = rest.split(['/', '?', '#', '\\']).next()?;
+            let host = authority.rsplit('@').next()?.split(':').next()?;
+            (!host.is_empty()).then(|| host.to_lowercase())
+        })
+        .collect()
+}
+
+/// A signed Windows binary used to run attacker-supplied code (T1218).
+pub struct ProxyExecution {
+    pub pattern: &'static str,
+    /// MITRE technique, e.g. `T1218.011`.
+    pub technique: &'static str,
+    /// The URL, share or file the binary was pointed at, when one was found.
+    pub target: Option<String>,
+    /// The payload comes from a URL or a network share.
+    pub remote: bool,
+}
+
+/// Signed binary proxy execution through rundll32, regsvr32 and mshta
+/// (.NET utilities are covered by [`identify_dotnet_lolbas`]). Only remote payloads, user-writable paths and
+/// argument shapes legitimate callers never use are reported, so ordinary
+/// control-panel and COM-registration launches stay quiet.
+pub fn identify_signed_binary_proxy(process_name: &str, command_line: &str) -> Option<ProxyExecution> {
+    let args = split_arguments(arguments_after_image(command_line));
+    let lower_args: Vec<String> = args.iter().map(|arg| arg.to_lowercase()).collect();
+    let joined = lower_args.join(" ");
+    let url = URL_IN_TEXT.find(command_line).map(|m| m.as_str().to_string());
+
+    let finding = |pattern: &'static str, technique: &'static str, target: Option<String>| {
+        let remote = target.as_deref()
+            .is_some_and(|target| URL_IN_TEXT.is_match(target) || is_unc_path(target));
+        Some(ProxyExecution { pattern, technique, target, remote })
+    };
+    let untrusted = |path: &str| is_user_writable_script_location(path) || is_unc_path(path);
+
+    match process_name.to_lowercase().as_str() {
+        "rundll32.exe" => {
+            const TECHNIQUE: &str = "T1218.011";
+            // A bare rundll32 is a sacrificial host for injected code (e.g. Cobalt Strike spawnto)
+            let Some(first) = args.first() else {
+                return finding("Rundll32 Without Arguments", TECHNIQUE, None);
+            };
+            if joined.contains("javascript:") || joined.contains("mshtml,runhtmlapplication") {
+                return finding("Rundll32 JavaScript Execution", TECHNIQUE, url);
+            }
+            const LAUNCHERS: &[&str] = &[
+                "url.dll,openurl", "url.dll,fileprotocolhandler", "ieframe.dll,openurl",
+                "shdocvw.dll,openurl", "shell32.dll,control_rundll", "shell32.dll,shellexec_rundll",
+                "zipfldr.dll,routethecall", "advpack.dll,launchinfsection", "pcwutl.dll,launchapplication",
+            ];
+            if LAUNCHERS.iter().any(|launcher| joined.contains(launcher)) {
+                let target = args.get(1).cloned();
+                return match (&url, &target) {
+                    (Some(_), _) => finding("Rundll32 Proxy Launch of Remote Target", TECHNIQUE, url),
+                    (None, Some(path)) if untrusted(path) => {
+                        finding("Rundll32 Proxy Launch from User-Writable Path", TECHNIQUE, target)
+                    }
+                    _ => None,
+                };
+            }
+
+            let (dll, entry_point) = first.split_once(',').unwrap_or((first.as_str(), ""));
+            if url.is_some() || is_unc_path(dll) {
+                return finding("Rundll32 Remote DLL Load", TECHNIQUE, url.or_else(|| Some(dll.to_string())));
+            }
+            if entry_point.is_empty() && args.len() == 1 && !first.contains(',') {
+                return finding("Rundll32 DLL Without Entry Point", TECHNIQUE, Some(dll.to_string()));
+            }
+            if untrusted(dll) {
+                return finding("Rundll32 DLL from User-Writable Path", TECHNIQUE, Some(dll.to_string()));
+            }
+        }
+        "regsvr32.exe" => {
+            const TECHNIQUE: &str = "T1218.010";
+            // Squiblydoo: /i:<url> scrobj.dll fetches and runs a scriptlet
+            let install_target = lower_args.iter().zip(&args)
+                .find(|(lower, _)| lower.starts_with("/i:") || lower.starts_with("-i:"))
+                .map(|(_, arg)| arg[3..].to_string());
+            if url.is_some() {
+                return finding("Regsvr32 Remote Script Execution", TECHNIQUE, url);
+            }
+            if joined.contains(".sct") || joined.contains("scrobj.dll") {
+                return finding("Regsvr32 SCT Scriptlet Execution", TECHNIQUE, install_target);
+            }
+            if let Some(dll) = args.iter().find(|arg| !arg.starts_with('/') && !arg.starts_with('-'))
+                && untrusted(dll)
+            {
+                return finding("Regsvr32 DLL from User-Writable Path", TECHNIQUE, Some(dll.clone()));
+            }
+        }
+        "mshta.exe" => {
+            const TECHNIQUE: &str = "T1218.005";
+            // Inline vbscript:/javascript: is reported by identify_inline_script
+            if url.is_some() {
+                return finding("Mshta Remote Script Execution", TECHNIQUE, url);
+            }
+            if let Some(hta) = args.iter().find(|arg| untrusted(arg)) {
+                return finding("Mshta HTA from User-Writable Path", TECHNIQUE, Some(hta.clone()));
+            }
+        }
+        _ => {}
     }
-    if addr.starts_with("172.") {
-        // RFC-1918: 172.16.0.0 – 172.31.255.255
-        let second_octet: Option<u8> = addr.split('.').nth(1).and_then(|o| o.parse().ok());
-        if let Some(n) = second_octet {
-            if (16..=31).contains(&n) {
-                return true;
+    None
+}
+
+/// Project file extensions MSBuild is normally pointed at.
+const MSBUILD_PROJECT_EXTENSIONS: &[&str] = &[
+    ".sln", ".slnf", ".proj", ".csproj", ".vbproj", ".fsproj", ".vcxproj", ".targets", ".props",
+];
+
+/// Project files larger than this aren't read for inline tasks.
+const MAX_PROJECT_SCAN_BYTES: u64 = 1024 * 1024;
+
+/// .NET developer and installer utilities compiling or loading attacker code:
+/// MSBuild inline tasks, InstallUtil uninstall hooks and RegAsm/RegSvcs
+/// registration of unsigned assemblies. These run the payload inside a
+/// Microsoft-signed process. The target project or assembly is read from disk
+/// for the inline-task and signature checks, so only absolute paths are judged
+/// on content.
+pub fn identify_dotnet_lolbas(process_name: &str, command_line: &str) -> Option<ProxyExecution> {
+    let args = split_arguments(arguments_after_image(command_line));
+    let lower_args: Vec<String> = args.iter().map(|arg| arg.to_lowercase()).collect();
+    let url = URL_IN_TEXT.find(command_line).map(|m| m.as_str().to_string());
+    let target = args.iter()
+        .find(|arg| !arg.starts_with('/') && !arg.starts_with('-') && !arg.starts_with('@'))
+        .cloned();
+
+    let finding = |pattern: &'static str, technique: &'static str, target: Option<String>| {
+        let remote = target.as_deref()
+            .is_some_and(|target| URL_IN_TEXT.is_match(target) || is_unc_path(target));
+        Some(ProxyExecution { pattern, technique, target, remote })
+    };
+    let untrusted = |path: &str| is_user_writable_script_location(path) || is_unc_path(path);
+    let has_switch = |name: &str| lower_args.iter()
+        .any(|arg| arg.len() > 1 && (arg.starts_with('/') || arg.starts_with('-')) && &arg[1..] == name);
+    let unsigned = |path: &str| drive_letter(path).is_some() && is_signed_executable(path) == Some(false);
+
+    match process_name.to_lowercase().as_str() {
+        "msbuild.exe" => {
+            const TECHNIQUE: &str = "T1127.001";
+            if url.is_some() {
+                return finding("MSBuild Remote Project", TECHNIQUE, url);
+            }
+            let project = target?;
+            if untrusted(&project) {
+                return finding("MSBuild Project from User-Writable Path", TECHNIQUE, Some(project));
+            }
+            if has_inline_task(&project) {
+                return finding("MSBuild Inline Task Execution", TECHNIQUE, Some(project));
+            }
+            let lower = project.to_lowercase();
+            if !MSBUILD_PROJECT_EXTENSIONS.iter().any(|ext| lower.ends_with(ext)) {
+                return finding("MSBuild Project with Unusual Extension", TECHNIQUE, Some(project));
+            }
+        }
+        "installutil.exe" => {
+            const TECHNIQUE: &str = "T1218.004";
+            if url.is_some() {
+                return finding("InstallUtil Remote Assembly", TECHNIQUE, url);
+            }
+            // `/logfile= /LogToConsole=false /U payload.dll` runs the Uninstall hook
+            // with logging switched off; installers never ship this shape
+            let silent_log = lower_args.iter()
+                .any(|arg| matches!(arg.as_str(), "/logfile=" | "-logfile=" | "/logtoconsole=false" | "-logtoconsole=false"));
+            if (has_switch("u") || has_switch("uninstall")) && silent_log {
+                return finding("InstallUtil Silent Uninstall Proxy Execution", TECHNIQUE, target);
+            }
+            let assembly = target?;
+            if untrusted(&assembly) {
+                return finding("InstallUtil Running Assembly from User-Writable Path", TECHNIQUE, Some(assembly));
+            }
+            if unsigned(&assembly) {
+                return finding("InstallUtil Running Unsigned Assembly", TECHNIQUE, Some(assembly));
+            }
+        }
+        "regasm.exe" | "regsvcs.exe" => {
+            const TECHNIQUE: &str = "T1218.009";
+            if url.is_some() {
+                return finding("RegAsm/RegSvcs Remote Assembly", TECHNIQUE, url);
+            }
+            let assembly = target?;
+            if untrusted(&assembly) {
+                return finding("RegAsm/RegSvcs Assembly from User-Writable Path", TECHNIQUE, Some(assembly));
+            }
+            if unsigned(&assembly) {
+                return finding("RegAsm/RegSvcs Registering Unsigned Assembly", TECHNIQUE, Some(assembly));
             }
         }
+        _ => {}
     }
-    // IPv6 loopback
-    if addr == "::1" || addr == "0:0:0:0:0:0:0:1" {
-        return true;
+    None
+}
+
+/// True when an MSBuild project on disk declares an inline task
+/// (`CodeTaskFactory`/`RoslynCodeTaskFactory`), which compiles and runs
+/// embedded C# or VB at build time.
+fn has_inline_task(project: &str) -> bool {
+    if drive_letter(project).is_none() {
+        return false;
     }
-    // Unspecified / any-address
-    if addr == "0.0.0.0" {
-        return true;
+    let Ok(metadata) = std::fs::metadata(project) else { return false };
+    if !metadata.is_file() || metadata.len() > MAX_PROJECT_SCAN_BYTES {
+        return false;
     }
-    false
+    std::fs::read(project)
+        .map(|bytes| {
+            let text = String::from_utf8_lossy(&bytes).to_lowercase();
+            text.contains("codetaskfactory") && text.contains("<usingtask")
+        })
+        .unwrap_or(false)
 }
 
-pub fn is_suspicious_domain(domain: &str) -> bool {
-    let lower = domain.to_lowercase();
-    // High digit count suggests DGA
-    if lower.chars().filter(|c| c.is_ascii_digit()).count() > 5 {
-        return true;
+/// The command line with the image path (quoted or not) removed.
+fn arguments_after_image(command_line: &str) -> &str {
+    let trimmed = command_line.trim_start();
+    let image_len = image_path_from_command_line(trimmed).len();
+    let skip = if trimmed.starts_with('"') { (image_len + 2).min(trimmed.len()) } else { image_len };
+    &trimmed[skip..]
+}
+
+/// Whitespace-separated arguments, keeping quoted runs together and dropping the quotes.
+fn split_arguments(args: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in args.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
     }
-    // Excessive subdomains
-    if lower.matches('.').count() > 4 {
-        return true;
+    if !current.is_empty() {
+        parts.push(current);
     }
-    // Suspicious TLDs
-    const SUSPICIOUS_TLDS: &[&str] = &[".xyz", ".top", ".club", ".bid", ".win", ".gq", ".ml", ".cf"];
-    SUSPICIOUS_TLDS.iter().any(|tld| lower.ends_with(tld))
+    parts
 }
 
-const PORT_METADATA: &[(u16, bool, &str)] = &[
-    (4444,  true,  "Metasploit default"),
-    (31337, true,  "Back Orifice"),
-    (6667,  true,  "IRC"),
-    (6660,  true,  "IRC"),
-    (9999,  true,  "Common malware"),
-    (5555,  true,  "Common malware"),
-    (8877,  true,  "Common malware"),
-    (1337,  true,  "Elite/Leet port"),
-    (1234,  true,  "Common malware / test port"),
-    (4321,  true,  "Common malware / test port"),
-    (6789,  true,  "Common malware"),
-    (9898,  true,  "Common malware"),
-    (9988,  true,  "Common malware"),
-    (2333,  true,  "Common malware"),
-    (2334,  true,  "Common malware"),
-    (3389,  false, "RDP"),
-    (22,    false, "SSH"),
-    (23,    false, "Telnet"),
-    (21,    false, "FTP"),
-    (25,    false, "SMTP"),
+/// Returns a label if the command line deletes shadow copies, backups or boot
+/// recovery settings — a near-universal ransomware precursor (T1490).
+pub fn identify_recovery_inhibition(command_line: &str) -> Option<&'static str> {
+    let lower_cmd = command_line.to_lowercase();
+
+    if lower_cmd.contains("vssadmin") &&
+       (lower_cmd.contains("delete shadows") || lower_cmd.contains("resize shadowstorage"))
+    {
+        return Some("Vssadmin Shadow Copy Deletion");
+    }
+    if lower_cmd.contains("shadowcopy") && lower_cmd.contains("delete") {
+        return Some("WMI Shadow Copy Deletion");
+    }
+    if lower_cmd.contains("bcdedit") &&
+       (lower_cmd.contains("recoveryenabled no") || lower_cmd.contains("bootstatuspolicy ignoreallfailures"))
+    {
+        return Some("Bcdedit Recovery Disabled");
+    }
+    if lower_cmd.contains("wbadmin") &&
+       (lower_cmd.contains("delete catalog") || lower_cmd.contains("delete systemstatebackup") ||
+        lower_cmd.contains("delete backup"))
+    {
+        return Some("Wbadmin Backup Catalog Deletion");
+    }
+    None
+}
+
+/// Describes the tampering if the command line turns off the firewall, stops or
+/// disables one of `protected_services`, or weakens boot-time integrity checks (T1562).
+pub fn identify_security_service_tamper(command_line: &str, protected_services: &[String]) -> Option<String> {
+    let lower_cmd = command_line.to_lowercase();
+
+    if lower_cmd.contains("advfirewall") && lower_cmd.contains("state off") {
+        return Some("Firewall disabled via netsh advfirewall".to_string());
+    }
+    if lower_cmd.contains("netsh") && lower_cmd.contains("firewall") && lower_cmd.contains("opmode disable") {
+        return Some("Firewall disabled via netsh firewall opmode".to_string());
+    }
+    if lower_cmd.contains("set-netfirewallprofile") &&
+       (lower_cmd.contains("-enabled false") || lower_cmd.contains("-enabled 0") ||
+        lower_cmd.contains("-enabled $false"))
+    {
+        return Some("Firewall disabled via Set-NetFirewallProfile".to_string());
+    }
+
+    if lower_cmd.contains("bcdedit") {
+        const BOOT_TAMPER: &[(&str, &str)] = &[
+            ("testsigning on", "test signing enabled"),
+            ("nointegritychecks on", "driver integrity checks disabled"),
+            ("disableelamdrivers yes", "early-launch anti-malware disabled"),
+            ("safeboot minimal", "forced safe-mode boot"),
+            ("safeboot network", "forced safe-mode boot"),
+        ];
+        if let Some((_, label)) = BOOT_TAMPER.iter().find(|(pattern, _)| lower_cmd.contains(pattern)) {
+            return Some(format!("Boot configuration tampering: {}", label));
+        }
+    }
+
+    // Split on separators PowerShell and cmd both use so `-Name "WinDefend",Sense` yields bare names
+    let tokens: Vec<&str> = lower_cmd
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | '"' | '\'' | ';' | '(' | ')'))
+        .filter(|token| !token.is_empty())
+        .collect();
+    let program = |token: &str| -> bool {
+        let base = token.rsplit('\\').next().unwrap_or(token);
+        matches!(base, "sc" | "sc.exe" | "net" | "net.exe" | "net1" | "net1.exe")
+    };
+
+    let action = if tokens.contains(&"stop-service") ||
+        tokens.windows(2).any(|pair| program(pair[0]) && pair[1] == "stop")
+    {
+        "Stop"
+    } else if (tokens.contains(&"set-service") && tokens.contains(&"disabled")) ||
+        (tokens.windows(2).any(|pair| program(pair[0]) && pair[1] == "config") && tokens.contains(&"disabled"))
+    {
+        "Disable"
+    } else {
+        return None;
+    };
+
+    protected_services.iter()
+        .find(|service| tokens.contains(&service.to_lowercase().as_str()))
+        .map(|service| format!("{} of protected service '{}'", action, service))
+}
+
+lazy_static::lazy_static! {
+    // -Version accepts any unambiguous prefix: -v 2, -ver 2, -version 2.0
+    static ref POWERSHELL_V2_ARG: regex::Regex =
+        regex::Regex::new(r"(?i)(?:^|\s)[-/]v(?:e(?:r(?:s(?:i(?:o(?:n)?)?)?)?)?)?\s+2(?:\.0)?(?:\s|$)").unwrap();
+}
+
+/// Returns a label if powershell.exe is started under the v2 engine, which predates
+/// AMSI and script-block logging (T1059.001). Downgrade and an AMSI bypass in the
+/// same command line yield one combined label so they score as a single indicator.
+pub fn identify_powershell_downgrade(process_name: &str, command_line: &str) -> Option<&'static str> {
+    const AMSI_BYPASS_MARKERS: &[&str] = &[
+        "amsiutils", "amsiinitfailed", "amsiscanbuffer", "amsicontext", "amsi.dll",
+    ];
+
+    if !process_name.eq_ignore_ascii_case("powershell.exe") || !POWERSHELL_V2_ARG.is_match(command_line) {
+        return None;
+    }
+    let lower_cmd = command_line.to_lowercase();
+    if AMSI_BYPASS_MARKERS.iter().any(|marker| lower_cmd.contains(marker)) {
+        Some("PowerShell v2 Downgrade with AMSI Bypass")
+    } else {
+        Some("PowerShell v2 Downgrade")
+    }
+}
+
+/// Returns a label if an auto-elevating binary spawned a child it never launches
+/// legitimately — the tell of an ms-settings / Folder handler hijack (T1548.002).
+pub fn identify_uac_bypass(parent_name: &str, process_name: &str) -> Option<&'static str> {
+    let lower_parent = parent_name.to_lowercase();
+    let lower_name = process_name.to_lowercase();
+
+    let (label, expected_children): (&'static str, &[&str]) = match lower_parent.as_str() {
+        "fodhelper.exe" => ("Fodhelper UAC Bypass", &[]),
+        "computerdefaults.exe" => ("ComputerDefaults UAC Bypass", &[]),
+        "sdclt.exe" => ("Sdclt UAC Bypass", &["control.exe", "sdclt.exe"]),
+        "eventvwr.exe" => ("Eventvwr UAC Bypass", &["mmc.exe"]),
+        _ => return None,
+    };
+
+    const ALWAYS_EXPECTED: &[&str] = &["conhost.exe", "werfault.exe"];
+    if ALWAYS_EXPECTED.contains(&lower_name.as_str()) || expected_children.contains(&lower_name.as_str()) {
+        return None;
+    }
+    Some(label)
+}
+
+/// Returns a label if a script host (wscript/cscript/mshta) looks like a dropper:
+/// launched by a browser, mail client, Office or archive tool, or pointed at a
+/// remote URL or a script sitting in a download/temp location.
+pub fn identify_script_host_dropper(process_name: &str, parent_name: &str, command_line: &str) -> Option<&'static str> {
+    const SCRIPT_HOSTS: &[&str] = &["wscript.exe", "cscript.exe", "mshta.exe"];
+    const BROWSERS: &[&str] = &[
+        "chrome.exe", "msedge.exe", "firefox.exe", "iexplore.exe", "brave.exe", "opera.exe",
+    ];
+    const MAIL_CLIENTS: &[&str] = &["thunderbird.exe"];
+    const ARCHIVERS: &[&str] = &["winrar.exe", "7zfm.exe", "7zg.exe", "winzip64.exe", "winzip32.exe"];
+    const SCRIPT_EXTENSIONS: &[&str] = &[".hta", ".js", ".jse", ".vbs", ".vbe", ".wsf"];
+    const DROP_LOCATIONS: &[&str] = &["\\downloads\\", "\\temp\\", "\\inetcache\\", "\\content.outlook\\"];
+
+    let lower_name = process_name.to_lowercase();
+    if !SCRIPT_HOSTS.contains(&lower_name.as_str()) {
+        return None;
+    }
+
+    let lower_parent = parent_name.to_lowercase();
+    if BROWSERS.contains(&lower_parent.as_str()) {
+        return Some("Script Host Launched by Browser");
+    }
+    if MAIL_CLIENTS.contains(&lower_parent.as_str()) || office_app(&lower_parent).is_some() {
+        return Some("Script Host Launched by Email/Office");
+    }
+    if ARCHIVERS.contains(&lower_parent.as_str()) {
+        return Some("Script Host Launched from Archive");
+    }
+
+    let lower_cmd = command_line.to_lowercase();
+    if lower_cmd.contains("http://") || lower_cmd.contains("https://") {
+        return Some("Script Host Remote Payload");
+    }
+    if has_token_extension(&lower_cmd, SCRIPT_EXTENSIONS) &&
+       DROP_LOCATIONS.iter().any(|dir| lower_cmd.contains(dir))
+    {
+        return Some("Script Host Running Downloaded Script");
+    }
+    None
+}
+
+/// A script host running script text from its own command line, or forced onto
+/// another engine with `//e:`, so no script file passes file-based scanning.
+pub struct InlineScript {
+    pub pattern: &'static str,
+    /// MITRE technique, e.g. `T1218.005`.
+    pub technique: &'static str,
+    /// The inline script text (mshta `vbscript:`/`javascript:`), or the file
+    /// handed to a `//e:` override.
+    pub body: Option<String>,
+    /// Download, execution and hiding markers found in the body.
+    pub indicators: Vec<&'static str>,
+}
+
+/// Inline script execution through mshta (`vbscript:`/`javascript:` URLs) and
+/// engine overrides on wscript/cscript (`//e:jscript` on a `.txt`, say). `//b`
+/// only adds an indicator; it is common in logon scripts on its own.
+pub fn identify_inline_script(process_name: &str, command_line: &str) -> Option<InlineScript> {
+    const MARKERS: &[(&str, &str)] = &[
+        ("msxml2.xmlhttp", "XMLHTTP download"),
+        ("msxml2.serverxmlhttp", "XMLHTTP download"),
+        ("winhttp.winhttprequest", "WinHttp download"),
+        ("adodb.stream", "ADODB.Stream file write"),
+        ("wscript.shell", "WScript.Shell"),
+        ("shell.application", "Shell.Application"),
+        ("scripting.filesystemobject", "FileSystemObject"),
+        (".run(", "Run()"),
+        (".run ", "Run()"),
+        (".exec(", "Exec()"),
+        ("shellexecute", "ShellExecute"),
+        ("getobject(", "GetObject()"),
+        ("powershell", "launches PowerShell"),
+        ("cmd /c", "launches cmd"),
+        ("close()", "self-closing window"),
+    ];
+
+    // ASCII-only lowering keeps byte offsets valid for slicing the original
+    let lower_cmd = command_line.to_ascii_lowercase();
+    let scan = |text: &str| -> Vec<&'static str> {
+        let lower = text.to_lowercase();
+        let mut found: Vec<&'static str> = Vec::new();
+        for (marker, label) in MARKERS {
+            if lower.contains(marker) && !found.contains(label) {
+                found.push(label);
+            }
+        }
+        if URL_IN_TEXT.is_match(text) {
+            found.push("URL");
+        }
+        found
+    };
+
+    match process_name.to_lowercase().as_str() {
+        "mshta.exe" => {
+            let (pattern, pos) = [("Mshta Inline VBScript", "vbscript:"), ("Mshta Inline JavaScript", "javascript:")]
+                .into_iter()
+                .filter_map(|(pattern, scheme)| lower_cmd.find(scheme).map(|pos| (pattern, pos)))
+                .min_by_key(|(_, pos)| *pos)?;
+            let body = command_line[pos..].trim().trim_end_matches('"').to_string();
+            let indicators = scan(&body);
+            Some(InlineScript { pattern, technique: "T1218.005", body: Some(body), indicators })
+        }
+        "wscript.exe" | "cscript.exe" => {
+            let args = split_arguments(arguments_after_image(command_line));
+            let engine = args.iter()
+                .find_map(|arg| arg.to_lowercase().strip_prefix("//e:").map(str::to_string))?;
+            let script = args.iter().find(|arg| !arg.starts_with("//")).cloned();
+            let jscript = engine.contains("jscript") || engine.contains("javascript");
+            let (pattern, technique, native) = if jscript {
+                ("Script Host JScript Engine Override", "T1059.007", [".js", ".jse"])
+            } else {
+                ("Script Host VBScript Engine Override", "T1059.005", [".vbs", ".vbe"])
+            };
+            // Naming the engine a file's extension already selects is redundant, not evasive
+            if script.as_deref().is_some_and(|script| {
+                let lower = script.to_lowercase();
+                native.iter().any(|ext| lower.ends_with(ext))
+            }) {
+                return None;
+            }
+            let mut indicators = scan(command_line);
+            if args.iter().any(|arg| arg.eq_ignore_ascii_case("//b")) {
+                indicators.push("batch mode (//b)");
+            }
+            Some(InlineScript { pattern, technique, body: script, indicators })
+        }
+        _ => None,
+    }
+}
+
+/// In-memory loading and injection patterns (T1055) in a command line, decoded
+/// payload or script: reflective .NET assembly loads, reflective PE injection and
+/// P/Invoke shellcode runners. Empty when none match.
+pub fn identify_reflective_loading(text: &str) -> Vec<&'static str> {
+    let lower = text.to_lowercase();
+    let mut findings = Vec::new();
+
+    if lower.contains("reflection.assembly]::load") {
+        findings.push("Reflective .NET Assembly Load");
+    }
+    if lower.contains("invoke-reflectivepeinjection") || lower.contains("invoke-shellcode") {
+        findings.push("Reflective PE/Shellcode Injection Cmdlet");
+    }
+    // A runner needs executable memory plus a way to copy into it and run it
+    let allocates = lower.contains("virtualalloc");
+    let copies = lower.contains("marshal]::copy") || lower.contains("rtlmovememory");
+    let executes = lower.contains("createthread")
+        || lower.contains("createremotethread")
+        || lower.contains("getdelegateforfunctionpointer");
+    if allocates && (copies || executes) {
+        findings.push("Shellcode Runner (VirtualAlloc + copy/execute)");
+    } else if copies && lower.contains("[byte[]]") && executes {
+        findings.push("Byte Array Copied to Executable Delegate");
+    }
+    findings
+}
+
+/// Finding from `identify_raw_socket_tunnel` for a known ICMP tunnel tool or cmdlet.
+pub const ICMP_TUNNEL_TOOL: &str = "ICMP Tunnel Tool";
+
+lazy_static::lazy_static! {
+    // ping -l <size> / Test-Connection -BufferSize <size>
+    static ref PING_PAYLOAD_SIZE: regex::Regex =
+        regex::Regex::new(r"(?i)\b(?:ping(?:\.exe)?\s[^|&;]*-l|test-connection\s[^|&;]*-buffersize)\s+(\d+)").unwrap();
+}
+
+/// Raw-socket and ICMP channels (T1095) in a command line, decoded payload or
+/// script: known ICMP tunnel tools, raw or ICMP sockets opened from .NET, and
+/// echo requests carrying data. The network trace only sees TCP and UDP, so this
+/// is where such traffic becomes visible. Empty when none match.
+pub fn identify_raw_socket_tunnel(text: &str) -> Vec<&'static str> {
+    const TUNNEL_TOOLS: &[&str] = &["icmpsh", "ptunnel", "pingtunnel", "icmptunnel", "invoke-powershellicmp"];
+    // Echo payloads above this many bytes carry data rather than probe reachability
+    const MAX_PROBE_PAYLOAD: u32 = 1000;
+
+    let lower = text.to_lowercase();
+    let mut findings = Vec::new();
+    if TUNNEL_TOOLS.iter().any(|tool| lower.contains(tool)) {
+        findings.push(ICMP_TUNNEL_TOOL);
+    }
+    if ["sockettype]::raw", "sockettype.raw", "sock_raw", "protocoltype]::icmp", "protocoltype.icmp"]
+        .iter()
+        .any(|marker| lower.contains(marker))
+    {
+        findings.push("Raw/ICMP Socket");
+    }
+    if lower.contains("networkinformation.ping")
+        && lower.contains(".send(")
+        && (lower.contains("getbytes") || lower.contains("[byte[]]"))
+    {
+        findings.push("Data Sent in ICMP Echo Payload");
+    }
+    if PING_PAYLOAD_SIZE.captures_iter(text)
+        .any(|caps| caps[1].parse::<u32>().is_ok_and(|size| size > MAX_PROBE_PAYLOAD))
+    {
+        findings.push("Oversized Ping Payload");
+    }
+    findings
+}
+
+/// The first `file:stream` reference in a command line whose stream is an
+/// executable or script, e.g. `C:\x.txt:payload.exe` (T1564.004). Drive letters,
+/// `\\?\` prefixes, URLs, PowerShell drives and `::` member access are not streams.
+pub fn identify_ads_reference(command_line: &str) -> Option<String> {
+    let arguments = split_arguments(command_line);
+    arguments.iter()
+        // Redirections (`type a > b.txt:c.exe`), rundll32 entry points and PowerShell quotes
+        .flat_map(|arg| arg.split(['>', '<', '|', ',', ';', '\'']))
+        .find_map(ads_stream_reference)
+}
+
+fn ads_stream_reference(token: &str) -> Option<String> {
+    const STREAM_EXTENSIONS: &[&str] = &[
+        ".exe", ".dll", ".scr", ".cpl", ".ps1", ".bat", ".cmd", ".vbs", ".vbe", ".js", ".jse",
+        ".wsf", ".hta", ".sct",
+    ];
+
+    if token.contains("://") {
+        return None;
+    }
+    let bytes = token.as_bytes();
+    let mut search_from = if token.starts_with(r"\\?\") { 4 } else { 0 };
+    if bytes.len() > search_from + 1 && bytes[search_from].is_ascii_alphabetic() && bytes[search_from + 1] == b':' {
+        search_from += 2;
+    }
+    let colon = search_from + token[search_from..].find(':')?;
+    let (file, rest) = (&token[..colon], &token[colon + 1..]);
+
+    // Switches (`/out:x.exe`, `-File:x.ps1`) and `Provider::Path` aren't file streams
+    if file.is_empty() || file.starts_with(['-', '/']) || rest.starts_with(':') {
+        return None;
+    }
+    // The host must look like a file or folder: `$env:TEMP` and `HKLM:` don't
+    let host_name = file.rsplit(['\\', '/']).next().unwrap_or(file);
+    if !host_name.contains('.') && !file.contains('\\') {
+        return None;
+    }
+    // `:$DATA` may follow the stream name
+    let stream = rest.split(':').next().unwrap_or(rest);
+    let lower_stream = stream.to_lowercase();
+    if stream.contains(['\\', '/']) || !STREAM_EXTENSIONS.iter().any(|ext| lower_stream.ends_with(ext)) {
+        return None;
+    }
+    Some(token.to_string())
+}
+
+/// Names the Office application among a shell or script interpreter's ancestors
+/// (`ancestors` nearest first) and how many generations up it is — the usual shape
+/// of a malicious macro (T1204.002), whether the macro starts the shell itself or
+/// goes through an intermediate, as in `winword.exe → cmd.exe → powershell.exe`.
+pub fn identify_office_ancestor_shell(ancestors: &[String], process_name: &str) -> Option<(&'static str, usize)> {
+    const SHELLS: &[&str] = &["powershell.exe", "pwsh.exe", "cmd.exe"];
+
+    if !SHELLS.contains(&process_name.to_lowercase().as_str()) {
+        return None;
+    }
+    ancestors.iter().enumerate().find_map(|(index, ancestor)| {
+        office_app(&ancestor.to_lowercase()).map(|app| (app, index + 1))
+    })
+}
+
+/// Names the kind of child when a process in `server_processes` (lowercased image
+/// names) is the direct parent of a command shell or script host — a webshell or an
+/// exploited service running commands (T1505.003, T1190).
+pub fn identify_server_process_shell(
+    parent_name: &str,
+    process_name: &str,
+    server_processes: &HashSet<String>,
+) -> Option<&'static str> {
+    const CHILDREN: &[(&str, &str)] = &[
+        ("cmd.exe", "command shell"),
+        ("powershell.exe", "command shell"),
+        ("pwsh.exe", "command shell"),
+        ("wscript.exe", "script host"),
+        ("cscript.exe", "script host"),
+        ("mshta.exe", "script host"),
+    ];
+
+    if !server_processes.contains(&parent_name.to_lowercase()) {
+        return None;
+    }
+    let lower_name = process_name.to_lowercase();
+    CHILDREN.iter()
+        .find(|(image, _)| *image == lower_name)
+        .map(|(_, kind)| *kind)
+}
+
+/// A BITS job used to download a payload or to run a command when it completes (T1197).
+pub struct BitsJobAbuse {
+    pub pattern: &'static str,
+    pub url: Option<String>,
+    /// Local file the job writes to, when the command line names one.
+    pub destination: Option<String>,
+    /// Program registered with `/setnotifycmdline`; BITS runs it (and reruns it
+    /// after a reboot) when the job finishes or fails.
+    pub notify_command: Option<String>,
+}
+
+impl BitsJobAbuse {
+    /// Downloads into Temp, AppData, Downloads, Public or a non-system drive.
+    pub fn writes_to_user_writable_path(&self) -> bool {
+        self.destination.as_deref().is_some_and(is_user_writable_script_location)
+    }
+}
+
+/// BITS jobs created from bitsadmin (`/transfer`, `/create`, `/addfile`,
+/// `/setnotifycmdline`) or PowerShell's BitsTransfer cmdlets. Each bitsadmin
+/// verb runs as its own process, so each is judged on its own.
+pub fn identify_bits_abuse(process_name: &str, command_line: &str) -> Option<BitsJobAbuse> {
+    const BITS_CMDLETS: &[(&str, &str)] = &[
+        ("start-bitstransfer", "PowerShell Start-BitsTransfer"),
+        ("add-bitsfile", "PowerShell Add-BitsFile"),
+    ];
+
+    let lower_name = process_name.to_lowercase();
+    let args = split_arguments(arguments_after_image(command_line));
+    let lower_args: Vec<String> = args.iter().map(|arg| arg.to_lowercase()).collect();
+    let url_index = args.iter().position(|arg| URL_IN_TEXT.is_match(arg));
+    let url = URL_IN_TEXT.find(command_line).map(|m| m.as_str().to_string());
+    // The local name follows the remote one in both bitsadmin and positional cmdlet use
+    let after_url = url_index.and_then(|index| args.get(index + 1)).cloned();
+
+    let pattern = match lower_name.as_str() {
+        "bitsadmin.exe" => {
+            let verb = |name: &str| lower_args.iter().position(|arg| arg == name);
+            if let Some(index) = verb("/setnotifycmdline") {
+                // /setnotifycmdline <job> <program> <parameters>
+                let notify_command = args.get(index + 2).map(|program| {
+                    match args.get(index + 3).filter(|params| !params.eq_ignore_ascii_case("null")) {
+                        Some(params) => format!("{} {}", program, params),
+                        None => program.clone(),
+                    }
+                });
+                return Some(BitsJobAbuse {
+                    pattern: "Bitsadmin Notify Command Persistence",
+                    url,
+                    destination: None,
+                    notify_command,
+                });
+            }
+            if verb("/transfer").is_some() {
+                "Bitsadmin Transfer"
+            } else if verb("/addfile").is_some() {
+                "Bitsadmin Add File"
+            } else if verb("/create").is_some() {
+                "Bitsadmin Job Creation"
+            } else {
+                return None;
+            }
+        }
+        "powershell.exe" | "pwsh.exe" => {
+            let lower_cmd = command_line.to_lowercase();
+            let (_, pattern) = BITS_CMDLETS.iter().find(|(cmdlet, _)| lower_cmd.contains(cmdlet))?;
+            // The cmdlet usually sits inside one quoted -Command argument, so look at words
+            let words: Vec<&str> = command_line.split_whitespace()
+                .map(|word| word.trim_matches(|c| matches!(c, '"' | '\'' | ';' | '(' | ')')))
+                .collect();
+            // -Dest is the shortest prefix that isn't also -Description
+            let destination = words.iter()
+                .position(|word| word.len() >= 5 && "-destination".starts_with(&word.to_lowercase()))
+                .or_else(|| words.iter().position(|word| URL_IN_TEXT.is_match(word)))
+                .and_then(|index| words.get(index + 1))
+                .filter(|word| !word.is_empty() && !word.starts_with('-'))
+                .map(|word| word.to_string());
+            return Some(BitsJobAbuse { pattern, url, destination, notify_command: None });
+        }
+        _ => return None,
+    };
+    Some(BitsJobAbuse { pattern, url, destination: after_url, notify_command: None })
+}
+
+/// Victim side of lateral movement: the WMI provider host or the WinRM plugin
+/// host starting a shell, script host, proxy binary or an image from a
+/// user-writable path. Returns the remoting channel and its MITRE technique.
+pub fn identify_remote_execution_child(
+    parent_name: &str,
+    process_name: &str,
+    image_path: &str,
+) -> Option<(&'static str, &'static str)> {
+    const REMOTE_HOSTS: &[(&str, &str, &str)] = &[
+        ("wmiprvse.exe", "WMI", "T1047"),
+        ("wsmprovhost.exe", "PowerShell remoting (WinRM)", "T1021.006"),
+    ];
+    const SUSPICIOUS_CHILDREN: &[&str] = &[
+        "cmd.exe", "powershell.exe", "pwsh.exe", "wscript.exe", "cscript.exe",
+        "mshta.exe", "rundll32.exe", "regsvr32.exe", "certutil.exe", "bitsadmin.exe",
+    ];
+
+    let lower_parent = parent_name.to_lowercase();
+    let (_, channel, technique) = REMOTE_HOSTS.iter().find(|(host, _, _)| *host == lower_parent)?;
+    let suspicious = SUSPICIOUS_CHILDREN.contains(&process_name.to_lowercase().as_str())
+        || is_user_writable_script_location(image_path);
+    suspicious.then_some((*channel, *technique))
+}
+
+/// Returns a label if a command line points at LSASS memory — a dumping tool, the
+/// comsvcs.dll MiniDump export or ProcDump aimed at lsass (T1003.001). Any other
+/// mention of lsass only counts alongside a dump verb (`-ma`, `dump`, `.dmp`), and
+/// never when the image sits in System32; those binaries are catalog-signed, so the
+/// location stands in for the signature check.
+pub fn identify_lsass_access(command_line: &str, image_path: &str) -> Option<&'static str> {
+    let lower = command_line.to_lowercase();
+    if lower.contains("mimikatz") {
+        return Some("Mimikatz");
+    }
+    if lower.contains("sekurlsa") {
+        return Some("sekurlsa module");
+    }
+    if lower.contains("comsvcs") && (lower.contains("minidump") || lower.contains("#24")) {
+        return Some("comsvcs.dll MiniDump");
+    }
+    if !lower.contains("lsass") {
+        return None;
+    }
+    if lower.contains("procdump") {
+        return Some("ProcDump of lsass");
+    }
+    let dump_verb = lower.contains("dump")
+        || lower.contains(".dmp")
+        || lower.split_whitespace().any(|token| token == "-ma" || token == "/ma");
+    if !dump_verb {
+        return None;
+    }
+
+    let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".to_string());
+    let system32 = format!(r"{}\system32\", system_root.trim_end_matches('\\').to_lowercase());
+    let image = image_path.trim().to_lowercase();
+    let image = image.strip_prefix(r"\\?\").unwrap_or(&image);
+    let in_system32 = image.starts_with(&system32) && !image[system32.len()..].contains('\\');
+    (!in_system32).then_some("lsass dump")
+}
+
+/// Returns true for an svchost.exe whose known parent is not services.exe — the
+/// Service Control Manager is its only legitimate launcher (T1036.005).
+pub fn is_anomalous_svchost_parent(process_name: &str, parent_name: &str) -> bool {
+    let lower_parent = parent_name.to_lowercase();
+    process_name.eq_ignore_ascii_case("svchost.exe")
+        && !lower_parent.is_empty()
+        && lower_parent != "unknown"
+        && lower_parent != "services.exe"
+}
+
+/// Windows binaries commonly borrowed as masquerade names, with the folders
+/// (relative to %SystemRoot%, "" being the Windows folder itself) they ship in.
+const SYSTEM_BINARY_LOCATIONS: &[(&str, &[&str])] = &[
+    ("svchost.exe", &["System32", "SysWOW64"]),
+    ("lsass.exe", &["System32"]),
+    ("services.exe", &["System32"]),
+    ("csrss.exe", &["System32"]),
+    ("smss.exe", &["System32"]),
+    ("wininit.exe", &["System32"]),
+    ("winlogon.exe", &["System32"]),
+    ("lsaiso.exe", &["System32"]),
+    ("spoolsv.exe", &["System32"]),
+    ("taskhostw.exe", &["System32"]),
+    ("sihost.exe", &["System32"]),
+    ("ctfmon.exe", &["System32", "SysWOW64"]),
+    ("dllhost.exe", &["System32", "SysWOW64"]),
+    ("conhost.exe", &["System32"]),
+    ("dwm.exe", &["System32"]),
+    ("fontdrvhost.exe", &["System32"]),
+    ("searchindexer.exe", &["System32"]),
+    ("wmiprvse.exe", &[r"System32\wbem", r"SysWOW64\wbem"]),
+    ("rundll32.exe", &["System32", "SysWOW64"]),
+    ("regsvr32.exe", &["System32", "SysWOW64"]),
+    ("cmd.exe", &["System32", "SysWOW64"]),
+    ("powershell.exe", &[r"System32\WindowsPowerShell\v1.0", r"SysWOW64\WindowsPowerShell\v1.0"]),
+    ("mshta.exe", &["System32", "SysWOW64"]),
+    ("wscript.exe", &["System32", "SysWOW64"]),
+    ("cscript.exe", &["System32", "SysWOW64"]),
+    ("msiexec.exe", &["System32", "SysWOW64"]),
+    ("taskmgr.exe", &["System32", "SysWOW64"]),
+    ("explorer.exe", &["", "SysWOW64"]),
+];
+
+/// Where a system binary's name was expected to run from, when `image_path` (a
+/// full path) is somewhere else — e.g. `taskhostw.exe` under `\AppData\` (T1036.005).
+/// Servicing copies under `%SystemRoot%\WinSxS` are accepted.
+pub fn identify_system_binary_relocation(process_name: &str, image_path: &str) -> Option<String> {
+    let lower_name = process_name.to_lowercase();
+    let (_, folders) = SYSTEM_BINARY_LOCATIONS.iter().find(|(name, _)| *name == lower_name)?;
+
+    let path = image_path.trim().to_lowercase();
+    let path = path.strip_prefix(r"\\?\").or_else(|| path.strip_prefix(r"\??\")).unwrap_or(&path);
+    let (directory, file_name) = path.rsplit_once('\\')?;
+    if file_name != lower_name || drive_letter(path).is_none() {
+        return None;
+    }
+
+    let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".to_string());
+    let system_root = system_root.trim_end_matches('\\');
+    if directory.starts_with(&format!(r"{}\winsxs\", system_root.to_lowercase())) {
+        return None;
+    }
+    let expected: Vec<String> = folders.iter()
+        .map(|folder| match *folder {
+            "" => system_root.to_string(),
+            folder => format!(r"{}\{}", system_root, folder),
+        })
+        .collect();
+    if expected.iter().any(|folder| folder.to_lowercase() == directory) {
+        return None;
+    }
+    Some(expected.join(" or "))
+}
+
+/// The executable named by a command line, when its file name differs from the
+/// file name of the image actually mapped into the process — the image was
+/// swapped after creation, as in process hollowing (T1055.012). Names compare
+/// case-insensitively and the command line may leave off `.exe`. An unquoted path
+/// with spaces is resolved as CreateProcess does, trying each space-separated
+/// prefix in turn. 8.3 short names are not resolved, so a token containing `~` is
+/// not compared. A login shell's leading `-` (`-bash`) is ignored, and a command
+/// line naming an app execution alias in a `WindowsApps` folder, or a bare name
+/// that resolves to a packaged app's image there, is taken to match.
+pub fn identify_image_command_line_mismatch<'a>(image_path: &str, command_line: &'a str) -> Option<&'a str> {
+    if command_line.trim().is_empty() || command_line.trim().eq_ignore_ascii_case("Unknown") {
+        return None;
+    }
+    let claimed = image_path_from_command_line(command_line).trim();
+    let file_name = |path: &str| {
+        let name = path.trim().trim_matches('"').rsplit(['\\', '/']).next().unwrap_or_default().to_lowercase();
+        name.strip_prefix('-').map(str::to_string).unwrap_or(name)
+    };
+    let image_name = file_name(image_path);
+    let claimed_name = file_name(claimed);
+    if image_name.is_empty() || claimed_name.is_empty() || claimed_name.contains('~') {
+        return None;
+    }
+    // An alias such as WindowsApps\python.exe starts PythonSoftwareFoundation...\python3.12.exe
+    let lower_claimed = claimed.to_lowercase();
+    if lower_claimed.contains("\\windowsapps\\")
+        || (image_path.to_lowercase().contains("\\windowsapps\\") && !lower_claimed.contains(['\\', '/']))
+    {
+        return None;
+    }
+    let names_image = |candidate: &str| {
+        let name = file_name(candidate);
+        name == image_name || format!("{}.exe", name) == image_name
+    };
+    if names_image(claimed) {
+        return None;
+    }
+
+    // `C:\Program Files\Vendor\app.exe -x` names app.exe, not C:\Program
+    let unquoted = command_line.trim();
+    if !unquoted.starts_with('"')
+        && unquoted.match_indices(' ').map(|(end, _)| end)
+            .chain([unquoted.len()])
+            .any(|end| names_image(&unquoted[..end]))
+    {
+        return None;
+    }
+    Some(claimed)
+}
+
+/// Returns a label if script text both reads and rewrites the clipboard in a
+/// polling loop or while matching wallet-address formats — the crypto
+/// clipper pattern (T1115).
+pub fn identify_clipboard_hijack(script_text: &str) -> Option<&'static str> {
+    let lower = script_text.to_lowercase();
+
+    let reads = lower.contains("get-clipboard") ||
+                lower.contains("clipboard]::gettext") ||
+                lower.contains("getclipboarddata");
+    let writes = lower.contains("set-clipboard") ||
+                 lower.contains("clipboard]::settext") ||
+                 lower.contains("setclipboarddata");
+    if !reads || !writes {
+        return None;
+    }
+
+    // Literal address prefixes, or the quantifiers of typical BTC/ETH address regexes
+    const WALLET_MARKERS: &[&str] = &["bc1q", "wallet", "{25,34}", "{26,35}", "{39,59}", "{40}"];
+    if WALLET_MARKERS.iter().any(|marker| lower.contains(marker)) {
+        return Some("Clipboard Crypto-Address Swapper");
+    }
+
+    let polls = (lower.contains("while ($true)") || lower.contains("while (1)") ||
+                 lower.contains("while($true)") || lower.contains("for (;;)")) &&
+                lower.contains("start-sleep");
+    if polls {
+        return Some("Clipboard Monitor-and-Replace Loop");
+    }
+    None
+}
+
+/// Returns true if a script path (or command line) points at a location any
+/// user can write to — Temp, AppData, Downloads, Public, or a non-system drive.
+pub fn is_user_writable_script_location(path: &str) -> bool {
+    let lower = path.to_lowercase().replace('"', "");
+    const WRITABLE_DIRS: &[&str] = &["\\temp\\", "\\appdata\\", "\\downloads\\", "\\users\\public\\"];
+    if WRITABLE_DIRS.iter().any(|dir| lower.contains(dir)) {
+        return true;
+    }
+    // Removable and secondary drives
+    drive_letter(&lower).is_some_and(|drive| !is_system_drive(drive))
+}
+
+/// Why an autostart (Run/RunOnce) value looks like a dropped payload rather than
+/// an installed application: scripts, user-writable locations, encoded commands
+/// and unsigned executables. Empty when nothing stands out.
+pub fn identify_suspicious_autorun(value_data: &str) -> Vec<&'static str> {
+    const SCRIPT_EXTENSIONS: &[&str] = &[".ps1", ".vbs", ".vbe", ".js", ".jse", ".bat", ".cmd", ".hta", ".wsf"];
+
+    let mut findings = Vec::new();
+    let lower = value_data.to_lowercase();
+    if SCRIPT_EXTENSIONS.iter().any(|ext| {
+        lower.match_indices(ext).any(|(pos, _)| {
+            lower[pos + ext.len()..].chars().next().is_none_or(|c| !c.is_ascii_alphanumeric())
+        })
+    }) {
+        findings.push("script payload");
+    }
+    if is_user_writable_script_location(value_data) {
+        findings.push("user-writable location");
+    }
+    if has_encoding_markers(value_data) {
+        findings.push("encoded command");
+    }
+
+    // OS binaries are mostly catalog-signed, which WinVerifyTrust on the file alone
+    // doesn't see, so only images outside the Windows directory are checked
+    let image = image_path_from_command_line(value_data);
+    let image_lower = image.to_lowercase();
+    if image_lower.ends_with(".exe")
+        && !image_lower.contains(":\\windows\\")
+        && is_signed_executable(image) == Some(false)
+    {
+        findings.push("unsigned executable");
+    }
+    findings
+}
+
+/// Authenticode check of an embedded signature, without revocation lookups.
+/// `None` when the file can't be read.
+pub fn is_signed_executable(path: &str) -> Option<bool> {
+    use windows::Win32::Foundation::{HANDLE, HWND};
+    use windows::Win32::Security::WinTrust::*;
+    use windows::core::HSTRING;
+
+    if !std::path::Path::new(path).is_file() {
+        return None;
+    }
+    let wide_path = HSTRING::from(path);
+    let mut file_info = WINTRUST_FILE_INFO {
+        cbStruct: std::mem::size_of::<WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: windows::core::PCWSTR(wide_path.as_ptr()),
+        hFile: HANDLE::default(),
+        pgKnownSubject: std::ptr::null_mut(),
+    };
+    let mut data = WINTRUST_DATA {
+        cbStruct: std::mem::size_of::<WINTRUST_DATA>() as u32,
+        dwUIChoice: WTD_UI_NONE,
+        fdwRevocationChecks: WTD_REVOKE_NONE,
+        dwUnionChoice: WTD_CHOICE_FILE,
+        Anonymous: WINTRUST_DATA_0 { pFile: &mut file_info },
+        dwStateAction: WTD_STATEACTION_VERIFY,
+        dwProvFlags: WTD_CACHE_ONLY_URL_RETRIEVAL,
+        ..Default::default()
+    };
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    let status = unsafe { WinVerifyTrust(HWND::default(), &mut action, &mut data as *mut _ as *mut _) };
+
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    unsafe {
+        WinVerifyTrust(HWND::default(), &mut action, &mut data as *mut _ as *mut _);
+    }
+
+    // No signature, a broken one or an untrusted root all count as unsigned
+    Some(status == 0)
+}
+
+/// Drive letter of the first `X:\` path in the text, lowercased.
+pub fn drive_letter(path: &str) -> Option<char> {
+    let pos = path.find(":\\")?;
+    path[..pos].chars().last()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_lowercase())
+}
+
+/// True for the Windows system drive (%SystemDrive%, normally C:).
+pub fn is_system_drive(drive: char) -> bool {
+    let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+    system_drive.chars().next()
+        .is_some_and(|c| c.eq_ignore_ascii_case(&drive))
+}
+
+/// True for `\\server\share` (or `\\?\UNC\server\share`) paths, excluding the
+/// local `\\?\` and `\\.\` device prefixes.
+pub fn is_unc_path(path: &str) -> bool {
+    if path.to_ascii_lowercase().starts_with("\\\\?\\unc\\") {
+        return true;
+    }
+    path.starts_with("\\\\") && !path.starts_with("\\\\?\\") && !path.starts_with("\\\\.\\")
+}
+
+/// Image path from a command line: the first token, honouring quotes.
+pub fn image_path_from_command_line(command_line: &str) -> &str {
+    let trimmed = command_line.trim_start();
+    if let Some(rest) = trimmed.strip_prefix('"') {
+        rest.split('"').next().unwrap_or(rest)
+    } else {
+        trimmed.split_whitespace().next().unwrap_or(trimmed)
+    }
+}
+
+/// Returns true if the address is a loopback, RFC-1918 private, link-local, or
+/// unspecified address that should not be treated as an external connection.
+pub fn is_private_or_local(addr: &str) -> bool {
+    if addr.starts_with("127.") || addr.starts_with("192.168.") {
+        return true;
+    }
+    if addr.starts_with("10.") {
+        return true;
+    }
+    if addr.starts_with("172.") {
+        // RFC-1918: 172.16.0.0 – 172.31.255.255
+        let second_octet: Option<u8> = addr.split('.').nth(1).and_then(|o| o.parse().ok());
+        if let Some(n) = second_octet {
+            if (16..=31).contains(&n) {
+                return true;
+            }
+        }
+    }
+    // IPv6 loopback
+    if addr == "::1" || addr == "0:0:0:0:0:0:0:1" {
+        return true;
+    }
+    // Unspecified / any-address
+    if addr == "0.0.0.0" {
+        return true;
+    }
+    false
+}
+
+/// A parsed `destination_allowlist` entry.
+#[derive(Clone, Debug)]
+pub enum AllowlistEntry {
+    Exact(String),
+    Suffix(String),
+    Cidr(IpAddr, u8),
+}
+
+/// Parses an allowlist entry: `a.b.c.d/nn` (or IPv6) is a CIDR range, a leading
+/// `.` is a domain-suffix match, anything else is an exact host or IP.
+pub fn parse_allowlist_entry(entry: &str) -> Option<AllowlistEntry> {
+    let entry = entry.trim().to_lowercase();
+    if entry.is_empty() {
+        return None;
+    }
+    if let Some((net, bits)) = entry.split_once('/') {
+        let net: IpAddr = net.parse().ok()?;
+        let bits: u8 = bits.parse().ok()?;
+        let max_bits = if net.is_ipv4() { 32 } else { 128 };
+        if bits > max_bits {
+            return None;
+        }
+        return Some(AllowlistEntry::Cidr(net, bits));
+    }
+    if let Some(suffix) = entry.strip_prefix('.') {
+        return Some(AllowlistEntry::Suffix(suffix.to_string()));
+    }
+    Some(AllowlistEntry::Exact(entry))
+}
+
+/// Returns true if the remote address (or the domain it was resolved from)
+/// matches an operator-supplied allowlist entry.
+pub fn is_allowlisted_destination(allowlist: &[AllowlistEntry], addr: &str, domain: Option<&str>) -> bool {
+    let lower_addr = addr.to_lowercase();
+    let lower_domain = domain.map(|d| d.trim_end_matches('.').to_lowercase());
+    let ip: Option<IpAddr> = addr.parse().ok();
+
+    allowlist.iter().any(|entry| match entry {
+        AllowlistEntry::Exact(host) => {
+            *host == lower_addr || lower_domain.as_deref() == Some(host.as_str())
+        }
+        AllowlistEntry::Suffix(suffix) => lower_domain
+            .as_deref()
+            .map(|d| d == suffix || d.ends_with(&format!(".{}", suffix)))
+            .unwrap_or(false),
+        AllowlistEntry::Cidr(net, bits) => ip
+            .map(|ip| ip_in_cidr(ip, *net, *bits))
+            .unwrap_or(false),
+    })
+}
+
+fn ip_in_cidr(ip: IpAddr, net: IpAddr, bits: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = if bits == 0 { 0 } else { u32::MAX << (32 - bits) };
+            (u32::from(ip) & mask) == (u32::from(net) & mask)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = if bits == 0 { 0 } else { u128::MAX << (128 - bits) };
+            (u128::from(ip) & mask) == (u128::from(net) & mask)
+        }
+        _ => false,
+    }
+}
+
+pub fn is_suspicious_domain(domain: &str) -> bool {
+    let lower = domain.to_lowercase();
+    // High digit count suggests DGA
+    if lower.chars().filter(|c| c.is_ascii_digit()).count() > 5 {
+        return true;
+    }
+    // Excessive subdomains
+    if lower.matches('.').count() > 4 {
+        return true;
+    }
+    // Suspicious TLDs
+    const SUSPICIOUS_TLDS: &[&str] = &[".xyz", ".top", ".club", ".bid", ".win", ".gq", ".ml", ".cf"];
+    SUSPICIOUS_TLDS.iter().any(|tld| lower.ends_with(tld))
+}
+
+/// How generated a domain's registrable label looks, 0–100.
+#[derive(Debug, Clone)]
+pub struct DgaScore {
+    pub score: u8,
+    /// Shannon entropy of the registrable label, in bits per character.
+    pub entropy: f64,
+}
+
+// Labels shorter than this are too short to tell a word from noise
+const DGA_MIN_LABEL_LEN: usize = 8;
+// Second-level labels that sit under a country code, as in `example.co.uk`
+const COUNTRY_SECOND_LEVELS: &[&str] = &["co", "com", "net", "org", "gov", "ac", "edu"];
+
+/// Splits a queried name into its registrable label and parent domain:
+/// `a.b.example.co.uk` → (`example`, `example.co.uk`). `None` for single labels.
+pub fn registrable_domain(domain: &str) -> Option<(&str, String)> {
+    let labels: Vec<&str> = domain.trim_end_matches('.').split('.').filter(|label| !label.is_empty()).collect();
+    if labels.len() < 2 {
+        return None;
+    }
+    let tld = labels[labels.len() - 1];
+    let under_country = tld.len() == 2
+        && labels.len() >= 3
+        && COUNTRY_SECOND_LEVELS.contains(&labels[labels.len() - 2]);
+    let parent_labels = if under_country { 3 } else { 2 };
+    let start = labels.len() - parent_labels;
+    Some((labels[start], labels[start..].join(".")))
+}
+
+/// Shannon entropy of the bytes, in bits per byte (0–8). Compressed and encrypted
+/// data sit near 8; documents and text well below.
+pub fn byte_entropy(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
+    }
+    let total = bytes.len() as f64;
+    counts.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Scores the registrable label of `domain` on entropy, length, scarcity of vowels,
+/// long consonant runs and letter/digit alternation. Words and brand names stay
+/// low; labels like `kq3v9zhxw2pm1r` score high. Short and punycode labels are not
+/// scored.
+pub fn dga_score(domain: &str) -> Option<DgaScore> {
+    let domain = domain.to_lowercase();
+    let (label, _) = registrable_domain(&domain)?;
+    if label.len() < DGA_MIN_LABEL_LEN || label.starts_with("xn--") {
+        return None;
+    }
+    let chars: Vec<char> = label.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    if chars.len() < DGA_MIN_LABEL_LEN {
+        return None;
+    }
+
+    let mut frequencies: HashMap<char, usize> = HashMap::new();
+    for c in &chars {
+        *frequencies.entry(*c).or_default() += 1;
+    }
+    let len = chars.len() as f64;
+    let entropy: f64 = frequencies.values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+
+    let letters = chars.iter().filter(|c| c.is_ascii_alphabetic()).count();
+    let vowels = chars.iter().filter(|c| "aeiouy".contains(**c)).count();
+    let mut longest_consonant_run = 0;
+    let mut run = 0;
+    let mut alternations = 0;
+    for (index, c) in chars.iter().enumerate() {
+        run = if c.is_ascii_alphabetic() && !"aeiouy".contains(*c) { run + 1 } else { 0 };
+        longest_consonant_run = longest_consonant_run.max(run);
+        if index > 0 && c.is_ascii_digit() != chars[index - 1].is_ascii_digit() {
+            alternations += 1;
+        }
+    }
+    let vowel_ratio = if letters == 0 { 0.0 } else { vowels as f64 / letters as f64 };
+
+    let entropy_points = ((entropy - 3.0) * 25.0).clamp(0.0, 25.0);
+    let length_points = ((chars.len() as f64 - 8.0) * 2.0).clamp(0.0, 20.0);
+    let vowel_points = ((0.35 - vowel_ratio) / 0.25 * 25.0).clamp(0.0, 25.0);
+    let run_points = ((longest_consonant_run as f64 - 3.0) * 5.0).clamp(0.0, 15.0);
+    let digit_points = if letters > 0 { (alternations as f64 * 5.0).min(15.0) } else { 0.0 };
+    let score = (entropy_points + length_points + vowel_points + run_points + digit_points).round().min(100.0) as u8;
+
+    Some(DgaScore { score, entropy })
+}
+
+const PORT_METADATA: &[(u16, bool, &str)] = &[
+    (4444,  true,  "Metasploit default"),
+    (31337, true,  "Back Orifice"),
+    (6667,  true,  "IRC"),
+    (6660,  true,  "IRC"),
+    (9999,  true,  "Common malware"),
+    (5555,  true,  "Common malware"),
+    (8877,  true,  "Common malware"),
+    (1337,  true,  "Elite/Leet port"),
+    (1234,  true,  "Common malware / test port"),
+    (4321,  true,  "Common malware / test port"),
+    (6789,  true,  "Common malware"),
+    (9898,  true,  "Common malware"),
+    (9988,  true,  "Common malware"),
+    (2333,  true,  "Common malware"),
+    (2334,  true,  "Common malware"),
+    (3389,  false, "RDP"),
+    (22,    false, "SSH"),
+    (23,    false, "Telnet"),
+    (21,    false, "FTP"),
+    (25,    false, "SMTP"),
     (110,   false, "POP3"),
     (143,   false, "IMAP"),
     (445,   false, "SMB"),
@@ -677,4 +2272,216 @@ pub fn truncate_string(s: &str, max_len: usize) -> String {
     } else {
         format!("{}...", &s[..max_len])
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_mismatch_accepts_quoted_and_matching_command_lines() {
+        let image = r"C:\Program Files\Vendor\app.exe";
+        assert_eq!(identify_image_command_line_mismatch(image, r#""C:\Program Files\Vendor\app.exe" -x"#), None);
+        assert_eq!(identify_image_command_line_mismatch(image, r"C:\PROGRAM FILES\VENDOR\APP.EXE"), None);
+        assert_eq!(
+            identify_image_command_line_mismatch(r"C:\Users\Public\payload.exe", r#""C:\Windows\System32\notepad.exe" x"#),
+            Some(r"C:\Windows\System32\notepad.exe")
+        );
+    }
+
+    #[test]
+    fn image_mismatch_resolves_unquoted_paths_with_spaces() {
+        let image = r"C:\Program Files\Vendor\app.exe";
+        assert_eq!(identify_image_command_line_mismatch(image, r"C:\Program Files\Vendor\app.exe -x"), None);
+        assert_eq!(identify_image_command_line_mismatch(image, r"C:\Program Files\Vendor\app -x"), None);
+        assert_eq!(
+            identify_image_command_line_mismatch(r"C:\Users\Public\payload.exe", r"C:\Program Files\Vendor\app.exe -x"),
+            Some(r"C:\Program")
+        );
+    }
+
+    #[test]
+    fn image_mismatch_tolerates_a_missing_exe() {
+        assert_eq!(identify_image_command_line_mismatch(r"C:\Windows\System32\cmd.exe", "cmd /c dir"), None);
+        assert_eq!(
+            identify_image_command_line_mismatch(r"C:\Users\Public\payload.exe", "notepad readme.txt"),
+            Some("notepad")
+        );
+    }
+
+    #[test]
+    fn image_mismatch_skips_unknown_empty_and_short_names() {
+        let image = r"C:\Users\Public\payload.exe";
+        assert_eq!(identify_image_command_line_mismatch(image, "Unknown"), None);
+        assert_eq!(identify_image_command_line_mismatch(image, "  "), None);
+        assert_eq!(identify_image_command_line_mismatch(image, r"C:\PROGRA~1\VENDOR~1\APP~1.EXE"), None);
+    }
+
+    #[test]
+    fn image_mismatch_accepts_app_aliases_and_login_shells() {
+        let packaged = r"C:\Program Files\WindowsApps\PythonSoftwareFoundation.Python.3.12_qbz5n2kfra8p0\python3.12.exe";
+        assert_eq!(identify_image_command_line_mismatch(packaged, r"C:\Users\alice\AppData\Local\Microsoft\WindowsApps\python.exe x.py"), None);
+        assert_eq!(identify_image_command_line_mismatch(packaged, "python x.py"), None);
+        assert_eq!(identify_image_command_line_mismatch(r"C:\Program Files\Git\usr\bin\bash.exe", "-bash"), None);
+        // A bare name only passes for a packaged image, and a dash is not a wildcard
+        assert_eq!(identify_image_command_line_mismatch(r"C:\Users\Public\payload.exe", "python x.py"), Some("python"));
+        assert_eq!(identify_image_command_line_mismatch(r"C:\Users\Public\payload.exe", "-bash"), Some("-bash"));
+    }
+
+    #[test]
+    fn lsass_access_flags_comsvcs_minidump_from_system32_rundll32() {
+        let command_line = r"rundll32.exe C:\Windows\System32\comsvcs.dll, MiniDump 624 C:\temp\out.dmp full";
+        let image = r"C:\Windows\System32\rundll32.exe";
+        assert_eq!(identify_lsass_access(command_line, image), Some("comsvcs.dll MiniDump"));
+        assert_eq!(
+            identify_lsass_access(r"rundll32 comsvcs.dll #24 624 C:\temp\out.dmp full", image),
+            Some("comsvcs.dll MiniDump")
+        );
+    }
+
+    #[test]
+    fn lsass_access_flags_dumping_tools() {
+        assert_eq!(
+            identify_lsass_access("procdump64.exe -accepteula -ma lsass.exe l.dmp", r"C:\tools\procdump64.exe"),
+            Some("ProcDump of lsass")
+        );
+        assert_eq!(
+            identify_lsass_access("x.exe privilege::debug sekurlsa::logonpasswords", r"C:\Users\a\x.exe"),
+            Some("sekurlsa module")
+        );
+        assert_eq!(
+            identify_lsass_access(r"powershell Out-Minidump -Process (Get-Process lsass)", r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe"),
+            Some("lsass dump")
+        );
+    }
+
+    #[test]
+    fn lsass_access_ignores_lookups_without_a_dump_verb() {
+        let powershell = r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe";
+        assert_eq!(identify_lsass_access("powershell Get-Process lsass", powershell), None);
+        assert_eq!(identify_lsass_access(r"C:\Windows\system32\lsass.exe", r"C:\Windows\System32\lsass.exe"), None);
+        assert_eq!(identify_lsass_access("notepad.exe", r"C:\Windows\System32\notepad.exe"), None);
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("edr-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn atomic_write_creates_and_replaces_the_file() {
+        let dir = scratch_dir("atomic-write");
+        let path = dir.join("state").join("metrics.json");
+
+        write_file_atomically(&path, b"{\"first\":1}").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{\"first\":1}");
+        write_file_atomically(&path, b"{}").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{}");
+        // Nothing is left beside the target
+        let names: Vec<_> = std::fs::read_dir(path.parent().unwrap()).unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("metrics.json")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn atomic_write_reports_a_read_only_location() {
+        let dir = scratch_dir("atomic-readonly");
+        let path = dir.join("baseline.json");
+        write_file_atomically(&path, b"old").unwrap();
+
+        // A read-only file where the temporary copy goes fails the way a
+        // read-only install directory does
+        let temp_path = dir.join("baseline.json.tmp");
+        std::fs::write(&temp_path, b"").unwrap();
+        let mut permissions = std::fs::metadata(&temp_path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&temp_path, permissions.clone()).unwrap();
+
+        let error = write_file_atomically(&path, b"new").unwrap_err();
+        assert!(error.starts_with(&format!("cannot write {}", temp_path.display())), "{}", error);
+        assert!(error.ends_with("(the directory is read-only or this account cannot write to it)"), "{}", error);
+        // The original survives the failed write
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        let _ = std::fs::set_permissions(&temp_path, permissions);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn script_dropper_matches_the_token_extension_only() {
+        let downloaded = r"wscript.exe C:\Users\bob\Downloads\invoice.js";
+        assert_eq!(identify_script_host_dropper("wscript.exe", "explorer.exe", downloaded), Some("Script Host Running Downloaded Script"));
+        let quoted = r#"cscript.exe //nologo "C:\Users\Bob Smith\AppData\Local\Temp\run.vbs""#;
+        assert_eq!(identify_script_host_dropper("cscript.exe", "explorer.exe", quoted), Some("Script Host Running Downloaded Script"));
+
+        let config = r"cscript.exe C:\Users\bob\Downloads\tool.wsc C:\Users\bob\Downloads\config.json";
+        assert_eq!(identify_script_host_dropper("cscript.exe", "explorer.exe", config), None);
+        assert!(!has_token_extension(r"c:\temp\app.jsx c:\temp\notes.vbs.txt", &[".js", ".vbs"]));
+    }
+
+    #[test]
+    fn office_apps_share_one_list() {
+        assert_eq!(identify_script_host_dropper("wscript.exe", "ONENOTE.EXE", "wscript.exe a.vbs"), Some("Script Host Launched by Email/Office"));
+        assert_eq!(identify_script_host_dropper("mshta.exe", "thunderbird.exe", "mshta.exe a.hta"), Some("Script Host Launched by Email/Office"));
+        let ancestors = vec!["cmd.exe".to_string(), "MSACCESS.EXE".to_string()];
+        assert_eq!(identify_office_ancestor_shell(&ancestors, "powershell.exe"), Some(("Access", 2)));
+        // Only the core four are trusted outright; the rest are Office for the lineage rules alone
+        assert!(is_known_good_process("WINWORD.EXE", ""));
+        assert!(!is_known_good_process("visio.exe", ""));
+        assert!(!is_known_good_process("onenote.exe", ""));
+    }
+
+    #[test]
+    fn byte_entropy_spans_zero_to_eight_bits() {
+        assert_eq!(byte_entropy(&[]), 0.0);
+        assert_eq!(byte_entropy(&[b'a'; 4096]), 0.0);
+        assert_eq!(byte_entropy(b"abababab"), 1.0);
+        let uniform: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        assert!((byte_entropy(&uniform) - 8.0).abs() < 1e-9);
+        let text = b"The quick brown fox jumps over the lazy dog. ".repeat(50);
+        assert!(byte_entropy(&text) < 5.0);
+    }
+
+    fn allowlist(entries: &[&str]) -> Vec<AllowlistEntry> {
+        entries.iter().filter_map(|entry| parse_allowlist_entry(entry)).collect()
+    }
+
+    #[test]
+    fn allowlist_matches_hosts_and_suffixes_case_insensitively() {
+        let list = allowlist(&["Update.Example.COM", ".WindowsUpdate.com", "203.0.113.7"]);
+        assert!(is_allowlisted_destination(&list, "198.51.100.1", Some("UPDATE.example.com.")));
+        assert!(is_allowlisted_destination(&list, "198.51.100.1", Some("download.windowsupdate.COM")));
+        assert!(is_allowlisted_destination(&list, "198.51.100.1", Some("windowsupdate.com")));
+        assert!(is_allowlisted_destination(&list, "203.0.113.7", None));
+    }
+
+    #[test]
+    fn allowlist_matches_cidr_ranges() {
+        let list = allowlist(&["10.20.0.0/16", "2001:db8::/32"]);
+        assert!(is_allowlisted_destination(&list, "10.20.255.1", None));
+        assert!(is_allowlisted_destination(&list, "2001:DB8::1", None));
+        assert!(!is_allowlisted_destination(&list, "10.21.0.1", None));
+        assert!(!is_allowlisted_destination(&list, "2001:db9::1", None));
+    }
+
+    #[test]
+    fn allowlist_rejects_lookalikes_and_bad_entries() {
+        let list = allowlist(&["update.example.com", ".windowsupdate.com", "10.0.0.0/8"]);
+        assert!(!is_allowlisted_destination(&list, "198.51.100.1", Some("evilwindowsupdate.com")));
+        assert!(!is_allowlisted_destination(&list, "198.51.100.1", Some("update.example.com.evil.net")));
+        assert!(!is_allowlisted_destination(&list, "198.51.100.1", None));
+        // A suffix entry never matches a bare address, nor a v4 range a v6 one
+        assert!(!is_allowlisted_destination(&allowlist(&[".1"]), "198.51.100.1", None));
+        assert!(!is_allowlisted_destination(&list, "::ffff:10.0.0.1", None));
+
+        assert!(parse_allowlist_entry("  ").is_none());
+        assert!(parse_allowlist_entry("10.0.0.0/33").is_none());
+        assert!(parse_allowlist_entry("example.com/8").is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Log target that passes the filter at any level. Used for the startup banner and
+/// replies to console commands, which must stay visible even at `warn`.
+pub const CONSOLE_TARGET: &str = "console";
+
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+
+/// A `RUST_LOG`-style filter: a default level plus per-module overrides, e.g.
+/// `info,monitoring::network=warn,monitoring::correlation_engine=debug`.
+/// Module paths may omit the crate name; the longest matching path wins.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self { default: LevelFilter::Info, modules: Vec::new() }
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim();
+                    if module.is_empty() {
+                        return Err(format!("'{}' names no module", directive));
+                    }
+                    let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+                    filter.modules.push((module.to_string(), parse_level(level)?));
+                }
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        // Longest path first so the most specific override is found first
+        filter.modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(filter)
+    }
+}
+
+impl LogFilter {
+    pub fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == CONSOLE_TARGET || metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let target = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+        self.modules.iter()
+            .find(|(module, _)| {
+                target.strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// The most verbose level any module can log at.
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules.iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+            .max(LevelFilter::Info)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level.trim().parse().map_err(|_| {
+        format!("unknown log level '{}' (expected off, error, warn, info, debug or trace)", level.trim())
+    })
+}
+
+/// Applies a replaceable `LogFilter` in front of the simplelog outputs, so the
+/// level from the rules file can take effect after logging has started.
+pub struct FilteredLogger {
+    inner: Box<dyn Log>,
+    filter: RwLock<LogFilter>,
+}
+
+static LOGGER: std::sync::OnceLock<&'static FilteredLogger> = std::sync::OnceLock::new();
+
+impl FilteredLogger {
+    pub fn init(inner: Box<dyn Log>, filter: LogFilter) -> Result<(), log::SetLoggerError> {
+        let max_level = filter.max_level();
+        let logger: &'static FilteredLogger = Box::leak(Box::new(Self { inner, filter: RwLock::new(filter) }));
+        log::set_logger(logger)?;
+        log::set_max_level(max_level);
+        let _ = LOGGER.set(logger);
+        Ok(())
+    }
+}
+
+/// Replaces the active filter. A no-op before `FilteredLogger::init`.
+pub fn set_log_filter(filter: LogFilter) {
+    if let Some(logger) = LOGGER.get()
+        && let Ok(mut current) = logger.filter.write()
+    {
+        log::set_max_level(filter.max_level());
+        *current = filter;
+    }
+}
+
+impl Log for FilteredLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.read().is_ok_and(|filter| filter.enabled(metadata)) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
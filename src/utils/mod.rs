@@ -1,2 +1,5 @@
 pub mod privilege;
-pub mod common;
\ No newline at end of file
+pub mod common;
+pub mod reputation;pub mod log_filter;
+#[cfg(test)]
+pub mod test_support;
@@ -1,15 +1,45 @@
+use crate::events::ProcessSecurity;
+use std::sync::OnceLock;
 use windows::{
-    core::{w, PCWSTR},
+    core::{w, PCWSTR, PWSTR},
     Win32::{
+        Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL, LUID},
         Security::{
-            AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES,
-            SE_PRIVILEGE_ENABLED, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
-            TOKEN_QUERY,
+            AdjustTokenPrivileges, GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation,
+            IsWellKnownSid, LookupAccountSidW, LookupPrivilegeValueW,
+            TokenElevation, TokenIntegrityLevel, TokenPrivileges, TokenUser,
+            WinLocalServiceSid, WinLocalSystemSid, WinNetworkServiceSid,
+            LUID_AND_ATTRIBUTES, PSID, SE_PRIVILEGE_ENABLED, SID_NAME_USE, TOKEN_ADJUST_PRIVILEGES,
+            TOKEN_ELEVATION, TOKEN_INFORMATION_CLASS, TOKEN_MANDATORY_LABEL, TOKEN_PRIVILEGES,
+            TOKEN_QUERY, TOKEN_USER,
+        },
+        Security::Authorization::ConvertSidToStringSidW,
+        System::Threading::{
+            GetCurrentProcess, OpenProcess, OpenProcessToken, QueryFullProcessImageNameW,
+            PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
         },
-        System::Threading::{GetCurrentProcess, OpenProcessToken},
     },
 };
 
+// Privileges that let a process read any process's memory, act as part of the OS,
+// or take over a client's token (T1134)
+const DANGEROUS_PRIVILEGES: &[(PCWSTR, &str)] = &[
+    (w!("SeDebugPrivilege"), "SeDebugPrivilege"),
+    (w!("SeTcbPrivilege"), "SeTcbPrivilege"),
+    (w!("SeImpersonatePrivilege"), "SeImpersonatePrivilege"),
+];
+
+static DANGEROUS_PRIVILEGE_LUIDS: OnceLock<Vec<(LUID, &'static str)>> = OnceLock::new();
+
+/// What another process's token says about it, for the privilege-abuse rule.
+pub struct ProcessToken {
+    pub image_path: Option<String>,
+    /// Dangerous privileges (see DANGEROUS_PRIVILEGES) currently enabled.
+    pub enabled_privileges: Vec<&'static str>,
+    /// LocalSystem, LocalService or NetworkService, which hold these by design.
+    pub service_account: bool,
+}
+
 pub fn enable_required_privileges() -> bool {
     unsafe {
         enable_privilege(w!("SeDebugPrivilege")) &&
@@ -54,4 +84,210 @@ unsafe fn enable_privilege(privilege_name: PCWSTR) -> bool {
         AdjustTokenPrivileges(token_handle, false, Some(&tp), 0, None, None)
     }
     .is_ok()
-}
\ No newline at end of file
+}
+/// Opens `pid`'s token read-only. `None` when the process is gone or protected.
+pub fn inspect_process_token(pid: u32) -> Option<ProcessToken> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut token = HANDLE::default();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        let image_path = process_image_path(process);
+        let _ = CloseHandle(process);
+        opened.ok()?;
+
+        let enabled_privileges = enabled_dangerous_privileges(token);
+        let service_account = is_service_account(token);
+        let _ = CloseHandle(token);
+        Some(ProcessToken {
+            image_path,
+            enabled_privileges: enabled_privileges?,
+            service_account: service_account?,
+        })
+    }
+}
+
+/// The user, elevation and integrity level of `pid`'s token. `None` when the
+/// process is gone or protected.
+pub fn query_process_security(pid: u32) -> Option<ProcessSecurity> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut token = HANDLE::default();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        let _ = CloseHandle(process);
+        opened.ok()?;
+
+        let security = token_security(token);
+        let _ = CloseHandle(token);
+        security
+    }
+}
+
+unsafe fn token_security(token: HANDLE) -> Option<ProcessSecurity> {
+    let user_buffer = unsafe { token_information(token, TokenUser) }?;
+    let sid = unsafe { (*user_buffer.as_ptr().cast::<TOKEN_USER>()).User.Sid };
+    let user_sid = unsafe { sid_string(sid) }?;
+    let user = unsafe { account_name(sid) }.unwrap_or_else(|| user_sid.clone());
+
+    let elevated = unsafe { token_information(token, TokenElevation) }
+        .is_some_and(|buffer| unsafe { (*buffer.as_ptr().cast::<TOKEN_ELEVATION>()).TokenIsElevated } != 0);
+    let integrity_level = unsafe { token_information(token, TokenIntegrityLevel) }
+        .and_then(|buffer| {
+            let label = unsafe { (*buffer.as_ptr().cast::<TOKEN_MANDATORY_LABEL>()).Label.Sid };
+            let count = unsafe { *GetSidSubAuthorityCount(label) };
+            let rid = unsafe { *GetSidSubAuthority(label, u32::from(count.checked_sub(1)?)) };
+            Some(integrity_level_name(rid))
+        })
+        .unwrap_or("Unknown")
+        .to_string();
+    Some(ProcessSecurity { user, user_sid, elevated, integrity_level })
+}
+
+// Mandatory label RIDs (SECURITY_MANDATORY_*_RID)
+fn integrity_level_name(rid: u32) -> &'static str {
+    match rid {
+        0x0000..0x1000 => "Untrusted",
+        0x1000..0x2000 => "Low",
+        0x2000..0x2100 => "Medium",
+        0x2100..0x3000 => "MediumPlus",
+        0x3000..0x4000 => "High",
+        0x4000..0x5000 => "System",
+        _ => "Protected",
+    }
+}
+
+unsafe fn sid_string(sid: PSID) -> Option<String> {
+    let mut string_sid = PWSTR::null();
+    unsafe { ConvertSidToStringSidW(sid, &mut string_sid) }.ok()?;
+    let text = unsafe { string_sid.to_string() }.ok();
+    unsafe { LocalFree(Some(HLOCAL(string_sid.0.cast()))) };
+    text
+}
+
+unsafe fn account_name(sid: PSID) -> Option<String> {
+    let mut name = [0u16; 256];
+    let mut domain = [0u16; 256];
+    let (mut name_len, mut domain_len) = (name.len() as u32, domain.len() as u32);
+    let mut use_kind = SID_NAME_USE::default();
+    unsafe {
+        LookupAccountSidW(
+            PCWSTR::null(),
+            sid,
+            Some(PWSTR(name.as_mut_ptr())),
+            &mut name_len,
+            Some(PWSTR(domain.as_mut_ptr())),
+            &mut domain_len,
+            &mut use_kind,
+        )
+    }
+    .ok()?;
+    let name = String::from_utf16_lossy(&name[..name_len as usize]);
+    let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+    Some(if domain.is_empty() { name } else { format!("{}\\{}", domain, name) })
+}
+
+/// Full Win32 path of `pid`'s main image. `None` when the process is gone or protected.
+pub fn query_process_image_path(pid: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let image_path = process_image_path(process);
+        let _ = CloseHandle(process);
+        image_path
+    }
+}
+
+/// Suspends every thread of `pid` (containment for an alert's auto-response).
+/// Returns how many threads were suspended; threads that exit or refuse access
+/// meanwhile are skipped. Resume with Process Explorer or `pssuspend -r`.
+pub fn suspend_process(pid: u32) -> Result<usize, String> {
+    use windows::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, THREADENTRY32, TH32CS_SNAPTHREAD,
+    };
+    use windows::Win32::System::Threading::{OpenThread, SuspendThread, THREAD_SUSPEND_RESUME};
+
+    if pid <= 4 || pid == std::process::id() {
+        return Err(format!("refusing to suspend PID {}", pid));
+    }
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0)
+            .map_err(|e| format!("thread snapshot failed: {}", e))?;
+        let mut entry = THREADENTRY32 {
+            dwSize: std::mem::size_of::<THREADENTRY32>() as u32,
+            ..Default::default()
+        };
+        let mut suspended = 0;
+        if Thread32First(snapshot, &mut entry).is_ok() {
+            loop {
+                if entry.th32OwnerProcessID == pid
+                    && let Ok(thread) = OpenThread(THREAD_SUSPEND_RESUME, false, entry.th32ThreadID)
+                {
+                    if SuspendThread(thread) != u32::MAX {
+                        suspended += 1;
+                    }
+                    let _ = CloseHandle(thread);
+                }
+                if Thread32Next(snapshot, &mut entry).is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = CloseHandle(snapshot);
+        match suspended {
+            0 => Err(format!("no thread of PID {} could be suspended", pid)),
+            count => Ok(count),
+        }
+    }
+}
+
+unsafe fn process_image_path(process: HANDLE) -> Option<String> {
+    let mut buffer = [0u16; 1024];
+    let mut len = buffer.len() as u32;
+    unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut len) }.ok()?;
+    Some(String::from_utf16_lossy(&buffer[..len as usize]))
+}
+
+/// Variable-length token information, as a buffer sized by a first probing call.
+unsafe fn token_information(token: HANDLE, class: TOKEN_INFORMATION_CLASS) -> Option<Vec<u64>> {
+    let mut needed = 0u32;
+    let _ = unsafe { GetTokenInformation(token, class, None, 0, &mut needed) };
+    if needed == 0 {
+        return None;
+    }
+    // u64 elements keep the buffer aligned for the structures read out of it
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    unsafe { GetTokenInformation(token, class, Some(buffer.as_mut_ptr().cast()), needed, &mut needed) }.ok()?;
+    Some(buffer)
+}
+
+unsafe fn enabled_dangerous_privileges(token: HANDLE) -> Option<Vec<&'static str>> {
+    let buffer = unsafe { token_information(token, TokenPrivileges) }?;
+    let privileges = buffer.as_ptr().cast::<TOKEN_PRIVILEGES>();
+    let entries = unsafe {
+        std::slice::from_raw_parts((*privileges).Privileges.as_ptr(), (*privileges).PrivilegeCount as usize)
+    };
+
+    let dangerous = DANGEROUS_PRIVILEGE_LUIDS.get_or_init(|| {
+        DANGEROUS_PRIVILEGES.iter()
+            .filter_map(|&(name, label)| {
+                let mut luid = LUID::default();
+                unsafe { LookupPrivilegeValueW(PCWSTR::null(), name, &mut luid) }.ok()?;
+                Some((luid, label))
+            })
+            .collect()
+    });
+    Some(entries.iter()
+        .filter(|entry| entry.Attributes.0 & SE_PRIVILEGE_ENABLED.0 != 0)
+        .filter_map(|entry| {
+            dangerous.iter()
+                .find(|(luid, _)| luid.LowPart == entry.Luid.LowPart && luid.HighPart == entry.Luid.HighPart)
+                .map(|&(_, label)| label)
+        })
+        .collect())
+}
+
+unsafe fn is_service_account(token: HANDLE) -> Option<bool> {
+    let buffer = unsafe { token_information(token, TokenUser) }?;
+    let sid = unsafe { (*buffer.as_ptr().cast::<TOKEN_USER>()).User.Sid };
+    Some([WinLocalSystemSid, WinLocalServiceSid, WinNetworkServiceSid]
+        .into_iter()
+        .any(|account| unsafe { IsWellKnownSid(sid, account) }.as_bool()))
+}
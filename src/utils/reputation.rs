@@ -0,0 +1,126 @@
+use std::net::IpAddr;
+
+/// What a local abuse feed says about an address.
+#[derive(Debug, Clone)]
+pub struct Reputation {
+    /// 0–100, higher is worse.
+    pub score: u8,
+    /// Feed category, e.g. `scanner`, `c2`, `tor-exit`.
+    pub category: String,
+}
+
+/// Blocks of one prefix length, sorted by network address. Blocks of equal
+/// length never partially overlap, so a binary search finds the only candidate.
+struct PrefixTable<T> {
+    bits: u8,
+    blocks: Vec<(T, Reputation)>,
+}
+
+/// IP/CIDR reputation loaded from a local file. Lookups binary-search one sorted
+/// vector per prefix length, longest first, so the most specific entry wins.
+#[derive(Default)]
+pub struct ReputationList {
+    v4: Vec<PrefixTable<u32>>,
+    v6: Vec<PrefixTable<u128>>,
+}
+
+impl ReputationList {
+    /// Parses `ip_or_cidr,score,category` lines; blank lines and `#` comments are
+    /// skipped. Returns the list and the number of malformed lines ignored.
+    pub fn parse(content: &str) -> (Self, usize) {
+        let mut v4: Vec<(u32, u8, Reputation)> = Vec::new();
+        let mut v6: Vec<(u128, u8, Reputation)> = Vec::new();
+        let mut skipped = 0;
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((addr, bits, reputation)) = parse_line(line) else {
+                skipped += 1;
+                continue;
+            };
+            match addr {
+                IpAddr::V4(addr) => v4.push((u32::from(addr) & v4_mask(bits), bits, reputation)),
+                IpAddr::V6(addr) => v6.push((u128::from(addr) & v6_mask(bits), bits, reputation)),
+            }
+        }
+
+        (Self { v4: build_tables(v4), v6: build_tables(v6) }, skipped)
+    }
+
+    pub fn len(&self) -> usize {
+        self.v4.iter().map(|table| table.blocks.len()).sum::<usize>()
+            + self.v6.iter().map(|table| table.blocks.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn lookup(&self, addr: &str) -> Option<&Reputation> {
+        match addr.parse::<IpAddr>().ok()? {
+            IpAddr::V4(addr) => find(&self.v4, u32::from(addr), v4_mask),
+            IpAddr::V6(addr) => find(&self.v6, u128::from(addr), v6_mask),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(IpAddr, u8, Reputation)> {
+    let mut fields = line.splitn(3, ',').map(str::trim);
+    let range = fields.next()?;
+    let score: u8 = fields.next()?.parse().ok()?;
+    let category = fields.next().filter(|c| !c.is_empty()).unwrap_or("unknown");
+
+    let (addr, bits) = match range.split_once('/') {
+        Some((addr, bits)) => (addr.parse::<IpAddr>().ok()?, bits.parse::<u8>().ok()?),
+        None => {
+            let addr = range.parse::<IpAddr>().ok()?;
+            (addr, if addr.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    let max_bits = if addr.is_ipv4() { 32 } else { 128 };
+    if bits > max_bits {
+        return None;
+    }
+
+    Some((addr, bits, Reputation { score: score.min(100), category: category.to_lowercase() }))
+}
+
+fn build_tables<T: Ord + Copy>(mut entries: Vec<(T, u8, Reputation)>) -> Vec<PrefixTable<T>> {
+    // Longest prefix first; a repeated block keeps its last line
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut tables: Vec<PrefixTable<T>> = Vec::new();
+    for (network, bits, reputation) in entries {
+        match tables.last_mut() {
+            Some(table) if table.bits == bits => match table.blocks.last_mut() {
+                Some(last) if last.0 == network => last.1 = reputation,
+                _ => table.blocks.push((network, reputation)),
+            },
+            _ => tables.push(PrefixTable { bits, blocks: vec![(network, reputation)] }),
+        }
+    }
+    tables
+}
+
+fn find<T>(tables: &[PrefixTable<T>], addr: T, mask: fn(u8) -> T) -> Option<&Reputation>
+where
+    T: Ord + Copy + std::ops::BitAnd<Output = T>,
+{
+    tables.iter().find_map(|table| {
+        let network = addr & mask(table.bits);
+        table.blocks
+            .binary_search_by(|(block, _)| block.cmp(&network))
+            .ok()
+            .map(|index| &table.blocks[index].1)
+    })
+}
+
+fn v4_mask(bits: u8) -> u32 {
+    if bits == 0 { 0 } else { u32::MAX << (32 - bits) }
+}
+
+fn v6_mask(bits: u8) -> u128 {
+    if bits == 0 { 0 } else { u128::MAX << (128 - bits) }
+}
@@ -0,0 +1,14 @@
+// Helpers shared by the unit tests of several modules.
+
+/// `len` pseudo-random bytes from a deterministic xorshift, so a failing buffer
+/// can be reproduced from its seed. Advances `seed`.
+pub fn random_buffer(seed: &mut u64, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            *seed as u8
+        })
+        .collect()
+}